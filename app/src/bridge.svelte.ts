@@ -1,4 +1,4 @@
-import type { ROMInfo, Proxy } from "wasm";
+import type { BridgeError, CheatCodeKind, CheatInfo, ROMInfo, Proxy } from "wasm";
 import type { Options } from "./options.svelte";
 import { toEmulatorOptions } from "./options.svelte";
 
@@ -11,27 +11,60 @@ export default class EmulatorBridge {
   public initialized = $state(false);
   public running = $state(false);
   public showOnscreenControls: boolean = false;
+  /** Called with the new RAM when battery backed RAM changes */
+  public onRAMChanged: ((ram: Uint8Array) => void) | undefined = undefined;
+  /** Called with an error when rendering fails, and without one once it works again */
+  public onRendererStatus: ((error?: BridgeError) => void) | undefined = undefined;
+  /** Called with an error when work not started by a query fails, like fetching the background */
+  public onError: ((error: BridgeError) => void) | undefined = undefined;
+  /** Called with the new sample rate when audio is rebuilt after its output failed */
+  public onAudioRecovered: ((sampleRate: number) => void) | undefined = undefined;
+  /** Called with true when audio waits for a user gesture, and with false once it plays */
+  public onAudioBlocked: ((blocked: boolean) => void) | undefined = undefined;
+  /** Called with true when the rumble motor of the cartridge turns on, and false when it stops */
+  public onRumble: ((active: boolean) => void) | undefined = undefined;
+  /** Called with the added cheats after they're changed */
+  public onCheatsChanged: ((cheats: CheatInfo[]) => void) | undefined = undefined;
 
   initialize = async (options: Options) => {
     const wasm = await import("wasm");
-    this.proxy = wasm.spawn_event_loop();
+    const callbacks = new wasm.ProxyCallbacks();
+    callbacks.set_ram_changed((ram: Uint8Array) => this.onRAMChanged?.(ram));
+    callbacks.set_renderer_status((error?: BridgeError) => this.onRendererStatus?.(error));
+    callbacks.set_error((error: BridgeError) => this.onError?.(error));
+    callbacks.set_audio_recovered((sampleRate: number) => this.onAudioRecovered?.(sampleRate));
+    callbacks.set_audio_blocked((blocked: boolean) => this.onAudioBlocked?.(blocked));
+    callbacks.set_rumble((active: boolean) => this.onRumble?.(active));
+    this.proxy = wasm.spawn_event_loop(callbacks, "canvas");
+    // Controls are remappable on the page, so keys are sent through UpdateInput
+    this.proxy.query({ SetBuiltinKeyboard: { enabled: false } });
     this.updateOptions(options);
     this.setSpeed(options.speed);
+    // Audio keeps playing on the old output unless rebuilt
+    navigator.mediaDevices?.addEventListener("devicechange", () => this.resetAudio());
+    // Browsers only allow audio after a user gesture, so any click on the page enables it
+    window.addEventListener("pointerdown", () => this.proxy?.enable_audio(), { once: true });
     this.initialized = true;
   }
 
-  loadROM = async (rom: ArrayBuffer, isZip: boolean) => {
+  /**
+   * Loads a ROM, rejecting ROMs with a broken header unless forced
+   */
+  loadROM = async (rom: ArrayBuffer, isZip: boolean, force?: boolean) => {
     if (!this.proxy) {
       throw new Error("Emulator is not initialized");
     }
-    return this.proxy.query({ LoadROM: { file: new Uint8Array(rom), is_zip: isZip } }) as Promise<ROMInfo>;
+    return this.proxy.query({ LoadROM: { file: new Uint8Array(rom), is_zip: isZip, force } }) as Promise<ROMInfo>;
   }
 
-  reload = async () => {
+  /**
+   * Restarts the emulator, resolves with whether cartridge RAM was kept
+   */
+  reload = async (preserveSRAM?: boolean) => {
     if (!this.proxy) {
       throw new Error("Emulator is not initialized");
     }
-    return this.proxy.query({ Reload: {} }) as Promise<void>;
+    return this.proxy.query({ Reload: { preserve_sram: preserveSRAM } }) as Promise<boolean>;
   }
 
   loadRAM = async (ram: Uint8Array) => {
@@ -94,6 +127,14 @@ export default class EmulatorBridge {
     return this.proxy.query({ DeserializeCPU: { buffer } }) as Promise<void>;
   }
 
+  /** Rebuilds audio playback on the current default output */
+  resetAudio = async () => {
+    if (!this.proxy) {
+      return;
+    }
+    return this.proxy.query({ ResetAudio: {} }) as Promise<void>;
+  }
+
   setSpeed = async (speed: number) => {
     this.speed = speed;
     if (!this.proxy) {
@@ -117,6 +158,13 @@ export default class EmulatorBridge {
     return this.proxy.query({ UpdateInput: { input, pressed } }) as Promise<void>;
   }
 
+  updateTilt = async (x: number, y: number) => {
+    if (!this.proxy) {
+      return;
+    }
+    return this.proxy.query({ UpdateTilt: { x, y } }) as Promise<void>;
+  }
+
   updatePointerPos = async (id: number, x: number, y: number) => {
     if (!this.proxy) {
       return;
@@ -131,4 +179,150 @@ export default class EmulatorBridge {
 
     return this.proxy.query({ UpdatePointerPressed: { id, pressed } }) as Promise<void>;
   }
+
+  releasePointers = async () => {
+    if (!this.proxy) {
+      return;
+    }
+    return this.proxy.query({ ReleasePointers: {} }) as Promise<void>;
+  }
+
+  setPageHidden = async (hidden: boolean) => {
+    if (!this.proxy) {
+      return;
+    }
+    return this.proxy.query({ SetPageHidden: { hidden } }) as Promise<void>;
+  }
+
+  // Insets are given in CSS pixels
+  setSafeAreaInsets = async (top: number, right: number, bottom: number, left: number) => {
+    if (!this.proxy) {
+      return;
+    }
+    const toPhysical = (value: number) => Math.round(value * window.devicePixelRatio);
+    return this.proxy.query({
+      SetSafeAreaInsets: {
+        insets: {
+          top: toPhysical(top),
+          right: toPhysical(right),
+          bottom: toPhysical(bottom),
+          left: toPhysical(left),
+        },
+      },
+    }) as Promise<void>;
+  }
+
+  // Skins other than the default one are fetched from public/skins/manifest.json
+  selectSkin = async (name: string) => {
+    if (!this.proxy) {
+      return;
+    }
+    return this.proxy.query({ SelectSkin: { name } }) as Promise<void>;
+  }
+
+  // Lets skin authors try out changes to the TOML definition of the shown skin
+  reloadBackgroundDefinition = async (definition: string) => {
+    if (!this.proxy) {
+      return;
+    }
+    return this.proxy.query({
+      ReloadBackgroundDefinition: { definition },
+    }) as Promise<void>;
+  }
+
+  // With effects, the whole canvas is captured as it's rendered at its physical size
+  takeScreenshot = async (includeEffects = false) => {
+    if (!this.proxy) {
+      return;
+    }
+    return this.proxy.query({
+      TakeScreenshot: { include_effects: includeEffects },
+    }) as Promise<ImageData>;
+  }
+
+  startRecording = async (maxSeconds: number) => {
+    if (!this.proxy) {
+      return;
+    }
+    return this.proxy.query({
+      StartRecording: { max_seconds: maxSeconds },
+    }) as Promise<void>;
+  }
+
+  // Resolves with an animated GIF once the recorded frames are encoded
+  stopRecording = async () => {
+    if (!this.proxy) {
+      return;
+    }
+    return this.proxy.query({ StopRecording: {} }) as Promise<Uint8Array>;
+  }
+
+  startAudioCapture = async (maxSeconds: number) => {
+    if (!this.proxy) {
+      return;
+    }
+    return this.proxy.query({
+      StartAudioCapture: { max_seconds: maxSeconds },
+    }) as Promise<void>;
+  }
+
+  // Resolves with the captured audio as a WAV file
+  stopAudioCapture = async () => {
+    if (!this.proxy) {
+      return;
+    }
+    return this.proxy.query({ StopAudioCapture: {} }) as Promise<Uint8Array>;
+  }
+
+  // Resolves with the id of the cheat, rejects with InvalidCheat if the code can't be parsed
+  addCheat = async (kind: CheatCodeKind, code: string) => {
+    if (!this.proxy) {
+      throw new Error("Emulator is not initialized");
+    }
+    const id = await (this.proxy.query({ AddCheat: { kind, code } }) as Promise<number>);
+    await this.notifyCheatsChanged();
+    return id;
+  }
+
+  // Adds cheats saved for the loaded ROM, without calling onCheatsChanged
+  restoreCheats = async (cheats: { kind: CheatCodeKind, code: string, enabled: boolean }[]) => {
+    if (!this.proxy) {
+      throw new Error("Emulator is not initialized");
+    }
+    for (const { kind, code, enabled } of cheats) {
+      const id = await (this.proxy.query({ AddCheat: { kind, code } }) as Promise<number>);
+      if (!enabled) {
+        await this.proxy.query({ SetCheatEnabled: { id, enabled } });
+      }
+    }
+  }
+
+  removeCheat = async (id: number) => {
+    if (!this.proxy) {
+      throw new Error("Emulator is not initialized");
+    }
+    await this.proxy.query({ RemoveCheat: { id } });
+    await this.notifyCheatsChanged();
+  }
+
+  setCheatEnabled = async (id: number, enabled: boolean) => {
+    if (!this.proxy) {
+      throw new Error("Emulator is not initialized");
+    }
+    await this.proxy.query({ SetCheatEnabled: { id, enabled } });
+    await this.notifyCheatsChanged();
+  }
+
+  listCheats = async () => {
+    if (!this.proxy) {
+      throw new Error("Emulator is not initialized");
+    }
+    return this.proxy.query({ ListCheats: {} }) as Promise<CheatInfo[]>;
+  }
+
+  private notifyCheatsChanged = async () => {
+    if (this.onCheatsChanged) {
+      this.onCheatsChanged(await this.listCheats());
+    }
+  }
 }
@@ -1,4 +1,5 @@
 import Dexie, { type EntityTable } from "dexie"
+import type { CheatCodeKind } from "wasm";
 
 interface StateData {
   id: number,
@@ -13,14 +14,25 @@ interface SaveData {
   ram: ArrayBuffer,
 }
 
+interface CheatData {
+  id: number,
+  romHash: number,
+  kind: CheatCodeKind,
+  code: string,
+  enabled: boolean,
+}
+
 type DexieDB = Dexie & {
   states: EntityTable<StateData, "id">,
   saves: EntityTable<SaveData, "id">,
+  cheats: EntityTable<CheatData, "id">,
 }
 
 interface SerializedDB {
   states: (Omit<StateData, "state"> & { state: string })[]
   saves: (Omit<SaveData, "ram"> & { ram: string })[]
+  // Missing from data exported before cheats were saved
+  cheats?: CheatData[]
 }
 
 export class Database {
@@ -32,6 +44,9 @@ export class Database {
       states: "++id, romHash, slot",
       saves: "++id, romHash"
     })
+    db.version(2).stores({
+      cheats: "++id, romHash"
+    })
     this.db = db;
   }
 
@@ -88,6 +103,21 @@ export class Database {
     }
   }
 
+  /// Replaces the cheats saved for a ROM
+  saveCheats = async (romHash: number, cheats: { kind: CheatCodeKind, code: string, enabled: boolean }[]) => {
+    await this.db.transaction('rw', ["cheats"], async () => {
+      await this.db.cheats.where("romHash").equals(romHash).delete();
+      await this.db.cheats.bulkAdd(cheats.map(({ kind, code, enabled }) => {
+        return { romHash, kind, code, enabled };
+      }));
+    });
+  }
+
+  /// Returns the cheats saved for a ROM in the order they were added
+  getCheats = async (romHash: number) => {
+    return this.db.cheats.where("romHash").equals(romHash).sortBy("id");
+  }
+
   /// Encodes an ArrayBuffer into Base64
   private encodeBuffer = (buffer: ArrayBuffer) => {
     let bytes = new Uint8Array(buffer);
@@ -115,7 +145,7 @@ export class Database {
   /// Serializes database into a JSON string
   serializeData = async () => {
     let serialized: SerializedDB | undefined = undefined;
-    await this.db.transaction('r', ["saves", "states"], async () => {
+    await this.db.transaction('r', ["saves", "states", "cheats"], async () => {
       // Map saved buffers into Base64
       serialized = {
         states: (await this.db.states.toArray()).map(state => {
@@ -133,6 +163,7 @@ export class Database {
             id: save.id
           };
         }),
+        cheats: await this.db.cheats.toArray(),
       };
     });
     if (!serialized) {
@@ -148,7 +179,7 @@ export class Database {
   deserializeData = async (json: string) => {
     const serialized: SerializedDB = JSON.parse(json);
 
-    await this.db.transaction('rw', ["saves", "states"], async () => {
+    await this.db.transaction('rw', ["saves", "states", "cheats"], async () => {
       // Overwrite states
       let serializedStates = serialized.states.map(state => {
         return {
@@ -172,6 +203,9 @@ export class Database {
       })
       await this.db.saves.clear();
       await this.db.saves.bulkAdd(serializedSaves);
+      // Overwrite cheats
+      await this.db.cheats.clear();
+      await this.db.cheats.bulkAdd(serialized.cheats ?? []);
     });
 
     console.info("Deserialized save data");
@@ -181,6 +215,7 @@ export class Database {
   deleteData = async () => {
     await this.db.saves.clear();
     await this.db.states.clear();
+    await this.db.cheats.clear();
   }
 }
 
@@ -1,4 +1,4 @@
-import type { Palette, EmulatorOptions } from "wasm";
+import type { Color, Palette, EmulatorOptions } from "wasm";
 
 export const palettes: Record<string, Palette> = {
   LCD: [
@@ -22,6 +22,18 @@ export const palettes: Record<string, Palette> = {
 };
 export const paletteNames = ["LCD", "Clear", "Raw"]
 
+// Linear colors of the light on the background
+export const ambientColors: Record<string, Color> = {
+  Neutral: [1.0, 1.0, 1.0, 1.0],
+  Warm: [1.0, 0.74, 0.47, 1.0],
+  Cool: [0.68, 0.82, 1.0, 1.0],
+};
+export const ambientColorNames = ["Neutral", "Warm", "Cool"]
+
+export const scalingModes = ["Integer", "Fit", "Stretch"] as const;
+export const presentModes = ["Auto", "Fifo", "LowLatency"] as const;
+export const rotations = ["None", "Clockwise", "CounterClockwise"] as const;
+
 export enum OnscreenControlsOption {
   Auto = 0,
   Visible = 1,
@@ -40,20 +52,53 @@ export namespace OnscreenControlsOption {
 
 export const defaultOptions = {
   paletteIndex: 0,
+  scalingModeIndex: 0,
+  scaleOffset: 0,
+  presentModeIndex: 0,
+  rotationIndex: 0,
   speed: 1,
   fastForwardSpeed: 2,
   fpsTarget: 10,
-  volume: 100,
+  // Volume on a decibel scale, 100 is full level and each 10 changes it by 3 dB
+  volumeLevel: 100,
   onScreenControls: OnscreenControlsOption.Auto,
   uiTransitions: true,
+  pauseIndicator: true,
+  osdEnabled: true,
+  osdDuration: 2,
+  debugOverlay: false,
   backgroundGlowStrength: 80,
   displayGlowStrength: 65,
   glowEnabled: true,
   glowQuality: 3,
   glowRadius: 0.5,
+  glowDownscale: 2,
   scanlineStrength: 20,
   scanlineSize: 0.25,
+  dotMatrix: false,
+  gridSize: 0.2,
+  gridStrength: 40,
+  gridTint: true,
+  framePersistence: 0,
+  gamma: 1,
+  brightness: 0,
+  contrast: 100,
+  saturation: 100,
+  crtCurvature: 0,
+  crtVignette: 0,
   ambientLight: 90,
+  ambientColorIndex: 0,
+  hapticsEnabled: true,
+  hapticsDuration: 15,
+  touchTargetScale: 100,
+  audioPacing: false,
+  audioRateControl: true,
+  audioLatency: 100,
+  square1Volume: 100,
+  square2Volume: 100,
+  waveVolume: 100,
+  noiseVolume: 100,
+  muteInBackground: false,
 };
 
 export type Options = typeof defaultOptions;
@@ -61,19 +106,64 @@ export type Options = typeof defaultOptions;
 export const toEmulatorOptions = (options: Options, showOnscreenControls: boolean) => {
   return {
     palette: palettes[paletteNames[options.paletteIndex]],
-    volume: options.volume / 100,
+    volume: options.volumeLevel / 100,
     show_controls: showOnscreenControls,
+    scaling_mode: scalingModes[options.scalingModeIndex],
+    scale_offset: options.scaleOffset,
+    present_mode: presentModes[options.presentModeIndex],
+    pause_indicator: options.pauseIndicator,
+    osd_enabled: options.osdEnabled,
+    osd_duration_ms: options.osdDuration * 1000,
+    debug_overlay: options.debugOverlay,
+    rotation: rotations[options.rotationIndex],
     background_glow_strength: options.backgroundGlowStrength / 100,
     display_glow_strength: options.displayGlowStrength / 100,
     glow_enabled: options.glowEnabled,
     glow_iterations: options.glowQuality * 2,
     glow_radius: options.glowRadius,
+    glow_downscale: options.glowDownscale,
     scanline_strength: options.scanlineStrength / 100,
     scanline_size: options.scanlineSize,
+    pixel_effect: options.dotMatrix ? "DotMatrix" : "Scanlines",
+    grid_size: options.gridSize,
+    grid_strength: options.gridStrength / 100,
+    grid_tint: options.gridTint,
+    frame_persistence: options.framePersistence / 100,
+    color_correction: {
+      gamma: options.gamma,
+      brightness: options.brightness / 100,
+      contrast: options.contrast / 100,
+      saturation: options.saturation / 100,
+    },
+    crt_curvature: options.crtCurvature / 100,
+    crt_vignette: options.crtVignette / 100,
     ambient_light: options.ambientLight / 100,
+    ambient_color: ambientColors[ambientColorNames[options.ambientColorIndex]],
+    haptics_enabled: options.hapticsEnabled,
+    haptics_duration_ms: options.hapticsDuration,
+    touch_target_scale: options.touchTargetScale / 100,
+    audio_pacing: options.audioPacing,
+    audio_rate_control: options.audioRateControl,
+    audio_latency_ms: options.audioLatency,
+    square1_volume: options.square1Volume / 100,
+    square2_volume: options.square2Volume / 100,
+    wave_volume: options.waveVolume / 100,
+    noise_volume: options.noiseVolume / 100,
+    mute_in_background: options.muteInBackground,
   } as EmulatorOptions
 }
 
+// Converts a volume from before it was on a decibel scale to a level of similar loudness.
+// Output used to be scaled down to about a third before the linear volume was applied
+const migrateVolume = (volume: number) => {
+  if (volume <= 0) {
+    return 0;
+  }
+  const decibels = 20 * Math.log10(0.32 * volume / 100);
+  const level = 100 + decibels * 10 / 3;
+  return Math.min(Math.max(Math.round(level / 5) * 5, 5), 200);
+}
+
 export const loadOptions = () => {
   let defaults = structuredClone(defaultOptions) as any;
   let loaded = localStorage.getItem("options");
@@ -81,6 +171,9 @@ export const loadOptions = () => {
     // Overwrite default settings if they exist on the loaded object
     // This handles migration between option versions
     let loadedOptions = JSON.parse(loaded);
+    if (loadedOptions.volume !== undefined && loadedOptions.volumeLevel === undefined) {
+      loadedOptions.volumeLevel = migrateVolume(loadedOptions.volume);
+    }
     Object.keys(defaults).forEach(key => {
       if (loadedOptions[key] !== undefined) {
         defaults[key] = loadedOptions[key];
@@ -0,0 +1,91 @@
+/*
+ * Boots a ROM through the C interface, runs it for given amount of frames
+ * and writes the display to a grayscale PGM image.
+ *
+ * Build the library and the example from the core directory:
+ *   cargo rustc --release --features ffi --crate-type cdylib
+ *   cc examples/ffi_dump_frame.c -Iinclude -Ltarget/release -lgb_web_core -o dump_frame
+ * Run it:
+ *   LD_LIBRARY_PATH=target/release ./dump_frame game.gb 300 frame.pgm
+ */
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "gb_web_core.h"
+
+static unsigned char *read_file(const char *path, size_t *len) {
+    FILE *file = fopen(path, "rb");
+    if (!file) {
+        return NULL;
+    }
+    fseek(file, 0, SEEK_END);
+    long size = ftell(file);
+    fseek(file, 0, SEEK_SET);
+    unsigned char *data = size > 0 ? malloc((size_t)size) : NULL;
+    if (data && fread(data, 1, (size_t)size, file) != (size_t)size) {
+        free(data);
+        data = NULL;
+    }
+    fclose(file);
+    *len = (size_t)size;
+    return data;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 4) {
+        fprintf(stderr, "Usage: %s <rom> <frames> <out.pgm>\n", argv[0]);
+        return 1;
+    }
+    size_t rom_len;
+    unsigned char *rom = read_file(argv[1], &rom_len);
+    if (!rom) {
+        fprintf(stderr, "Couldn't read %s\n", argv[1]);
+        return 1;
+    }
+
+    char *error = NULL;
+    GBEmulator *emulator = gb_create(rom, rom_len, &error);
+    /* The ROM is copied, so it can be freed right away */
+    free(rom);
+    if (!emulator) {
+        fprintf(stderr, "%s\n", error);
+        gb_free_error(error);
+        return 1;
+    }
+
+    int frames = atoi(argv[2]);
+    for (int i = 0; i < frames; i++) {
+        if (!gb_run_frame(emulator)) {
+            fprintf(stderr, "Emulation failed at frame %d\n", i);
+            gb_destroy(emulator);
+            return 1;
+        }
+    }
+
+    /* Save states are sized by calling with a null buffer first */
+    size_t state_len = gb_save_state(emulator, NULL, 0);
+    unsigned char *state = malloc(state_len);
+    if (state && gb_save_state(emulator, state, state_len) == state_len &&
+        gb_load_state(emulator, state, state_len)) {
+        printf("Saved and restored a state of %zu bytes\n", state_len);
+    }
+    free(state);
+
+    unsigned char pixels[GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT];
+    gb_get_framebuffer(emulator, pixels);
+    gb_destroy(emulator);
+
+    FILE *out = fopen(argv[3], "wb");
+    if (!out) {
+        fprintf(stderr, "Couldn't write %s\n", argv[3]);
+        return 1;
+    }
+    fprintf(out, "P5\n%d %d\n255\n", GB_SCREEN_WIDTH, GB_SCREEN_HEIGHT);
+    for (size_t i = 0; i < sizeof(pixels); i++) {
+        /* Palette index 0 is the lightest shade */
+        fputc(255 - pixels[i] * 85, out);
+    }
+    fclose(out);
+    printf("Wrote frame %d to %s\n", frames, argv[3]);
+    return 0;
+}
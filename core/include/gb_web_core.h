@@ -0,0 +1,117 @@
+#ifndef GB_WEB_CORE_H
+#define GB_WEB_CORE_H
+
+/* Generated with cbindgen from src/ffi.rs, don't edit by hand */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * Width of the display in pixels
+ */
+#define GB_SCREEN_WIDTH 160
+
+/**
+ * Height of the display in pixels
+ */
+#define GB_SCREEN_HEIGHT 144
+
+/**
+ * Emulator instance, only handled through pointers
+ */
+typedef struct GBEmulator GBEmulator;
+
+/**
+ * Creates an emulator running given ROM, which is copied.
+ * Returns null on failure, setting `out_err` to a message if it isn't null.
+ * The emulator is freed with `gb_destroy`
+ *
+ * # Safety
+ * `rom` has to point to `len` readable bytes, `out_err` has to be null or writable
+ */
+GBEmulator *gb_create(const uint8_t *rom, size_t len, char **out_err);
+
+/**
+ * Frees an emulator created with `gb_create`, does nothing if it's null
+ *
+ * # Safety
+ * `emulator` has to be null or returned by `gb_create` and not freed already
+ */
+void gb_destroy(GBEmulator *emulator);
+
+/**
+ * Frees an error message returned through `out_err`, does nothing if it's null
+ *
+ * # Safety
+ * `message` has to be null or returned through `out_err` and not freed already
+ */
+void gb_free_error(char *message);
+
+/**
+ * Runs the emulator until the next frame is drawn. Returns false if it panicked
+ *
+ * # Safety
+ * `emulator` has to be a live emulator returned by `gb_create`
+ */
+bool gb_run_frame(GBEmulator *emulator);
+
+/**
+ * Writes the latest frame to `out` as a palette index from 0 to 3 for each pixel,
+ * row by row. `out` has to hold `GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT` bytes
+ *
+ * # Safety
+ * `emulator` has to be a live emulator and `out` has to point to enough writable bytes
+ */
+void gb_get_framebuffer(const GBEmulator *emulator, uint8_t *out);
+
+/**
+ * Sets the pressed buttons, a set bit meaning pressed: start 0x80, select 0x40,
+ * B 0x20, A 0x10, down 0x08, up 0x04, left 0x02 and right 0x01
+ *
+ * # Safety
+ * `emulator` has to be a live emulator returned by `gb_create`
+ */
+void gb_set_input(GBEmulator *emulator, uint8_t input);
+
+/**
+ * Copies cartridge RAM to `out` if it fits in `capacity` bytes, and returns its size.
+ * For cartridges with a clock, RAM is followed by the 48-byte RTC footer used by other
+ * emulators. Calling with a null `out` only returns the size
+ *
+ * # Safety
+ * `emulator` has to be a live emulator, `out` has to be null or point to `capacity`
+ * writable bytes
+ */
+size_t gb_get_ram(const GBEmulator *emulator, uint8_t *out, size_t capacity);
+
+/**
+ * Replaces cartridge RAM with a save, which is copied. An RTC footer sets the cartridge
+ * clock, counting the time since it was saved. Returns false if the save doesn't fit
+ * the cartridge RAM
+ *
+ * # Safety
+ * `emulator` has to be a live emulator and `data` has to point to `len` readable bytes
+ */
+bool gb_set_ram(GBEmulator *emulator, const uint8_t *data, size_t len);
+
+/**
+ * Serializes the emulator state to `out` if it fits in `capacity` bytes, and returns its size.
+ * Calling with a null `out` only returns the size. Returns 0 if serializing fails
+ *
+ * # Safety
+ * `emulator` has to be a live emulator, `out` has to be null or point to `capacity`
+ * writable bytes
+ */
+size_t gb_save_state(const GBEmulator *emulator, uint8_t *out, size_t capacity);
+
+/**
+ * Replaces the emulator state with one saved by `gb_save_state` with the same ROM.
+ * Returns false if the state is invalid, leaving the emulator unchanged
+ *
+ * # Safety
+ * `emulator` has to be a live emulator and `data` has to point to `len` readable bytes
+ */
+bool gb_load_state(GBEmulator *emulator, const uint8_t *data, size_t len);
+
+#endif  /* GB_WEB_CORE_H */
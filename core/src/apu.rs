@@ -583,7 +583,7 @@ bitflags! {
 use ringbuf::{
     HeapRb, SharedRb,
     storage::Heap,
-    traits::{Producer, Split},
+    traits::{Observer, Producer, Split},
     wrap::caching::Caching,
 };
 use std::sync::Arc;
@@ -591,18 +591,37 @@ use std::sync::Arc;
 pub type AudioBufferProducer = Caching<Arc<SharedRb<Heap<f32>>>, true, false>;
 pub type AudioBufferConsumer = Caching<Arc<SharedRb<Heap<f32>>>, false, true>;
 
+/// Channel volumes of a new APU, mixing every channel unchanged
+fn full_channel_volumes() -> [f32; 4] {
+    [1.0; 4]
+}
+
 /// Audio processing unit
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Deserialize, Serialize)]
 pub struct APU {
     #[serde(skip)]
     buffer_producer: Option<AudioBufferProducer>,
+    /// T-cycles between samples, in units of `SAMPLE_DELAY_SCALE`
     #[serde(skip)]
     pub sample_delay: u32,
     #[serde(skip)]
     pub hpf_capacitor_charge_factor: f32,
     #[serde(skip)]
     pub channels: usize,
+    /// Stops pushing samples to the buffer while set
+    #[serde(skip)]
+    pub muted: bool,
+    /// Volumes the channels are mixed at, from 0.0 for muted to 1.0 for unchanged.
+    /// Not part of the emulated hardware, so not kept in save states
+    #[serde(skip, default = "full_channel_volumes")]
+    pub channel_volumes: [f32; 4],
+    /// T-cycles not emulated yet, always caught up before the state can be observed
+    #[serde(skip)]
+    lag_t_cycles: u32,
+    /// Timer divider before the first T-cycle not emulated yet
+    #[serde(skip)]
+    lag_div: u16,
 
     pub on: bool,
     pub sample_delay_counter: u32,
@@ -628,6 +647,10 @@ impl APU {
             sample_delay: 0,
             channels: 0,
             hpf_capacitor_charge_factor: 0.0,
+            muted: false,
+            channel_volumes: full_channel_volumes(),
+            lag_t_cycles: 0,
+            lag_div: 0,
 
             on: true,
             sample_delay_counter: 0,
@@ -649,9 +672,17 @@ impl APU {
 
     const CLOCK_SPEED: u32 = 4194304;
     const CAPACITOR_CHARGE_FACTOR: f64 = 0.999958;
+    /// Fractions of a T-cycle the sample delay is counted in,
+    /// so sample rates that don't divide the clock speed are followed exactly
+    const SAMPLE_DELAY_SCALE: u32 = 1 << 16;
+    /// Bit of the timer divider whose falling edge increments DIV-APU
+    const DIV_APU_BIT: u16 = 1 << 12;
+    /// T-cycles the APU can lag behind before being caught up, about 16 ms
+    const MAX_LAG_T_CYCLES: u32 = 1 << 16;
 
     pub fn set_sample_rate(&mut self, sample_rate: u32) {
-        self.sample_delay = Self::CLOCK_SPEED / sample_rate;
+        self.sample_delay = (u64::from(Self::CLOCK_SPEED) * u64::from(Self::SAMPLE_DELAY_SCALE)
+            / u64::from(sample_rate)) as u32;
         self.hpf_capacitor_charge_factor = Self::CAPACITOR_CHARGE_FACTOR
             .powf((Self::CLOCK_SPEED as f64) / (sample_rate as f64))
             as f32
@@ -666,56 +697,160 @@ impl APU {
         consumer
     }
 
-    pub fn cycle(&mut self, timer_div: u16) {
-        // Increment DIV-APU when DIV register bit 4 (actual divider bit 12)
-        // goes from 1 to 0
-        let div_bit = timer_div & 0b1_0000_0000_0000 > 0;
-        if self.last_div_bit && !div_bit {
-            self.div_apu = self.div_apu.wrapping_add(1);
-            // Update length timers at 256hz (every 2 ticks)
-            if self.div_apu.is_multiple_of(2) {
-                self.square_channel_1.update_length_timer();
-                self.square_channel_2.update_length_timer();
-                self.wave_channel.update_length_timer();
-                self.noise_channel.update_length_timer();
-                // Update CH1 period sweep at 128hz (every 4 ticks)
-                if self.div_apu.is_multiple_of(4) {
-                    self.square_channel_1.update_sweep();
-                    // Update envelopes at 64hz (every 8 ticks)
-                    if self.div_apu.is_multiple_of(8) {
-                        self.square_channel_1.update_envelope();
-                        self.square_channel_2.update_envelope();
-                        self.noise_channel.update_envelope();
-                    }
+    /// Returns how full the audio buffer is, between 0.0 and 1.0
+    pub fn buffer_fill(&self) -> f32 {
+        match &self.buffer_producer {
+            Some(buffer) => (buffer.occupied_len() as f32) / (buffer.capacity().get() as f32),
+            None => 0.0,
+        }
+    }
+
+    /// Returns how many T-cycles of samples still fit in the audio buffer,
+    /// or None if no samples are being pushed to a buffer
+    pub fn buffer_room_t_cycles(&self) -> Option<u64> {
+        let buffer = self.buffer_producer.as_ref()?;
+        if self.muted || self.sample_delay == 0 || self.channels == 0 {
+            return None;
+        }
+        let free_samples = (buffer.vacant_len() / self.channels) as u64;
+        Some(free_samples * u64::from(self.sample_delay) / u64::from(Self::SAMPLE_DELAY_SCALE))
+    }
+
+    /// Cycles the APU forward by given amount of T-cycles. As the timer divider is
+    /// incremented every T-cycle, its value before the cycles gives the values to detect
+    /// DIV-APU ticks from.
+    ///
+    /// Nothing outside the APU depends on it, so the cycles are only added to the lag
+    /// it's caught up on when its registers are accessed, DIV is reset or a run ends
+    pub fn tick(&mut self, div_before: u16, t_cycles: u8) {
+        if self.lag_t_cycles == 0 {
+            self.lag_div = div_before;
+        }
+        self.lag_t_cycles += u32::from(t_cycles);
+        // Long runs are caught up on the way, so samples keep reaching the buffer
+        if self.lag_t_cycles >= Self::MAX_LAG_T_CYCLES {
+            self.catch_up();
+        }
+    }
+
+    /// Emulates the T-cycles the APU lags behind the rest of the system.
+    ///
+    /// They are split only where DIV-APU ticks or a sample is taken,
+    /// the channel timers are advanced over the T-cycles in between at once
+    pub fn catch_up(&mut self) {
+        let mut div = self.lag_div;
+        let mut remaining = std::mem::take(&mut self.lag_t_cycles);
+        if remaining == 0 {
+            return;
+        }
+        let end_div = div.wrapping_add(remaining as u16);
+        while let Some(edge) = self.div_apu_edge(div, remaining) {
+            // The frame sequencer is stepped before the channel timers of the same T-cycle
+            self.advance(edge - 1);
+            self.step_frame_sequencer();
+            self.advance(1);
+            self.last_div_bit = false;
+            div = div.wrapping_add(edge as u16);
+            remaining -= edge;
+        }
+        self.advance(remaining);
+        self.last_div_bit = end_div & Self::DIV_APU_BIT > 0;
+    }
+
+    /// Returns at which of the next T-cycles, counting from 1, DIV-APU is next incremented.
+    /// It happens when DIV register bit 4 (actual divider bit 12) goes from 1 to 0
+    fn div_apu_edge(&self, div_before: u16, t_cycles: u32) -> Option<u32> {
+        // The first T-cycle compares against the bit seen last,
+        // which differs from the divider's after DIV has been reset
+        if self.last_div_bit && div_before.wrapping_add(1) & Self::DIV_APU_BIT == 0 {
+            return Some(1);
+        }
+        // After that the bit falls when the divider reaches a multiple of 0x2000
+        let period = Self::DIV_APU_BIT << 1;
+        let until_edge = u32::from(period - (div_before & (period - 1)));
+        (until_edge > 1 && until_edge <= t_cycles).then_some(until_edge)
+    }
+
+    /// Updates length timers, sweep and envelopes on a DIV-APU tick
+    fn step_frame_sequencer(&mut self) {
+        self.div_apu = self.div_apu.wrapping_add(1);
+        // Update length timers at 256hz (every 2 ticks)
+        if self.div_apu.is_multiple_of(2) {
+            self.square_channel_1.update_length_timer();
+            self.square_channel_2.update_length_timer();
+            self.wave_channel.update_length_timer();
+            self.noise_channel.update_length_timer();
+            // Update CH1 period sweep at 128hz (every 4 ticks)
+            if self.div_apu.is_multiple_of(4) {
+                self.square_channel_1.update_sweep();
+                // Update envelopes at 64hz (every 8 ticks)
+                if self.div_apu.is_multiple_of(8) {
+                    self.square_channel_1.update_envelope();
+                    self.square_channel_2.update_envelope();
+                    self.noise_channel.update_envelope();
                 }
             }
         }
-        self.last_div_bit = div_bit;
+    }
+
+    /// Advances the channel timers by given amount of T-cycles,
+    /// taking samples at the T-cycles they fall on
+    fn advance(&mut self, t_cycles: u32) {
+        let mut remaining = t_cycles;
+        while remaining > 0 {
+            // Samples aren't produced without a sample rate
+            let until_sample = match self.sample_delay {
+                0 => u32::MAX,
+                delay => delay
+                    .saturating_sub(self.sample_delay_counter)
+                    .div_ceil(Self::SAMPLE_DELAY_SCALE)
+                    .max(1),
+            };
+            let steps = until_sample.min(remaining);
+            self.advance_channels(steps);
+            remaining -= steps;
+            if self.sample_delay == 0 {
+                continue;
+            }
+            self.sample_delay_counter += steps * Self::SAMPLE_DELAY_SCALE;
+            if steps == until_sample {
+                // Keep the remaining fraction, so the average delay matches the sample rate
+                self.sample_delay_counter -= self.sample_delay;
+                self.push_sample();
+            }
+        }
+    }
 
-        self.period_delay_counter = self.period_delay_counter.wrapping_add(1);
+    /// Updates the periods of the channels for given amount of T-cycles
+    fn advance_channels(&mut self, t_cycles: u32) {
+        let start = u32::from(self.period_delay_counter);
+        let end = start + t_cycles;
+        self.period_delay_counter = end as u8;
+        // Counts the T-cycles in the span that are multiples of given interval,
+        // which divides the wrapping point of the counter
+        let updates = |interval: u32| end / interval - start / interval;
         // Update wave channel period every 2 T-cycles
-        if self.period_delay_counter.is_multiple_of(2) {
+        for _ in 0..updates(2) {
             self.wave_channel.update_period();
-            // Update square channel period every 4 T-cycles
-            if self.period_delay_counter.is_multiple_of(4) {
-                self.square_channel_1.update_period();
-                self.square_channel_2.update_period();
-                // Update noise channel frequency every 16 T-cycles
-                if self.period_delay_counter.is_multiple_of(16) {
-                    self.noise_channel.update_lfsr();
-                }
-            }
         }
+        // Update square channel period every 4 T-cycles
+        for _ in 0..updates(4) {
+            self.square_channel_1.update_period();
+            self.square_channel_2.update_period();
+        }
+        // Update noise channel frequency every 16 T-cycles
+        for _ in 0..updates(16) {
+            self.noise_channel.update_lfsr();
+        }
+    }
 
-        // Only calculate next sample when needed
-        if self.sample_delay_counter < self.sample_delay {
-            self.sample_delay_counter += 1;
+    /// Mixes the channels into a sample and pushes it to the buffer
+    fn push_sample(&mut self) {
+        if self.muted {
             return;
         }
 
         if let Some(buffer) = &mut self.buffer_producer {
-            self.sample_delay_counter = 0;
-
             // If APU or all DACs are turned off, just push silence to the buffer
             if !self.on
                 || (!self.square_channel_1.dac_on
@@ -730,10 +865,11 @@ impl APU {
             }
 
             // Get samples from all channels
-            let ch1 = self.square_channel_1.get_sample();
-            let ch2 = self.square_channel_2.get_sample();
-            let ch3 = self.wave_channel.get_sample();
-            let ch4 = self.noise_channel.get_sample();
+            let [ch1_volume, ch2_volume, ch3_volume, ch4_volume] = self.channel_volumes;
+            let ch1 = self.square_channel_1.get_sample() * ch1_volume;
+            let ch2 = self.square_channel_2.get_sample() * ch2_volume;
+            let ch3 = self.wave_channel.get_sample() * ch3_volume;
+            let ch4 = self.noise_channel.get_sample() * ch4_volume;
 
             // Combine left and right channels
             let mut left_sample = 0f32;
@@ -780,6 +916,8 @@ impl APU {
             // +1.0 (max channel output voltage)
             // * 4  (max amount of channels outputting at once)
             // * 8  (max master volume)
+            let left_output = left_output / 32.0;
+            let right_output = right_output / 32.0;
             left_sample /= 32.0;
             right_sample /= 32.0;
 
@@ -0,0 +1,131 @@
+use super::*;
+
+/// Format of a cheat code
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum CheatKind {
+    /// Codes like `00A-17B-C49`, patching bytes read from ROM
+    GameGenie,
+    /// Codes like `010738C1`, writing bytes to RAM every frame
+    GameShark,
+}
+
+/// Decoded cheat code, applied by the CPU while it runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cheat {
+    /// Replaces the byte read from given ROM address. With a compare value the byte is
+    /// only replaced when the original matches it, which picks one of the banks mapped there
+    ROMPatch {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    /// Writes given byte to given address at the start of every VBlank
+    RAMWrite { address: u16, value: u8 },
+}
+
+impl Cheat {
+    /// Decodes a cheat code of given format. Dashes and whitespace are ignored
+    pub fn parse(kind: CheatKind, code: &str) -> Result<Self, CheatParseError> {
+        let digits = code
+            .chars()
+            .filter(|c| *c != '-' && !c.is_whitespace())
+            .map(|c| {
+                c.to_digit(16)
+                    .map(|digit| digit as u8)
+                    .ok_or(CheatParseError::InvalidCharacter(c))
+            })
+            .collect::<Result<Vec<u8>, _>>()?;
+        match kind {
+            CheatKind::GameGenie => Self::parse_game_genie(&digits),
+            CheatKind::GameShark => Self::parse_game_shark(&digits),
+        }
+    }
+
+    /// Game Genie codes are `ABC-DEF` or `ABC-DEF-GHI`: AB is the new value
+    /// and the address is CDEF with the top digit inverted. GI is the compare value,
+    /// rotated right by 2 and XORed with 0xBA, while H only serves as a checksum
+    fn parse_game_genie(digits: &[u8]) -> Result<Self, CheatParseError> {
+        if digits.len() != 6 && digits.len() != 9 {
+            return Err(CheatParseError::InvalidLength {
+                expected: "6 or 9",
+                actual: digits.len(),
+            });
+        }
+        let value = (digits[0] << 4) | digits[1];
+        let address = (u16::from(digits[5] ^ 0xF) << 12)
+            | (u16::from(digits[2]) << 8)
+            | (u16::from(digits[3]) << 4)
+            | u16::from(digits[4]);
+        if address > 0x7FFF {
+            return Err(CheatParseError::InvalidAddress(address));
+        }
+        let compare =
+            (digits.len() == 9).then(|| ((digits[6] << 4) | digits[8]).rotate_right(2) ^ 0xBA);
+        Ok(Self::ROMPatch {
+            address,
+            value,
+            compare,
+        })
+    }
+
+    /// GameShark codes are `TTVVLLHH`: type 01, the value,
+    /// then the low and high bytes of the address
+    fn parse_game_shark(digits: &[u8]) -> Result<Self, CheatParseError> {
+        if digits.len() != 8 {
+            return Err(CheatParseError::InvalidLength {
+                expected: "8",
+                actual: digits.len(),
+            });
+        }
+        let bytes: Vec<u8> = digits
+            .chunks(2)
+            .map(|pair| (pair[0] << 4) | pair[1])
+            .collect();
+        if bytes[0] != 0x01 {
+            return Err(CheatParseError::UnsupportedType(bytes[0]));
+        }
+        let address = u16::from_le_bytes([bytes[2], bytes[3]]);
+        if address < 0x8000 {
+            return Err(CheatParseError::InvalidAddress(address));
+        }
+        Ok(Self::RAMWrite {
+            address,
+            value: bytes[1],
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheatParseError {
+    /// Code has a character that isn't a hexadecimal digit, dash or whitespace
+    InvalidCharacter(char),
+    /// Code has the wrong number of digits for its format
+    InvalidLength {
+        expected: &'static str,
+        actual: usize,
+    },
+    /// Address is outside the memory the format can change
+    InvalidAddress(u16),
+    /// GameShark code type other than 01
+    UnsupportedType(u8),
+}
+
+impl std::fmt::Display for CheatParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheatParseError::InvalidCharacter(c) => {
+                write!(f, "Cheat code contains invalid character '{c}'")
+            }
+            CheatParseError::InvalidLength { expected, actual } => write!(
+                f,
+                "Cheat code has {actual} digits, but its format has {expected}"
+            ),
+            CheatParseError::InvalidAddress(address) => {
+                write!(f, "Cheat code can't change address {address:04X}")
+            }
+            CheatParseError::UnsupportedType(code_type) => {
+                write!(f, "GameShark code type {code_type:02X} isn't supported")
+            }
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use super::*;
+use std::fmt;
+
+/// Copy of the CPU registers, used for inspecting the CPU while debugging
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegisterSnapshot {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    /// Master interrupt enable
+    pub ime: bool,
+    pub halted: bool,
+}
+
+impl fmt::Display for RegisterSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flag = |mask: u8, name: char| if self.f & mask > 0 { name } else { '-' };
+        write!(
+            f,
+            "AF={:02X}{:02X} BC={:02X}{:02X} DE={:02X}{:02X} HL={:02X}{:02X} SP={:04X} PC={:04X} \
+             [{}{}{}{}] IME={} {}",
+            self.a,
+            self.f,
+            self.b,
+            self.c,
+            self.d,
+            self.e,
+            self.h,
+            self.l,
+            self.sp,
+            self.pc,
+            flag(0b1000_0000, 'Z'),
+            flag(0b0100_0000, 'N'),
+            flag(0b0010_0000, 'H'),
+            flag(0b0001_0000, 'C'),
+            u8::from(self.ime),
+            if self.halted { "HALT" } else { "" },
+        )
+    }
+}
+
+/// Called with the CPU before each executed instruction, after interrupts are dispatched
+pub type TraceHook = Box<dyn FnMut(&CPU) + Send>;
+
+/// Instruction decoded from memory by the disassembler
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisassembledInstruction {
+    /// Assembly text, like `LD A,$12`
+    pub text: String,
+    /// Length of the instruction in bytes, including the opcode
+    pub length: u16,
+}
+
+const REG8_NAMES: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const REG16_NAMES: [&str; 4] = ["BC", "DE", "HL", "SP"];
+/// 16-bit register names used by PUSH and POP, where AF replaces SP
+const STACK_REG16_NAMES: [&str; 4] = ["BC", "DE", "HL", "AF"];
+const CONDITION_NAMES: [&str; 4] = ["NZ", "Z", "NC", "C"];
+const ALU_NAMES: [&str; 8] = [
+    "ADD A,", "ADC A,", "SUB ", "SBC A,", "AND ", "XOR ", "OR ", "CP ",
+];
+const ROTATE_NAMES: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+const ACCUMULATOR_OP_NAMES: [&str; 8] = ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"];
+
+impl CPU {
+    /// Returns a copy of the registers
+    pub fn get_registers(&self) -> RegisterSnapshot {
+        RegisterSnapshot {
+            a: self.reg.a,
+            f: self.reg.f.bits(),
+            b: self.reg.b,
+            c: self.reg.c,
+            d: self.reg.d,
+            e: self.reg.e,
+            h: self.reg.h,
+            l: self.reg.l,
+            sp: self.reg.sp,
+            pc: self.reg.pc,
+            ime: self.istate.ime,
+            halted: self.halt,
+        }
+    }
+
+    /// Reads from given memory address like the CPU would, without affecting emulation
+    pub fn peek(&self, address: u16) -> u8 {
+        self.read_bus(address)
+    }
+
+    /// Executes a single instruction, or waits a cycle while HALTed
+    pub fn step(&mut self) {
+        self.run_instruction();
+        self.finish_run();
+    }
+
+    /// Stops `run_frame_until_breakpoint` before executing the instruction at given address
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Removes a breakpoint, returns if it existed
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    /// Returns the addresses of breakpoints in ascending order
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Runs like `run_frame`, but stops before executing an instruction at a breakpoint.
+    /// The first instruction is always executed, so running can continue from a breakpoint.
+    /// Returns if a breakpoint was hit
+    pub fn run_frame_until_breakpoint(&mut self) -> bool {
+        let frame = self.frame_counter;
+        let mut hit = false;
+        self.run_instruction();
+        while self.frame_counter == frame && self.cycle_counter < Self::M_CYCLES_PER_FRAME * 2 {
+            if !self.halt && self.breakpoints.contains(&self.reg.pc) {
+                hit = true;
+                break;
+            }
+            self.run_instruction();
+        }
+        self.finish_run();
+        hit
+    }
+
+    /// Sets a hook called before each executed instruction, used for tracing execution.
+    /// Instructions aren't executed while HALTed, so the hook isn't called either
+    pub fn set_trace_hook(&mut self, hook: Option<TraceHook>) {
+        self.trace_hook = hook;
+    }
+
+    /// Decodes the instruction at given address
+    pub fn disassemble(&self, address: u16) -> DisassembledInstruction {
+        let opcode = self.peek(address);
+        let d8 = self.peek(address.wrapping_add(1));
+        let d16 = u16::from_le_bytes([d8, self.peek(address.wrapping_add(2))]);
+        // Relative jumps are shown with their target address
+        let jump_target = address.wrapping_add(2).wrapping_add_signed(d8 as i8 as i16);
+
+        let x = opcode >> 6;
+        let y = ((opcode >> 3) & 0b111) as usize;
+        let z = (opcode & 0b111) as usize;
+        let p = y >> 1;
+        let q = y & 1;
+        let (text, length) = match (x, z) {
+            (0, 0) => match y {
+                0 => ("NOP".to_string(), 1),
+                1 => (format!("LD (${d16:04X}),SP"), 3),
+                2 => ("STOP".to_string(), 2),
+                3 => (format!("JR ${jump_target:04X}"), 2),
+                _ => (
+                    format!("JR {},${jump_target:04X}", CONDITION_NAMES[y - 4]),
+                    2,
+                ),
+            },
+            (0, 1) if q == 0 => (format!("LD {},${d16:04X}", REG16_NAMES[p]), 3),
+            (0, 1) => (format!("ADD HL,{}", REG16_NAMES[p]), 1),
+            (0, 2) => {
+                let address = ["(BC)", "(DE)", "(HL+)", "(HL-)"][p];
+                if q == 0 {
+                    (format!("LD {address},A"), 1)
+                } else {
+                    (format!("LD A,{address}"), 1)
+                }
+            }
+            (0, 3) => {
+                let name = if q == 0 { "INC" } else { "DEC" };
+                (format!("{name} {}", REG16_NAMES[p]), 1)
+            }
+            (0, 4) => (format!("INC {}", REG8_NAMES[y]), 1),
+            (0, 5) => (format!("DEC {}", REG8_NAMES[y]), 1),
+            (0, 6) => (format!("LD {},${d8:02X}", REG8_NAMES[y]), 2),
+            (0, _) => (ACCUMULATOR_OP_NAMES[y].to_string(), 1),
+            (1, _) if opcode == 0x76 => ("HALT".to_string(), 1),
+            (1, _) => (format!("LD {},{}", REG8_NAMES[y], REG8_NAMES[z]), 1),
+            (2, _) => (format!("{}{}", ALU_NAMES[y], REG8_NAMES[z]), 1),
+            (_, 0) => match y {
+                0..=3 => (format!("RET {}", CONDITION_NAMES[y]), 1),
+                4 => (format!("LDH (${:04X}),A", 0xFF00 | d8 as u16), 2),
+                5 => (format!("ADD SP,{}", d8 as i8), 2),
+                6 => (format!("LDH A,(${:04X})", 0xFF00 | d8 as u16), 2),
+                _ => (format!("LD HL,SP{:+}", d8 as i8), 2),
+            },
+            (_, 1) if q == 0 => (format!("POP {}", STACK_REG16_NAMES[p]), 1),
+            (_, 1) => (["RET", "RETI", "JP HL", "LD SP,HL"][p].to_string(), 1),
+            (_, 2) => match y {
+                0..=3 => (format!("JP {},${d16:04X}", CONDITION_NAMES[y]), 3),
+                4 => ("LD (C),A".to_string(), 1),
+                5 => (format!("LD (${d16:04X}),A"), 3),
+                6 => ("LD A,(C)".to_string(), 1),
+                _ => (format!("LD A,(${d16:04X})"), 3),
+            },
+            (_, 3) => match y {
+                0 => (format!("JP ${d16:04X}"), 3),
+                1 => {
+                    let y = ((d8 >> 3) & 0b111) as usize;
+                    let reg = REG8_NAMES[(d8 & 0b111) as usize];
+                    let text = match d8 >> 6 {
+                        0 => format!("{} {reg}", ROTATE_NAMES[y]),
+                        1 => format!("BIT {y},{reg}"),
+                        2 => format!("RES {y},{reg}"),
+                        _ => format!("SET {y},{reg}"),
+                    };
+                    (text, 2)
+                }
+                6 => ("DI".to_string(), 1),
+                7 => ("EI".to_string(), 1),
+                _ => (format!("DB ${opcode:02X}"), 1),
+            },
+            (_, 4) if y < 4 => (format!("CALL {},${d16:04X}", CONDITION_NAMES[y]), 3),
+            (_, 5) if q == 0 => (format!("PUSH {}", STACK_REG16_NAMES[p]), 1),
+            (_, 5) if p == 0 => (format!("CALL ${d16:04X}"), 3),
+            // Opcodes that don't exist on the Game Boy
+            (_, 4) | (_, 5) => (format!("DB ${opcode:02X}"), 1),
+            (_, 6) => (format!("{}${d8:02X}", ALU_NAMES[y]), 2),
+            _ => (format!("RST ${:02X}", y * 8), 1),
+        };
+        DisassembledInstruction { text, length }
+    }
+}
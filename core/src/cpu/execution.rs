@@ -4,26 +4,37 @@ impl CPU {
     /// Emulates the Game Boy (apart from instructions) for given amount of M-cycles
     pub(crate) fn cycle(&mut self, cycles: u32) {
         self.cycle_counter += cycles;
-        // Rest of the system runs on T-cycles, which are 1/4 of an M-cycle
-        for _ in 0..(4 * cycles) {
+        self.mem.tick_rtc(cycles);
+        // Rest of the system runs on T-cycles, which are 1/4 of an M-cycle.
+        // They are ticked an M-cycle at a time, as the CPU can only observe them
+        // between M-cycles, so the result is the same as ticking every T-cycle
+        for _ in 0..cycles {
             // Check if OAM DMA should be started
             if self.ppu.oam_dma_request {
                 self.ppu.oam_dma_request = false;
                 self.oam_dma(self.ppu.oam_dma_source);
             }
             // Cycle PPU
-            self.ppu.cycle();
+            self.ppu.tick(Self::T_CYCLES_PER_M_CYCLE);
             self.request_interrupt(self.ppu.interrupt_request);
             // Cycle timer
-            self.timer.cycle();
+            let div_before = self.timer.div;
+            self.timer.tick(Self::T_CYCLES_PER_M_CYCLE);
             if self.timer.request_interrupt {
                 self.request_interrupt(InterruptFlag::TIMER);
             }
             // Cycle APU based on timer state
-            self.apu.cycle(self.timer.div);
+            self.apu.tick(div_before, Self::T_CYCLES_PER_M_CYCLE);
         }
     }
 
+    /// Ends running instructions for a caller outside the crate. The APU is caught up,
+    /// so everything the caller can observe is emulated to the same point
+    pub(crate) fn finish_run(&mut self) {
+        self.cycle_counter = 0;
+        self.apu.catch_up();
+    }
+
     /// Executes the next instruction at program counter,
     /// ticking the rest of the system too
     pub(crate) fn run_instruction(&mut self) {
@@ -37,6 +48,13 @@ impl CPU {
             // so just cycle the system forward until HALT is lifted
             self.cycle(1);
         } else {
+            // Taken out for the call, as the hook needs to borrow the CPU
+            if let Some(mut hook) = self.trace_hook.take() {
+                // The hook may read audio registers
+                self.apu.catch_up();
+                hook(self);
+                self.trace_hook = Some(hook);
+            }
             self.run_opcode();
         }
 
@@ -45,6 +63,12 @@ impl CPU {
         let end_vblank = self.ppu.mode == PPUMode::VBlank;
         if (!start_vblank && end_vblank) || (start_active && end_disabled) {
             self.frame_counter = self.frame_counter.wrapping_add(1);
+            if let Some(sgb) = &mut self.sgb {
+                sgb.end_frame(&self.ppu.display);
+            }
+            if end_vblank {
+                self.apply_ram_cheats();
+            }
         }
     }
 
@@ -57,6 +57,13 @@ impl CPU {
         self.istate.iflag.insert(interrupt);
     }
 
+    /// Requests the serial interrupt if a transfer has finished
+    pub(crate) fn take_serial_interrupt(&mut self) {
+        if std::mem::take(&mut self.serial.request_interrupt) {
+            self.request_interrupt(InterruptFlag::SERIAL);
+        }
+    }
+
     /// Executes interrupt handler
     fn run_interrupt(&mut self, interrupt: InterruptFlag) {
         self.istate.iflag.remove(interrupt);
@@ -1,8 +1,10 @@
+mod debug;
 mod execution;
 mod interrupts;
 mod readwrite;
 
 use super::*;
+pub use debug::{DisassembledInstruction, RegisterSnapshot, TraceHook};
 pub(crate) use interrupts::*;
 pub(crate) use readwrite::*;
 
@@ -15,38 +17,102 @@ pub struct CPU {
     ppu: PPU,
     apu: APU,
     timer: Timer,
+    /// Not kept in save states, as unlinked transfers finish right away
+    /// and linked ones can't continue without the partner's state
+    #[serde(skip)]
+    serial: Serial,
     input: InputReg,
+    /// Only present for cartridges that support the Super Game Boy
+    sgb: Option<SGB>,
     istate: InterruptState,
     halt: bool,
-    pub frame_counter: u8,
+    pub frame_counter: u32,
     cycle_counter: u32,
+    /// Addresses `run_frame_until_breakpoint` stops at, not kept in save states
+    #[serde(skip)]
+    breakpoints: BTreeSet<u16>,
+    /// Active cheats, not kept in save states
+    #[serde(skip)]
+    cheats: Vec<Cheat>,
+    /// Called before each executed instruction, not kept in save states
+    #[serde(skip)]
+    trace_hook: Option<TraceHook>,
 }
 
 impl CPU {
-    pub fn new(rom_file: Vec<u8>) -> Result<Self, MemoryInitializationError> {
-        let mem = Memory::new(rom_file)?;
+    /// Version of the save state format. Increased when a change to the emulated state
+    /// makes states serialized by earlier versions fail to load or load incorrectly
+    pub const STATE_FORMAT_VERSION: u32 = 9;
+
+    /// Creates an emulator running given ROM. Only a missing header or an unsupported
+    /// cartridge type fail, so ROMs with broken headers can still be run
+    pub fn new(rom_file: Arc<[u8]>) -> Result<Self, MemoryInitializationError> {
+        Self::init(rom_file, false)
+    }
+
+    /// Creates an emulator like `new`, also failing if the header doesn't have the logo
+    /// or its checksum doesn't match, as such files likely aren't working Game Boy ROMs
+    pub fn new_checked(rom_file: Arc<[u8]>) -> Result<Self, MemoryInitializationError> {
+        Self::init(rom_file, true)
+    }
+
+    fn init(rom_file: Arc<[u8]>, check_header: bool) -> Result<Self, MemoryInitializationError> {
+        let mem = Memory::new(rom_file, check_header)?;
+        let sgb = mem.info.sgb_support.then(SGB::new);
         Ok(Self {
             mem,
             reg: Registers::new(),
             ppu: PPU::new(),
             apu: APU::new(),
             timer: Timer::new(),
+            serial: Serial::default(),
             input: InputReg::new(),
+            sgb,
             istate: InterruptState::new(),
             halt: false,
             frame_counter: 0,
             cycle_counter: 0,
+            breakpoints: BTreeSet::new(),
+            cheats: Vec::new(),
+            trace_hook: None,
         })
     }
 
-    /// Writes ROM to memory: used to rewrite ROM after deserialization
-    pub fn set_rom(&mut self, rom: Vec<u8>) {
+    /// Writes ROM to memory: used to rewrite ROM after deserialization.
+    /// The ROM is shared, so the same ROM can be given to every restored state without copying
+    pub fn set_rom(&mut self, rom: Arc<[u8]>) {
         self.mem.set_rom(rom);
     }
 
-    /// Writes RAM to memory: used to initialize RAM from external save
-    pub fn set_ram(&mut self, ram: Vec<u8>) {
-        self.mem.set_ram(ram);
+    /// Serializes the CPU into given buffer in place of its contents, in the same format
+    /// as serializing it with postcard directly. The buffer's allocation is reused,
+    /// so capturing states repeatedly doesn't allocate once the buffer has grown
+    pub fn save_state_into(&self, buffer: &mut Vec<u8>) -> Result<(), postcard::Error> {
+        let mut writer = std::mem::take(buffer);
+        writer.clear();
+        *buffer = postcard::to_extend(self, writer)?;
+        Ok(())
+    }
+
+    /// Returns a hash of everything kept in save states, equal for CPUs that have run
+    /// the same ROM from the same state with the same inputs. Used for noticing when
+    /// emulators that should run in step have diverged. The state is hashed while it's
+    /// serialized, so nothing is allocated
+    pub fn state_hash(&self) -> Result<u64, postcard::Error> {
+        postcard::serialize_with_flavor(self, StateHasher(StateHasher::OFFSET_BASIS))
+    }
+
+    /// Writes RAM to memory: used to initialize RAM from external save.
+    /// Fails if the save doesn't fit the cartridge RAM.
+    /// An RTC footer sets the cartridge clock to the time it was saved at
+    pub fn set_ram(&mut self, ram: Vec<u8>) -> Result<(), RAMLoadError> {
+        self.mem.set_ram(ram, None)
+    }
+
+    /// Writes RAM to memory like `set_ram`, with an RTC footer also counting the time
+    /// from when it was saved until given Unix time in seconds on the cartridge clock
+    pub fn set_ram_with_rtc(&mut self, ram: Vec<u8>, now: u64) -> Result<(), RAMLoadError> {
+        self.mem.set_ram(ram, Some(now))
     }
 
     /// Returns a copy of RAM buffer, used to save RAM externally
@@ -54,6 +120,44 @@ impl CPU {
         self.mem.get_ram()
     }
 
+    /// Returns a copy of RAM buffer followed by the 48-byte RTC footer used by other
+    /// emulators, saved at given Unix time in seconds. Without a cartridge clock
+    /// it's the same as `get_ram`
+    pub fn get_ram_with_rtc(&self, now: u64) -> Vec<u8> {
+        self.mem.get_ram_with_rtc(now)
+    }
+
+    /// Counts given amount of real seconds on the cartridge clock, if there is one.
+    /// The clock otherwise counts emulated time, so this keeps it running
+    /// while the emulator isn't
+    pub fn advance_rtc(&mut self, seconds: u64) {
+        self.mem.advance_rtc(seconds);
+    }
+
+    /// Returns if the rumble motor of the cartridge is on. Games pulse the motor
+    /// to vary its strength, so this is polled after running to follow it
+    pub fn rumble_active(&self) -> bool {
+        self.mem.rumble_active()
+    }
+
+    /// Sets the tilt read by the accelerometer of MBC7 cartridges, as offsets
+    /// from the value read when the cartridge lies flat. The values change about 0x70 per 1 g
+    pub fn set_accelerometer(&mut self, x: i16, y: i16) {
+        self.mem.set_accelerometer(x, y);
+    }
+
+    /// Returns if RAM has been written to since the last call,
+    /// used to know when RAM should be saved externally
+    pub fn take_ram_dirty(&mut self) -> bool {
+        self.mem.take_ram_dirty()
+    }
+
+    /// Sets a hook called after each write to cartridge RAM,
+    /// used to keep RAM saved externally without polling
+    pub fn set_ram_write_hook(&mut self, hook: Option<RAMWriteHook>) {
+        self.mem.set_ram_write_hook(hook);
+    }
+
     /// Initializes a ring buffer for audio playback and returns its consumer.
     /// Remember to set sample rate using set_audio_sample_rate
     pub fn init_audio_buffer(
@@ -70,11 +174,136 @@ impl CPU {
         self.apu.set_sample_rate(sample_rate);
     }
 
+    /// Stops or resumes producing audio samples,
+    /// used for discarding audio when emulation isn't played in real time
+    pub fn set_audio_muted(&mut self, muted: bool) {
+        self.apu.muted = muted;
+    }
+
+    /// Sets the volumes of the square 1, square 2, wave and noise channels,
+    /// from 0.0 for muted to 1.0 for unchanged
+    pub fn set_audio_channel_volumes(&mut self, volumes: [f32; 4]) {
+        self.apu.channel_volumes = volumes.map(|volume| volume.clamp(0.0, 1.0));
+    }
+
+    /// Returns the bytes sent through the serial port since the last call,
+    /// used for reading the results of test ROMs
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.serial.output)
+    }
+
+    /// Connects or disconnects a link cable partner. While linked, transfers clocked by this
+    /// Game Boy wait until `finish_serial_transfer` is called with the partner's byte.
+    /// A transfer waiting when the partner is disconnected finishes as if the cable was pulled
+    pub fn set_serial_linked(&mut self, linked: bool) {
+        if self.serial.linked == linked {
+            return;
+        }
+        if self.serial.is_waiting() {
+            self.finish_serial_transfer(0xFF);
+        }
+        self.serial.linked = linked;
+        self.serial.outgoing = None;
+    }
+
+    /// Returns the byte of a transfer this Game Boy has started with its own clock,
+    /// to be sent to the link partner. Only returns bytes while linked
+    pub fn take_serial_outgoing(&mut self) -> Option<u8> {
+        self.serial.outgoing.take()
+    }
+
+    /// Returns if a transfer clocked by this Game Boy waits for the partner's byte
+    pub fn is_serial_waiting(&self) -> bool {
+        self.serial.is_waiting()
+    }
+
+    /// Finishes the waiting transfer with the byte the partner shifted out,
+    /// does nothing if no transfer is waiting
+    pub fn finish_serial_transfer(&mut self, received: u8) {
+        if self.serial.is_waiting() {
+            self.serial.finish_transfer(received);
+            self.take_serial_interrupt();
+        }
+    }
+
+    /// Handles a transfer clocked by the link partner, which shifted out given byte.
+    /// Returns the byte shifted out in exchange, or None if the game hasn't started
+    /// a transfer using the partner's clock, in which case the byte is lost
+    pub fn receive_serial_byte(&mut self, byte: u8) -> Option<u8> {
+        let sent = self.serial.receive(byte)?;
+        self.take_serial_interrupt();
+        Some(sent)
+    }
+
+    /// Returns how full the audio buffer is, between 0.0 and 1.0
+    pub fn get_audio_buffer_fill(&self) -> f32 {
+        self.apu.buffer_fill()
+    }
+
+    /// Returns how many milliseconds of emulation the audio buffer can still take
+    /// before samples are dropped, or None if audio isn't being buffered.
+    /// Used to keep from emulating more than the audio buffer can absorb
+    pub fn get_audio_buffer_room_ms(&self) -> Option<f32> {
+        let t_cycles = self.apu.buffer_room_t_cycles()?;
+        let m_cycles = t_cycles / u64::from(Self::T_CYCLES_PER_M_CYCLE);
+        Some(m_cycles as f32 * Self::MS_PER_M_CYCLE)
+    }
+
     /// Returns the latest fully drawn display buffer for rendering
     pub fn get_display_buffer(&self) -> &DisplayBuffer {
         &self.ppu.display
     }
 
+    /// Returns the colors and border set by a Super Game Boy game,
+    /// or None if the cartridge doesn't support the Super Game Boy
+    pub fn get_sgb(&self) -> Option<&SGB> {
+        self.sgb.as_ref()
+    }
+
+    /// Replaces the active cheats. ROM patches apply to the next read,
+    /// RAM writes at the start of every VBlank
+    pub fn set_cheats(&mut self, cheats: Vec<Cheat>) {
+        self.cheats = cheats;
+    }
+
+    /// Returns given byte read from ROM, replaced if a cheat patches it
+    #[inline]
+    fn patch_rom_read(&self, address: u16, original: u8) -> u8 {
+        if self.cheats.is_empty() {
+            return original;
+        }
+        self.cheats
+            .iter()
+            .find_map(|cheat| match *cheat {
+                Cheat::ROMPatch {
+                    address: patched,
+                    value,
+                    compare,
+                } if patched == address && compare.is_none_or(|compare| compare == original) => {
+                    Some(value)
+                }
+                _ => None,
+            })
+            .unwrap_or(original)
+    }
+
+    /// Writes the bytes of RAM cheats
+    fn apply_ram_cheats(&mut self) {
+        // Taken out for the loop, as writing needs to borrow the CPU
+        let cheats = std::mem::take(&mut self.cheats);
+        for cheat in &cheats {
+            if let Cheat::RAMWrite { address, value } = *cheat {
+                self.write(address, value);
+            }
+        }
+        self.cheats = cheats;
+    }
+
+    /// Returns the sprite and scroll state of the PPU, for debug overlays
+    pub fn get_ppu_debug_info(&self) -> PPUDebugInfo {
+        self.ppu.debug_info()
+    }
+
     /// Updates input state.
     /// For easier interfacing, in this input byte 0 = not pressed and 1 = pressed,
     /// as opposed to how it is actually in the Game Boy's memory
@@ -90,6 +319,8 @@ impl CPU {
     }
 
     const MS_PER_M_CYCLE: f32 = 0.0009536743;
+    const M_CYCLES_PER_FRAME: u32 = 17556;
+    const T_CYCLES_PER_M_CYCLE: u8 = 4;
 
     /// Runs Game Boy for given amount of milliseconds
     pub fn run(&mut self, millis: f32) {
@@ -97,6 +328,37 @@ impl CPU {
         while self.cycle_counter < target_cycles {
             self.run_instruction();
         }
-        self.cycle_counter = 0;
+        self.finish_run();
+    }
+
+    /// Runs Game Boy until the next frame is fully drawn.
+    /// Stops after two frames worth of cycles if the LCD is off and no frames are drawn
+    pub fn run_frame(&mut self) {
+        let frame = self.frame_counter;
+        while self.frame_counter == frame && self.cycle_counter < Self::M_CYCLES_PER_FRAME * 2 {
+            self.run_instruction();
+        }
+        self.finish_run();
+    }
+}
+
+/// Postcard output hashing the serialized bytes with 64-bit FNV-1a
+struct StateHasher(u64);
+
+impl StateHasher {
+    const OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01B3;
+}
+
+impl postcard::ser_flavors::Flavor for StateHasher {
+    type Output = u64;
+
+    fn try_push(&mut self, data: u8) -> postcard::Result<()> {
+        self.0 = (self.0 ^ u64::from(data)).wrapping_mul(Self::PRIME);
+        Ok(())
+    }
+
+    fn finalize(self) -> postcard::Result<u64> {
+        Ok(self.0)
     }
 }
@@ -9,17 +9,42 @@ pub(crate) trait MemoryAccess {
 }
 
 impl CPU {
-    /// Reads from given memory address
-    pub(crate) fn read(&self, address: u16) -> u8 {
+    /// Reads from given memory address, catching the APU up first if its registers are read
+    #[inline]
+    pub(crate) fn read(&mut self, address: u16) -> u8 {
+        if (0xFF10..=0xFF3F).contains(&address) {
+            self.apu.catch_up();
+        }
+        self.read_bus(address)
+    }
+
+    /// Reads from given memory address without emulating anything.
+    /// ROM and work RAM are checked first, as nearly all reads go there
+    #[inline]
+    pub(crate) fn read_bus(&self, address: u16) -> u8 {
         match address {
-            // ROM, external, work and echo RAM, high RAM
-            0x0000..=0x7FFF | 0xA000..=0xFDFF | 0xFF80..=0xFFFE => self.mem.mem_read(address),
+            // ROM
+            0x0000..=0x7FFF => self.patch_rom_read(address, self.mem.read_cartridge(address)),
+            // External RAM
+            0xA000..=0xBFFF => self.mem.read_cartridge(address),
+            // Work RAM
+            0xC000..=0xDFFF => self.mem.wram[(address - 0xC000) as usize],
             // VRAM, OAM, LCD I/O
             0x8000..=0x9FFF | 0xFE00..=0xFE9F | 0xFF40..=0xFF4B => self.ppu.mem_read(address),
+            // Echo RAM, high RAM
+            0xE000..=0xFDFF | 0xFF80..=0xFFFE => self.mem.mem_read(address),
             // Audio I/O registers
             0xFF10..=0xFF3F => self.apu.mem_read(address),
             // Input register
-            0xFF00 => self.input.mem_read(address),
+            0xFF00 => {
+                let value = self.input.mem_read(address);
+                match &self.sgb {
+                    Some(sgb) => sgb.read_joypad(value),
+                    None => value,
+                }
+            }
+            // Serial transfer
+            0xFF01..=0xFF02 => self.serial.mem_read(address),
             // Timer control
             0xFF04..=0xFF07 => self.timer.mem_read(address),
             // Interrupt control (IF and IE)
@@ -29,15 +54,20 @@ impl CPU {
     }
 
     /// Reads 16-bit value from given memory address
-    pub(crate) fn read_16(&self, address: u16) -> u16 {
+    #[inline]
+    pub(crate) fn read_16(&mut self, address: u16) -> u16 {
         u16::from_le_bytes([self.read(address), self.read(address + 1)])
     }
 
-    /// Writes to given memory address
+    /// Writes to given memory address.
+    /// Work RAM is checked first, as nearly all writes go there
+    #[inline]
     pub(crate) fn write(&mut self, address: u16, value: u8) {
         match address {
-            // ROM, external, work and echo RAM, high RAM
-            0x0000..=0x7FFF | 0xA000..=0xFDFF | 0xFF80..=0xFFFE => {
+            // Work RAM
+            0xC000..=0xDFFF => self.mem.wram[(address - 0xC000) as usize] = value,
+            // ROM, external and echo RAM, high RAM
+            0x0000..=0x7FFF | 0xA000..=0xBFFF | 0xE000..=0xFDFF | 0xFF80..=0xFFFE => {
                 self.mem.mem_write(address, value)
             }
             // VRAM, OAM, LCD I/O
@@ -45,11 +75,30 @@ impl CPU {
                 self.ppu.mem_write(address, value)
             }
             // Audio I/O registers
-            0xFF10..=0xFF3F => self.apu.mem_write(address, value),
+            0xFF10..=0xFF3F => {
+                self.apu.catch_up();
+                self.apu.mem_write(address, value)
+            }
             // Input register
-            0xFF00 => self.input.mem_write(address, value),
+            0xFF00 => {
+                self.input.mem_write(address, value);
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write_joypad(value);
+                }
+            }
+            // Serial transfer
+            0xFF01..=0xFF02 => {
+                self.serial.mem_write(address, value);
+                self.take_serial_interrupt();
+            }
             // Timer control
-            0xFF04..=0xFF07 => self.timer.mem_write(address, value),
+            0xFF04..=0xFF07 => {
+                // The APU follows the divider, so it's caught up before DIV is reset
+                if address == 0xFF04 {
+                    self.apu.catch_up();
+                }
+                self.timer.mem_write(address, value)
+            }
             // Interrupt control
             0xFF0F | 0xFFFF => self.istate.mem_write(address, value),
             _ => {}
@@ -0,0 +1,244 @@
+//! C interface for embedding the emulator in other languages, enabled with the `ffi` feature.
+//! The header `include/gb_web_core.h` is generated from this module with cbindgen.
+//!
+//! Ownership follows two rules: an emulator created with `gb_create` is freed with
+//! `gb_destroy`, and an error message returned through `out_err` is freed with
+//! `gb_free_error`. All other buffers are owned by the caller, and functions only
+//! read from or write to them during the call.
+//!
+//! Panics don't cross the boundary. A function that panics returns its failure value
+//! instead, after which the emulator may be in an inconsistent state and should be destroyed
+
+use crate::{CPU, InputFlag, display_color_indices, unix_time_secs};
+use std::{
+    ffi::{CString, c_char},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+    sync::Arc,
+};
+
+/// Width of the display in pixels
+pub const GB_SCREEN_WIDTH: usize = 160;
+/// Height of the display in pixels
+pub const GB_SCREEN_HEIGHT: usize = 144;
+
+/// Emulator instance, only handled through pointers
+pub struct GBEmulator {
+    cpu: CPU,
+    /// Kept for restoring save states, which don't contain the ROM
+    rom: Arc<[u8]>,
+}
+
+/// Runs given function, returning the fallback if it panics
+fn catch_panic<T>(fallback: T, function: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(function)).unwrap_or(fallback)
+}
+
+/// Returns the bytes of a buffer given by the caller, treating null as empty
+///
+/// # Safety
+/// If not null, `data` has to point to `len` readable bytes
+unsafe fn input_slice<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if data.is_null() {
+        &[]
+    } else {
+        unsafe { slice::from_raw_parts(data, len) }
+    }
+}
+
+/// Copies bytes to a buffer given by the caller if they fit, returning their length
+///
+/// # Safety
+/// If not null, `out` has to point to `capacity` writable bytes
+unsafe fn copy_out(bytes: &[u8], out: *mut u8, capacity: usize) -> usize {
+    if !out.is_null() && bytes.len() <= capacity {
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), out, bytes.len()) };
+    }
+    bytes.len()
+}
+
+/// Creates an emulator running given ROM, which is copied.
+/// Returns null on failure, setting `out_err` to a message if it isn't null.
+/// The emulator is freed with `gb_destroy`
+///
+/// # Safety
+/// `rom` has to point to `len` readable bytes, `out_err` has to be null or writable
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_create(
+    rom: *const u8,
+    len: usize,
+    out_err: *mut *mut c_char,
+) -> *mut GBEmulator {
+    let result = catch_panic(Err("Panicked while loading ROM".to_string()), || {
+        let rom: Arc<[u8]> = unsafe { input_slice(rom, len) }.into();
+        CPU::new(Arc::clone(&rom))
+            .map(|cpu| GBEmulator { cpu, rom })
+            .map_err(|e| format!("Couldn't load ROM: {e:?}"))
+    });
+    match result {
+        Ok(emulator) => Box::into_raw(Box::new(emulator)),
+        Err(message) => {
+            if !out_err.is_null() {
+                let message = CString::new(message).unwrap_or_default();
+                unsafe { *out_err = message.into_raw() };
+            }
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Frees an emulator created with `gb_create`, does nothing if it's null
+///
+/// # Safety
+/// `emulator` has to be null or returned by `gb_create` and not freed already
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_destroy(emulator: *mut GBEmulator) {
+    if !emulator.is_null() {
+        catch_panic((), || drop(unsafe { Box::from_raw(emulator) }));
+    }
+}
+
+/// Frees an error message returned through `out_err`, does nothing if it's null
+///
+/// # Safety
+/// `message` has to be null or returned through `out_err` and not freed already
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_free_error(message: *mut c_char) {
+    if !message.is_null() {
+        drop(unsafe { CString::from_raw(message) });
+    }
+}
+
+/// Runs the emulator until the next frame is drawn. Returns false if it panicked
+///
+/// # Safety
+/// `emulator` has to be a live emulator returned by `gb_create`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_run_frame(emulator: *mut GBEmulator) -> bool {
+    let emulator = unsafe { &mut *emulator };
+    catch_panic(false, || {
+        emulator.cpu.run_frame();
+        true
+    })
+}
+
+/// Writes the latest frame to `out` as a palette index from 0 to 3 for each pixel,
+/// row by row. `out` has to hold `GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT` bytes
+///
+/// # Safety
+/// `emulator` has to be a live emulator and `out` has to point to enough writable bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_get_framebuffer(emulator: *const GBEmulator, out: *mut u8) {
+    let emulator = unsafe { &*emulator };
+    let out = unsafe { slice::from_raw_parts_mut(out, GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT) };
+    catch_panic((), || {
+        for (pixel, index) in out
+            .iter_mut()
+            .zip(display_color_indices(emulator.cpu.get_display_buffer()))
+        {
+            *pixel = index;
+        }
+    });
+}
+
+/// Sets the pressed buttons, a set bit meaning pressed: start 0x80, select 0x40,
+/// B 0x20, A 0x10, down 0x08, up 0x04, left 0x02 and right 0x01
+///
+/// # Safety
+/// `emulator` has to be a live emulator returned by `gb_create`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_set_input(emulator: *mut GBEmulator, input: u8) {
+    let emulator = unsafe { &mut *emulator };
+    catch_panic((), || {
+        emulator
+            .cpu
+            .update_input(&InputFlag::from_bits_retain(input))
+    });
+}
+
+/// Copies cartridge RAM to `out` if it fits in `capacity` bytes, and returns its size.
+/// For cartridges with a clock, RAM is followed by the 48-byte RTC footer used by other
+/// emulators. Calling with a null `out` only returns the size
+///
+/// # Safety
+/// `emulator` has to be a live emulator, `out` has to be null or point to `capacity`
+/// writable bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_get_ram(
+    emulator: *const GBEmulator,
+    out: *mut u8,
+    capacity: usize,
+) -> usize {
+    let emulator = unsafe { &*emulator };
+    catch_panic(0, || {
+        let ram = emulator.cpu.get_ram_with_rtc(unix_time_secs());
+        unsafe { copy_out(&ram, out, capacity) }
+    })
+}
+
+/// Replaces cartridge RAM with a save, which is copied. An RTC footer sets the cartridge
+/// clock, counting the time since it was saved. Returns false if the save doesn't fit
+/// the cartridge RAM
+///
+/// # Safety
+/// `emulator` has to be a live emulator and `data` has to point to `len` readable bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_set_ram(
+    emulator: *mut GBEmulator,
+    data: *const u8,
+    len: usize,
+) -> bool {
+    let emulator = unsafe { &mut *emulator };
+    let data = unsafe { input_slice(data, len) };
+    catch_panic(false, || {
+        emulator
+            .cpu
+            .set_ram_with_rtc(data.to_vec(), unix_time_secs())
+            .is_ok()
+    })
+}
+
+/// Serializes the emulator state to `out` if it fits in `capacity` bytes, and returns its size.
+/// Calling with a null `out` only returns the size. Returns 0 if serializing fails
+///
+/// # Safety
+/// `emulator` has to be a live emulator, `out` has to be null or point to `capacity`
+/// writable bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_save_state(
+    emulator: *const GBEmulator,
+    out: *mut u8,
+    capacity: usize,
+) -> usize {
+    let emulator = unsafe { &*emulator };
+    catch_panic(0, || {
+        let mut state = Vec::new();
+        match emulator.cpu.save_state_into(&mut state) {
+            Ok(()) => unsafe { copy_out(&state, out, capacity) },
+            Err(_) => 0,
+        }
+    })
+}
+
+/// Replaces the emulator state with one saved by `gb_save_state` with the same ROM.
+/// Returns false if the state is invalid, leaving the emulator unchanged
+///
+/// # Safety
+/// `emulator` has to be a live emulator and `data` has to point to `len` readable bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_load_state(
+    emulator: *mut GBEmulator,
+    data: *const u8,
+    len: usize,
+) -> bool {
+    let emulator = unsafe { &mut *emulator };
+    let data = unsafe { input_slice(data, len) };
+    catch_panic(false, || match postcard::from_bytes::<CPU>(data) {
+        Ok(mut cpu) => {
+            cpu.set_rom(Arc::clone(&emulator.rom));
+            emulator.cpu = cpu;
+            true
+        }
+        Err(_) => false,
+    })
+}
@@ -1,24 +1,48 @@
 use bitflags::bitflags;
 use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
+use std::collections::BTreeSet;
+use std::sync::Arc;
 
 mod apu;
+mod cheat;
 mod cpu;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 mod input;
+mod mbc7;
 mod memory;
 mod ppu;
 mod registers;
+mod rtc;
+mod serial;
+mod sgb;
 mod timer;
 use apu::*;
 use cpu::*;
 use input::*;
+use mbc7::*;
 use memory::*;
 use ppu::*;
 use registers::*;
+use rtc::*;
+use serial::*;
 use timer::*;
 
 pub use apu::AudioBufferConsumer;
-pub use cpu::CPU;
+pub use cheat::{Cheat, CheatKind, CheatParseError};
+pub use cpu::{CPU, DisassembledInstruction, RegisterSnapshot, TraceHook};
 pub use input::InputFlag;
-pub use memory::{CartridgeInfo, MemoryInitializationError, MemoryInitializationErrorType};
-pub use ppu::{DISPLAY_BUFFER_SIZE, DisplayBuffer};
+pub use memory::{
+    CGBSupport, CartridgeInfo, MemoryInitializationError, MemoryInitializationErrorType,
+    RAMLoadError, RAMWriteHook,
+};
+pub use ppu::{
+    DISPLAY_BUFFER_SIZE, DebugSprite, DisplayBuffer, PPUDebugInfo, display_color_indices,
+};
+#[cfg(not(target_family = "wasm"))]
+pub use rtc::unix_time_secs;
+pub use sgb::{SGB, SGBAttributes, SGBBorder, SGBMask};
+
+/// Version of the core crate
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
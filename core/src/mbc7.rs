@@ -0,0 +1,238 @@
+use super::*;
+use std::ops::Range;
+
+/// Two-axis accelerometer of MBC7, read by latching both axes through $Ax0x and $Ax1x
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct Accelerometer {
+    /// Offsets from the resting value set by the host
+    input: (i16, i16),
+    /// Values read by the game, erased to 0x8000 before latching
+    latched: (u16, u16),
+}
+
+impl Default for Accelerometer {
+    fn default() -> Self {
+        Self {
+            input: (0, 0),
+            latched: (Self::ERASED, Self::ERASED),
+        }
+    }
+}
+
+impl Accelerometer {
+    /// Value both axes read when the cartridge lies flat,
+    /// the values change about 0x70 per 1 g
+    pub const CENTER: u16 = 0x81D0;
+    const ERASED: u16 = 0x8000;
+
+    /// Sets the offsets from the resting value, read when the game latches them next
+    pub fn set_input(&mut self, x: i16, y: i16) {
+        self.input = (x, y);
+    }
+
+    /// Reads register selected by bits 4-7 of the address
+    pub fn read(&self, register: u8) -> u8 {
+        let (x, y) = self.latched;
+        match register {
+            0x2 => x as u8,
+            0x3 => (x >> 8) as u8,
+            0x4 => y as u8,
+            0x5 => (y >> 8) as u8,
+            0x6 => 0x00,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes register selected by bits 4-7 of the address.
+    /// Writing 0x55 to register 0 erases the values, and writing 0xAA to register 1
+    /// latches new ones, but only after they're erased
+    pub fn write(&mut self, register: u8, value: u8) {
+        match (register, value) {
+            (0x0, 0x55) => self.latched = (Self::ERASED, Self::ERASED),
+            (0x1, 0xAA) if self.latched == (Self::ERASED, Self::ERASED) => {
+                let (x, y) = self.input;
+                self.latched = (
+                    Self::CENTER.wrapping_add_signed(x),
+                    Self::CENTER.wrapping_add_signed(y),
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Step of the command the EEPROM is shifting bits for
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+enum EepromState {
+    /// Waiting for the start bit of a command
+    #[default]
+    Idle,
+    /// Shifting in the opcode and address
+    Command,
+    /// Shifting out words from the address onwards
+    Read { address: u8 },
+    /// Shifting in the word to write to the address, or to every word without one
+    Write { address: Option<u8> },
+}
+
+/// Words of the EEPROM changed by a command
+pub struct EepromWrite {
+    pub words: Range<u8>,
+    pub value: u16,
+}
+
+/// 93LC56 serial EEPROM of MBC7 with 128 16-bit words, driven one bit at a time
+/// through the pins mapped to $Ax8x. The words are kept in cartridge RAM
+/// in little-endian order, which is also how other emulators save them
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default)]
+pub struct Eeprom {
+    state: EepromState,
+    chip_select: bool,
+    clock: bool,
+    data_in: bool,
+    data_out: bool,
+    /// Bits shifted in, or left to shift out while reading
+    shift: u16,
+    bit_count: u8,
+    /// Set by the EWEN command, writes and erases are ignored without it
+    write_enabled: bool,
+}
+
+impl Eeprom {
+    pub const SIZE: usize = 0x100;
+    const WORDS: u8 = 0x80;
+
+    /// Reads the pins, with the data output in bit 0
+    pub fn read(&self) -> u8 {
+        u8::from(self.chip_select) << 7
+            | u8::from(self.clock) << 6
+            | u8::from(self.data_in) << 1
+            | u8::from(self.data_out)
+    }
+
+    /// Sets the chip select, clock and data input pins from bits 7, 6 and 1,
+    /// shifting a bit on the rising edge of the clock. Returns the words to change
+    /// once a write or erase command is complete, as the contents are only read here
+    pub fn write(&mut self, value: u8, contents: &[u8]) -> Option<EepromWrite> {
+        let rising_edge = !self.clock && value & 0x40 != 0;
+        self.chip_select = value & 0x80 != 0;
+        self.clock = value & 0x40 != 0;
+        self.data_in = value & 0x02 != 0;
+        if !self.chip_select {
+            // Deselecting ends the command, and writes finish instantly
+            self.state = EepromState::Idle;
+            self.data_out = true;
+            return None;
+        }
+        if !rising_edge {
+            return None;
+        }
+        match self.state {
+            EepromState::Idle => {
+                if self.data_in {
+                    self.state = EepromState::Command;
+                    self.shift = 0;
+                    self.bit_count = 0;
+                }
+                None
+            }
+            EepromState::Command => {
+                if self.shift_in() < 10 {
+                    return None;
+                }
+                self.run_command(contents)
+            }
+            EepromState::Read { address } => {
+                self.data_out = self.shift & 0x8000 != 0;
+                self.shift <<= 1;
+                self.bit_count += 1;
+                // Reading continues from the next word
+                if self.bit_count == 16 {
+                    let address = (address + 1) % Self::WORDS;
+                    self.state = EepromState::Read { address };
+                    self.shift = Self::word(contents, address);
+                    self.bit_count = 0;
+                }
+                None
+            }
+            EepromState::Write { address } => {
+                if self.shift_in() < 16 {
+                    return None;
+                }
+                self.state = EepromState::Idle;
+                self.data_out = true;
+                let words = match address {
+                    Some(address) => address..address + 1,
+                    None => 0..Self::WORDS,
+                };
+                self.write_enabled.then_some(EepromWrite {
+                    words,
+                    value: self.shift,
+                })
+            }
+        }
+    }
+
+    /// Shifts in the data input, returns the amount of bits shifted in
+    fn shift_in(&mut self) -> u8 {
+        self.shift = self.shift << 1 | u16::from(self.data_in);
+        self.bit_count += 1;
+        self.bit_count
+    }
+
+    /// Runs the command from 2 opcode bits and 8 address bits, of which the highest is unused
+    fn run_command(&mut self, contents: &[u8]) -> Option<EepromWrite> {
+        let address = (self.shift & 0x7F) as u8;
+        self.state = EepromState::Idle;
+        self.bit_count = 0;
+        match self.shift >> 8 & 0b11 {
+            // READ, starting with a dummy 0 bit
+            0b10 => {
+                self.state = EepromState::Read { address };
+                self.shift = Self::word(contents, address);
+                self.data_out = false;
+                None
+            }
+            // WRITE
+            0b01 => {
+                self.state = EepromState::Write {
+                    address: Some(address),
+                };
+                None
+            }
+            // ERASE
+            0b11 => self.erase(address..address + 1),
+            _ => match self.shift >> 6 & 0b11 {
+                // EWEN
+                0b11 => {
+                    self.write_enabled = true;
+                    None
+                }
+                // EWDS
+                0b00 => {
+                    self.write_enabled = false;
+                    None
+                }
+                // ERAL
+                0b10 => self.erase(0..Self::WORDS),
+                // WRAL
+                _ => {
+                    self.state = EepromState::Write { address: None };
+                    None
+                }
+            },
+        }
+    }
+
+    fn erase(&self, words: Range<u8>) -> Option<EepromWrite> {
+        self.write_enabled.then_some(EepromWrite {
+            words,
+            value: 0xFFFF,
+        })
+    }
+
+    fn word(contents: &[u8], address: u8) -> u16 {
+        let index = usize::from(address) * 2;
+        u16::from_le_bytes([contents[index], contents[index + 1]])
+    }
+}
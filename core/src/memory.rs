@@ -14,24 +14,70 @@ pub enum MBCType {
     HuC1,
 }
 
+/// Game Boy Color support declared in the cartridge header
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum CGBSupport {
+    None,
+    /// Works on both the original Game Boy and Game Boy Color
+    Compatible,
+    /// Works only on Game Boy Color
+    Only,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct CartridgeInfo {
     /// Type of memory bank controller
     pub mbc: MBCType,
-    /// If cartridge provides external RAM
+    /// If cartridge provides external RAM, or has RAM built into the MBC like MBC2
     pub has_ram: bool,
     /// If cartridge has battery, meaning it can store external RAM in itself
     /// (a.k.a. saving is possible)
     pub has_battery: bool,
+    /// If cartridge has a real time clock
+    pub has_rtc: bool,
+    /// If cartridge has a rumble motor
+    pub has_rumble: bool,
+    /// If cartridge has an accelerometer, which only MBC7 cartridges have
+    pub has_accelerometer: bool,
+    /// If cartridge is an MBC1 multicart, with several games and a menu for choosing one
+    pub multicart: bool,
     /// Amount of 16 KiB ROM banks cartridge provides
     pub rom_banks: u16,
-    /// Amount of 8 KiB RAM banks cartridge provides
+    /// Amount of 8 KiB RAM banks cartridge provides,
+    /// 0 for the 512 half-bytes of RAM built into MBC2 and the 256-byte EEPROM of MBC7
     pub ram_banks: u16,
     /// Title of the cartridge
     pub title: String,
+    pub cgb_support: CGBSupport,
+    /// If the cartridge uses Super Game Boy functions
+    pub sgb_support: bool,
+    /// If the header checksum matches the header,
+    /// a mismatch usually means the ROM is corrupted
+    pub header_checksum_valid: bool,
+    /// If the header has the logo checked by the boot ROM,
+    /// which every Game Boy ROM needs to boot on hardware
+    pub logo_valid: bool,
+    /// If the global checksum matches the ROM. Hardware doesn't check it,
+    /// so it's often wrong in homebrew and patched ROMs
+    pub global_checksum_valid: bool,
 }
 
 impl CartridgeInfo {
+    /// Logo every licensed game has in its header, checked by the boot ROM
+    pub const NINTENDO_LOGO: [u8; 48] = [
+        0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00,
+        0x0D, 0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD,
+        0xD9, 0x99, 0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB,
+        0xB9, 0x33, 0x3E,
+    ];
+
+    /// Returns if an MBC1 ROM is a multicart. The headers give no sign of it,
+    /// but the games on 1 MiB multicarts start every 256 KiB, and the second one
+    /// has a header with the logo like the menu at the start
+    pub fn detect_multicart(rom: &[u8]) -> bool {
+        rom.len() == 0x100000 && rom.get(0x40104..0x40134) == Some(Self::NINTENDO_LOGO.as_slice())
+    }
+
     /// Returns info about cartridge features from the ROM header
     pub fn from_header(header: &[u8]) -> Self {
         let mbc = match header[0x47] {
@@ -46,16 +92,21 @@ impl CartridgeInfo {
             0xFF => MBCType::HuC1,
             _ => MBCType::NoMBC,
         };
-        let has_ram = matches!(
-            header[0x47],
-            0x02 | 0x03 | 0x0C | 0x0D | 0x10 | 0x12 | 0x13 | 0x1A | 0x1B | 0x1D | 0x1E | 0x22
-        );
+        // RAM built into MBC2 is always there, and the only HuC1 cartridge type has RAM
+        let has_ram = matches!(mbc, MBCType::MBC2 | MBCType::HuC1)
+            || matches!(
+                header[0x47],
+                0x02 | 0x03 | 0x0C | 0x0D | 0x10 | 0x12 | 0x13 | 0x1A | 0x1B | 0x1D | 0x1E | 0x22
+            );
         let has_battery = matches!(
             header[0x47],
-            0x03 | 0x06 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22
+            0x03 | 0x06 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
         );
+        let has_rtc = matches!(header[0x47], 0x0F | 0x10);
+        let has_rumble = matches!(header[0x47], 0x1C..=0x1E);
+        let has_accelerometer = matches!(mbc, MBCType::MBC7);
         let rom_banks = 2u16.saturating_pow(1 + (header[0x48] as u32));
-        let ram_banks = if !has_ram {
+        let ram_banks = if !has_ram || matches!(mbc, MBCType::MBC2 | MBCType::MBC7) {
             0
         } else {
             match header[0x49] {
@@ -69,21 +120,63 @@ impl CartridgeInfo {
         let title = std::str::from_utf8(&header[0x34..=0x42])
             .unwrap_or_default()
             .to_string();
+        let cgb_support = match header[0x43] {
+            0xC0 => CGBSupport::Only,
+            0x80 => CGBSupport::Compatible,
+            _ => CGBSupport::None,
+        };
+        // Super Game Boy functions are only enabled with the new licensee code
+        let sgb_support = header[0x46] == 0x03 && header[0x4B] == 0x33;
+        let checksum = header[0x34..=0x4C]
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+        let logo_valid = header[0x04..0x34] == Self::NINTENDO_LOGO;
         Self {
             mbc,
             has_ram,
             has_battery,
+            has_rtc,
+            has_rumble,
+            has_accelerometer,
+            // Needs the rest of the ROM to detect
+            multicart: false,
             rom_banks,
             ram_banks,
             title,
+            cgb_support,
+            sgb_support,
+            header_checksum_valid: checksum == header[0x4D],
+            logo_valid,
+            // Needs the rest of the ROM to compute
+            global_checksum_valid: false,
         }
     }
+
+    /// Returns if the global checksum in the header at given offset matches the ROM,
+    /// it's the sum of every byte of the ROM apart from the checksum itself
+    pub fn verify_global_checksum(rom: &[u8], header_start: usize) -> bool {
+        let checksum_start = header_start + 0x014E;
+        let Some(expected) = rom.get(checksum_start..checksum_start + 2) else {
+            return false;
+        };
+        let sum = rom
+            .iter()
+            .fold(0u16, |sum, byte| sum.wrapping_add(u16::from(*byte)));
+        let sum = sum
+            .wrapping_sub(u16::from(expected[0]))
+            .wrapping_sub(u16::from(expected[1]));
+        sum == u16::from_be_bytes([expected[0], expected[1]])
+    }
 }
 
 #[derive(Debug)]
 pub enum MemoryInitializationErrorType {
     NoHeader,
     UnimplementedMBC(MBCType),
+    /// The header checksum doesn't match the header
+    BadHeaderChecksum,
+    /// The header doesn't have the logo checked by the boot ROM
+    BadLogo,
 }
 
 #[derive(Debug)]
@@ -91,6 +184,12 @@ pub struct MemoryInitializationError {
     error_type: MemoryInitializationErrorType,
 }
 
+impl MemoryInitializationError {
+    pub fn error_type(&self) -> &MemoryInitializationErrorType {
+        &self.error_type
+    }
+}
+
 impl std::fmt::Display for MemoryInitializationError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.error_type {
@@ -100,10 +199,45 @@ impl std::fmt::Display for MemoryInitializationError {
             MemoryInitializationErrorType::UnimplementedMBC(mbc) => {
                 write!(f, "MBC type {:?} isn't yet implemented. Sorry!", mbc)
             }
+            MemoryInitializationErrorType::BadHeaderChecksum => {
+                write!(
+                    f,
+                    "ROM header checksum doesn't match, the file might be corrupted"
+                )
+            }
+            MemoryInitializationErrorType::BadLogo => {
+                write!(
+                    f,
+                    "ROM header doesn't have the Nintendo logo, it might not be a Game Boy ROM"
+                )
+            }
         }
     }
 }
 
+#[derive(Debug)]
+pub enum RAMLoadError {
+    /// Cartridge doesn't provide external RAM
+    NoRAM,
+    /// Size of the given RAM doesn't match the RAM size of the cartridge
+    SizeMismatch { expected: usize, actual: usize },
+}
+
+impl std::fmt::Display for RAMLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RAMLoadError::NoRAM => write!(f, "Cartridge doesn't have RAM"),
+            RAMLoadError::SizeMismatch { expected, actual } => write!(
+                f,
+                "Save data is {actual} bytes, but cartridge RAM is {expected} bytes"
+            ),
+        }
+    }
+}
+
+/// Called with the offset into cartridge RAM and the value after each write to it
+pub type RAMWriteHook = Box<dyn FnMut(usize, u8) + Send>;
+
 #[derive(Deserialize, Serialize)]
 pub struct Memory {
     #[serde(with = "BigArray")]
@@ -115,16 +249,47 @@ pub struct Memory {
 }
 
 impl Memory {
-    pub fn new(rom: Vec<u8>) -> Result<Self, MemoryInitializationError> {
-        if rom.len() < 0x014F {
+    /// Initializes memory with the cartridge of given ROM. With `check_header`,
+    /// ROMs without the logo or with a header checksum mismatch are rejected
+    pub fn new(rom: Arc<[u8]>, check_header: bool) -> Result<Self, MemoryInitializationError> {
+        if rom.len() < 0x0150 {
             return Err(MemoryInitializationError {
                 error_type: MemoryInitializationErrorType::NoHeader,
             });
         }
-        let info = CartridgeInfo::from_header(&rom[0x0100..=0x014F]);
+        // MMM01 cartridges boot from their last 32 KiB, where the header of the menu is,
+        // while the start of the ROM has the header of the first game
+        let header_start = rom
+            .len()
+            .checked_sub(0x8000)
+            .filter(|menu_start| matches!(rom.get(menu_start + 0x0147), Some(0x0B..=0x0D)))
+            .unwrap_or(0);
+        let mut info =
+            CartridgeInfo::from_header(&rom[header_start + 0x0100..=header_start + 0x014F]);
+        info.global_checksum_valid = CartridgeInfo::verify_global_checksum(&rom, header_start);
+        if check_header {
+            let error_type = if !info.logo_valid {
+                Some(MemoryInitializationErrorType::BadLogo)
+            } else if !info.header_checksum_valid {
+                Some(MemoryInitializationErrorType::BadHeaderChecksum)
+            } else {
+                None
+            };
+            if let Some(error_type) = error_type {
+                return Err(MemoryInitializationError { error_type });
+            }
+        }
+        info.multicart = matches!(info.mbc, MBCType::MBC1) && CartridgeInfo::detect_multicart(&rom);
         if !matches!(
             info.mbc,
-            MBCType::NoMBC | MBCType::MBC1 | MBCType::MBC3 | MBCType::MBC5
+            MBCType::NoMBC
+                | MBCType::MBC1
+                | MBCType::MBC2
+                | MBCType::MBC3
+                | MBCType::MBC5
+                | MBCType::MMM01
+                | MBCType::MBC7
+                | MBCType::HuC1
         ) {
             return Err(MemoryInitializationError {
                 error_type: MemoryInitializationErrorType::UnimplementedMBC(info.mbc),
@@ -141,19 +306,98 @@ impl Memory {
     }
 
     /// Overwrites ROM of simulated cartridge
-    pub fn set_rom(&mut self, rom: Vec<u8>) {
+    pub fn set_rom(&mut self, rom: Arc<[u8]>) {
         self.mbc.rom = rom;
     }
 
-    /// Overwrites RAM of simulated cartridge
-    pub fn set_ram(&mut self, ram: Vec<u8>) {
+    /// Overwrites RAM of simulated cartridge.
+    /// The RAM has to match the size of the cartridge RAM, apart from a possible RTC footer
+    /// which sets the clock if the cartridge has one. With the current Unix time in seconds,
+    /// the clock also counts the time since the footer was saved
+    pub fn set_ram(&mut self, mut ram: Vec<u8>, now: Option<u64>) -> Result<(), RAMLoadError> {
+        let expected = self.mbc.ram.len();
+        if expected == 0 && self.mbc.rtc.is_none() {
+            return Err(RAMLoadError::NoRAM);
+        }
+        let actual = ram.len();
+        if actual != expected {
+            let footer_size = actual.checked_sub(expected);
+            if footer_size.is_none_or(|size| {
+                size != RealTimeClock::FOOTER_SIZE && size != RealTimeClock::SHORT_FOOTER_SIZE
+            }) {
+                return Err(RAMLoadError::SizeMismatch { expected, actual });
+            }
+            let footer = ram.split_off(expected);
+            match (&mut self.mbc.rtc, RealTimeClock::from_footer(&footer)) {
+                (Some(rtc), Some((mut saved, saved_at))) => {
+                    if let Some(now) = now {
+                        saved.advance(now.saturating_sub(saved_at));
+                    }
+                    *rtc = saved;
+                }
+                // The cartridge has no clock for the data
+                _ => log::info!("Ignoring RTC footer of {} bytes", footer.len()),
+            }
+        }
         self.mbc.ram = ram;
+        Ok(())
     }
 
     /// Returns copy of RAM buffer in simulated cartridge
     pub fn get_ram(&self) -> Vec<u8> {
         self.mbc.ram.clone()
     }
+
+    /// Returns copy of RAM buffer followed by the RTC footer other emulators use,
+    /// saved at given Unix time in seconds. Cartridges without a clock have no footer
+    pub fn get_ram_with_rtc(&self, now: u64) -> Vec<u8> {
+        let mut ram = self.get_ram();
+        if let Some(rtc) = &self.mbc.rtc {
+            ram.extend_from_slice(&rtc.to_footer(now));
+        }
+        ram
+    }
+
+    /// Counts given amount of M-cycles on the cartridge clock, if there is one
+    #[inline]
+    pub fn tick_rtc(&mut self, cycles: u32) {
+        if let Some(rtc) = &mut self.mbc.rtc {
+            rtc.tick(cycles);
+        }
+    }
+
+    /// Counts given amount of real seconds on the cartridge clock, if there is one
+    pub fn advance_rtc(&mut self, seconds: u64) {
+        if let Some(rtc) = &mut self.mbc.rtc {
+            rtc.advance(seconds);
+        }
+    }
+
+    /// Returns if the game has turned the rumble motor of the cartridge on
+    pub fn rumble_active(&self) -> bool {
+        self.mbc.rumble_active
+    }
+
+    /// Sets the tilt read by the accelerometer of MBC7 cartridges
+    pub fn set_accelerometer(&mut self, x: i16, y: i16) {
+        self.mbc.accelerometer.set_input(x, y);
+    }
+
+    /// Returns if RAM has been written to since the last call
+    pub fn take_ram_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.mbc.ram_dirty)
+    }
+
+    pub fn set_ram_write_hook(&mut self, hook: Option<RAMWriteHook>) {
+        self.mbc.ram_write_hook = hook;
+    }
+
+    /// Reads from ROM or external RAM through the memory bank controller,
+    /// used directly by the CPU on the hot path
+    #[inline]
+    pub fn read_cartridge(&self, address: u16) -> u8 {
+        self.mbc.read(address)
+    }
 }
 
 impl MemoryAccess for Memory {
@@ -177,14 +421,39 @@ impl MemoryAccess for Memory {
     }
 }
 
+/// Registers of MMM01, which maps one of the games on the cartridge
+/// to look like a cartridge of its own once the menu locks it
+#[derive(Deserialize, Serialize, Default)]
+struct MMM01Registers {
+    /// Set when the menu has chosen a game, after which only the bits of the registers
+    /// the game controls can be written
+    locked: bool,
+    /// Bits 0-4 of the ROM bank number
+    rom_bank_low: u8,
+    /// Bits 5-6 of the ROM bank number
+    rom_bank_mid: u8,
+    /// Bits 7-8 of the ROM bank number
+    rom_bank_high: u8,
+    /// Bits 1-4 of the ROM bank number that are kept from the menu when locked,
+    /// the rest are controlled by the game
+    rom_bank_mask: u8,
+    /// Bits 0-1 of the RAM bank number
+    ram_bank_low: u8,
+    /// Bits 2-3 of the RAM bank number
+    ram_bank_high: u8,
+    /// Bits of the lower RAM bank bits that are kept from the menu when locked
+    ram_bank_mask: u8,
+}
+
 /// Simulates behavior of MBC cartridges
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Deserialize, Serialize)]
 struct MBC {
     // ROM is loaded manually using load_rom function
-    // This is so ROM isnt also saved in the save state for no reason
+    // This is so ROM isnt also saved in the save state for no reason.
+    // ROM is only read, so it's shared with the frontend instead of copied
     #[serde(skip_serializing, skip_deserializing)]
-    pub rom: Vec<u8>,
+    pub rom: Arc<[u8]>,
     pub ram: Vec<u8>,
     rom_bank: usize,
     ram_bank: usize,
@@ -192,29 +461,74 @@ struct MBC {
     info: CartridgeInfo,
     /// Used only by MBC1
     advanced_banking: bool,
+    /// Real-time clock of MBC3 cartridges that have one
+    rtc: Option<RealTimeClock>,
+    /// Clock register mapped to $A000-$BFFF in place of RAM
+    rtc_register: Option<u8>,
+    /// Used only by MBC5 cartridges with a rumble motor
+    rumble_active: bool,
+    /// Used only by HuC1, maps the infrared port to $A000-$BFFF in place of RAM
+    ir_mode: bool,
+    /// Used only by MMM01
+    mmm01: MMM01Registers,
+    /// Used only by MBC7, second enable for the registers at $A000-$AFFF
+    ram_enabled_2: bool,
+    /// Used only by MBC7, with its contents kept in RAM
+    eeprom: Eeprom,
+    /// Used only by MBC7
+    accelerometer: Accelerometer,
+    /// Set when RAM is written to, cleared when polled with take_ram_dirty
+    #[serde(skip)]
+    ram_dirty: bool,
+    #[serde(skip)]
+    ram_write_hook: Option<RAMWriteHook>,
 }
 
 impl MBC {
-    pub fn init(rom: Vec<u8>, info: CartridgeInfo) -> Self {
+    /// Half-bytes of RAM built into MBC2
+    const MBC2_RAM_SIZE: usize = 0x200;
+
+    pub fn init(rom: Arc<[u8]>, info: CartridgeInfo) -> Self {
+        let ram = match info.mbc {
+            MBCType::MBC2 => vec![0; Self::MBC2_RAM_SIZE],
+            // A blank EEPROM reads all ones
+            MBCType::MBC7 => vec![0xFF; Eeprom::SIZE],
+            _ => vec![0; usize::from(0x2000 * info.ram_banks)],
+        };
         Self {
             rom,
-            ram: vec![0; usize::from(0x2000 * info.ram_banks)],
+            ram,
             rom_bank: 1,
             ram_bank: 0,
             ram_enabled: false,
             advanced_banking: false,
+            rtc: info.has_rtc.then(RealTimeClock::default),
+            rtc_register: None,
+            rumble_active: false,
+            ir_mode: false,
+            mmm01: MMM01Registers::default(),
+            ram_enabled_2: false,
+            eeprom: Eeprom::default(),
+            accelerometer: Accelerometer::default(),
+            ram_dirty: false,
+            ram_write_hook: None,
             info,
         }
     }
 
     /// Returns value from memory at address
     /// Should handle addresses between $0000-$7FFF and $A000-$BFFF
+    #[inline]
     pub fn read(&self, address: u16) -> u8 {
         match self.info.mbc {
             MBCType::NoMBC => self.read_nombc(address),
             MBCType::MBC1 => self.read_mbc1(address),
+            MBCType::MBC2 => self.read_mbc2(address),
             MBCType::MBC3 => self.read_mbc3(address),
             MBCType::MBC5 => self.read_mbc5(address),
+            MBCType::MMM01 => self.read_mmm01(address),
+            MBCType::MBC7 => self.read_mbc7(address),
+            MBCType::HuC1 => self.read_huc1(address),
             _ => todo!("MBC type {:?} not supported", self.info.mbc),
         }
     }
@@ -224,8 +538,12 @@ impl MBC {
         match self.info.mbc {
             MBCType::NoMBC => self.write_nombc(address, value),
             MBCType::MBC1 => self.write_mbc1(address, value),
+            MBCType::MBC2 => self.write_mbc2(address, value),
             MBCType::MBC3 => self.write_mbc3(address, value),
             MBCType::MBC5 => self.write_mbc5(address, value),
+            MBCType::MMM01 => self.write_mmm01(address, value),
+            MBCType::MBC7 => self.write_mbc7(address, value),
+            MBCType::HuC1 => self.write_huc1(address, value),
             _ => todo!("MBC type {:?} not supported", self.info.mbc),
         }
     }
@@ -264,6 +582,10 @@ impl MBC {
             return;
         }
         self.ram[address] = value;
+        self.ram_dirty = true;
+        if let Some(hook) = &mut self.ram_write_hook {
+            hook(address, value);
+        }
     }
 
     /// Used to mask bank number register value to wrap around
@@ -299,24 +621,27 @@ impl MBC {
         let mut address = address as usize;
         match address {
             0x0000..=0x7FFF => {
+                let second_range = address >= 0x4000;
                 // The ROM bank register is only applied
                 // to the second ROM address range ($4000-$7FFF)
-                if address >= 0x4000 {
+                if second_range {
                     address -= 0x4000;
                     address += self.rom_bank * 0x4000;
                 }
                 // If cartridge has >512 KiB ROM, the 2-bit register that is also used to select RAM banks
                 // can be used to select one of four large banks of 512 KiB memory
+                // (256 KiB on multicarts, where each game is in one of them)
                 // It is also applied to the first address range if using advanced banking mode
-                if self.info.rom_banks > 32 && (address >= 0x4000 || self.advanced_banking) {
+                let bank_span = self.mbc1_bank_span();
+                if self.info.rom_banks > bank_span && (second_range || self.advanced_banking) {
                     // Mask out upper bit of high address if not enough banks
                     let high_address = self.ram_bank
-                        & if self.info.rom_banks <= 64 {
+                        & if self.info.rom_banks <= bank_span * 2 {
                             0b01
                         } else {
                             0b11
                         };
-                    address += 0x20 * high_address * 0x4000
+                    address += usize::from(bank_span) * high_address * 0x4000
                 }
                 self.read_rom(address)
             }
@@ -345,7 +670,8 @@ impl MBC {
             0x2000..=0x3FFF => {
                 // Only needed amount of bits to change between all ROM banks
                 // are saved to the register, rest are masked out
-                let mut masked = self.mask_bank_number(value, self.info.rom_banks.clamp(0, 32));
+                let mut masked = self
+                    .mask_bank_number(value, self.info.rom_banks.clamp(0, self.mbc1_bank_span()));
                 // If register is tried to set to 0, it should be incremented to 1
                 // The check is only done for the 5-bit version for the value though,
                 // so for example if only 3 bits are used,
@@ -377,6 +703,61 @@ impl MBC {
         };
     }
 
+    /// Amount of ROM banks the lower MBC1 ROM bank register selects between.
+    /// Multicarts leave the top bit of the register unconnected,
+    /// so the 2-bit register is applied from the bit below it instead
+    fn mbc1_bank_span(&self) -> u16 {
+        if self.info.multicart { 16 } else { 32 }
+    }
+
+    fn read_mbc2(&self, address: u16) -> u8 {
+        let mut address = address as usize;
+        match address {
+            0x0000..=0x3FFF => self.read_rom(address),
+            0x4000..=0x7FFF => {
+                address += 0x4000 * (self.rom_bank - 1);
+                self.read_rom(address)
+            }
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    return 0xFF;
+                }
+                // The 512 half-bytes repeat across the whole range,
+                // with the missing upper bits reading as 1s
+                self.read_ram(address & (Self::MBC2_RAM_SIZE - 1)) | 0xF0
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write_mbc2(&mut self, address: u16, value: u8) {
+        match address {
+            // Bit 8 of the address selects the register
+            0x0000..=0x3FFF => {
+                if address & 0x0100 == 0 {
+                    // RAM enabled
+                    self.ram_enabled = (value & 0x0F) == 0x0A;
+                } else {
+                    // ROM bank number
+                    let mut masked = self.mask_bank_number(value & 0x0F, self.info.rom_banks);
+                    if masked == 0 {
+                        masked = 1
+                    };
+                    self.rom_bank = masked;
+                }
+            }
+            // Write to RAM, of which only the lower 4 bits exist
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    return;
+                }
+                let address = address as usize & (Self::MBC2_RAM_SIZE - 1);
+                self.write_ram(address, value & 0x0F);
+            }
+            _ => {}
+        };
+    }
+
     fn read_mbc3(&self, address: u16) -> u8 {
         let mut address = address as usize;
         match address {
@@ -389,6 +770,9 @@ impl MBC {
                 if !self.ram_enabled {
                     return 0xFF;
                 }
+                if let (Some(rtc), Some(register)) = (&self.rtc, self.rtc_register) {
+                    return rtc.read(register);
+                }
                 address -= 0xA000;
                 address += self.ram_bank * 0x2000;
                 self.read_ram(address)
@@ -409,17 +793,32 @@ impl MBC {
                 };
                 self.rom_bank = masked;
             }
-            // RAM bank number
+            // RAM bank number, or clock register
             0x4000..=0x5FFF => {
-                if self.info.ram_banks != 0 {
+                self.rtc_register = None;
+                if self.rtc.is_some() && matches!(value, 0x08..=0x0C) {
+                    self.rtc_register = Some(value);
+                } else if self.info.ram_banks != 0 {
                     self.ram_bank = self.mask_bank_number(value, self.info.ram_banks);
                 }
             }
-            // Write to RAM
+            // Latch clock data
+            0x6000..=0x7FFF => {
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write_latch(value);
+                }
+            }
+            // Write to RAM or clock register
             0xA000..=0xBFFF => {
                 if !self.ram_enabled {
                     return;
                 }
+                if let (Some(rtc), Some(register)) = (&mut self.rtc, self.rtc_register) {
+                    rtc.write(register, value);
+                    // A clock set by the game is saved with RAM
+                    self.ram_dirty = true;
+                    return;
+                }
                 let mut address = address as usize;
                 address -= 0xA000;
                 address += self.ram_bank * 0x2000;
@@ -460,8 +859,14 @@ impl MBC {
             }
             // 9th bit of ROM bank number
             0x3000..=0x3FFF => self.rom_bank |= ((value & 1) as usize) << 8,
-            // RAM bank number
+            // RAM bank number, with bit 3 driving the motor on rumble cartridges
             0x4000..=0x5FFF => {
+                let value = if self.info.has_rumble {
+                    self.rumble_active = value & 0x08 != 0;
+                    value & !0x08
+                } else {
+                    value
+                };
                 if self.info.ram_banks != 0 {
                     self.ram_bank = self.mask_bank_number(value, self.info.ram_banks);
                 }
@@ -479,4 +884,204 @@ impl MBC {
             _ => {}
         };
     }
+
+    /// Returns the ROM bank MMM01 maps to $0000-$3FFF or to $4000-$7FFF
+    fn mmm01_rom_bank(&self, second_range: bool) -> usize {
+        let regs = &self.mmm01;
+        let bank = if regs.locked {
+            // The bits the game controls are cleared for its first bank
+            // and work like the ROM bank register of MBC1 for the switchable one
+            let game_bits = 0x1F & !(regs.rom_bank_mask << 1);
+            let base = usize::from(regs.rom_bank_high) << 7
+                | usize::from(regs.rom_bank_mid) << 5
+                | usize::from(regs.rom_bank_low & !game_bits);
+            if second_range {
+                base | usize::from((regs.rom_bank_low & game_bits).max(1))
+            } else {
+                base
+            }
+        } else {
+            // Before locking, the last 32 KiB with the menu is mapped
+            0x1FE | usize::from(second_range)
+        };
+        bank & (usize::from(self.info.rom_banks) - 1)
+    }
+
+    fn read_mmm01(&self, address: u16) -> u8 {
+        let mut address = address as usize;
+        match address {
+            0x0000..=0x3FFF => self.read_rom(self.mmm01_rom_bank(false) * 0x4000 + address),
+            0x4000..=0x7FFF => {
+                address -= 0x4000;
+                self.read_rom(self.mmm01_rom_bank(true) * 0x4000 + address)
+            }
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    return 0xFF;
+                }
+                address -= 0xA000;
+                address += self.ram_bank * 0x2000;
+                self.read_ram(address)
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write_mmm01(&mut self, address: u16, value: u8) {
+        let regs = &mut self.mmm01;
+        let locked = regs.locked;
+        match address {
+            // RAM enabled, and before locking the RAM bank mask and the lock
+            0x0000..=0x1FFF => {
+                self.ram_enabled = (value & 0x0F) == 0x0A;
+                if !locked {
+                    regs.ram_bank_mask = (value >> 4) & 0b11;
+                    regs.locked = value & 0x40 != 0;
+                }
+            }
+            // ROM bank number, with bits 5-6 only written before locking
+            0x2000..=0x3FFF => {
+                let writable = if locked {
+                    0x1F & !(regs.rom_bank_mask << 1)
+                } else {
+                    regs.rom_bank_mid = (value >> 5) & 0b11;
+                    0x1F
+                };
+                regs.rom_bank_low = (regs.rom_bank_low & !writable) | (value & writable);
+            }
+            // RAM bank number, with the upper RAM and ROM bank bits only written before locking
+            0x4000..=0x5FFF => {
+                let writable = if locked {
+                    0b11 & !regs.ram_bank_mask
+                } else {
+                    regs.ram_bank_high = (value >> 2) & 0b11;
+                    regs.rom_bank_high = (value >> 4) & 0b11;
+                    0b11
+                };
+                regs.ram_bank_low = (regs.ram_bank_low & !writable) | (value & writable);
+                let bank = regs.ram_bank_high << 2 | regs.ram_bank_low;
+                if self.info.ram_banks != 0 {
+                    self.ram_bank = self.mask_bank_number(bank, self.info.ram_banks);
+                }
+            }
+            // ROM bank mask, only written before locking
+            0x6000..=0x7FFF if !locked => regs.rom_bank_mask = (value >> 2) & 0x0F,
+            // Write to RAM
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    return;
+                }
+                let mut address = address as usize;
+                address -= 0xA000;
+                address += self.ram_bank * 0x2000;
+                self.write_ram(address, value);
+            }
+            _ => {}
+        };
+    }
+
+    fn read_mbc7(&self, address: u16) -> u8 {
+        let mut address = address as usize;
+        match address {
+            0x0000..=0x3FFF => self.read_rom(address),
+            0x4000..=0x7FFF => {
+                address = address.saturating_add_signed(0x4000 * ((self.rom_bank as isize) - 1));
+                self.read_rom(address)
+            }
+            // Registers selected by bits 4-7, mapped only when both enables are set
+            0xA000..=0xAFFF if self.ram_enabled && self.ram_enabled_2 => {
+                match (address >> 4) & 0x0F {
+                    register @ 0x0..=0x7 => self.accelerometer.read(register as u8),
+                    0x8 => self.eeprom.read(),
+                    _ => 0xFF,
+                }
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write_mbc7(&mut self, address: u16, value: u8) {
+        match address {
+            // RAM enabled
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+            // ROM bank number
+            0x2000..=0x3FFF => self.rom_bank = self.mask_bank_number(value, self.info.rom_banks),
+            // Second RAM enable
+            0x4000..=0x5FFF => self.ram_enabled_2 = value == 0x40,
+            0xA000..=0xAFFF if self.ram_enabled && self.ram_enabled_2 => {
+                match (address >> 4) & 0x0F {
+                    register @ 0x0..=0x1 => self.accelerometer.write(register as u8, value),
+                    0x8 => {
+                        if let Some(write) = self.eeprom.write(value, &self.ram) {
+                            for word in write.words {
+                                let address = usize::from(word) * 2;
+                                let [low, high] = write.value.to_le_bytes();
+                                self.write_ram(address, low);
+                                self.write_ram(address + 1, high);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        };
+    }
+
+    fn read_huc1(&self, address: u16) -> u8 {
+        let mut address = address as usize;
+        match address {
+            0x0000..=0x3FFF => self.read_rom(address),
+            0x4000..=0x7FFF => {
+                address += 0x4000 * (self.rom_bank - 1);
+                self.read_rom(address)
+            }
+            0xA000..=0xBFFF => {
+                // The infrared receiver isn't emulated, so it never sees light
+                if self.ir_mode {
+                    return 0xC0;
+                }
+                if !self.ram_enabled {
+                    return 0xFF;
+                }
+                address -= 0xA000;
+                address += self.ram_bank * 0x2000;
+                self.read_ram(address)
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write_huc1(&mut self, address: u16, value: u8) {
+        match address {
+            // RAM enabled, or infrared port mapped in place of it
+            0x0000..=0x1FFF => {
+                self.ram_enabled = (value & 0x0F) == 0x0A;
+                self.ir_mode = (value & 0x0F) == 0x0E;
+            }
+            // ROM bank number
+            0x2000..=0x3FFF => {
+                let mut masked = self.mask_bank_number(value & 0x3F, self.info.rom_banks);
+                if masked == 0 {
+                    masked = 1
+                };
+                self.rom_bank = masked;
+            }
+            // RAM bank number
+            0x4000..=0x5FFF if self.info.ram_banks != 0 => {
+                self.ram_bank = self.mask_bank_number(value & 0x03, self.info.ram_banks);
+            }
+            // Write to RAM, writes to the infrared LED are ignored
+            0xA000..=0xBFFF => {
+                if self.ir_mode || !self.ram_enabled {
+                    return;
+                }
+                let mut address = address as usize;
+                address -= 0xA000;
+                address += self.ram_bank * 0x2000;
+                self.write_ram(address, value);
+            }
+            _ => {}
+        };
+    }
 }
@@ -129,6 +129,14 @@ pub const DISPLAY_BUFFER_SIZE: usize = (2 * 160 * 144) / 32;
 /// pixels.
 pub type DisplayBuffer = [u32; DISPLAY_BUFFER_SIZE];
 
+/// Returns the palette index of each pixel of the display, row by row
+pub fn display_color_indices(display: &DisplayBuffer) -> impl Iterator<Item = u8> + '_ {
+    (0..160 * 144).map(|i| {
+        let bit_i = 2 * i;
+        ((display[bit_i / 32] >> (bit_i % 32)) & 0b11) as u8
+    })
+}
+
 fn empty_display() -> DoubleBuffer<DisplayBuffer> {
     DoubleBuffer::new([0; DISPLAY_BUFFER_SIZE], [0; DISPLAY_BUFFER_SIZE])
 }
@@ -165,6 +173,28 @@ pub enum PPUState {
     Starting,
 }
 
+/// Sprite as placed on the display, for debugging
+#[derive(Debug, Clone, Copy)]
+pub struct DebugSprite {
+    /// Top left corner on the display, can be partially offscreen
+    pub x: i16,
+    pub y: i16,
+    pub height: u8,
+    /// If the sprite is skipped on any of its scanlines due to the limit of 10 sprites per line
+    pub dropped: bool,
+}
+
+/// Sprite and scroll state of the PPU, for debugging
+#[derive(Debug, Clone, Default)]
+pub struct PPUDebugInfo {
+    /// Sprites on the visible scanlines in OAM order, empty if sprites are disabled
+    pub sprites: Vec<DebugSprite>,
+    /// Background scroll position, the top left corner of the display in the background map
+    pub scroll: (u8, u8),
+    /// Top left corner of the window on the display, if it's enabled
+    pub window: Option<(i16, i16)>,
+}
+
 /// The graphics processing unit
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Deserialize, Serialize)]
@@ -238,16 +268,32 @@ impl PPU {
         }
     }
 
-    pub fn cycle(&mut self) {
-        use {PPUMode::*, PPUState::*};
+    /// Cycles the PPU forward by given amount of dots (T-cycles).
+    /// Interrupts requested during any of them are collected into `interrupt_request`
+    pub fn tick(&mut self, dots: u8) {
+        use PPUMode::*;
         self.interrupt_request = InterruptFlag::from_bits_truncate(0);
 
-        if self.state == Disabled {
+        if self.state == PPUState::Disabled {
             return;
         }
 
-        if self.lx < 455 {
-            self.lx += 1;
+        let mut remaining = dots as u16;
+        while remaining > 0 {
+            if self.lx == 455 {
+                self.end_line();
+                remaining -= 1;
+                continue;
+            }
+            // Advance straight to the next dot where something happens
+            let next_event = match self.lx {
+                0..80 if self.mode != VBlank => 80,
+                80..252 if self.mode != VBlank => 80 + 172,
+                _ => 455,
+            };
+            let advance = (next_event - self.lx).min(remaining);
+            self.lx += advance;
+            remaining -= advance;
             if self.mode != VBlank {
                 if self.lx == 80 {
                     self.update_mode(Drawing);
@@ -255,45 +301,49 @@ impl PPU {
                     self.update_mode(HBlank);
                 }
             }
-        } else {
-            self.lx = 0;
-
-            match self.ly {
-                0..=143 => {
-                    // Draw new line
-                    self.update_mode(OAMScan);
-                    self.draw_scanline(self.ly);
-                    self.ly += 1;
-                }
-                144 => {
-                    // Reset line counter
-                    self.win_line = 0;
-                    // Send VBlank interrupt
-                    self.interrupt_request.insert(InterruptFlag::VBLANK);
-                    self.update_mode(VBlank);
-                    // Swap double buffer for rendering new frame
-                    self.display.swap();
-                    self.ly += 1;
-                }
-                153 => {
-                    // Start drawing new frame
-                    self.ly = 0;
-                    self.update_mode(OAMScan);
-                    // End drawing delay after PPU was enabled again
-                    if self.state == Starting {
-                        self.state = Active;
-                    }
-                }
-                _ => {
-                    self.ly += 1;
+        }
+    }
+
+    /// Moves on to the next scanline after its last dot
+    fn end_line(&mut self) {
+        use {PPUMode::*, PPUState::*};
+        self.lx = 0;
+
+        match self.ly {
+            0..=143 => {
+                // Draw new line
+                self.update_mode(OAMScan);
+                self.draw_scanline(self.ly);
+                self.ly += 1;
+            }
+            144 => {
+                // Reset line counter
+                self.win_line = 0;
+                // Send VBlank interrupt
+                self.interrupt_request.insert(InterruptFlag::VBLANK);
+                self.update_mode(VBlank);
+                // Swap double buffer for rendering new frame
+                self.display.swap();
+                self.ly += 1;
+            }
+            153 => {
+                // Start drawing new frame
+                self.ly = 0;
+                self.update_mode(OAMScan);
+                // End drawing delay after PPU was enabled again
+                if self.state == Starting {
+                    self.state = Active;
                 }
             }
-
-            // Check for LYC=LY interrupt if its enabled
-            if self.stat_enable.intersects(STATEnable::LYC) && self.lyc == self.ly {
-                self.interrupt_request.insert(InterruptFlag::LCD);
+            _ => {
+                self.ly += 1;
             }
         }
+
+        // Check for LYC=LY interrupt if its enabled
+        if self.stat_enable.intersects(STATEnable::LYC) && self.lyc == self.ly {
+            self.interrupt_request.insert(InterruptFlag::LCD);
+        }
     }
 
     fn update_mode(&mut self, mode: PPUMode) {
@@ -338,8 +388,8 @@ impl PPU {
         self.vram[usize::from(tile_map_root + tile_map_index)]
     }
 
-    /// Returns the color ID of given tile at specified coordinates
-    fn get_tile_color(&self, x: u8, y: u8, tile_index: u8, addressing_mode: bool) -> u8 {
+    /// Returns the two bytes making up given row of a tile's color data
+    fn get_tile_row(&self, y: u8, tile_index: u8, addressing_mode: bool) -> (u8, u8) {
         // Get memory position of tile inside VRAM (one tile is 16 bytes)
         // and add target row to it to get address of the two bytes
         // that make up a tile row of color data
@@ -352,8 +402,14 @@ impl PPU {
         }
 
         // Get the color bytes from VRAM
-        let a_byte = self.vram[usize::from(byte_index)];
-        let b_byte = self.vram[usize::from(byte_index + 1)];
+        (
+            self.vram[usize::from(byte_index)],
+            self.vram[usize::from(byte_index + 1)],
+        )
+    }
+
+    /// Returns the color ID of given column in a tile row
+    fn get_row_color((a_byte, b_byte): (u8, u8), x: u8) -> u8 {
         // Get the bit values of correct tile column
         let a = a_byte & (0b1000_0000 >> (x % 8)) != 0;
         let b = b_byte & (0b1000_0000 >> (x % 8)) != 0;
@@ -362,44 +418,154 @@ impl PPU {
         (a as u8) | ((b as u8) << 1)
     }
 
-    /// Returns list of sprites that occupy given scanline
-    fn get_sprites(&self, y: u8, sprite_height: u8) -> Vec<OAMSprite> {
+    /// Returns the color ID of given tile at specified coordinates
+    fn get_tile_color(&self, x: u8, y: u8, tile_index: u8, addressing_mode: bool) -> u8 {
+        Self::get_row_color(self.get_tile_row(y, tile_index, addressing_mode), x)
+    }
+
+    /// Fills given pixels with the color IDs of a tile map starting from given coordinates.
+    /// Each tile row is read once for all of its pixels, only the first tile
+    /// is entered partway when the coordinates are scrolled within it
+    fn get_tile_map_colors(
+        &self,
+        pixels: &mut [u8],
+        x: u8,
+        y: u8,
+        tile_map: bool,
+        addressing_mode: bool,
+    ) {
+        let mut map_x = x;
+        let mut pixels = pixels.iter_mut().peekable();
+        while pixels.peek().is_some() {
+            let tile = self.get_tile_index(map_x, y, tile_map);
+            let row = self.get_tile_row(y, tile, addressing_mode);
+            for (col, pixel) in (map_x % 8..8).zip(pixels.by_ref()) {
+                *pixel = Self::get_row_color(row, col);
+            }
+            // Coordinates of background tiles may wrap around
+            map_x = (map_x & !7).wrapping_add(8);
+        }
+    }
+
+    /// Returns the sprites that occupy given scanline and their amount,
+    /// kept inline as the list is built for every scanline
+    fn get_sprites(&self, y: u8, sprite_height: u8) -> ([OAMSprite; 10], usize) {
         // Convert screen Y to object space,
         // where y = 0 completely hides the object
         let obj_y = y + 16;
 
-        let mut sprites: Vec<OAMSprite> = vec![];
+        let mut sprites = [OAMSprite::from([0; 4]); 10];
+        let mut count = 0;
         for sprite in &self.oam.sprites {
             if obj_y < sprite.y.saturating_add(sprite_height) && obj_y >= sprite.y {
-                sprites.push(*sprite);
+                // Sort sprites by their x coordinate while inserting,
+                // giving render priority to the sprite with the smallest x.
+                // Sprites with the same x keep their OAM order
+                let mut i = count;
+                while i > 0 && sprites[i - 1].x > sprite.x {
+                    sprites[i] = sprites[i - 1];
+                    i -= 1;
+                }
+                sprites[i] = *sprite;
+                count += 1;
                 // There's a limit of 10 objects per scanline
-                if sprites.len() == 10 {
+                if count == sprites.len() {
                     break;
                 }
             }
         }
-        // Sort sprites by their x coordinate,
-        // giving render priority to the sprite with the smallest x
-        sprites.sort_by_key(|sprite| sprite.x);
-        sprites
+        (sprites, count)
     }
 
-    fn draw_scanline(&mut self, y: u8) {
-        // Get object height based on current LCD control
+    /// Returns the placement of sprites and layers that isn't visible on the display
+    pub fn debug_info(&self) -> PPUDebugInfo {
         let sprite_height = if self.lcdc.intersects(LCDControl::OBJ_SIZE) {
             16
         } else {
             8
         };
+        let mut sprites = vec![];
+        if self.lcdc.intersects(LCDControl::OBJ_ENABLE) {
+            // Go through the scanlines like get_sprites, tracking which sprites hit the limit
+            let mut visible = [false; 40];
+            let mut dropped = [false; 40];
+            for y in 0..144u8 {
+                let obj_y = y + 16;
+                let mut count = 0;
+                for (i, sprite) in self.oam.sprites.iter().enumerate() {
+                    if obj_y < sprite.y.saturating_add(sprite_height) && obj_y >= sprite.y {
+                        if count < 10 {
+                            visible[i] = true;
+                            count += 1;
+                        } else {
+                            dropped[i] = true;
+                        }
+                    }
+                }
+            }
+            for (i, sprite) in self.oam.sprites.iter().enumerate() {
+                if visible[i] || dropped[i] {
+                    sprites.push(DebugSprite {
+                        x: sprite.x as i16 - 8,
+                        y: sprite.y as i16 - 16,
+                        height: sprite_height,
+                        dropped: dropped[i],
+                    });
+                }
+            }
+        }
+        let window_shown = self
+            .lcdc
+            .contains(LCDControl::WINDOW_ENABLE | LCDControl::BG_WINDOW_ENABLE)
+            && self.win_x < 160
+            && self.win_y < 144;
+        PPUDebugInfo {
+            sprites,
+            scroll: (self.bg_x, self.bg_y),
+            window: window_shown.then_some((self.win_x as i16, self.win_y as i16)),
+        }
+    }
 
-        let sprites = self.get_sprites(y, sprite_height);
+    fn draw_scanline(&mut self, y: u8) {
+        // LCD control doesn't change during the scanline, so read the flags once
+        let tall_sprites = self.lcdc.intersects(LCDControl::OBJ_SIZE);
+        let sprites_enabled = self.lcdc.intersects(LCDControl::OBJ_ENABLE);
+        let bg_window_enabled = self.lcdc.intersects(LCDControl::BG_WINDOW_ENABLE);
+        let window_enabled = self.lcdc.intersects(LCDControl::WINDOW_ENABLE);
+        let window_tile_map = self.lcdc.intersects(LCDControl::WINDOW_TILE_MAP);
+        let bg_tile_map = self.lcdc.intersects(LCDControl::BG_TILE_MAP);
+        let signed_tile_data = !self.lcdc.intersects(LCDControl::TILE_DATA_AREA);
+        // Get object height based on current LCD control
+        let sprite_height = if tall_sprites { 16 } else { 8 };
+
+        let (sprites, sprite_count) = self.get_sprites(y, sprite_height);
+        let sprites = &sprites[..sprite_count];
+        // Fetch the background and window color IDs of the whole scanline a tile at a time.
+        // The window covers the background from its left edge onwards
+        let mut bg_window_colors = [0u8; 160];
+        if bg_window_enabled {
+            let window_start = if window_enabled && y >= self.win_y {
+                usize::from(self.win_x).min(160)
+            } else {
+                160
+            };
+            let (background, window) = bg_window_colors.split_at_mut(window_start);
+            self.get_tile_map_colors(
+                background,
+                self.bg_x,
+                y.wrapping_add(self.bg_y),
+                bg_tile_map,
+                signed_tile_data,
+            );
+            self.get_tile_map_colors(window, 0, self.win_line, window_tile_map, signed_tile_data);
+        }
         for x in 0..=159u8 {
             let mut drawn_sprite: Option<&OAMSprite> = None;
             let mut sprite_col = 0u8;
-            if self.lcdc.intersects(LCDControl::OBJ_ENABLE) {
+            if sprites_enabled {
                 // Convert screen X to object space
                 let obj_x = x + 8;
-                for sprite in &sprites {
+                for sprite in sprites {
                     if obj_x < sprite.x.saturating_add(8) && obj_x >= sprite.x {
                         // Calculate X coordinate inside sprite
                         let mut tile_x = (x as i16) - ((sprite.x as i16) - 8);
@@ -416,7 +582,7 @@ impl PPU {
                         }
 
                         let mut tile_index = sprite.tile_index;
-                        if self.lcdc.intersects(LCDControl::OBJ_SIZE) {
+                        if tall_sprites {
                             // 8x16 objects ignore last bit of tile index
                             tile_index &= 0b1111_1110;
                             // Read bottom pixels of 8x16 object from the tile at next index
@@ -456,7 +622,7 @@ impl PPU {
             }
 
             // If background and window are disabled, render object or just blank
-            if !self.lcdc.intersects(LCDControl::BG_WINDOW_ENABLE) {
+            if !bg_window_enabled {
                 if sprite_on_background {
                     self.set_pixel(x, y, sprite_col);
                 } else {
@@ -466,37 +632,7 @@ impl PPU {
                 continue;
             }
 
-            // Get window pixel instead of background if
-            // window is enabled and pixel is inside window bounds
-            let col_id = if self.lcdc.intersects(LCDControl::WINDOW_ENABLE)
-                && x >= self.win_x
-                && y >= self.win_y
-            {
-                let tile = self.get_tile_index(
-                    x - self.win_x,
-                    self.win_line,
-                    self.lcdc.intersects(LCDControl::WINDOW_TILE_MAP),
-                );
-                self.get_tile_color(
-                    x - self.win_x,
-                    self.win_line,
-                    tile,
-                    !self.lcdc.intersects(LCDControl::TILE_DATA_AREA),
-                )
-            } else {
-                // Coordinates of background tiles may wrap around
-                let tile = self.get_tile_index(
-                    x.wrapping_add(self.bg_x),
-                    y.wrapping_add(self.bg_y),
-                    self.lcdc.intersects(LCDControl::BG_TILE_MAP),
-                );
-                self.get_tile_color(
-                    x.wrapping_add(self.bg_x),
-                    y.wrapping_add(self.bg_y),
-                    tile,
-                    !self.lcdc.intersects(LCDControl::TILE_DATA_AREA),
-                )
-            };
+            let col_id = bg_window_colors[x as usize];
             // If pixel color ID is 0, render sprite instead
             if col_id == 0 && sprite_on_background {
                 self.set_pixel(x, y, sprite_col);
@@ -508,7 +644,7 @@ impl PPU {
             }
         }
         // Increment line counter if window was displayed on this scanline
-        if self.lcdc.intersects(LCDControl::WINDOW_ENABLE) && self.win_x < 160 && self.win_y <= y {
+        if window_enabled && self.win_x < 160 && self.win_y <= y {
             self.win_line += 1;
         }
     }
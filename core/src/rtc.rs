@@ -0,0 +1,211 @@
+use super::*;
+
+/// Counters of the MBC3 real-time clock, as selected by writing 0x08-0x0C to 0x4000-0x5FFF
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+struct ClockRegisters {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    /// 9-bit day counter
+    days: u16,
+    /// Stops the clock while set
+    halt: bool,
+    /// Set when the day counter overflows, stays set until the game clears it
+    day_carry: bool,
+}
+
+impl ClockRegisters {
+    const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+    fn read(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.days as u8,
+            0x0C => {
+                (self.days >> 8) as u8 | u8::from(self.halt) << 6 | u8::from(self.day_carry) << 7
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => self.seconds = value & 0x3F,
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | u16::from(value),
+            0x0C => {
+                self.days = (self.days & 0xFF) | u16::from(value & 0x01) << 8;
+                self.halt = value & 0x40 != 0;
+                self.day_carry = value & 0x80 != 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// Counts a second. Counters set past their range count up to the limit of their bits
+    /// and wrap to 0 without carrying into the next counter, like on hardware
+    fn tick_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        self.days += 1;
+        if self.days == 512 {
+            self.days = 0;
+            self.day_carry = true;
+        }
+    }
+
+    fn in_range(&self) -> bool {
+        self.seconds < 60 && self.minutes < 60 && self.hours < 24
+    }
+
+    /// Counts given amount of seconds, unless the clock is halted
+    fn advance(&mut self, mut seconds: u64) {
+        if self.halt {
+            return;
+        }
+        // Counters past their range are ticked back into it, after which the time can be added
+        while seconds > 0 && !self.in_range() {
+            self.tick_second();
+            seconds -= 1;
+        }
+        let total = u64::from(self.days) * Self::SECONDS_PER_DAY
+            + u64::from(self.hours) * 3600
+            + u64::from(self.minutes) * 60
+            + u64::from(self.seconds)
+            + seconds;
+        let days = total / Self::SECONDS_PER_DAY;
+        if days >= 512 {
+            self.day_carry = true;
+        }
+        let time = total % Self::SECONDS_PER_DAY;
+        self.days = (days % 512) as u16;
+        self.hours = (time / 3600) as u8;
+        self.minutes = (time / 60 % 60) as u8;
+        self.seconds = (time % 60) as u8;
+    }
+}
+
+/// Returns the wall clock time in whole seconds since the Unix epoch,
+/// which RTC footers of saves are timestamped with.
+/// The system clock isn't available to wasm, so web frontends read the date from JS instead
+#[cfg(not(target_family = "wasm"))]
+pub fn unix_time_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Real-time clock of MBC3 cartridges, counting in emulated time
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct RealTimeClock {
+    clock: ClockRegisters,
+    /// Copy of the counters the game reads, updated when the clock is latched
+    latched: ClockRegisters,
+    /// M-cycles counted towards the next second
+    cycles: u32,
+    /// If 0 was last written to the latch register, so writing 1 latches the clock
+    latch_armed: bool,
+}
+
+impl RealTimeClock {
+    /// M-cycles in a second
+    const CYCLES_PER_SECOND: u32 = 1 << 20;
+    /// Size of the footer other emulators append to save files after cartridge RAM.
+    /// Older ones write a 32-bit timestamp, making the footer 4 bytes shorter
+    pub const FOOTER_SIZE: usize = 48;
+    pub const SHORT_FOOTER_SIZE: usize = 44;
+
+    /// Reads given register from the latched counters
+    pub fn read(&self, register: u8) -> u8 {
+        self.latched.read(register)
+    }
+
+    /// Writes given register of the running clock. The latched counters are written too,
+    /// so the game reads back what it wrote without latching again
+    pub fn write(&mut self, register: u8, value: u8) {
+        // Writing the seconds resets the count towards the next second
+        if register == 0x08 {
+            self.cycles = 0;
+        }
+        self.clock.write(register, value);
+        self.latched.write(register, value);
+    }
+
+    /// Handles a write to 0x6000-0x7FFF, where writing 0 and then 1 latches the clock
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.latched = self.clock;
+        }
+        self.latch_armed = value == 0x00;
+    }
+
+    /// Counts given amount of emulated M-cycles
+    #[inline]
+    pub fn tick(&mut self, cycles: u32) {
+        if self.clock.halt {
+            return;
+        }
+        self.cycles += cycles;
+        while self.cycles >= Self::CYCLES_PER_SECOND {
+            self.cycles -= Self::CYCLES_PER_SECOND;
+            self.clock.tick_second();
+        }
+    }
+
+    /// Counts given amount of real seconds, like ones passed while the emulator wasn't running
+    pub fn advance(&mut self, seconds: u64) {
+        self.clock.advance(seconds);
+    }
+
+    /// Returns the footer other emulators append to save files, with the counters
+    /// and latched counters as 32-bit values followed by given Unix timestamp in seconds
+    pub fn to_footer(&self, timestamp: u64) -> [u8; Self::FOOTER_SIZE] {
+        let mut footer = [0; Self::FOOTER_SIZE];
+        let registers = [&self.clock, &self.latched]
+            .into_iter()
+            .flat_map(|clock| (0x08..=0x0C).map(|register| clock.read(register)));
+        for (chunk, value) in footer.chunks_exact_mut(4).zip(registers) {
+            chunk.copy_from_slice(&u32::from(value).to_le_bytes());
+        }
+        footer[40..].copy_from_slice(&timestamp.to_le_bytes());
+        footer
+    }
+
+    /// Reads a footer of either size, returning the clock and the timestamp it was saved at
+    pub fn from_footer(footer: &[u8]) -> Option<(Self, u64)> {
+        let timestamp = match footer.len() {
+            Self::FOOTER_SIZE => u64::from_le_bytes(footer[40..48].try_into().ok()?),
+            Self::SHORT_FOOTER_SIZE => {
+                u64::from(u32::from_le_bytes(footer[40..44].try_into().ok()?))
+            }
+            _ => return None,
+        };
+        let mut rtc = Self::default();
+        let values = footer[..40].chunks_exact(4).map(|chunk| chunk[0]);
+        for (i, value) in values.enumerate() {
+            let clock = if i < 5 {
+                &mut rtc.clock
+            } else {
+                &mut rtc.latched
+            };
+            clock.write(0x08 + (i % 5) as u8, value);
+        }
+        Some((rtc, timestamp))
+    }
+}
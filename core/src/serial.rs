@@ -0,0 +1,84 @@
+use super::*;
+
+/// Serial port of the Game Boy. Without a link partner, transfers started with the internal
+/// clock finish right away, shifting in 0xFF like an unconnected cable. When linked, they wait
+/// for the partner's byte, which the frontend passes in through the CPU's serial link methods.
+/// The sent bytes are kept so test ROMs that print through the serial port can be read
+#[derive(Default)]
+pub struct Serial {
+    /// Serial transfer data (SB)
+    pub data: u8,
+    /// Serial transfer control (SC)
+    pub control: u8,
+    pub request_interrupt: bool,
+    /// Bytes sent since the output was last taken
+    pub output: Vec<u8>,
+    /// Set while the frontend has a link partner connected
+    pub linked: bool,
+    /// Byte of a transfer clocked by this Game Boy, waiting to be sent to the partner
+    pub outgoing: Option<u8>,
+}
+
+impl Serial {
+    /// Bytes kept when nothing takes the output, so games using the link cable don't grow it
+    const MAX_OUTPUT_LEN: usize = 4096;
+    const TRANSFER_ENABLE: u8 = 0b1000_0000;
+    const INTERNAL_CLOCK: u8 = 0b0000_0001;
+
+    /// Ends the transfer in progress with the byte shifted in
+    pub fn finish_transfer(&mut self, received: u8) {
+        self.data = received;
+        self.control &= !Self::TRANSFER_ENABLE;
+        self.request_interrupt = true;
+    }
+
+    /// Returns if a transfer clocked by this Game Boy waits for the partner's byte
+    pub fn is_waiting(&self) -> bool {
+        let clocked = Self::TRANSFER_ENABLE | Self::INTERNAL_CLOCK;
+        self.linked && self.control & clocked == clocked
+    }
+
+    /// Exchanges bytes in a transfer clocked by the partner, returning the byte shifted out.
+    /// Returns None if no transfer using the partner's clock has been started
+    pub fn receive(&mut self, byte: u8) -> Option<u8> {
+        let clocked = Self::TRANSFER_ENABLE | Self::INTERNAL_CLOCK;
+        if self.control & clocked != Self::TRANSFER_ENABLE {
+            return None;
+        }
+        let sent = self.data;
+        self.finish_transfer(byte);
+        Some(sent)
+    }
+}
+
+impl MemoryAccess for Serial {
+    fn mem_read(&self, address: u16) -> u8 {
+        match address {
+            0xFF01 => self.data,
+            // Unused bits read as 1
+            0xFF02 => self.control | 0b0111_1110,
+            _ => 0xFF,
+        }
+    }
+
+    fn mem_write(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF01 => self.data = value,
+            0xFF02 => {
+                self.control = value;
+                // Only transfers clocked by the Game Boy itself can finish without a partner
+                if value & Self::TRANSFER_ENABLE > 0 && value & Self::INTERNAL_CLOCK > 0 {
+                    if self.output.len() < Self::MAX_OUTPUT_LEN {
+                        self.output.push(self.data);
+                    }
+                    if self.linked {
+                        self.outgoing = Some(self.data);
+                    } else {
+                        self.finish_transfer(0xFF);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
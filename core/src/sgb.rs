@@ -0,0 +1,474 @@
+use super::*;
+
+/// Palette of each 8x8 tile of the display, row by row
+pub type SGBAttributes = [u8; SGB::ATTRIBUTES_SIZE];
+
+/// Mask set by the MASK_EN command, which games use to hide VRAM transfers from the player
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum SGBMask {
+    /// The display is shown normally
+    #[default]
+    None,
+    /// The frame shown when the mask was set stays on the screen
+    Freeze,
+    /// The screen is black
+    Black,
+    /// The screen is filled with color 0
+    Color0,
+}
+
+/// Border drawn around the display by the Super Game Boy
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SGBBorder {
+    /// 256 tiles in the SNES format of 4 bits per pixel, 32 bytes each
+    #[serde(with = "BigArray")]
+    tiles: [u8; 0x2000],
+    /// 32x32 map of tiles, of which 28 rows are shown, followed by 4 palettes of 16 colors
+    #[serde(with = "BigArray")]
+    map: [u8; 0x880],
+}
+
+impl SGBBorder {
+    pub const WIDTH: usize = 256;
+    pub const HEIGHT: usize = 224;
+    /// Top left corner of the display inside the border
+    pub const DISPLAY_X: usize = 48;
+    pub const DISPLAY_Y: usize = 40;
+
+    fn new() -> Self {
+        Self {
+            tiles: [0; 0x2000],
+            map: [0; 0x880],
+        }
+    }
+
+    /// Returns the RGB555 color of each pixel, row by row. Pixels with color 0 of their
+    /// palette are transparent and show given backdrop color, as does the area of the display
+    pub fn pixels(&self, backdrop: u16) -> impl Iterator<Item = u16> + '_ {
+        (0..Self::HEIGHT)
+            .flat_map(move |y| (0..Self::WIDTH).map(move |x| self.pixel(x, y, backdrop)))
+    }
+
+    fn pixel(&self, x: usize, y: usize, backdrop: u16) -> u16 {
+        let entry_i = 2 * ((y / 8) * 32 + x / 8);
+        let entry = u16::from_le_bytes([self.map[entry_i], self.map[entry_i + 1]]);
+        let tile = (entry & 0xFF) as usize;
+        // Border palettes are numbered from 4 to 7
+        let palette = ((entry >> 10) & 0b11) as usize;
+        let tile_x = if entry & 0x4000 != 0 {
+            7 - x % 8
+        } else {
+            x % 8
+        };
+        let tile_y = if entry & 0x8000 != 0 {
+            7 - y % 8
+        } else {
+            y % 8
+        };
+        // Bit planes 0 and 1 are interleaved in the first half of the tile, 2 and 3 in the second
+        let row = &self.tiles[tile * 32 + tile_y * 2..];
+        let color = [row[0], row[1], row[16], row[17]]
+            .iter()
+            .enumerate()
+            .fold(0, |color, (plane, byte)| {
+                color | (((byte >> (7 - tile_x)) & 1) << plane)
+            });
+        if color == 0 {
+            return backdrop;
+        }
+        let color_i = 0x800 + 2 * (palette * 16 + color as usize);
+        u16::from_le_bytes([self.map[color_i], self.map[color_i + 1]])
+    }
+}
+
+/// Data read from the display by a VRAM transfer command
+#[derive(Deserialize, Serialize, Clone, Copy)]
+enum VRAMTransfer {
+    /// Border tiles, the upper 128 of them if set
+    BorderTiles(bool),
+    /// Border map and palettes
+    BorderMap,
+    /// System palettes selected with PAL_SET
+    Palettes,
+    /// Attribute files selected with ATTR_SET or PAL_SET
+    AttributeFiles,
+}
+
+/// Super Game Boy functions, enabled for cartridges that declare support for them.
+/// Games send commands as packets through the select bits of the joypad register,
+/// setting colors for areas of the display and a border drawn around it.
+/// Timing isn't emulated, commands take effect as soon as their last packet arrives
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Serialize)]
+pub struct SGB {
+    /// Bits of the packet being received, starting from the lowest bit of the first byte
+    packet: [u8; 16],
+    /// Amount of bits received of the packet, None if no packet is being received
+    packet_bits: Option<u8>,
+    /// Select bits of the previous joypad register write
+    previous_select: u8,
+    /// Packets received of a command longer than one packet
+    command: Vec<u8>,
+    /// Amount of controllers enabled with MLT_REQ, and the one read next
+    players: u8,
+    player: u8,
+    /// Four palettes of RGB555 colors, of which color 0 is shared by all of them
+    palettes: [[u16; 4]; 4],
+    /// Palettes sent with PAL_TRN, 8 bytes each. Kept as sent instead of as colors,
+    /// so save states have the same size whatever the colors are
+    system_palettes: Vec<u8>,
+    #[serde(with = "BigArray")]
+    attributes: SGBAttributes,
+    /// Attribute files sent with ATTR_TRN, 90 bytes each
+    attribute_files: Vec<u8>,
+    mask: SGBMask,
+    /// Transfer waiting for the display to show its data, and the frames left until then
+    transfer: Option<(VRAMTransfer, u8)>,
+    border: Box<SGBBorder>,
+    /// If a border map has been sent, until then no border is shown
+    has_border: bool,
+}
+
+impl SGB {
+    pub const ATTRIBUTES_SIZE: usize = 20 * 18;
+    const ATTRIBUTE_FILES: usize = 45;
+    const ATTRIBUTE_FILE_SIZE: usize = Self::ATTRIBUTES_SIZE / 4;
+    /// Colors shown until the game sets its own, the default palette of the Super Game Boy
+    const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+    /// Frames ended after a VRAM transfer command before the display is read,
+    /// so the whole frame has been drawn after the command
+    const TRANSFER_DELAY_FRAMES: u8 = 2;
+
+    pub(crate) fn new() -> Self {
+        Self {
+            packet: [0; 16],
+            packet_bits: None,
+            previous_select: 0x30,
+            command: Vec::new(),
+            players: 1,
+            player: 0,
+            palettes: [Self::DEFAULT_PALETTE; 4],
+            system_palettes: vec![0; 0x1000],
+            attributes: [0; Self::ATTRIBUTES_SIZE],
+            attribute_files: vec![0; Self::ATTRIBUTE_FILES * Self::ATTRIBUTE_FILE_SIZE],
+            mask: SGBMask::None,
+            transfer: None,
+            border: Box::new(SGBBorder::new()),
+            has_border: false,
+        }
+    }
+
+    /// Returns the four palettes of RGB555 colors, indexed by the attributes
+    pub fn palettes(&self) -> &[[u16; 4]; 4] {
+        &self.palettes
+    }
+
+    /// Returns the palette of each tile of the display
+    pub fn attributes(&self) -> &SGBAttributes {
+        &self.attributes
+    }
+
+    pub fn mask(&self) -> SGBMask {
+        self.mask
+    }
+
+    /// Returns the border, or None if the game hasn't sent one
+    pub fn border(&self) -> Option<&SGBBorder> {
+        self.has_border.then_some(&*self.border)
+    }
+
+    /// Replaces the lower bits of a joypad register read with the controller ID while
+    /// several controllers are enabled and neither buttons nor the d-pad are selected.
+    /// Only the first controller has buttons pressed
+    pub(crate) fn read_joypad(&self, value: u8) -> u8 {
+        if self.players == 1 {
+            value
+        } else if self.previous_select == 0x30 {
+            (value & 0xF0) | (0x0F - self.player)
+        } else if self.player == 0 {
+            value
+        } else {
+            value | 0x0F
+        }
+    }
+
+    /// Receives packet bits from a joypad register write. Writing both select bits low starts
+    /// a packet, after which each pulse of one of them low sends a bit:
+    /// the d-pad select bit for 0 and the button select bit for 1
+    pub(crate) fn write_joypad(&mut self, value: u8) {
+        let select = value & 0x30;
+        match select {
+            0x00 => {
+                self.packet = [0; 16];
+                self.packet_bits = Some(0);
+            }
+            0x30 => {
+                // The next controller is read after the buttons have been selected
+                if self.players > 1 && self.packet_bits.is_none() && self.previous_select == 0x10 {
+                    self.player = (self.player + 1) % self.players;
+                }
+            }
+            _ => {
+                if let Some(bits) = self.packet_bits
+                    && self.previous_select == 0x30
+                {
+                    if select == 0x10 {
+                        self.packet[usize::from(bits / 8)] |= 1 << (bits % 8);
+                    }
+                    if bits == 127 {
+                        // The stop bit that follows is read as a normal write
+                        self.packet_bits = None;
+                        self.receive_packet();
+                    } else {
+                        self.packet_bits = Some(bits + 1);
+                    }
+                }
+            }
+        }
+        self.previous_select = select;
+    }
+
+    /// Runs the command once all of its packets have been received.
+    /// The amount of packets is in the lowest bits of the first byte
+    fn receive_packet(&mut self) {
+        self.command.extend_from_slice(&self.packet);
+        let packets = usize::from(self.command[0] & 0b111).max(1);
+        if self.command.len() >= packets * 16 {
+            let command = std::mem::take(&mut self.command);
+            self.run_command(&command);
+        }
+    }
+
+    fn run_command(&mut self, data: &[u8]) {
+        match data[0] >> 3 {
+            // PAL01, PAL23, PAL03, PAL12
+            0x00 => self.set_palette_pair(0, 1, &data[1..]),
+            0x01 => self.set_palette_pair(2, 3, &data[1..]),
+            0x02 => self.set_palette_pair(0, 3, &data[1..]),
+            0x03 => self.set_palette_pair(1, 2, &data[1..]),
+            // ATTR_BLK
+            0x04 => self.set_attribute_blocks(data),
+            // ATTR_LIN
+            0x05 => self.set_attribute_lines(data),
+            // ATTR_DIV
+            0x06 => self.set_attribute_division(data[1], data[2]),
+            // PAL_SET
+            0x0A => self.set_system_palettes(data),
+            // PAL_TRN
+            0x0B => self.start_transfer(VRAMTransfer::Palettes),
+            // MLT_REQ
+            0x11 => {
+                self.players = match data[1] & 0b11 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            }
+            // CHR_TRN
+            0x13 => self.start_transfer(VRAMTransfer::BorderTiles(data[1] & 1 != 0)),
+            // PCT_TRN
+            0x14 => self.start_transfer(VRAMTransfer::BorderMap),
+            // ATTR_TRN
+            0x15 => self.start_transfer(VRAMTransfer::AttributeFiles),
+            // ATTR_SET
+            0x16 => {
+                self.set_attribute_file(data[1] & 0x3F);
+                if data[1] & 0x40 != 0 {
+                    self.mask = SGBMask::None;
+                }
+            }
+            // MASK_EN
+            0x17 => {
+                self.mask = match data[1] & 0b11 {
+                    1 => SGBMask::Freeze,
+                    2 => SGBMask::Black,
+                    3 => SGBMask::Color0,
+                    _ => SGBMask::None,
+                }
+            }
+            // Sound, SNES code and other commands aren't supported
+            _ => {}
+        }
+    }
+
+    /// Returns the RGB555 color at given index of little endian colors
+    fn color(data: &[u8], index: usize) -> u16 {
+        u16::from_le_bytes([data[index * 2], data[index * 2 + 1]]) & 0x7FFF
+    }
+
+    fn set_shared_color(&mut self, color: u16) {
+        for palette in &mut self.palettes {
+            palette[0] = color;
+        }
+    }
+
+    /// Sets the shared color followed by colors 1-3 of both palettes
+    fn set_palette_pair(&mut self, first: usize, second: usize, colors: &[u8]) {
+        self.set_shared_color(Self::color(colors, 0));
+        for i in 1..4 {
+            self.palettes[first][i] = Self::color(colors, i);
+            self.palettes[second][i] = Self::color(colors, i + 3);
+        }
+    }
+
+    fn set_attribute(&mut self, x: usize, y: usize, palette: u8) {
+        self.attributes[y * 20 + x] = palette & 0b11;
+    }
+
+    /// Sets the palettes inside, on the edge and outside of rectangles of tiles
+    fn set_attribute_blocks(&mut self, data: &[u8]) {
+        let count = usize::from(data[1]);
+        for block in data[2..].chunks_exact(6).take(count) {
+            let &[control, palettes, left, top, right, bottom] = block else {
+                continue;
+            };
+            let (inside, mut edge, outside) =
+                (control & 1 != 0, control & 2 != 0, control & 4 != 0);
+            let (inside_palette, mut edge_palette, outside_palette) =
+                (palettes, palettes >> 2, palettes >> 4);
+            // A rectangle changing only the inside or the outside changes its edge too
+            if inside && !edge && !outside {
+                edge = true;
+                edge_palette = inside_palette;
+            } else if outside && !edge && !inside {
+                edge = true;
+                edge_palette = outside_palette;
+            }
+            let (left, top, right, bottom) = (
+                usize::from(left),
+                usize::from(top),
+                usize::from(right),
+                usize::from(bottom),
+            );
+            for y in 0..18 {
+                for x in 0..20 {
+                    if x < left || x > right || y < top || y > bottom {
+                        if outside {
+                            self.set_attribute(x, y, outside_palette);
+                        }
+                    } else if x > left && x < right && y > top && y < bottom {
+                        if inside {
+                            self.set_attribute(x, y, inside_palette);
+                        }
+                    } else if edge {
+                        self.set_attribute(x, y, edge_palette);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sets the palettes of whole rows or columns of tiles
+    fn set_attribute_lines(&mut self, data: &[u8]) {
+        let count = usize::from(data[1]);
+        for &line in data[2..].iter().take(count) {
+            let (index, palette) = (usize::from(line & 0x1F), line >> 5);
+            if line & 0x80 != 0 {
+                for x in 0..20 {
+                    self.set_attribute(x, index.min(17), palette);
+                }
+            } else {
+                for y in 0..18 {
+                    self.set_attribute(index.min(19), y, palette);
+                }
+            }
+        }
+    }
+
+    /// Divides the display at a row or a column, setting the palettes of both sides and the line
+    fn set_attribute_division(&mut self, palettes: u8, line: u8) {
+        let horizontal = palettes & 0x40 != 0;
+        for y in 0..18 {
+            for x in 0..20 {
+                let position = if horizontal { y } else { x };
+                let palette = match position.cmp(&usize::from(line)) {
+                    std::cmp::Ordering::Less => palettes >> 2,
+                    std::cmp::Ordering::Equal => palettes >> 4,
+                    std::cmp::Ordering::Greater => palettes,
+                };
+                self.set_attribute(x, y, palette);
+            }
+        }
+    }
+
+    /// Sets the palettes from the system palettes, optionally applying an attribute file
+    fn set_system_palettes(&mut self, data: &[u8]) {
+        for i in 0..4 {
+            let index = usize::from(Self::color(&data[1..], i) & 0x1FF);
+            let colors = &self.system_palettes[index * 8..];
+            self.palettes[i] = std::array::from_fn(|color| Self::color(colors, color));
+        }
+        self.set_shared_color(self.palettes[0][0]);
+        if data[9] & 0x80 != 0 {
+            self.mask = SGBMask::None;
+        }
+        if data[9] & 0x40 != 0 {
+            self.set_attribute_file(data[9] & 0x3F);
+        }
+    }
+
+    /// Sets the palettes of all tiles from an attribute file, 2 bits per tile
+    fn set_attribute_file(&mut self, file: u8) {
+        let file = usize::from(file);
+        if file >= Self::ATTRIBUTE_FILES {
+            return;
+        }
+        let start = file * Self::ATTRIBUTE_FILE_SIZE;
+        let bytes = &self.attribute_files[start..start + Self::ATTRIBUTE_FILE_SIZE];
+        for (i, attribute) in self.attributes.iter_mut().enumerate() {
+            *attribute = (bytes[i / 4] >> (6 - 2 * (i % 4))) & 0b11;
+        }
+    }
+
+    fn start_transfer(&mut self, transfer: VRAMTransfer) {
+        self.transfer = Some((transfer, Self::TRANSFER_DELAY_FRAMES));
+    }
+
+    /// Reads the data of a waiting VRAM transfer once the frame has been drawn after it
+    pub(crate) fn end_frame(&mut self, display: &DisplayBuffer) {
+        let Some((transfer, frames_left)) = &mut self.transfer else {
+            return;
+        };
+        *frames_left -= 1;
+        if *frames_left > 0 {
+            return;
+        }
+        let transfer = *transfer;
+        self.transfer = None;
+        let data = Self::read_transfer(display);
+        match transfer {
+            VRAMTransfer::BorderTiles(upper) => {
+                let start = if upper { 0x1000 } else { 0 };
+                self.border.tiles[start..start + 0x1000].copy_from_slice(&data);
+            }
+            VRAMTransfer::BorderMap => {
+                self.border.map.copy_from_slice(&data[..0x880]);
+                self.has_border = true;
+            }
+            VRAMTransfer::Palettes => self.system_palettes.copy_from_slice(&data),
+            VRAMTransfer::AttributeFiles => {
+                let size = self.attribute_files.len();
+                self.attribute_files.copy_from_slice(&data[..size]);
+            }
+        }
+    }
+
+    /// Converts the first 256 tiles of the display, 20 on each row, back into 4 KiB of
+    /// tile data in the Game Boy's format of 2 bits per pixel. Games show the data with the
+    /// identity palette, so the colors on the display are the color indices of the tiles
+    fn read_transfer(display: &DisplayBuffer) -> Vec<u8> {
+        let pixels: Vec<u8> = display_color_indices(display).collect();
+        let mut data = vec![0; 0x1000];
+        for (tile, tile_data) in data.chunks_exact_mut(16).enumerate() {
+            for row in 0..8 {
+                let start = ((tile / 20) * 8 + row) * 160 + (tile % 20) * 8;
+                for (x, color) in pixels[start..start + 8].iter().enumerate() {
+                    tile_data[row * 2] |= (color & 1) << (7 - x);
+                    tile_data[row * 2 + 1] |= (color >> 1) << (7 - x);
+                }
+            }
+        }
+        data
+    }
+}
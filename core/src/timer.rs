@@ -42,10 +42,61 @@ impl Timer {
         }
     }
 
-    // Cycles the timer forward by one T-cycle
-    pub fn cycle(&mut self) {
+    /// Cycles the timer forward by given amount of T-cycles.
+    /// `request_interrupt` is set if TIMA overflowed during any of them.
+    ///
+    /// Only the T-cycles where TIMA is incremented or reloaded are stepped one at a time,
+    /// the divider is advanced over the others at once
+    pub fn tick(&mut self, t_cycles: u8) {
         self.request_interrupt = false;
+        let mut remaining = u16::from(t_cycles);
+        while remaining > 0 {
+            let quiet = self.quiet_cycles().min(remaining);
+            if quiet > 0 {
+                self.div = self.div.wrapping_add(quiet);
+                if self.overflow_delay >= 0 {
+                    // Stays above -1, as the reload isn't reached during quiet cycles
+                    self.overflow_delay -= quiet as i8;
+                }
+                self.previous_and = self.and_value(self.div);
+                remaining -= quiet;
+            }
+            if remaining > 0 {
+                self.cycle();
+                remaining -= 1;
+            }
+        }
+    }
+
+    /// Returns the AND value of the selected DIV bit and the enabled flag for given divider
+    fn and_value(&self, div: u16) -> bool {
+        self.enabled && (div >> self.div_bit) & 0b1 > 0
+    }
 
+    /// Returns how many of the next T-cycles only increment the divider,
+    /// before TIMA is either incremented on a falling edge or reloaded after overflowing
+    fn quiet_cycles(&self) -> u16 {
+        // The first T-cycle compares against the previous AND value, which differs from
+        // the current divider's after DIV or TAC have been written
+        if self.previous_and && !self.and_value(self.div.wrapping_add(1)) {
+            return 0;
+        }
+        let mut quiet = u16::MAX;
+        if self.overflow_delay >= 0 {
+            quiet = self.overflow_delay as u16;
+        }
+        if self.enabled {
+            // After that the AND value falls when the divider reaches a multiple of
+            // twice the selected bit
+            let period = 1 << (self.div_bit + 1);
+            let until_edge = period - (self.div & (period - 1));
+            quiet = quiet.min(until_edge - 1);
+        }
+        quiet
+    }
+
+    // Cycles the timer forward by one T-cycle
+    fn cycle(&mut self) {
         // Simulate the 4 T-cycle delay after overflowing
         // before TMA is written to TIMA and interrupt is requested
         if self.overflow_delay >= 0 {
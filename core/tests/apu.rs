@@ -0,0 +1,69 @@
+//! Checks that the APU, which is emulated lazily, is up to date wherever it can be observed,
+//! using a generated ROM that waits for a sound to end by polling NR52
+
+mod common;
+
+use common::TestRom;
+use gb_web_core::CPU;
+use std::sync::Arc;
+
+/// Builds a 32 KiB ROM without a mapper that plays a sound on channel 1 with its length timer
+/// enabled, then polls NR52 until it reports the channel has turned off. The number of polls
+/// is written to $C000 and $C001, and 1 to $C002
+fn length_rom() -> Arc<[u8]> {
+    TestRom::new(0x8000, b"APULAZY ")
+        .program(&[
+            0x3E, 0x80, // LD A, $80
+            0xE0, 0x26, // LDH ($26), A (turn APU on)
+            0x3E, 0xF0, // LD A, $F0
+            0xE0, 0x12, // LDH ($12), A (full volume, DAC on)
+            0x3E, 0xC0, // LD A, $C0
+            0xE0, 0x14, // LDH ($14), A (trigger with length timer)
+            0x03, // loop: INC BC
+            0xF0, 0x26, // LDH A, ($26)
+            0xE6, 0x01, // AND $01
+            0x20, 0xF9, // JR NZ, loop
+            0x79, // LD A, C
+            0xEA, 0x00, 0xC0, // LD ($C000), A
+            0x78, // LD A, B
+            0xEA, 0x01, 0xC0, // LD ($C001), A
+            0x3E, 0x01, // LD A, $01
+            0xEA, 0x02, 0xC0, // LD ($C002), A
+            0x18, 0xFE, // JR -2
+        ])
+        .build()
+        .into()
+}
+
+#[test]
+fn register_reads_see_channel_turn_off() {
+    let mut cpu = CPU::new(length_rom()).unwrap();
+    // The length timer runs out after 64 ticks at 256 Hz, about 15 frames
+    for _ in 0..5 {
+        cpu.run_frame();
+    }
+    assert_eq!(cpu.peek(0xC002), 0);
+    for _ in 0..25 {
+        cpu.run_frame();
+    }
+    assert_eq!(cpu.peek(0xC002), 1);
+}
+
+/// Stepping catches the APU up after every instruction,
+/// so the polls are only counted the same if running catches it up on every read
+#[test]
+fn stepping_matches_running() {
+    let rom = length_rom();
+    let mut running = CPU::new(Arc::clone(&rom)).unwrap();
+    let mut stepping = CPU::new(rom).unwrap();
+    for _ in 0..20 {
+        running.run_frame();
+        while stepping.frame_counter != running.frame_counter {
+            stepping.step();
+        }
+        assert_eq!(
+            running.state_hash().unwrap(),
+            stepping.state_hash().unwrap()
+        );
+    }
+}
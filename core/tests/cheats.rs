@@ -0,0 +1,73 @@
+//! Checks that cheat codes are decoded and applied,
+//! using a generated ROM that loops without touching memory
+
+mod common;
+
+use common::TestRom;
+use gb_web_core::{CPU, Cheat, CheatKind, CheatParseError};
+use std::sync::Arc;
+
+/// Builds a 32 KiB ROM without a mapper that loops forever, with given byte at 0x4A17
+fn looping_rom(patched_byte: u8) -> Arc<[u8]> {
+    TestRom::new(0x8000, b"CHEATS  ")
+        .program(&[0x18, 0xFE]) // JR -2
+        .data(0x4A17, &[patched_byte])
+        .build()
+        .into()
+}
+
+#[test]
+fn codes_are_decoded() {
+    assert_eq!(
+        Cheat::parse(CheatKind::GameGenie, "00A-17B-C49"),
+        Ok(Cheat::ROMPatch {
+            address: 0x4A17,
+            value: 0x00,
+            compare: Some(0xC8),
+        })
+    );
+    assert_eq!(
+        Cheat::parse(CheatKind::GameShark, "010738C1"),
+        Ok(Cheat::RAMWrite {
+            address: 0xC138,
+            value: 0x07,
+        })
+    );
+    assert_eq!(
+        Cheat::parse(CheatKind::GameGenie, "00A-17B-C4"),
+        Err(CheatParseError::InvalidLength {
+            expected: "6 or 9",
+            actual: 8,
+        })
+    );
+    assert_eq!(
+        Cheat::parse(CheatKind::GameShark, "0107 38CX"),
+        Err(CheatParseError::InvalidCharacter('X'))
+    );
+}
+
+#[test]
+fn rom_patch_checks_compare_value() {
+    let patch = Cheat::parse(CheatKind::GameGenie, "00A-17B-C49").unwrap();
+    let mut matching = CPU::new(looping_rom(0xC8)).unwrap();
+    matching.set_cheats(vec![patch]);
+    assert_eq!(matching.peek(0x4A17), 0x00);
+
+    let mut other = CPU::new(looping_rom(0x12)).unwrap();
+    other.set_cheats(vec![patch]);
+    assert_eq!(other.peek(0x4A17), 0x12);
+}
+
+#[test]
+fn ram_write_applies_every_frame() {
+    let mut cpu = CPU::new(looping_rom(0)).unwrap();
+    cpu.set_cheats(vec![
+        Cheat::parse(CheatKind::GameShark, "010738C1").unwrap(),
+    ]);
+    cpu.run_frame();
+    assert_eq!(cpu.peek(0xC138), 0x07);
+
+    cpu.set_cheats(Vec::new());
+    cpu.run_frame();
+    assert_eq!(cpu.peek(0xC138), 0x07);
+}
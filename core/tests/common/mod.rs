@@ -0,0 +1,109 @@
+//! Builder for the ROMs the tests generate, so no ROMs need to be shipped with them
+
+// Every test uses only some of the builder
+#![allow(dead_code)]
+
+use gb_web_core::CartridgeInfo;
+
+/// Start of the program, which the entry point jumps to over the header
+const PROGRAM_START: usize = 0x150;
+
+/// ROM with a valid header checksum and a program the entry point jumps to.
+/// Without setting the cartridge type it has no mapper
+pub struct TestRom {
+    rom: Vec<u8>,
+    program_end: usize,
+    numbered_banks: bool,
+    mirrored_banks: bool,
+    global_checksum: bool,
+}
+
+impl TestRom {
+    /// Starts a ROM of given size, which is at least 32 KiB
+    pub fn new(size: usize, title: &[u8; 8]) -> Self {
+        let mut rom = vec![0; size];
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        rom[0x134..0x13C].copy_from_slice(title);
+        Self {
+            rom,
+            program_end: PROGRAM_START,
+            numbered_banks: false,
+            mirrored_banks: false,
+            global_checksum: false,
+        }
+    }
+
+    /// Sets the cartridge type and the ROM and RAM size codes of the header
+    pub fn cartridge(mut self, cartridge_type: u8, rom_size: u8, ram_size: u8) -> Self {
+        self.rom[0x147..0x14A].copy_from_slice(&[cartridge_type, rom_size, ram_size]);
+        self
+    }
+
+    /// Puts the logo checked by the boot ROM in the header
+    pub fn logo(self) -> Self {
+        self.data(0x104, &CartridgeInfo::NINTENDO_LOGO)
+    }
+
+    /// Sets the program the entry point jumps to
+    pub fn program(mut self, program: &[u8]) -> Self {
+        self.program_end = PROGRAM_START + program.len();
+        self.data(PROGRAM_START, program)
+    }
+
+    /// Writes bytes at given address, like header fields or data read by the program
+    pub fn data(mut self, address: usize, bytes: &[u8]) -> Self {
+        self.rom[address..address + bytes.len()].copy_from_slice(bytes);
+        self
+    }
+
+    /// Writes the number of each 16 KiB bank at its start
+    pub fn numbered_banks(mut self) -> Self {
+        self.numbered_banks = true;
+        self
+    }
+
+    /// Copies the header and program to every 16 KiB bank,
+    /// so they're found wherever the banks are mapped
+    pub fn mirrored_banks(mut self) -> Self {
+        self.mirrored_banks = true;
+        self
+    }
+
+    /// Sets the global checksum, which hardware doesn't check but homebrew toolchains set
+    pub fn global_checksum(mut self) -> Self {
+        self.global_checksum = true;
+        self
+    }
+
+    /// Sets the header checksum and returns the ROM
+    pub fn build(mut self) -> Vec<u8> {
+        let rom = &mut self.rom;
+        rom[0x14D] = rom[0x134..0x14D].iter().fold(0u8, |checksum, byte| {
+            checksum.wrapping_sub(*byte).wrapping_sub(1)
+        });
+        let (first, rest) = rom.split_at_mut(0x4000);
+        for (bank, contents) in rest.chunks_exact_mut(0x4000).enumerate() {
+            if self.mirrored_banks {
+                contents[0x100..self.program_end].copy_from_slice(&first[0x100..self.program_end]);
+            }
+            if self.numbered_banks {
+                contents[0] = bank as u8 + 1;
+            }
+        }
+        if self.global_checksum {
+            let checksum = rom
+                .iter()
+                .fold(0u16, |sum, byte| sum.wrapping_add(u16::from(*byte)));
+            rom[0x14E..0x150].copy_from_slice(&checksum.to_be_bytes());
+        }
+        self.rom
+    }
+}
+
+/// Starts a ROM with given cartridge type and ROM and RAM size codes, sized to match the
+/// header and with the number of each switchable bank at its start
+pub fn banked_rom(cartridge_type: u8, rom_size: u8, ram_size: u8) -> TestRom {
+    TestRom::new(0x8000 << rom_size, b"BANKTEST")
+        .cartridge(cartridge_type, rom_size, ram_size)
+        .numbered_banks()
+}
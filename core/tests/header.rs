@@ -0,0 +1,59 @@
+//! Checks the validation of ROM headers, using a generated ROM with a valid header
+//! that is then truncated or corrupted
+
+mod common;
+
+use common::TestRom;
+use gb_web_core::{CPU, MemoryInitializationError, MemoryInitializationErrorType};
+
+/// Builds a 32 KiB ROM with a header like homebrew toolchains generate,
+/// with the logo and both checksums
+fn homebrew_rom() -> Vec<u8> {
+    TestRom::new(0x8000, b"HOMEBREW")
+        .logo()
+        .program(&[0x18, 0xFE]) // JR -2
+        .global_checksum()
+        .build()
+}
+
+/// Returns the error of loading a ROM with its header checked
+fn load_error(rom: Vec<u8>) -> MemoryInitializationError {
+    CPU::new_checked(rom.into()).err().expect("ROM was loaded")
+}
+
+#[test]
+fn truncated_rom_has_no_header() {
+    let mut rom = homebrew_rom();
+    rom.truncate(0x14F);
+    assert!(matches!(
+        load_error(rom.clone()).error_type(),
+        MemoryInitializationErrorType::NoHeader
+    ));
+    assert!(CPU::new(rom.into()).is_err());
+}
+
+#[test]
+fn broken_header_is_only_loaded_unchecked() {
+    let mut rom = homebrew_rom();
+    rom[0x14D] ^= 0xFF;
+    assert!(matches!(
+        load_error(rom.clone()).error_type(),
+        MemoryInitializationErrorType::BadHeaderChecksum
+    ));
+    let cpu = CPU::new(rom.into()).unwrap();
+    assert!(!cpu.get_cartridge_info().header_checksum_valid);
+
+    let mut rom = homebrew_rom();
+    rom[0x104] = 0;
+    assert!(matches!(
+        load_error(rom).error_type(),
+        MemoryInitializationErrorType::BadLogo
+    ));
+}
+
+#[test]
+fn homebrew_header_is_valid() {
+    let cpu = CPU::new_checked(homebrew_rom().into()).unwrap();
+    let info = cpu.get_cartridge_info();
+    assert!(info.logo_valid && info.header_checksum_valid && info.global_checksum_valid);
+}
@@ -0,0 +1,41 @@
+//! Checks HuC1 bank switching and its infrared mode, using a generated ROM
+//! that switches banks, writes to RAM and then maps the infrared port
+
+mod common;
+
+use common::banked_rom;
+use gb_web_core::CPU;
+use std::sync::Arc;
+
+/// Builds a 64 KiB HuC1+RAM+BATTERY ROM with 32 KiB RAM
+fn huc1_rom() -> Arc<[u8]> {
+    banked_rom(0xFF, 0x01, 0x03)
+        .program(&[
+            0x3E, 0x03, // LD A, $03
+            0xEA, 0x00, 0x20, // LD ($2000), A (ROM bank 3)
+            0x3E, 0x0A, // LD A, $0A
+            0xEA, 0x00, 0x00, // LD ($0000), A (enable RAM)
+            0x3E, 0x02, // LD A, $02
+            0xEA, 0x00, 0x40, // LD ($4000), A (RAM bank 2)
+            0x3E, 0x55, // LD A, $55
+            0xEA, 0x00, 0xA0, // LD ($A000), A
+            0x3E, 0x0E, // LD A, $0E
+            0xEA, 0x00, 0x00, // LD ($0000), A (infrared mode)
+            0x18, 0xFE, // JR -2
+        ])
+        .build()
+        .into()
+}
+
+#[test]
+fn banks_switch_and_infrared_reads_no_light() {
+    let mut cpu = CPU::new(huc1_rom()).unwrap();
+    let info = cpu.get_cartridge_info();
+    assert!(info.has_ram && info.has_battery);
+    assert_eq!(cpu.peek(0x4000), 1);
+
+    cpu.run_frame();
+    assert_eq!(cpu.peek(0x4000), 3);
+    assert_eq!(cpu.get_ram()[0x4000], 0x55);
+    assert_eq!(cpu.peek(0xA000), 0xC0);
+}
@@ -0,0 +1,47 @@
+//! Checks MBC1 bank switching with the usual and the multicart wiring, using generated
+//! 1 MiB ROMs that select ROM bank 2 with the 2-bit register set to 1 in advanced banking mode
+
+mod common;
+
+use common::banked_rom;
+use gb_web_core::CPU;
+use std::sync::Arc;
+
+/// Builds a 1 MiB MBC1 ROM. The header and program are copied to every bank, so they're
+/// found wherever the banks are switched, and a multicart has the logo in the header
+/// of each game
+fn mbc1_rom(multicart: bool) -> Arc<[u8]> {
+    let mut rom = banked_rom(0x01, 0x05, 0x00);
+    if multicart {
+        rom = rom.logo();
+    }
+    rom.program(&[
+        0x3E, 0x02, // LD A, $02
+        0xEA, 0x00, 0x20, // LD ($2000), A (lower ROM bank bits)
+        0x3E, 0x01, // LD A, $01
+        0xEA, 0x00, 0x40, // LD ($4000), A (upper ROM bank bits)
+        0xEA, 0x00, 0x60, // LD ($6000), A (advanced banking mode)
+        0x18, 0xFE, // JR -2
+    ])
+    .mirrored_banks()
+    .build()
+    .into()
+}
+
+#[test]
+fn usual_wiring_applies_upper_bits_from_bit_5() {
+    let mut cpu = CPU::new(mbc1_rom(false)).unwrap();
+    assert!(!cpu.get_cartridge_info().multicart);
+    cpu.run_frame();
+    assert_eq!(cpu.peek(0x4000), 0x22);
+    assert_eq!(cpu.peek(0x0000), 0x20);
+}
+
+#[test]
+fn multicart_wiring_applies_upper_bits_from_bit_4() {
+    let mut cpu = CPU::new(mbc1_rom(true)).unwrap();
+    assert!(cpu.get_cartridge_info().multicart);
+    cpu.run_frame();
+    assert_eq!(cpu.peek(0x4000), 0x12);
+    assert_eq!(cpu.peek(0x0000), 0x10);
+}
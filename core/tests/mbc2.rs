@@ -0,0 +1,50 @@
+//! Checks the MBC2 registers and its built-in RAM,
+//! using a generated ROM that writes to RAM and switches the ROM bank once
+
+mod common;
+
+use common::banked_rom;
+use gb_web_core::CPU;
+use std::sync::Arc;
+
+/// Builds a 64 KiB MBC2+BATTERY ROM
+fn mbc2_rom() -> Arc<[u8]> {
+    banked_rom(0x06, 0x01, 0x00)
+        .program(&[
+            0x3E, 0x0A, // LD A, $0A
+            0xEA, 0x00, 0x00, // LD ($0000), A (enable RAM)
+            0x3E, 0x5C, // LD A, $5C
+            0xEA, 0x00, 0xA0, // LD ($A000), A
+            0x3E, 0x37, // LD A, $37
+            0xEA, 0x01, 0xA2, // LD ($A201), A (echo of $A001)
+            0x3E, 0x02, // LD A, $02
+            0xEA, 0x00, 0x21, // LD ($2100), A (select ROM bank 2)
+            0x18, 0xFE, // JR -2
+        ])
+        .build()
+        .into()
+}
+
+#[test]
+fn ram_echoes_and_keeps_lower_nibbles() {
+    let mut cpu = CPU::new(mbc2_rom()).unwrap();
+    let info = cpu.get_cartridge_info();
+    assert!(info.has_ram && info.has_battery);
+    assert_eq!(cpu.get_ram().len(), 512);
+
+    cpu.run_frame();
+    for address in [0xA000, 0xA200, 0xBE00] {
+        assert_eq!(cpu.peek(address), 0xFC);
+    }
+    assert_eq!(cpu.peek(0xA001), 0xF7);
+    assert_eq!(cpu.peek(0xBFFF), 0xF0);
+    assert_eq!(cpu.get_ram()[..2], [0x0C, 0x07]);
+}
+
+#[test]
+fn address_bit_8_selects_rom_bank_register() {
+    let mut cpu = CPU::new(mbc2_rom()).unwrap();
+    assert_eq!(cpu.peek(0x4000), 1);
+    cpu.run_frame();
+    assert_eq!(cpu.peek(0x4000), 2);
+}
@@ -0,0 +1,71 @@
+//! Checks the MBC7 accelerometer and EEPROM, using a generated ROM that latches the
+//! accelerometer and sends the EEPROM commands for enabling writes and writing a word
+
+mod common;
+
+use common::banked_rom;
+use gb_web_core::CPU;
+use std::sync::Arc;
+
+/// Appends writes to the EEPROM pins at (HL) that clock in given bits, highest first
+fn clock_bits(program: &mut Vec<u8>, bits: u32, count: u32) {
+    for index in (0..count).rev() {
+        let data_in = ((bits >> index) & 1) as u8;
+        for pins in [0x80, 0xC0] {
+            // LD A, pins; LD (HL), A
+            program.extend_from_slice(&[0x3E, pins | data_in << 1, 0x77]);
+        }
+    }
+    // LD A, $00; LD (HL), A (deselect)
+    program.extend_from_slice(&[0x3E, 0x00, 0x77]);
+}
+
+/// Builds a 32 KiB MBC7+SENSOR+RUMBLE+RAM+BATTERY ROM
+fn mbc7_rom() -> Arc<[u8]> {
+    let mut program = vec![
+        0x3E, 0x0A, // LD A, $0A
+        0xEA, 0x00, 0x00, // LD ($0000), A (enable RAM)
+        0x3E, 0x40, // LD A, $40
+        0xEA, 0x00, 0x40, // LD ($4000), A (second RAM enable)
+        0x3E, 0x55, // LD A, $55
+        0xEA, 0x00, 0xA0, // LD ($A000), A (erase accelerometer)
+        0x3E, 0xAA, // LD A, $AA
+        0xEA, 0x10, 0xA0, // LD ($A010), A (latch accelerometer)
+        0x21, 0x80, 0xA0, // LD HL, $A080
+    ];
+    // Start bit, then EWEN with opcode 00 and address 11xxxxxx
+    clock_bits(&mut program, 0b100 << 8 | 0xC0, 11);
+    // Start bit, then WRITE with opcode 01 and address 5, followed by the word
+    clock_bits(&mut program, (0b101 << 8 | 0x05) << 16 | 0xBEEF, 27);
+    program.extend_from_slice(&[0x18, 0xFE]); // JR -2
+    banked_rom(0x22, 0x00, 0x00)
+        .program(&program)
+        .build()
+        .into()
+}
+
+#[test]
+fn accelerometer_latches_tilt() {
+    let mut cpu = CPU::new(mbc7_rom()).unwrap();
+    assert!(cpu.get_cartridge_info().has_accelerometer);
+    cpu.set_accelerometer(0x70, -0x70);
+
+    cpu.run_frame();
+    let latched: Vec<u8> = (0..4)
+        .map(|register| cpu.peek(0xA020 + register * 0x10))
+        .collect();
+    assert_eq!(latched, [0x40, 0x82, 0x60, 0x81]);
+}
+
+#[test]
+fn eeprom_word_is_written_to_ram() {
+    let mut cpu = CPU::new(mbc7_rom()).unwrap();
+    assert_eq!(cpu.get_ram(), [0xFF; 0x100]);
+
+    cpu.run_frame();
+    let ram = cpu.get_ram();
+    assert_eq!(ram[10..12], [0xEF, 0xBE]);
+    assert_eq!(ram.iter().filter(|&&byte| byte != 0xFF).count(), 2);
+    // Data output signals the write is done
+    assert_eq!(cpu.peek(0xA080) & 1, 1);
+}
@@ -0,0 +1,50 @@
+//! Checks that MMM01 boots into its menu and maps the chosen game once locked,
+//! using a generated 128 KiB ROM whose menu picks a game of two banks at bank 2
+
+mod common;
+
+use common::banked_rom;
+use gb_web_core::CPU;
+use std::sync::Arc;
+
+/// Builds a 128 KiB ROM with the MMM01 header in its last 32 KiB. The header and program
+/// are copied to every bank, so the program keeps running wherever the banks are mapped
+fn mmm01_rom(cartridge_type: u8) -> Arc<[u8]> {
+    // 8 KiB RAM unless the cartridge has none
+    let ram_size = if cartridge_type == 0x0B { 0x00 } else { 0x02 };
+    banked_rom(cartridge_type, 0x02, ram_size)
+        .program(&[
+            0x3E, 0x02, // LD A, $02
+            0xEA, 0x00, 0x20, // LD ($2000), A (game starts at bank 2)
+            0x3E, 0x3C, // LD A, $3C
+            0xEA, 0x00, 0x60, // LD ($6000), A (game controls only bit 0 of the bank)
+            0x3E, 0x40, // LD A, $40
+            0xEA, 0x00, 0x00, // LD ($0000), A (lock)
+            0x18, 0xFE, // JR -2
+        ])
+        .mirrored_banks()
+        .build()
+        .into()
+}
+
+#[test]
+fn boots_from_last_banks_and_maps_game_when_locked() {
+    let mut cpu = CPU::new(mmm01_rom(0x0B)).unwrap();
+    assert_eq!(cpu.peek(0x0000), 6);
+    assert_eq!(cpu.peek(0x4000), 7);
+
+    cpu.run_frame();
+    assert_eq!(cpu.peek(0x0000), 2);
+    assert_eq!(cpu.peek(0x4000), 3);
+}
+
+#[test]
+fn menu_with_battery_is_detected() {
+    // MMM01+RAM+BATTERY
+    let mut cpu = CPU::new(mmm01_rom(0x0D)).unwrap();
+    assert!(cpu.get_cartridge_info().has_battery);
+    assert_eq!(cpu.peek(0x0000), 6);
+
+    cpu.run_frame();
+    assert_eq!(cpu.peek(0x0000), 2);
+}
@@ -0,0 +1,63 @@
+//! Checks the MBC3 real-time clock, using a generated ROM that latches the clock
+//! and copies its seconds register to WRAM in a loop
+
+mod common;
+
+use common::banked_rom;
+use gb_web_core::CPU;
+use std::sync::Arc;
+
+/// Builds a 32 KiB MBC3+TIMER+RAM+BATTERY ROM with 8 KiB RAM
+fn clock_rom() -> Arc<[u8]> {
+    banked_rom(0x10, 0x00, 0x02)
+        .program(&[
+            0x3E, 0x0A, // LD A, $0A
+            0xEA, 0x00, 0x00, // LD ($0000), A (enable RAM and clock)
+            0x3E, 0x08, // LD A, $08
+            0xEA, 0x00, 0x40, // LD ($4000), A (select seconds)
+            0xAF, // loop: XOR A
+            0xEA, 0x00, 0x60, // LD ($6000), A
+            0x3C, // INC A
+            0xEA, 0x00, 0x60, // LD ($6000), A (latch)
+            0xFA, 0x00, 0xA0, // LD A, ($A000)
+            0xEA, 0x00, 0xC0, // LD ($C000), A
+            0x18, 0xF0, // JR loop
+        ])
+        .build()
+        .into()
+}
+
+/// Runs a bit over given amount of emulated seconds
+fn run_seconds(cpu: &mut CPU, seconds: u32) {
+    for _ in 0..seconds * 60 + 5 {
+        cpu.run_frame();
+    }
+}
+
+#[test]
+fn clock_counts_emulated_time() {
+    let mut cpu = CPU::new(clock_rom()).unwrap();
+    run_seconds(&mut cpu, 2);
+    assert_eq!(cpu.peek(0xC000), 2);
+}
+
+#[test]
+fn footer_catches_up_time_since_saving() {
+    let mut cpu = CPU::new(clock_rom()).unwrap();
+    run_seconds(&mut cpu, 2);
+    let save = cpu.get_ram_with_rtc(1_000);
+    assert_eq!(save.len(), 0x2000 + 48);
+
+    // A day, an hour and a minute pass before loading, and a footer with a 32-bit
+    // timestamp loads the same as the full one
+    let mut loaded = CPU::new(clock_rom()).unwrap();
+    loaded
+        .set_ram_with_rtc(save[..save.len() - 4].to_vec(), 1_000 + 86_400 + 3_600 + 60)
+        .unwrap();
+    let footer = loaded.get_ram_with_rtc(0).split_off(0x2000);
+    let counters: Vec<u8> = footer[..20].chunks_exact(4).map(|chunk| chunk[0]).collect();
+    assert_eq!(counters, [2, 1, 1, 1, 0]);
+
+    run_seconds(&mut loaded, 1);
+    assert_eq!(loaded.peek(0xC000), 3);
+}
@@ -0,0 +1,36 @@
+//! Checks that the rumble bit of MBC5 is kept apart from the RAM bank number,
+//! using a generated ROM that turns the motor on while selecting a RAM bank
+
+mod common;
+
+use common::banked_rom;
+use gb_web_core::CPU;
+use std::sync::Arc;
+
+/// Builds a 32 KiB MBC5+RUMBLE+RAM+BATTERY ROM with 32 KiB RAM
+fn rumble_rom() -> Arc<[u8]> {
+    banked_rom(0x1E, 0x00, 0x03)
+        .program(&[
+            0x3E, 0x0A, // LD A, $0A
+            0xEA, 0x00, 0x00, // LD ($0000), A (enable RAM)
+            0xEA, 0x00, 0x40, // LD ($4000), A (motor on, RAM bank 2)
+            0x3E, 0x42, // LD A, $42
+            0xEA, 0x00, 0xA0, // LD ($A000), A
+            0x18, 0xFE, // JR -2
+        ])
+        .build()
+        .into()
+}
+
+#[test]
+fn rumble_bit_is_split_from_ram_bank() {
+    let mut cpu = CPU::new(rumble_rom()).unwrap();
+    assert!(cpu.get_cartridge_info().has_rumble);
+    assert!(!cpu.rumble_active());
+
+    cpu.run_frame();
+    assert!(cpu.rumble_active());
+    let ram = cpu.get_ram();
+    assert_eq!(ram[0x4000], 0x42);
+    assert_eq!(ram.iter().filter(|&&byte| byte != 0).count(), 1);
+}
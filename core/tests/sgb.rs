@@ -0,0 +1,114 @@
+//! Checks that Super Game Boy commands are decoded from the joypad register,
+//! using a generated ROM that sends one packet and halts
+
+mod common;
+
+use common::TestRom;
+use gb_web_core::CPU;
+use std::sync::Arc;
+
+/// Builds a 32 KiB ROM declaring Super Game Boy support that runs given code
+/// and then sends given packet
+fn packet_rom(setup: &[u8], packet: [u8; 16]) -> Arc<[u8]> {
+    let mut program = setup.to_vec();
+    program.extend_from_slice(&[
+        0x21, 0x00, 0x02, // LD HL, $0200 (packet)
+        0x3E, 0x00, 0xE0, 0x00, // reset pulse: LD A, $00; LDH ($00), A
+        0x3E, 0x30, 0xE0, 0x00, // LD A, $30; LDH ($00), A
+        0x0E, 0x10, // LD C, 16
+        0x2A, // byte loop: LD A, (HL+)
+        0x57, // LD D, A
+        0x06, 0x08, // LD B, 8
+        0xCB, 0x3A, // bit loop: SRL D
+        0x3E, 0x20, // LD A, $20 (bit 0)
+        0x30, 0x02, // JR NC, +2
+        0x3E, 0x10, // LD A, $10 (bit 1)
+        0xE0, 0x00, // LDH ($00), A
+        0x3E, 0x30, 0xE0, 0x00, // LD A, $30; LDH ($00), A
+        0x05, // DEC B
+        0x20, 0xEF, // JR NZ, bit loop
+        0x0D, // DEC C
+        0x20, 0xE8, // JR NZ, byte loop
+        0x3E, 0x20, 0xE0, 0x00, // stop bit
+        0x3E, 0x30, 0xE0, 0x00, // LD A, $30; LDH ($00), A
+        0x18, 0xFE, // JR -2
+    ]);
+    TestRom::new(0x8000, b"SGBTEST ")
+        .data(0x146, &[0x03]) // SGB functions
+        .data(0x14B, &[0x33]) // New licensee code, which SGB functions need
+        .program(&program)
+        .data(0x200, &packet)
+        .build()
+        .into()
+}
+
+fn run_packet(packet: [u8; 16]) -> CPU {
+    let mut cpu = CPU::new(packet_rom(&[], packet)).unwrap();
+    for _ in 0..2 {
+        cpu.run_frame();
+    }
+    cpu
+}
+
+#[test]
+fn palette_pair_sets_colors() {
+    // PAL01 with one packet: the shared color, then colors 1-3 of palettes 0 and 1
+    let colors: [u16; 7] = [0x7FFF, 0x001F, 0x03E0, 0x7C00, 0x1234, 0x0000, 0x4321];
+    let mut packet = [0; 16];
+    packet[0] = 0x01;
+    for (i, color) in colors.iter().enumerate() {
+        packet[1 + 2 * i..3 + 2 * i].copy_from_slice(&color.to_le_bytes());
+    }
+    let cpu = run_packet(packet);
+    let palettes = cpu.get_sgb().unwrap().palettes();
+    assert_eq!(palettes[0], [0x7FFF, 0x001F, 0x03E0, 0x7C00]);
+    assert_eq!(palettes[1], [0x7FFF, 0x1234, 0x0000, 0x4321]);
+    assert_eq!(palettes[2][0], 0x7FFF);
+}
+
+#[test]
+fn attribute_division_sets_rows() {
+    // ATTR_DIV dividing at row 5: palette 2 above, 3 on the row and 1 below
+    let mut packet = [0; 16];
+    packet[..3].copy_from_slice(&[0x31, 0b0111_1001, 5]);
+    let cpu = run_packet(packet);
+    let attributes = cpu.get_sgb().unwrap().attributes();
+    for (i, palette) in attributes.iter().enumerate() {
+        let expected = match i / 20 {
+            0..5 => 2,
+            5 => 3,
+            _ => 1,
+        };
+        assert_eq!(*palette, expected, "tile {i}");
+    }
+}
+
+#[test]
+fn palette_transfer_keeps_state_size() {
+    // Fills the tiles shown on the display with color 3, so every transferred color is 0x7FFF
+    let setup = [
+        0xAF, // XOR A
+        0xE0, 0x40, // LDH ($40), A (LCD off)
+        0x21, 0x00, 0x80, // LD HL, $8000
+        0x3E, 0xFF, // LD A, $FF
+        0x22, // fill loop: LD (HL+), A
+        0xCB, 0x64, // BIT 4, H
+        0x28, 0xFB, // JR Z, fill loop (until $9000)
+        0x3E, 0xE4, 0xE0, 0x47, // LD A, $E4; LDH ($47), A (identity palette)
+        0x3E, 0x91, 0xE0, 0x40, // LD A, $91; LDH ($40), A (LCD on)
+    ];
+    let mut packet = [0; 16];
+    packet[0] = 0x59; // PAL_TRN
+    let mut cpu = CPU::new(packet_rom(&setup, packet)).unwrap();
+    let mut state = Vec::new();
+    cpu.save_state_into(&mut state).unwrap();
+    let initial_size = state.len();
+
+    for _ in 0..8 {
+        cpu.run_frame();
+    }
+    cpu.save_state_into(&mut state).unwrap();
+    // Frontends like libretro need a fixed state size, while 512 palettes of
+    // varint colors would grow the state by 4 KiB
+    assert!(state.len().abs_diff(initial_size) < 64);
+}
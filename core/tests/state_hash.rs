@@ -0,0 +1,75 @@
+//! Checks that state hashes tell apart emulators that have diverged,
+//! using a generated ROM that copies the joypad register to WRAM in a loop
+
+mod common;
+
+use common::TestRom;
+use gb_web_core::{CPU, InputFlag};
+use std::sync::Arc;
+
+/// Builds a 32 KiB ROM without a mapper that reads the action buttons forever
+fn joypad_rom() -> Arc<[u8]> {
+    TestRom::new(0x8000, b"STATEHSH")
+        .program(&[
+            0x3E, 0x10, // LD A, $10 (select action buttons)
+            0xE0, 0x00, // LDH ($00), A
+            0xF0, 0x00, // loop: LDH A, ($00)
+            0xEA, 0x00, 0xC0, // LD ($C000), A
+            0x18, 0xF9, // JR loop
+        ])
+        .build()
+        .into()
+}
+
+fn run_frames(cpu: &mut CPU, frames: u32) {
+    for _ in 0..frames {
+        cpu.run_frame();
+    }
+}
+
+#[test]
+fn same_inputs_give_same_hash() {
+    let rom = joypad_rom();
+    let mut first = CPU::new(Arc::clone(&rom)).unwrap();
+    let mut second = CPU::new(rom).unwrap();
+    run_frames(&mut first, 30);
+    run_frames(&mut second, 30);
+    assert_eq!(first.state_hash().unwrap(), second.state_hash().unwrap());
+}
+
+#[test]
+fn divergence_changes_hash_until_resynced() {
+    let rom = joypad_rom();
+    let mut host = CPU::new(Arc::clone(&rom)).unwrap();
+    let mut guest = CPU::new(Arc::clone(&rom)).unwrap();
+    run_frames(&mut host, 10);
+    run_frames(&mut guest, 10);
+
+    // Pressing a button only on the guest stands in for a dropped input
+    guest.update_input(&InputFlag::A);
+    run_frames(&mut host, 1);
+    run_frames(&mut guest, 1);
+    assert_ne!(host.state_hash().unwrap(), guest.state_hash().unwrap());
+
+    // Resync by giving the guest the host's state, after which they run in step again
+    let mut state = Vec::new();
+    host.save_state_into(&mut state).unwrap();
+    guest = postcard::from_bytes(&state).unwrap();
+    guest.set_rom(rom);
+    assert_eq!(host.state_hash().unwrap(), guest.state_hash().unwrap());
+    run_frames(&mut host, 10);
+    run_frames(&mut guest, 10);
+    assert_eq!(host.state_hash().unwrap(), guest.state_hash().unwrap());
+}
+
+#[test]
+fn hash_matches_serialized_state() {
+    let mut cpu = CPU::new(joypad_rom()).unwrap();
+    run_frames(&mut cpu, 5);
+    let mut state = Vec::new();
+    cpu.save_state_into(&mut state).unwrap();
+    let expected = state.iter().fold(0xCBF2_9CE4_8422_2325, |hash: u64, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01B3)
+    });
+    assert_eq!(cpu.state_hash().unwrap(), expected);
+}
@@ -0,0 +1,106 @@
+//! Conformance checks against public test ROMs, run with `cargo test -- --ignored`.
+//!
+//! The ROMs aren't distributed with the repository, so the tests are skipped unless
+//! `GB_TEST_ROMS` points at a directory containing `cpu_instrs.gb`, `instr_timing.gb`
+//! and `dmg-acid2.gb`. The expected dmg-acid2 hash is read from `frame_hashes.txt`
+//! in the same directory, in the manifest format of the debugger's `test-frame` command
+//! (`<rom> <frames> <hash>` per line), as the hash only depends on the reference image
+
+use gb_web_core::{CPU, display_color_indices};
+use std::{env, fs, path::PathBuf};
+
+/// Returns the path of a test ROM, or None if the test ROM directory isn't set
+fn rom_path(name: &str) -> Option<PathBuf> {
+    let Ok(dir) = env::var("GB_TEST_ROMS") else {
+        eprintln!("GB_TEST_ROMS isn't set, skipping {name}");
+        return None;
+    };
+    Some(PathBuf::from(dir).join(name))
+}
+
+fn load(path: &PathBuf) -> CPU {
+    let rom = fs::read(path).unwrap_or_else(|e| panic!("Couldn't read {}: {e}", path.display()));
+    let mut cpu =
+        CPU::new(rom.into()).unwrap_or_else(|e| panic!("Couldn't load {}: {e:?}", path.display()));
+    cpu.set_audio_sample_rate(44100);
+    cpu
+}
+
+/// Runs a ROM printing its result through the serial port until it passes, fails
+/// or runs out of frames, like the debugger's `test-serial` command
+fn assert_serial_passes(name: &str, max_frames: u32) {
+    let Some(path) = rom_path(name) else {
+        return;
+    };
+    let mut cpu = load(&path);
+    let mut output = String::new();
+    for _ in 0..max_frames {
+        cpu.run_frame();
+        output.extend(cpu.take_serial_output().into_iter().map(char::from));
+        if output.contains("Passed") {
+            return;
+        }
+        if output.contains("Failed") {
+            break;
+        }
+    }
+    panic!("{name} didn't pass, serial output:\n{output}");
+}
+
+/// Hashes the display like the debugger's `test-frame` command
+fn frame_hash(cpu: &CPU) -> u64 {
+    display_color_indices(cpu.get_display_buffer()).fold(0xCBF2_9CE4_8422_2325, |hash, index| {
+        (hash ^ index as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// Runs a ROM showing its result on the display and compares the frame hash
+/// to the one listed for it in `frame_hashes.txt`
+fn assert_frame_matches(name: &str) {
+    let Some(path) = rom_path(name) else {
+        return;
+    };
+    let manifest_path = path.with_file_name("frame_hashes.txt");
+    let manifest = fs::read_to_string(&manifest_path)
+        .unwrap_or_else(|e| panic!("Couldn't read {}: {e}", manifest_path.display()));
+    let (frames, expected) = manifest
+        .lines()
+        .find_map(
+            |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [rom, frames, hash] if rom == name => Some((frames, hash)),
+                _ => None,
+            },
+        )
+        .unwrap_or_else(|| panic!("{name} isn't listed in {}", manifest_path.display()));
+    let frames: u32 = frames.parse().expect("Invalid frame count");
+    let expected =
+        u64::from_str_radix(expected.trim_start_matches("0x"), 16).expect("Invalid hash");
+
+    let mut cpu = load(&path);
+    for _ in 0..frames {
+        cpu.run_frame();
+    }
+    let hash = frame_hash(&cpu);
+    assert_eq!(
+        hash, expected,
+        "{name} frame hash {hash:016x} doesn't match the expected {expected:016x}"
+    );
+}
+
+#[test]
+#[ignore = "needs test ROMs in GB_TEST_ROMS"]
+fn blargg_cpu_instrs() {
+    assert_serial_passes("cpu_instrs.gb", 60 * 120);
+}
+
+#[test]
+#[ignore = "needs test ROMs in GB_TEST_ROMS"]
+fn blargg_instr_timing() {
+    assert_serial_passes("instr_timing.gb", 60 * 10);
+}
+
+#[test]
+#[ignore = "needs test ROMs in GB_TEST_ROMS"]
+fn dmg_acid2() {
+    assert_frame_matches("dmg-acid2.gb");
+}
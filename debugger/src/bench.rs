@@ -0,0 +1,103 @@
+use gb_web_core::CPU;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    error, fs,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+const USAGE: &str = "Usage: bench <rom> [--seconds N] [--save-states N]";
+/// Emulated time run at once
+const CHUNK_MS: f32 = 100.0;
+
+/// Allocations and reallocations made by the debugger, reported by benchmarks
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// System allocator counting allocations
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Runs a ROM as fast as possible for given amount of emulated seconds
+/// and prints how many emulated seconds were run per second.
+/// With `--save-states N`, the state it ends in is then serialized N times
+/// both into new buffers and into a reused buffer, comparing time and allocations
+pub fn run(args: &[String]) -> Result<(), Box<dyn error::Error + 'static>> {
+    let mut rom_path = None;
+    let mut seconds = 60.0;
+    let mut save_states = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seconds" => seconds = args.next().ok_or(USAGE)?.parse::<f32>()?,
+            "--save-states" => save_states = Some(args.next().ok_or(USAGE)?.parse::<u32>()?),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let rom = fs::read(rom_path.ok_or(USAGE)?)?;
+
+    let mut cpu = CPU::new(rom.into()).map_err(|e| format!("Couldn't load ROM: {e:?}"))?;
+    cpu.set_audio_sample_rate(44100);
+    let chunks = (seconds * 1000.0 / CHUNK_MS).ceil() as u32;
+    let start = Instant::now();
+    for _ in 0..chunks {
+        cpu.run(CHUNK_MS);
+    }
+    let elapsed = start.elapsed().as_secs_f32();
+    let emulated = chunks as f32 * CHUNK_MS / 1000.0;
+    println!(
+        "Ran {emulated} emulated seconds in {elapsed:.3} s, {:.2} emulated seconds per second",
+        emulated / elapsed
+    );
+
+    if let Some(count) = save_states.filter(|&count| count > 0) {
+        bench_save_states("New buffers", count, || {
+            postcard::to_stdvec(&cpu).map(|state| state.len())
+        })?;
+        let mut buffer = Vec::new();
+        bench_save_states("Reused buffer", count, || {
+            cpu.save_state_into(&mut buffer).map(|()| buffer.len())
+        })?;
+    }
+    Ok(())
+}
+
+/// Serializes states given amount of times, printing the average time and allocations
+fn bench_save_states(
+    name: &str,
+    count: u32,
+    mut serialize: impl FnMut() -> Result<usize, postcard::Error>,
+) -> Result<(), postcard::Error> {
+    let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut size = 0;
+    for _ in 0..count {
+        size = serialize()?;
+    }
+    let elapsed_us = start.elapsed().as_secs_f64() * 1e6;
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+    println!(
+        "{name}: {count} states of {size} bytes, {:.1} µs and {:.2} allocations per state",
+        elapsed_us / count as f64,
+        allocations as f64 / count as f64
+    );
+    Ok(())
+}
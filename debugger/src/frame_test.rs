@@ -0,0 +1,115 @@
+use crate::{
+    input_script::{self, InputScript},
+    screenshot,
+};
+use gb_web_core::{CPU, display_color_indices};
+use std::{error, fs, path::Path};
+
+const USAGE: &str = "\
+Usage: test-frame <rom> --frames N [--expect-hash <hex>] [--dump out.png]
+                  [--input-script <path>]
+       test-frame --manifest <file>";
+
+/// Hashes the display after running given ROM for given amount of frames,
+/// with buttons pressed as the optional input script says.
+///
+/// The hash is 64-bit FNV-1a over the palette index (0-3) of each pixel as a byte,
+/// row by row from the top left, so it only changes when the image does
+fn run_rom(
+    rom_path: &Path,
+    frames: u32,
+    script: Option<&InputScript>,
+) -> Result<(u64, CPU), Box<dyn error::Error>> {
+    let rom = fs::read(rom_path)?;
+    let mut cpu = CPU::new(rom.into()).map_err(|e| format!("Couldn't load ROM: {e:?}"))?;
+    cpu.set_audio_sample_rate(44100);
+    input_script::run_frames(&mut cpu, frames, script);
+    let hash = fnv1a(display_color_indices(cpu.get_display_buffer()));
+    Ok((hash, cpu))
+}
+
+/// 64-bit FNV-1a hash of given bytes
+pub fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+fn parse_hash(hex: &str) -> Result<u64, Box<dyn error::Error>> {
+    let digits = hex.strip_prefix("0x").unwrap_or(hex);
+    Ok(u64::from_str_radix(digits, 16).map_err(|_| format!("Invalid hash \"{hex}\""))?)
+}
+
+/// Checks every ROM listed in a manifest. Each line of the manifest has a ROM path
+/// relative to the manifest, the amount of frames to run and the expected hash,
+/// separated by whitespace. Empty lines and lines starting with # are ignored
+fn run_manifest(manifest_path: &str) -> Result<bool, Box<dyn error::Error>> {
+    let manifest = fs::read_to_string(manifest_path)?;
+    let base = Path::new(manifest_path).parent().unwrap_or(Path::new(""));
+    let mut failures = 0;
+    let mut total = 0;
+    for (i, line) in manifest.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [rom, frames, expected] = fields[..] else {
+            return Err(format!("Line {} should be \"<rom> <frames> <hash>\"", i + 1).into());
+        };
+        let frames = frames.parse()?;
+        let expected = parse_hash(expected)?;
+        total += 1;
+        match run_rom(&base.join(rom), frames, None) {
+            Ok((hash, _)) if hash == expected => println!("PASS {rom}"),
+            Ok((hash, _)) => {
+                failures += 1;
+                println!("FAIL {rom}: got {hash:016x}, expected {expected:016x}");
+            }
+            Err(e) => {
+                failures += 1;
+                println!("FAIL {rom}: {e}");
+            }
+        }
+    }
+    println!("{} of {total} passed", total - failures);
+    Ok(failures == 0)
+}
+
+/// Runs a ROM whose result is shown on the display, like dmg-acid2,
+/// and compares the hash of the final frame. Without an expected hash,
+/// only prints it. Returns if the hash matched
+pub fn run(args: &[String]) -> Result<bool, Box<dyn error::Error + 'static>> {
+    let mut rom_path = None;
+    let mut frames = None;
+    let mut expected = None;
+    let mut dump_path = None;
+    let mut script = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--manifest" => return run_manifest(args.next().ok_or(USAGE)?),
+            "--frames" => frames = Some(args.next().ok_or(USAGE)?.parse::<u32>()?),
+            "--expect-hash" => expected = Some(parse_hash(args.next().ok_or(USAGE)?)?),
+            "--dump" => dump_path = Some(args.next().ok_or(USAGE)?),
+            "--input-script" => script = Some(InputScript::load(args.next().ok_or(USAGE)?)?),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let (rom_path, frames) = rom_path.zip(frames).ok_or(USAGE)?;
+
+    let (hash, cpu) = run_rom(Path::new(rom_path), frames, script.as_ref())?;
+    println!("{hash:016x}");
+    let Some(expected) = expected else {
+        return Ok(true);
+    };
+    if hash == expected {
+        return Ok(true);
+    }
+    println!("Hash doesn't match the expected {expected:016x}");
+    if let Some(path) = dump_path {
+        screenshot::write_png(path, cpu.get_display_buffer(), 1, &screenshot::GRAY_PALETTE)?;
+    }
+    Ok(false)
+}
@@ -0,0 +1,97 @@
+use gb_web_core::{CPU, InputFlag};
+use std::fs;
+
+/// Button pressed or released at the start of a frame
+struct InputEvent {
+    frame: u32,
+    button: InputFlag,
+    pressed: bool,
+    line: usize,
+}
+
+/// Buttons pressed and released at given frames, for running ROMs headlessly.
+///
+/// Each line of a script has a frame number, a button (a, b, start, select, up, down,
+/// left or right) and `down` or `up`, like `120 start down`. Frames count from 0 and
+/// the input is applied before running the frame, so frame 120 is after 120 frames.
+/// Empty lines and lines starting with # are ignored
+pub struct InputScript {
+    events: Vec<InputEvent>,
+}
+
+impl InputScript {
+    /// Reads and validates a script, naming the line of the first problem
+    pub fn load(path: &str) -> Result<Self, String> {
+        let script = fs::read_to_string(path).map_err(|e| format!("Couldn't read {path}: {e}"))?;
+        let mut events: Vec<InputEvent> = Vec::new();
+        for (i, line) in script.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("{path}:{line_number}: {message}");
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [frame, button, action] = fields[..] else {
+                return Err(error("expected \"<frame> <button> down|up\"".to_string()));
+            };
+            let frame = frame
+                .parse::<u32>()
+                .map_err(|_| error(format!("invalid frame \"{frame}\"")))?;
+            let button = match button.to_ascii_lowercase().as_str() {
+                "a" => InputFlag::A,
+                "b" => InputFlag::B,
+                "start" => InputFlag::START,
+                "select" => InputFlag::SELECT,
+                "up" => InputFlag::UP,
+                "down" => InputFlag::DOWN,
+                "left" => InputFlag::LEFT,
+                "right" => InputFlag::RIGHT,
+                _ => {
+                    return Err(error(format!(
+                        "unknown button \"{button}\", expected one of \
+                         a, b, start, select, up, down, left or right"
+                    )));
+                }
+            };
+            let pressed = match action {
+                "down" => true,
+                "up" => false,
+                _ => return Err(error(format!("expected down or up, got \"{action}\""))),
+            };
+            if let Some(previous) = events.last().filter(|previous| previous.frame > frame) {
+                return Err(error(format!(
+                    "frame {frame} comes after frame {} on line {}, frames have to be in order",
+                    previous.frame, previous.line
+                )));
+            }
+            events.push(InputEvent {
+                frame,
+                button,
+                pressed,
+                line: line_number,
+            });
+        }
+        Ok(Self { events })
+    }
+}
+
+/// Runs given amount of frames, pressing and releasing buttons as the script says
+pub fn run_frames(cpu: &mut CPU, frames: u32, script: Option<&InputScript>) {
+    let mut events = script
+        .map_or(&[][..], |script| &script.events[..])
+        .iter()
+        .peekable();
+    let mut input = InputFlag::empty();
+    for frame in 0..frames {
+        let mut changed = false;
+        while let Some(event) = events.next_if(|event| event.frame == frame) {
+            input.set(event.button, event.pressed);
+            changed = true;
+        }
+        if changed {
+            cpu.update_input(&input);
+        }
+        cpu.run_frame();
+    }
+}
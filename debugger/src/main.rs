@@ -1,6 +1,26 @@
+mod bench;
+mod frame_test;
+mod input_script;
+mod repl;
+mod save;
+mod screenshot;
+mod serial_test;
+mod state;
+#[cfg(test)]
+mod testing;
+mod trace;
+
 use dotenv::dotenv;
 use gb_web_core::CPU;
-use std::{env, error, fs};
+use save::SaveFile;
+use std::{
+    env, error, fs,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 
 /// This module simply loads a ROM from file path and runs it on the CPU
 /// It's ran locally instead of on WASM, so it can be used for debugging the emulator core
@@ -9,19 +29,92 @@ use std::{env, error, fs};
 /// that has the following variables:
 /// EXECUTION_TIME: determines how many milliseconds the core is ran for at a time
 /// ROM_PATH: the local path to a ROM file
+///
+/// Running with the `debug` subcommand opens an interactive prompt instead,
+/// for stepping through instructions and setting breakpoints
+///
+/// Cartridges with battery backed RAM load `<rom>.sav` next to the ROM,
+/// or the file given with `--save <path>`, and write it back while running and on exit.
+/// With `--mmap-save` the file is memory mapped instead, so every write lands in it right away
+/// and the save survives the debugger crashing.
+/// `--load-state <path>` starts from a save state written with the `savestate` command
+///
+/// The `test-serial <rom>` subcommand runs a test ROM printing through the serial port
+/// without needing the .env file, exiting with 0 if the test passed and 1 otherwise.
+/// The `test-frame <rom> --frames N --expect-hash <hex>` subcommand does the same for
+/// test ROMs showing their result on the display, comparing a hash of the final frame.
+/// The `screenshot <rom> --at-frame N -o out.png` subcommand saves the display at given frame,
+/// and `trace <rom> --frames N -o trace.log` logs the CPU state before every instruction.
+/// `bench <rom> [--seconds N]` measures how fast the core runs without any pacing,
+/// and with `--save-states N` how long serializing save states takes.
+/// `test-frame` and `screenshot` take `--input-script <path>` for pressing buttons at given frames
 pub fn main() -> Result<(), Box<dyn error::Error + 'static>> {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("screenshot") => return screenshot::run(&args[2..]),
+        Some("trace") => return trace::run(&args[2..]),
+        Some("bench") => return bench::run(&args[2..]),
+        _ => {}
+    }
+    let test = match args.get(1).map(String::as_str) {
+        Some("test-serial") => Some(serial_test::run as fn(&[String]) -> _),
+        Some("test-frame") => Some(frame_test::run as fn(&[String]) -> _),
+        _ => None,
+    };
+    if let Some(test) = test {
+        let passed = test(&args[2..])?;
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
     dotenv().expect("No .env file found in working directory");
 
     let time = env::var("EXECUTION_TIME")?.parse::<f32>()?;
     let rom_path = env::var("ROM_PATH")?;
-    let rom = fs::read(rom_path)?;
+    let rom: Arc<[u8]> = fs::read(&rom_path)?.into();
+    let flag_value = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .map(|i| args.get(i + 1).ok_or(format!("Missing path after {flag}")))
+            .transpose()
+    };
+    let save_path = flag_value("--save")?;
 
-    let mut cpu = CPU::new(rom).unwrap();
-    cpu.set_audio_sample_rate(44100);
+    let mut cpu = match flag_value("--load-state")? {
+        Some(path) => state::load(path, &rom)?,
+        None => {
+            let mut cpu = CPU::new(Arc::clone(&rom)).unwrap();
+            cpu.set_audio_sample_rate(44100);
+            cpu
+        }
+    };
+    let mmap_save = args.iter().any(|arg| arg == "--mmap-save");
+    let mut save = SaveFile::for_rom(
+        &cpu,
+        Path::new(&rom_path),
+        save_path.map(Path::new),
+        mmap_save,
+    );
+    if let Some(save) = &save {
+        save.load(&mut cpu);
+    }
+    if args.get(1).map(String::as_str) == Some("debug") {
+        return repl::run(cpu, &rom, save);
+    }
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_interrupted = Arc::clone(&interrupted);
+    ctrlc::set_handler(move || handler_interrupted.store(true, Ordering::Relaxed))?;
     let mut elapsed = 0.0;
-    loop {
+    while !interrupted.load(Ordering::Relaxed) {
         cpu.run(time);
         elapsed += time;
-        println!("Ran CPU for {elapsed} ms")
+        println!("Ran CPU for {elapsed} ms");
+        if let Some(save) = &mut save {
+            save.update(&mut cpu);
+        }
+    }
+    if let Some(save) = &mut save {
+        save.flush(&mut cpu);
     }
+    Ok(())
 }
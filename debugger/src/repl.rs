@@ -0,0 +1,306 @@
+use crate::{save::SaveFile, screenshot, state};
+use gb_web_core::CPU;
+use std::{
+    io::{self, BufRead, Write},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+const HELP: &str = "\
+Commands:
+  s [n]            step n instructions (default 1)
+  c                continue until a breakpoint or Ctrl-C
+  b <addr>         add a breakpoint
+  d <addr>         delete a breakpoint
+  r                print registers
+  m <addr> <len>   dump memory
+  dis <addr> [n]   disassemble n instructions (default 10)
+  p [path] [n]     save the display as a PNG scaled n times (default screenshot.png, 1)
+  savestate <path> write a save state file
+  loadstate <path> load a save state file, keeping breakpoints
+  reset            restart the ROM, keeping breakpoints and save RAM
+  q                quit
+Addresses and lengths are hexadecimal, with an optional $ or 0x prefix";
+
+/// Command entered at the prompt
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Step(u32),
+    Continue,
+    AddBreakpoint(u16),
+    DeleteBreakpoint(u16),
+    Registers,
+    Memory { address: u16, length: u16 },
+    Disassemble { address: u16, count: u32 },
+    Screenshot { path: String, scale: u32 },
+    SaveState(String),
+    LoadState(String),
+    Reset,
+    Help,
+    Quit,
+}
+
+/// Parses a hexadecimal number with an optional $ or 0x prefix
+pub fn parse_hex(arg: Option<&str>, name: &str) -> Result<u16, String> {
+    let arg = arg.ok_or_else(|| format!("Missing {name}"))?;
+    let digits = arg
+        .strip_prefix('$')
+        .or_else(|| arg.strip_prefix("0x"))
+        .unwrap_or(arg);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid {name} \"{arg}\""))
+}
+
+fn parse_count(arg: Option<&str>, default: u32) -> Result<u32, String> {
+    match arg {
+        Some(arg) => arg.parse().map_err(|_| format!("Invalid count \"{arg}\"")),
+        None => Ok(default),
+    }
+}
+
+fn parse_path(arg: Option<&str>) -> Result<String, String> {
+    arg.map(str::to_string)
+        .ok_or_else(|| "Missing path".to_string())
+}
+
+/// Parses a line entered at the prompt
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let mut args = line.split_whitespace();
+    let Some(name) = args.next() else {
+        return Err("No command given".to_string());
+    };
+    let command = match name {
+        "s" => Command::Step(parse_count(args.next(), 1)?),
+        "c" => Command::Continue,
+        "b" => Command::AddBreakpoint(parse_hex(args.next(), "address")?),
+        "d" => Command::DeleteBreakpoint(parse_hex(args.next(), "address")?),
+        "r" => Command::Registers,
+        "m" => Command::Memory {
+            address: parse_hex(args.next(), "address")?,
+            length: parse_hex(args.next(), "length")?,
+        },
+        "dis" => Command::Disassemble {
+            address: parse_hex(args.next(), "address")?,
+            count: parse_count(args.next(), 10)?,
+        },
+        "p" => Command::Screenshot {
+            path: args.next().unwrap_or("screenshot.png").to_string(),
+            scale: parse_count(args.next(), 1)?,
+        },
+        "savestate" => Command::SaveState(parse_path(args.next())?),
+        "loadstate" => Command::LoadState(parse_path(args.next())?),
+        "reset" => Command::Reset,
+        "h" | "help" => Command::Help,
+        "q" => Command::Quit,
+        _ => return Err(format!("Unknown command \"{name}\", enter h for help")),
+    };
+    if let Some(extra) = args.next() {
+        return Err(format!("Unexpected argument \"{extra}\""));
+    }
+    Ok(command)
+}
+
+/// Prints the instruction at the program counter
+fn print_current(cpu: &CPU) {
+    let pc = cpu.get_registers().pc;
+    println!("{pc:04X}  {}", cpu.disassemble(pc).text);
+}
+
+fn dump_memory(cpu: &CPU, address: u16, length: u16) {
+    let end = address as u32 + length as u32;
+    for row in (address as u32..end).step_by(16) {
+        let bytes: Vec<String> = (row..end.min(row + 16))
+            .map(|address| format!("{:02X}", cpu.peek(address as u16)))
+            .collect();
+        println!("{row:04X}  {}", bytes.join(" "));
+    }
+}
+
+/// Reads commands from standard input and runs them on the CPU until quit.
+/// Ctrl-C breaks into the prompt while the CPU is running.
+/// Changed save RAM is written after commands and on quit
+pub fn run(
+    mut cpu: CPU,
+    rom: &Arc<[u8]>,
+    mut save: Option<SaveFile>,
+) -> Result<(), Box<dyn std::error::Error + 'static>> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_interrupted = Arc::clone(&interrupted);
+    ctrlc::set_handler(move || handler_interrupted.store(true, Ordering::Relaxed))?;
+
+    println!("{HELP}");
+    print_current(&cpu);
+    let stdin = io::stdin();
+    loop {
+        if let Some(save) = &mut save {
+            save.update(&mut cpu);
+        }
+        print!("> ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        // Quit at end of input
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match parse_command(&line) {
+            Ok(command) => command,
+            Err(e) => {
+                println!("{e}");
+                continue;
+            }
+        };
+        match command {
+            Command::Step(count) => {
+                for _ in 0..count {
+                    cpu.step();
+                }
+                print_current(&cpu);
+            }
+            Command::Continue => {
+                interrupted.store(false, Ordering::Relaxed);
+                loop {
+                    if cpu.run_frame_until_breakpoint() {
+                        println!("Breakpoint hit");
+                        break;
+                    }
+                    if interrupted.load(Ordering::Relaxed) {
+                        println!("Interrupted");
+                        break;
+                    }
+                }
+                print_current(&cpu);
+            }
+            Command::AddBreakpoint(address) => {
+                cpu.add_breakpoint(address);
+                println!("Breakpoint added at {address:04X}");
+            }
+            Command::DeleteBreakpoint(address) => {
+                if cpu.remove_breakpoint(address) {
+                    println!("Breakpoint deleted at {address:04X}");
+                } else {
+                    println!("No breakpoint at {address:04X}");
+                }
+            }
+            Command::Registers => println!("{}", cpu.get_registers()),
+            Command::Memory { address, length } => dump_memory(&cpu, address, length),
+            Command::Disassemble { address, count } => {
+                let mut address = address;
+                for _ in 0..count {
+                    let instruction = cpu.disassemble(address);
+                    println!("{address:04X}  {}", instruction.text);
+                    address = address.wrapping_add(instruction.length);
+                }
+            }
+            Command::Screenshot { path, scale } => {
+                let display = cpu.get_display_buffer();
+                let palette = screenshot::GRAY_PALETTE;
+                if let Err(e) = screenshot::write_png(&path, display, scale, &palette) {
+                    println!("Couldn't save the screenshot: {e}");
+                }
+            }
+            Command::SaveState(path) => match state::save(&path, &cpu, rom) {
+                Ok(()) => println!("Saved state to {path}"),
+                Err(e) => println!("Couldn't save state: {e}"),
+            },
+            Command::LoadState(path) => match state::load(&path, rom) {
+                Ok(mut loaded) => {
+                    for address in cpu.breakpoints() {
+                        loaded.add_breakpoint(address);
+                    }
+                    cpu = loaded;
+                    if let Some(save) = &mut save {
+                        save.replace_ram(&mut cpu);
+                    }
+                    println!("Loaded state from {path}");
+                    print_current(&cpu);
+                }
+                Err(e) => println!("Couldn't load state: {e}"),
+            },
+            Command::Reset => {
+                let mut new_cpu = CPU::new(Arc::clone(rom))
+                    .map_err(|e| format!("Couldn't restart ROM: {e:?}"))?;
+                new_cpu.set_audio_sample_rate(44100);
+                for address in cpu.breakpoints() {
+                    new_cpu.add_breakpoint(address);
+                }
+                // Battery backed RAM survives the restart like on a real cartridge
+                if let Some(save) = &mut save {
+                    save.flush(&mut cpu);
+                    save.load(&mut new_cpu);
+                }
+                cpu = new_cpu;
+                print_current(&cpu);
+            }
+            Command::Help => println!("{HELP}"),
+            Command::Quit => break,
+        }
+    }
+    if let Some(save) = &mut save {
+        save.flush(&mut cpu);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_are_parsed_with_defaults() {
+        assert_eq!(parse_command("s"), Ok(Command::Step(1)));
+        assert_eq!(parse_command("  s 25 "), Ok(Command::Step(25)));
+        assert_eq!(
+            parse_command("m $C000 0x10"),
+            Ok(Command::Memory {
+                address: 0xC000,
+                length: 0x10,
+            })
+        );
+        assert_eq!(
+            parse_command("dis 150"),
+            Ok(Command::Disassemble {
+                address: 0x150,
+                count: 10,
+            })
+        );
+        assert_eq!(
+            parse_command("p"),
+            Ok(Command::Screenshot {
+                path: "screenshot.png".to_string(),
+                scale: 1,
+            })
+        );
+        assert_eq!(
+            parse_command("savestate slot1.state"),
+            Ok(Command::SaveState("slot1.state".to_string()))
+        );
+        assert_eq!(parse_command("help"), Ok(Command::Help));
+    }
+
+    #[test]
+    fn invalid_commands_are_reported() {
+        assert_eq!(parse_command(""), Err("No command given".to_string()));
+        assert_eq!(
+            parse_command("x"),
+            Err("Unknown command \"x\", enter h for help".to_string())
+        );
+        assert_eq!(parse_command("b"), Err("Missing address".to_string()));
+        assert_eq!(
+            parse_command("b $10000"),
+            Err("Invalid address \"$10000\"".to_string())
+        );
+        assert_eq!(
+            parse_command("s ten"),
+            Err("Invalid count \"ten\"".to_string())
+        );
+        assert_eq!(parse_command("loadstate"), Err("Missing path".to_string()));
+        assert_eq!(
+            parse_command("r 1"),
+            Err("Unexpected argument \"1\"".to_string())
+        );
+    }
+}
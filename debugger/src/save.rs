@@ -0,0 +1,234 @@
+use gb_web_core::{CPU, unix_time_secs};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// How often changed RAM is written while running
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Battery backed cartridge RAM kept in a file, like on the real cartridge
+pub struct SaveFile {
+    path: PathBuf,
+    last_save: Instant,
+    /// If RAM has changed since it was last written
+    dirty: bool,
+    /// If the file is memory mapped, so every write to RAM lands in it right away
+    /// and nothing needs to be written separately
+    mapped: bool,
+}
+
+impl SaveFile {
+    /// Returns the save file next to the ROM with the .sav extension,
+    /// or None if the cartridge has no battery backed RAM or clock to save
+    pub fn for_rom(
+        cpu: &CPU,
+        rom_path: &Path,
+        save_path: Option<&Path>,
+        mapped: bool,
+    ) -> Option<Self> {
+        let info = cpu.get_cartridge_info();
+        if !((info.has_ram || info.has_rtc) && info.has_battery) {
+            return None;
+        }
+        let path = save_path
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| rom_path.with_extension("sav"));
+        Some(Self {
+            path,
+            last_save: Instant::now(),
+            dirty: false,
+            mapped,
+        })
+    }
+
+    /// Loads the save into cartridge RAM if the file exists.
+    /// A save that doesn't fit the cartridge is left untouched and reported
+    pub fn load(&self, cpu: &mut CPU) {
+        if self.mapped {
+            #[cfg(not(target_family = "wasm"))]
+            if let Err(e) = mapped::map(&self.path, cpu, true) {
+                println!("Couldn't map save {}: {e}", self.path.display());
+            }
+            return;
+        }
+        let ram = match fs::read(&self.path) {
+            Ok(ram) => ram,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => {
+                println!("Couldn't read save {}: {e}", self.path.display());
+                return;
+            }
+        };
+        // The cartridge clock counts the time the save has been sitting in the file
+        match cpu.set_ram_with_rtc(ram, unix_time_secs()) {
+            Ok(()) => println!("Loaded save {}", self.path.display()),
+            Err(e) => println!("Couldn't load save {}: {e}", self.path.display()),
+        }
+        // Loading isn't a change that needs saving
+        cpu.take_ram_dirty();
+    }
+
+    /// Makes the save follow RAM replaced by loading a save state
+    pub fn replace_ram(&mut self, cpu: &mut CPU) {
+        if self.mapped {
+            #[cfg(not(target_family = "wasm"))]
+            if let Err(e) = mapped::map(&self.path, cpu, false) {
+                println!("Couldn't map save {}: {e}", self.path.display());
+            }
+            return;
+        }
+        self.dirty = true;
+    }
+
+    /// Writes RAM if it has changed and enough time has passed since the last write.
+    /// The clock changes without writes to RAM, so a save with one is written every time
+    pub fn update(&mut self, cpu: &mut CPU) {
+        self.dirty |= cpu.take_ram_dirty() || cpu.get_cartridge_info().has_rtc;
+        if self.dirty && self.last_save.elapsed() >= SAVE_INTERVAL {
+            self.flush(cpu);
+        }
+    }
+
+    /// Writes RAM right away if it has changed, used before exiting.
+    /// The file is written through a temporary file, so a failed write
+    /// doesn't corrupt an existing save
+    pub fn flush(&mut self, cpu: &mut CPU) {
+        self.dirty |= cpu.take_ram_dirty() || cpu.get_cartridge_info().has_rtc;
+        if self.mapped {
+            self.dirty = false;
+            self.last_save = Instant::now();
+            #[cfg(not(target_family = "wasm"))]
+            if let Err(e) = mapped::write_footer(&self.path, cpu) {
+                println!("Couldn't write clock of save {}: {e}", self.path.display());
+            }
+            return;
+        }
+        if !self.dirty {
+            return;
+        }
+        self.last_save = Instant::now();
+        let temp_path = self.path.with_extension("sav.tmp");
+        let result = fs::write(&temp_path, cpu.get_ram_with_rtc(unix_time_secs()))
+            .and_then(|_| fs::rename(&temp_path, &self.path));
+        match result {
+            Ok(()) => self.dirty = false,
+            // Kept dirty so the next update tries again
+            Err(e) => println!("Couldn't write save {}: {e}", self.path.display()),
+        }
+    }
+}
+
+/// Memory mapped saves, where writes to cartridge RAM go straight to the page cache
+/// of the file, so they survive the debugger crashing or being killed
+#[cfg(not(target_family = "wasm"))]
+mod mapped {
+    use gb_web_core::{CPU, unix_time_secs};
+    use memmap2::MmapOptions;
+    use std::{
+        error,
+        fs::OpenOptions,
+        io::{Seek, SeekFrom, Write},
+        path::Path,
+    };
+
+    /// Maps the cartridge RAM part of the save file and mirrors every write to RAM into it.
+    /// With `load`, an existing save is loaded into RAM first, otherwise the file is
+    /// overwritten with the current RAM. The RTC footer after RAM is written separately
+    pub fn map(path: &Path, cpu: &mut CPU, load: bool) -> Result<(), Box<dyn error::Error>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let ram = cpu.get_ram();
+        let file_len = file.metadata()?.len();
+        let existing = load && file_len > 0;
+        if existing {
+            // The cartridge clock counts the time the save has been sitting in the file
+            cpu.set_ram_with_rtc(std::fs::read(path)?, unix_time_secs())
+                .map_err(|e| e.to_string())?;
+            println!("Loaded save {}", path.display());
+        } else if file_len < ram.len() as u64 {
+            file.set_len(ram.len() as u64)?;
+        }
+        // Cartridges with only a clock have no RAM to map
+        if ram.is_empty() {
+            return write_footer(path, cpu);
+        }
+
+        // Safety: the debugger doesn't resize the file or write the mapped part through
+        // other handles while it's mapped, the mapping lives as long as the hook
+        let mut map = unsafe { MmapOptions::new().len(ram.len()).map_mut(&file)? };
+        if !existing {
+            map.copy_from_slice(&ram);
+        }
+        cpu.take_ram_dirty();
+        cpu.set_ram_write_hook(Some(Box::new(move |address, value| {
+            map[address] = value;
+        })));
+        write_footer(path, cpu)
+    }
+
+    /// Writes the RTC footer after RAM, if the cartridge has a clock
+    pub fn write_footer(path: &Path, cpu: &CPU) -> Result<(), Box<dyn error::Error>> {
+        let ram_len = cpu.get_ram().len();
+        let save = cpu.get_ram_with_rtc(unix_time_secs());
+        if save.len() == ram_len {
+            return Ok(());
+        }
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.seek(SeekFrom::Start(ram_len as u64))?;
+        file.write_all(&save[ram_len..])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{counter_rom, run_frames, temp_path};
+
+    #[test]
+    fn flushed_save_loads_back() {
+        let path = temp_path("flushed.sav");
+        let mut cpu = CPU::new(counter_rom()).unwrap();
+        run_frames(&mut cpu, 2);
+        let mut save = SaveFile::for_rom(&cpu, Path::new("counter.gb"), Some(&path), false)
+            .expect("Cartridge has a battery");
+        save.flush(&mut cpu);
+        // The file is written through a temporary file that is renamed over it
+        assert!(!path.with_extension("sav.tmp").exists());
+        assert_eq!(fs::read(&path).unwrap().len(), 0x2000 + 48);
+
+        let mut loaded = CPU::new(counter_rom()).unwrap();
+        save.load(&mut loaded);
+        assert_eq!(loaded.get_ram(), cpu.get_ram());
+        assert_ne!(loaded.get_ram()[0], 0);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn mapped_save_follows_ram_writes() {
+        let path = temp_path("mapped.sav");
+        let mut cpu = CPU::new(counter_rom()).unwrap();
+        let mut save = SaveFile::for_rom(&cpu, Path::new("counter.gb"), Some(&path), true)
+            .expect("Cartridge has a battery");
+        save.load(&mut cpu);
+        run_frames(&mut cpu, 2);
+        // Writes land in the file without flushing, and the clock follows RAM
+        let file = fs::read(&path).unwrap();
+        assert_eq!(file.len(), 0x2000 + 48);
+        assert_eq!(file[..0x2000], cpu.get_ram());
+
+        save.flush(&mut cpu);
+        let mut loaded = CPU::new(counter_rom()).unwrap();
+        let loaded_save = SaveFile::for_rom(&loaded, Path::new("counter.gb"), Some(&path), true)
+            .expect("Cartridge has a battery");
+        loaded_save.load(&mut loaded);
+        assert_eq!(loaded.get_ram(), cpu.get_ram());
+        fs::remove_file(path).unwrap();
+    }
+}
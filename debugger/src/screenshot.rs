@@ -0,0 +1,107 @@
+use crate::input_script::{self, InputScript};
+use gb_web_core::{CPU, DisplayBuffer, display_color_indices};
+use std::{error, fs};
+
+const USAGE: &str = "\
+Usage: screenshot <rom> --at-frame N -o out.png [--scale N] [--palette gray|green|<colors>]
+                  [--input-script <path>]
+Custom palettes are four comma separated hex colors from lightest to darkest";
+
+/// RGB colors of the palette indices, from lightest to darkest
+pub type Palette = [[u8; 3]; 4];
+
+pub const GRAY_PALETTE: Palette = [
+    [0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+    [0x00, 0x00, 0x00],
+];
+/// Colors of the original Game Boy screen
+const GREEN_PALETTE: Palette = [
+    [0x9B, 0xBC, 0x0F],
+    [0x8B, 0xAC, 0x0F],
+    [0x30, 0x62, 0x30],
+    [0x0F, 0x38, 0x0F],
+];
+
+/// Parses a palette name or four comma separated hex colors like `e0f8d0,88c070,346856,081820`
+pub fn parse_palette(arg: &str) -> Result<Palette, String> {
+    match arg {
+        "gray" => return Ok(GRAY_PALETTE),
+        "green" => return Ok(GREEN_PALETTE),
+        _ => {}
+    }
+    let colors: Vec<[u8; 3]> = arg
+        .split(',')
+        .map(|color| {
+            let color = color.strip_prefix('#').unwrap_or(color);
+            let rgb = u32::from_str_radix(color, 16)
+                .ok()
+                .filter(|_| color.len() == 6)
+                .ok_or_else(|| format!("Invalid color \"{color}\""))?;
+            let [_, r, g, b] = rgb.to_be_bytes();
+            Ok([r, g, b])
+        })
+        .collect::<Result<_, String>>()?;
+    colors
+        .try_into()
+        .map_err(|_| "Palettes need four colors".to_string())
+}
+
+/// Writes the display as a PNG, with each Game Boy pixel scaled to a square of given size.
+/// While the LCD is off, the display is blank white like on the real screen
+pub fn write_png(
+    path: &str,
+    display: &DisplayBuffer,
+    scale: u32,
+    palette: &Palette,
+) -> Result<(), Box<dyn error::Error>> {
+    let scale = scale.max(1) as usize;
+    let indices: Vec<u8> = display_color_indices(display).collect();
+    let mut pixels = Vec::with_capacity(160 * 144 * scale * scale * 4);
+    for row in indices.chunks(160) {
+        for _ in 0..scale {
+            for &index in row {
+                let [r, g, b] = palette[index as usize];
+                for _ in 0..scale {
+                    pixels.extend_from_slice(&[r, g, b, 0xFF]);
+                }
+            }
+        }
+    }
+    let (width, height) = (160 * scale as u32, 144 * scale as u32);
+    image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)?;
+    println!("Wrote the frame to {path}");
+    Ok(())
+}
+
+/// Runs a ROM to given frame and writes the display as a PNG
+pub fn run(args: &[String]) -> Result<(), Box<dyn error::Error + 'static>> {
+    let mut rom_path = None;
+    let mut frame = None;
+    let mut output = None;
+    let mut scale = 1;
+    let mut palette = GRAY_PALETTE;
+    let mut script = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--at-frame" => frame = Some(args.next().ok_or(USAGE)?.parse::<u32>()?),
+            "-o" => output = Some(args.next().ok_or(USAGE)?),
+            "--scale" => scale = args.next().ok_or(USAGE)?.parse::<u32>()?,
+            "--palette" => palette = parse_palette(args.next().ok_or(USAGE)?)?,
+            "--input-script" => script = Some(InputScript::load(args.next().ok_or(USAGE)?)?),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let (Some(rom_path), Some(frame), Some(output)) = (rom_path, frame, output) else {
+        return Err(USAGE.into());
+    };
+
+    let rom = fs::read(rom_path)?;
+    let mut cpu = CPU::new(rom.into()).map_err(|e| format!("Couldn't load ROM: {e:?}"))?;
+    cpu.set_audio_sample_rate(44100);
+    input_script::run_frames(&mut cpu, frame, script.as_ref());
+    write_png(output, cpu.get_display_buffer(), scale, &palette)
+}
@@ -0,0 +1,52 @@
+use gb_web_core::CPU;
+use std::{
+    error, fs,
+    time::{Duration, Instant},
+};
+
+const USAGE: &str = "Usage: test-serial <rom> [--timeout-seconds N] [--expect \"Passed\"]";
+/// Printed by test ROMs when any test fails
+const FAILURE_TEXT: &str = "Failed";
+
+/// Runs a test ROM that prints its results through the serial port, like the blargg tests,
+/// until the expected text or a failure is printed or the timeout elapses.
+/// Prints the output and returns if the test passed
+pub fn run(args: &[String]) -> Result<bool, Box<dyn error::Error + 'static>> {
+    let mut rom_path = None;
+    let mut timeout = Duration::from_secs(60);
+    let mut expected = "Passed".to_string();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--timeout-seconds" => {
+                let seconds = args.next().ok_or(USAGE)?.parse::<f32>()?;
+                timeout = Duration::from_secs_f32(seconds);
+            }
+            "--expect" => expected = args.next().ok_or(USAGE)?.clone(),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let rom = fs::read(rom_path.ok_or(USAGE)?)?;
+
+    let mut cpu = CPU::new(rom.into()).map_err(|e| format!("Couldn't load ROM: {e:?}"))?;
+    cpu.set_audio_sample_rate(44100);
+    let start = Instant::now();
+    let mut output = String::new();
+    let passed = loop {
+        cpu.run_frame();
+        output.extend(cpu.take_serial_output().into_iter().map(char::from));
+        if output.contains(&expected) {
+            break true;
+        }
+        if output.contains(FAILURE_TEXT) {
+            break false;
+        }
+        if start.elapsed() >= timeout {
+            println!("Timed out after {} seconds", timeout.as_secs_f32());
+            break false;
+        }
+    };
+    println!("{output}");
+    Ok(passed)
+}
@@ -0,0 +1,93 @@
+use crate::frame_test::fnv1a;
+use gb_web_core::CPU;
+use std::{error, fs, sync::Arc};
+
+/// Start of every save state file
+const MAGIC: &[u8; 4] = b"GBWS";
+/// Increased when the layout of the file changes
+const VERSION: u8 = 1;
+/// Magic, version and the 64-bit ROM hash
+const HEADER_SIZE: usize = MAGIC.len() + 1 + 8;
+
+/// Writes the CPU to a save state file. The file has a header identifying the ROM,
+/// followed by the CPU serialized with postcard like the save states of the web frontend
+pub fn save(path: &str, cpu: &CPU, rom: &[u8]) -> Result<(), Box<dyn error::Error>> {
+    let mut file = Vec::with_capacity(HEADER_SIZE);
+    file.extend_from_slice(MAGIC);
+    file.push(VERSION);
+    file.extend_from_slice(&fnv1a(rom.iter().copied()).to_le_bytes());
+    file.extend(postcard::to_stdvec(cpu)?);
+    fs::write(path, file)?;
+    Ok(())
+}
+
+/// Reads a CPU from a save state file, failing if the state was saved from another ROM
+pub fn load(path: &str, rom: &Arc<[u8]>) -> Result<CPU, Box<dyn error::Error>> {
+    let file = fs::read(path)?;
+    if file.len() < HEADER_SIZE || &file[..MAGIC.len()] != MAGIC {
+        return Err(format!("{path} isn't a save state").into());
+    }
+    let version = file[MAGIC.len()];
+    if version != VERSION {
+        return Err(format!("Unsupported save state version {version}").into());
+    }
+    let hash_bytes = file[MAGIC.len() + 1..HEADER_SIZE].try_into()?;
+    if u64::from_le_bytes(hash_bytes) != fnv1a(rom.iter().copied()) {
+        return Err("Save state was made with a different ROM".into());
+    }
+    let mut cpu = postcard::from_bytes::<CPU>(&file[HEADER_SIZE..])?;
+    // The ROM isn't kept in save states
+    cpu.set_rom(Arc::clone(rom));
+    cpu.set_audio_sample_rate(44100);
+    Ok(cpu)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{counter_rom, run_frames, temp_path};
+
+    /// Returns the state hash after each of given amount of frames
+    fn hash_trajectory(cpu: &mut CPU, frames: u32) -> Vec<u64> {
+        (0..frames)
+            .map(|_| {
+                run_frames(cpu, 1);
+                cpu.state_hash().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loaded_state_runs_like_the_saved_one() {
+        let path = temp_path("trajectory.state");
+        let path = path.to_str().unwrap();
+        let rom = counter_rom();
+        let mut cpu = CPU::new(Arc::clone(&rom)).unwrap();
+        cpu.set_audio_sample_rate(44100);
+        run_frames(&mut cpu, 10);
+        save(path, &cpu, &rom).unwrap();
+
+        let mut loaded = load(path, &rom).unwrap();
+        assert_eq!(loaded.state_hash().unwrap(), cpu.state_hash().unwrap());
+        assert_eq!(
+            hash_trajectory(&mut loaded, 30),
+            hash_trajectory(&mut cpu, 30)
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn state_of_another_rom_is_rejected() {
+        let path = temp_path("other_rom.state");
+        let path = path.to_str().unwrap();
+        let rom = counter_rom();
+        let cpu = CPU::new(Arc::clone(&rom)).unwrap();
+        save(path, &cpu, &rom).unwrap();
+
+        let mut other_rom = rom.to_vec();
+        other_rom[0x7FFF] = 1;
+        let error = load(path, &other_rom.into()).err().unwrap();
+        assert!(error.to_string().contains("different ROM"));
+        fs::remove_file(path).unwrap();
+    }
+}
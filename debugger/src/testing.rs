@@ -0,0 +1,41 @@
+//! ROM and files for the tests, which generate their ROM instead of shipping one
+
+use gb_web_core::CPU;
+use std::{env, path::PathBuf, process, sync::Arc};
+
+/// Builds a 32 KiB MBC3 ROM with a clock, 8 KiB of RAM and a battery,
+/// which counts up the first byte of RAM in a loop
+pub fn counter_rom() -> Arc<[u8]> {
+    let mut rom = vec![0; 0x8000];
+    // Entry point jumps over the header
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x134..0x13C].copy_from_slice(b"COUNTER ");
+    rom[0x147] = 0x10; // MBC3+TIMER+RAM+BATTERY
+    rom[0x149] = 0x02; // 8 KiB RAM
+    rom[0x150..0x15F].copy_from_slice(&[
+        0x3E, 0x0A, // LD A, $0A
+        0xEA, 0x00, 0x00, // LD ($0000), A (enable RAM and clock)
+        0x21, 0x00, 0xA0, // LD HL, $A000
+        0x34, // loop: INC (HL)
+        0x7E, // LD A, (HL)
+        0xEA, 0x00, 0xC0, // LD ($C000), A
+        0x18, 0xF9, // JR loop
+    ]);
+    rom[0x14D] = rom[0x134..0x14D].iter().fold(0u8, |checksum, byte| {
+        checksum.wrapping_sub(*byte).wrapping_sub(1)
+    });
+    rom.into()
+}
+
+pub fn run_frames(cpu: &mut CPU, frames: u32) {
+    for _ in 0..frames {
+        cpu.run_frame();
+    }
+}
+
+/// Returns a path in the temporary directory unique to this process, removing what's there
+pub fn temp_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("gb_web_debugger_{}_{name}", process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
@@ -0,0 +1,172 @@
+use crate::repl::parse_hex;
+use gb_web_core::CPU;
+use std::{
+    error,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+const USAGE: &str = "\
+Usage: trace <rom> --frames N -o trace.log [--format doctor|human]
+             [--start-frame N] [--start-pc <addr>]";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    /// Lines compared by Gameboy Doctor, like
+    /// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`
+    Doctor,
+    /// Lines with the disassembled instruction, like
+    /// `0150  3E 0A     LD A,$0A          AF=01B0 BC=0013 DE=00D8 HL=014D SP=FFFE`
+    Human,
+}
+
+/// State shared between the trace hook and the frame loop
+struct Tracer {
+    writer: BufWriter<File>,
+    format: Format,
+    /// Frame being run, counted from the start
+    frame: u32,
+    start_frame: u32,
+    /// Logging starts once the program counter first reaches this after the start frame
+    start_pc: Option<u16>,
+    started: bool,
+    /// First write error, after which nothing more is written
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    fn trace(&mut self, cpu: &CPU) {
+        let reg = cpu.get_registers();
+        if !self.started {
+            self.started =
+                self.frame >= self.start_frame && self.start_pc.is_none_or(|pc| pc == reg.pc);
+        }
+        if !self.started || self.error.is_some() {
+            return;
+        }
+        let result = match self.format {
+            Format::Doctor => {
+                let mem = [0, 1, 2, 3].map(|offset| cpu.peek(reg.pc.wrapping_add(offset)));
+                writeln!(
+                    self.writer,
+                    "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} \
+                     SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+                    reg.a,
+                    reg.f,
+                    reg.b,
+                    reg.c,
+                    reg.d,
+                    reg.e,
+                    reg.h,
+                    reg.l,
+                    reg.sp,
+                    reg.pc,
+                    mem[0],
+                    mem[1],
+                    mem[2],
+                    mem[3],
+                )
+            }
+            Format::Human => {
+                let instruction = cpu.disassemble(reg.pc);
+                let bytes: Vec<String> = (0..instruction.length)
+                    .map(|offset| format!("{:02X}", cpu.peek(reg.pc.wrapping_add(offset))))
+                    .collect();
+                writeln!(
+                    self.writer,
+                    "{:04X}  {:<8}  {:<16}  AF={:02X}{:02X} BC={:02X}{:02X} DE={:02X}{:02X} \
+                     HL={:02X}{:02X} SP={:04X}",
+                    reg.pc,
+                    bytes.join(" "),
+                    instruction.text,
+                    reg.a,
+                    reg.f,
+                    reg.b,
+                    reg.c,
+                    reg.d,
+                    reg.e,
+                    reg.h,
+                    reg.l,
+                    reg.sp,
+                )
+            }
+        };
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+}
+
+/// Runs a ROM for given amount of frames, logging the CPU state before every instruction.
+/// Ctrl-C stops early, keeping everything logged so far
+pub fn run(args: &[String]) -> Result<(), Box<dyn error::Error + 'static>> {
+    let mut rom_path = None;
+    let mut frames = None;
+    let mut output = None;
+    let mut format = Format::Doctor;
+    let mut start_frame = 0;
+    let mut start_pc = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => frames = Some(args.next().ok_or(USAGE)?.parse::<u32>()?),
+            "-o" => output = Some(args.next().ok_or(USAGE)?),
+            "--format" => {
+                format = match args.next().ok_or(USAGE)?.as_str() {
+                    "doctor" => Format::Doctor,
+                    "human" => Format::Human,
+                    _ => return Err(USAGE.into()),
+                }
+            }
+            "--start-frame" => start_frame = args.next().ok_or(USAGE)?.parse::<u32>()?,
+            "--start-pc" => start_pc = Some(parse_hex(args.next().map(String::as_str), "address")?),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let (Some(rom_path), Some(frames), Some(output)) = (rom_path, frames, output) else {
+        return Err(USAGE.into());
+    };
+
+    let rom = fs::read(rom_path)?;
+    let mut cpu = CPU::new(rom.into()).map_err(|e| format!("Couldn't load ROM: {e:?}"))?;
+    cpu.set_audio_sample_rate(44100);
+    let tracer = Arc::new(Mutex::new(Tracer {
+        writer: BufWriter::new(File::create(output)?),
+        format,
+        frame: 0,
+        start_frame,
+        start_pc,
+        started: false,
+        error: None,
+    }));
+    let hook_tracer = Arc::clone(&tracer);
+    cpu.set_trace_hook(Some(Box::new(move |cpu| {
+        hook_tracer.lock().unwrap().trace(cpu);
+    })));
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_interrupted = Arc::clone(&interrupted);
+    ctrlc::set_handler(move || handler_interrupted.store(true, Ordering::Relaxed))?;
+    for frame in 0..frames {
+        tracer.lock().unwrap().frame = frame;
+        cpu.run_frame();
+        if interrupted.load(Ordering::Relaxed) {
+            println!("Interrupted at frame {frame}");
+            break;
+        }
+    }
+    cpu.set_trace_hook(None);
+
+    let mut tracer = tracer.lock().unwrap();
+    if let Some(e) = tracer.error.take() {
+        return Err(format!("Couldn't write the trace: {e}").into());
+    }
+    tracer.writer.flush()?;
+    println!("Wrote the trace to {output}");
+    Ok(())
+}
@@ -0,0 +1,68 @@
+//! Checks that memory mapped saves survive the debugger being killed, by running it on
+//! a generated ROM that writes to cartridge RAM and killing it right after the write
+
+use std::{
+    env, fs,
+    io::{BufRead, BufReader},
+    process::{self, Command, Stdio},
+};
+
+/// Builds a 32 KiB MBC3 ROM with a clock, 8 KiB of RAM and a battery,
+/// which writes 16 down to 1 to the start of RAM and then loops
+fn pattern_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    // Entry point jumps over the header
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x134..0x13C].copy_from_slice(b"MMAPSAVE");
+    rom[0x147] = 0x10; // MBC3+TIMER+RAM+BATTERY
+    rom[0x149] = 0x02; // 8 KiB RAM
+    rom[0x150..0x161].copy_from_slice(&[
+        0x3E, 0x0A, // LD A, $0A
+        0xEA, 0x00, 0x00, // LD ($0000), A (enable RAM and clock)
+        0x21, 0x00, 0xA0, // LD HL, $A000
+        0x06, 0x10, // LD B, 16
+        0x78, // loop: LD A, B
+        0x22, // LD (HL+), A
+        0x05, // DEC B
+        0x20, 0xFB, // JR NZ, loop
+        0x18, 0xFE, // JR -2
+    ]);
+    rom[0x14D] = rom[0x134..0x14D].iter().fold(0u8, |checksum, byte| {
+        checksum.wrapping_sub(*byte).wrapping_sub(1)
+    });
+    rom
+}
+
+#[test]
+fn killed_debugger_leaves_written_ram_in_save() {
+    let dir = env::temp_dir().join(format!("gb_web_debugger_mmap_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("pattern.gb"), pattern_rom()).unwrap();
+    // The debugger requires a .env file, though the variables are also set directly
+    fs::write(dir.join(".env"), "").unwrap();
+
+    let mut debugger = Command::new(env!("CARGO_BIN_EXE_gb_web_debugger"))
+        .arg("--mmap-save")
+        .current_dir(&dir)
+        .env("EXECUTION_TIME", "100")
+        .env("ROM_PATH", "pattern.gb")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The first 100 ms of emulation have run once the debugger reports them,
+    // long after the writes, and the debugger is killed without a chance to save
+    let stdout = BufReader::new(debugger.stdout.take().unwrap());
+    let ran = stdout
+        .lines()
+        .any(|line| line.unwrap().starts_with("Ran CPU"));
+    debugger.kill().unwrap();
+    debugger.wait().unwrap();
+    assert!(ran, "Debugger exited before running");
+
+    let save = fs::read(dir.join("pattern.sav")).unwrap();
+    assert_eq!(save.len(), 0x2000 + 48);
+    let expected: Vec<u8> = (1..=16).rev().collect();
+    assert_eq!(save[..16], expected);
+    assert!(save[16..0x2000].iter().all(|&byte| byte == 0));
+    fs::remove_dir_all(dir).unwrap();
+}
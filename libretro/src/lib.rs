@@ -0,0 +1,434 @@
+//! Libretro core for running the emulator in RetroArch and other libretro frontends.
+//!
+//! The frontend calls the `retro_*` functions from one thread. Callbacks it sets
+//! and the loaded game are kept in globals, as the API has no handles to keep them in.
+//! Video is sent as XRGB8888 colored with a fixed palette, audio as 16-bit stereo
+//! drained from the core's audio ring buffer after every frame
+
+mod sys;
+
+use gb_web_core::{AudioBufferConsumer, CPU, InputFlag, display_color_indices, unix_time_secs};
+use ringbuf::traits::Consumer;
+use std::{
+    ffi::{c_char, c_uint, c_void},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use sys::*;
+
+const SCREEN_WIDTH: usize = 160;
+const SCREEN_HEIGHT: usize = 144;
+/// Frames per second, the clock speed divided by the T-cycles of a frame
+const FPS: f64 = 4194304.0 / 70224.0;
+const SAMPLE_RATE: u32 = 44100;
+/// Stereo samples the audio buffer holds. A frame needs less than 800, but with the LCD off
+/// `run_frame` runs for two frames, and a margin keeps samples from being dropped
+const AUDIO_BUFFER_FRAMES: usize = 4096;
+/// Room left in save states on top of the size when the game was loaded, as the serialized size
+/// varies slightly with the values in the state and frontends expect a fixed size
+const STATE_SIZE_MARGIN: usize = 1024;
+/// XRGB8888 colors of the palette indices, from lightest to darkest
+const PALETTE: [u32; 4] = [0xE0F8D0, 0x88C070, 0x346856, 0x081820];
+/// Libretro joypad buttons and the Game Boy buttons they press
+const JOYPAD_BUTTONS: [(c_uint, InputFlag); 8] = [
+    (RETRO_DEVICE_ID_JOYPAD_A, InputFlag::A),
+    (RETRO_DEVICE_ID_JOYPAD_B, InputFlag::B),
+    (RETRO_DEVICE_ID_JOYPAD_SELECT, InputFlag::SELECT),
+    (RETRO_DEVICE_ID_JOYPAD_START, InputFlag::START),
+    (RETRO_DEVICE_ID_JOYPAD_UP, InputFlag::UP),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, InputFlag::DOWN),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, InputFlag::LEFT),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, InputFlag::RIGHT),
+];
+
+/// Functions given by the frontend
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<RetroEnvironment>,
+    video_refresh: Option<RetroVideoRefresh>,
+    audio_sample_batch: Option<RetroAudioSampleBatch>,
+    input_poll: Option<RetroInputPoll>,
+    input_state: Option<RetroInputState>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+/// Locks a global, ignoring panics while it was locked as they're caught at the boundary
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Runs given function, returning the fallback if it panics
+fn catch_panic<T>(fallback: T, function: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(function)).unwrap_or(fallback)
+}
+
+/// Loaded game and the buffers for passing its output to the frontend
+struct Core {
+    cpu: CPU,
+    /// Kept for resetting and restoring save states, which don't contain the ROM
+    rom: Arc<[u8]>,
+    audio: AudioBufferConsumer,
+    samples: Vec<f32>,
+    audio_out: Vec<i16>,
+    pixels: Vec<u32>,
+    /// Copy of cartridge RAM handed to the frontend, which reads saves from it
+    /// and writes loaded saves to it. Cartridges with a clock have the RTC footer after RAM.
+    /// Its length never changes, so the pointer stays valid
+    sram: Vec<u8>,
+    /// Set until the first frame, as the frontend loads the save after the game
+    sram_pending: bool,
+    state: Vec<u8>,
+    state_size: usize,
+}
+
+impl Core {
+    fn new(rom: Arc<[u8]>) -> Result<Self, String> {
+        let mut cpu = CPU::new(Arc::clone(&rom)).map_err(|e| format!("{e:?}"))?;
+        let audio = init_audio(&mut cpu);
+        let mut state = Vec::new();
+        cpu.save_state_into(&mut state)
+            .map_err(|e| format!("Couldn't serialize state: {e}"))?;
+        Ok(Self {
+            sram: cpu.get_ram_with_rtc(unix_time_secs()),
+            cpu,
+            rom,
+            audio,
+            samples: vec![0.0; AUDIO_BUFFER_FRAMES * 2],
+            audio_out: Vec::with_capacity(AUDIO_BUFFER_FRAMES * 2),
+            pixels: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            sram_pending: true,
+            state_size: state.len() + STATE_SIZE_MARGIN,
+            state,
+        })
+    }
+
+    /// Replaces the CPU, keeping the buffers handed to the frontend
+    fn replace_cpu(&mut self, mut cpu: CPU) {
+        self.audio = init_audio(&mut cpu);
+        self.cpu = cpu;
+        self.sync_sram();
+    }
+
+    /// Copies cartridge RAM and clock to the frontend's copy
+    fn sync_sram(&mut self) {
+        let ram = self.cpu.get_ram_with_rtc(unix_time_secs());
+        if ram.len() == self.sram.len() {
+            self.sram.copy_from_slice(&ram);
+        }
+    }
+
+    fn run(&mut self, callbacks: &Callbacks) {
+        if std::mem::take(&mut self.sram_pending) && !self.sram.is_empty() {
+            let sram = self.sram.clone();
+            let _ = self.cpu.set_ram_with_rtc(sram, unix_time_secs());
+        }
+
+        let mut input = InputFlag::empty();
+        if let Some(input_poll) = callbacks.input_poll {
+            unsafe { input_poll() };
+        }
+        if let Some(input_state) = callbacks.input_state {
+            for (id, flag) in JOYPAD_BUTTONS {
+                if unsafe { input_state(0, RETRO_DEVICE_JOYPAD, 0, id) } != 0 {
+                    input |= flag;
+                }
+            }
+        }
+        self.cpu.update_input(&input);
+        self.cpu.run_frame();
+        // The clock changes without writes to RAM, so it's copied on every frame
+        if self.cpu.take_ram_dirty() || self.cpu.get_cartridge_info().has_rtc {
+            self.sync_sram();
+        }
+
+        for (pixel, index) in self
+            .pixels
+            .iter_mut()
+            .zip(display_color_indices(self.cpu.get_display_buffer()))
+        {
+            *pixel = PALETTE[index as usize];
+        }
+        if let Some(video_refresh) = callbacks.video_refresh {
+            unsafe {
+                video_refresh(
+                    self.pixels.as_ptr().cast(),
+                    SCREEN_WIDTH as c_uint,
+                    SCREEN_HEIGHT as c_uint,
+                    SCREEN_WIDTH * size_of::<u32>(),
+                )
+            };
+        }
+
+        let count = self.audio.pop_slice(&mut self.samples);
+        self.audio_out.clear();
+        self.audio_out.extend(
+            self.samples[..count]
+                .iter()
+                .map(|sample| (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16),
+        );
+        if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+            let mut frames = self.audio_out.chunks_exact(2).len();
+            let mut written = 0;
+            while frames > 0 {
+                let data = self.audio_out[written * 2..].as_ptr();
+                let accepted = unsafe { audio_sample_batch(data, frames) }.min(frames);
+                if accepted == 0 {
+                    break;
+                }
+                written += accepted;
+                frames -= accepted;
+            }
+        }
+    }
+}
+
+/// Sets up stereo audio at the sample rate given to the frontend, returning the buffer's consumer
+fn init_audio(cpu: &mut CPU) -> AudioBufferConsumer {
+    cpu.set_audio_sample_rate(SAMPLE_RATE);
+    cpu.init_audio_buffer(AUDIO_BUFFER_FRAMES * 2, 2)
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_environment(callback: RetroEnvironment) {
+    lock(&CALLBACKS).environment = Some(callback);
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_video_refresh(callback: RetroVideoRefresh) {
+    lock(&CALLBACKS).video_refresh = Some(callback);
+}
+
+/// Samples are only sent in batches
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_audio_sample(_callback: RetroAudioSample) {}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_audio_sample_batch(callback: RetroAudioSampleBatch) {
+    lock(&CALLBACKS).audio_sample_batch = Some(callback);
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_input_poll(callback: RetroInputPoll) {
+    lock(&CALLBACKS).input_poll = Some(callback);
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_input_state(callback: RetroInputState) {
+    lock(&CALLBACKS).input_state = Some(callback);
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_init() {}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_deinit() {
+    *lock(&CORE) = None;
+}
+
+/// # Safety
+/// `info` has to point to a writable `retro_system_info`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    let version = concat!(env!("CARGO_PKG_VERSION"), "\0");
+    unsafe {
+        info.write(RetroSystemInfo {
+            library_name: c"gb-web".as_ptr(),
+            library_version: version.as_ptr().cast::<c_char>(),
+            valid_extensions: c"gb|gbc".as_ptr(),
+            need_fullpath: false,
+            block_extract: false,
+        })
+    };
+}
+
+/// # Safety
+/// `info` has to point to a writable `retro_system_av_info`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    unsafe {
+        info.write(RetroSystemAvInfo {
+            geometry: RetroGameGeometry {
+                base_width: SCREEN_WIDTH as c_uint,
+                base_height: SCREEN_HEIGHT as c_uint,
+                max_width: SCREEN_WIDTH as c_uint,
+                max_height: SCREEN_HEIGHT as c_uint,
+                aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
+            },
+            timing: RetroSystemTiming {
+                fps: FPS,
+                sample_rate: f64::from(SAMPLE_RATE),
+            },
+        })
+    };
+}
+
+/// Only the joypad is supported
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+/// Restarts the game, keeping cartridge RAM
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_reset() {
+    catch_panic((), || {
+        if let Some(core) = lock(&CORE).as_mut()
+            && let Ok(mut cpu) = CPU::new(Arc::clone(&core.rom))
+        {
+            let _ = cpu.set_ram(core.cpu.get_ram());
+            core.replace_cpu(cpu);
+        }
+    });
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_run() {
+    let callbacks = *lock(&CALLBACKS);
+    catch_panic((), || {
+        if let Some(core) = lock(&CORE).as_mut() {
+            core.run(&callbacks);
+        }
+    });
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_serialize_size() -> usize {
+    lock(&CORE).as_ref().map_or(0, |core| core.state_size)
+}
+
+/// Writes a save state padded with zeros to the size from `retro_serialize_size`
+///
+/// # Safety
+/// `data` has to point to `size` writable bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    catch_panic(false, || {
+        let mut core = lock(&CORE);
+        let Some(core) = core.as_mut() else {
+            return false;
+        };
+        core.state.clear();
+        if core.cpu.save_state_into(&mut core.state).is_err() || core.state.len() > size {
+            return false;
+        }
+        let out = unsafe { slice::from_raw_parts_mut(data.cast::<u8>(), size) };
+        let (state, padding) = out.split_at_mut(core.state.len());
+        state.copy_from_slice(&core.state);
+        padding.fill(0);
+        true
+    })
+}
+
+/// Restores a save state written by `retro_serialize`, the padding after it is ignored
+///
+/// # Safety
+/// `data` has to point to `size` readable bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    catch_panic(false, || {
+        let mut core = lock(&CORE);
+        let Some(core) = core.as_mut() else {
+            return false;
+        };
+        let data = unsafe { slice::from_raw_parts(data.cast::<u8>(), size) };
+        match postcard::from_bytes::<CPU>(data) {
+            Ok(mut cpu) => {
+                cpu.set_rom(Arc::clone(&core.rom));
+                core.replace_cpu(cpu);
+                true
+            }
+            Err(_) => false,
+        }
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// Loads the ROM given as data, as `need_fullpath` is false
+///
+/// # Safety
+/// `game` has to be null or point to a valid `retro_game_info`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    let Some(game) = (unsafe { game.as_ref() }) else {
+        return false;
+    };
+    if game.data.is_null() {
+        return false;
+    }
+    let Some(environment) = lock(&CALLBACKS).environment else {
+        return false;
+    };
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    let format_set = unsafe {
+        environment(
+            RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+            ptr::from_mut(&mut format).cast(),
+        )
+    };
+    if !format_set {
+        return false;
+    }
+    let rom: Arc<[u8]> = unsafe { slice::from_raw_parts(game.data.cast::<u8>(), game.size) }.into();
+    catch_panic(false, || match Core::new(rom) {
+        Ok(core) => {
+            *lock(&CORE) = Some(core);
+            true
+        }
+        Err(_) => false,
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const RetroGameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_unload_game() {
+    *lock(&CORE) = None;
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+/// Returns cartridge RAM for saving and loading battery saves
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    match lock(&CORE).as_mut() {
+        Some(core) if id == RETRO_MEMORY_SAVE_RAM && !core.sram.is_empty() => {
+            core.sram.as_mut_ptr().cast()
+        }
+        _ => ptr::null_mut(),
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    match lock(&CORE).as_ref() {
+        Some(core) if id == RETRO_MEMORY_SAVE_RAM => core.sram.len(),
+        _ => 0,
+    }
+}
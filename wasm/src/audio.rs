@@ -1,52 +1,235 @@
+use crate::{AudioCapture, BridgeError, ErrorCode};
 use cpal::{
     FromSample, SizedSample, Stream,
     traits::{DeviceTrait, HostTrait, StreamTrait},
 };
 use gb_web_core::AudioBufferConsumer;
-use ringbuf::traits::Consumer;
-use std::sync::{Arc, RwLock};
+use ringbuf::traits::{Consumer, Observer};
+use std::sync::{
+    Arc, Mutex, RwLock,
+    atomic::{AtomicBool, AtomicU32, Ordering},
+};
 
 pub struct AudioHandler {
+    /// Linear gain applied to samples, set from the volume option with `volume_gain`
     pub volume: Arc<RwLock<f32>>,
     pub paused: Arc<RwLock<bool>>,
+    /// Plays silence while set, without pausing or touching the volume,
+    /// so the buffer keeps draining and sound returns as it was when cleared
+    muted: Arc<AtomicBool>,
+    /// Amount of times the stream has run out of samples since last reset
+    pub underruns: Arc<AtomicU32>,
+    /// Fill of the buffer after the stream last took samples, as the bits of an f32
+    buffer_fill: Arc<AtomicU32>,
+    /// Copy of the played samples, while audio is being captured
+    capture: Arc<Mutex<Option<AudioCapture>>>,
+    /// Set when the stream reports an error, like its device being removed.
+    /// The stream has to be rebuilt to recover
+    stream_failed: Arc<AtomicBool>,
     pub sample_rate: u32,
     pub channels: usize,
     pub sample_capacity: usize,
+    /// Length of audio the buffer fits. Longer buffers survive slow frames without crackling,
+    /// but delay the sound by up to as long
+    latency_ms: u32,
     sample_format: cpal::SampleFormat,
+    /// Buffer sizes the device supports, if it reports them
+    buffer_size_range: cpal::SupportedBufferSize,
+    /// Frames the device takes at a time, if a fixed size could be requested
+    device_buffer_frames: Option<u32>,
     device: cpal::Device,
     config: cpal::StreamConfig,
     stream: Option<Stream>,
 }
 
 impl AudioHandler {
-    const BUFFER_CAPACITY_MS: f32 = 100.0;
+    /// Change in decibels for each 1.0 of the volume option
+    const VOLUME_RANGE_DB: f32 = 30.0;
+    /// Level above which samples are compressed by the soft clipper
+    const CLIP_THRESHOLD: f32 = 0.8;
+
+    pub const DEFAULT_LATENCY_MS: u32 = 100;
+    pub const MIN_LATENCY_MS: u32 = 30;
+    pub const MAX_LATENCY_MS: u32 = 200;
 
     pub fn new() -> Self {
-        let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .expect("No default output device found");
-        let config = device.default_output_config().unwrap();
+        let (device, config) = Self::default_output().expect("No default output device found");
 
         let sample_rate = config.sample_rate().0;
         let channels = config.channels() as usize;
-        let sample_capacity =
-            (((Self::BUFFER_CAPACITY_MS / 1000.0) * sample_rate as f32) as usize) * channels;
         let sample_format = config.sample_format();
+        let latency_ms = Self::DEFAULT_LATENCY_MS;
 
         Self {
             volume: Arc::new(RwLock::new(1.0)),
             paused: Arc::new(RwLock::new(true)),
+            muted: Arc::new(AtomicBool::new(false)),
+            underruns: Arc::new(AtomicU32::new(0)),
+            buffer_fill: Arc::new(AtomicU32::new(0)),
+            capture: Arc::new(Mutex::new(None)),
+            stream_failed: Arc::new(AtomicBool::new(false)),
             sample_rate,
             channels,
-            sample_capacity,
+            sample_capacity: Self::sample_capacity(latency_ms, sample_rate, channels),
+            latency_ms,
             sample_format,
+            buffer_size_range: *config.buffer_size(),
+            device_buffer_frames: None,
             device,
             config: config.into(),
             stream: None,
         }
     }
 
+    /// Returns the current default output device and its preferred config
+    fn default_output() -> Result<(cpal::Device, cpal::SupportedStreamConfig), BridgeError> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| {
+                BridgeError::new(ErrorCode::AudioUnavailable, "No audio output device found")
+            })?;
+        let config = device.default_output_config().map_err(|e| {
+            BridgeError::new(
+                ErrorCode::AudioUnavailable,
+                "Audio output device can't be used",
+            )
+            .with_details(e)
+        })?;
+        Ok((device, config))
+    }
+
+    /// Converts the volume option to a linear gain. The option is on a decibel scale,
+    /// with 1.0 for full level, each 0.1 from it changing the level by 3 dB and 0.0 muting
+    pub fn volume_gain(volume: f32) -> f32 {
+        if volume <= 0.0 {
+            return 0.0;
+        }
+        let decibels = (volume - 1.0) * Self::VOLUME_RANGE_DB;
+        10f32.powf(decibels / 20.0)
+    }
+
+    /// Compresses samples above the threshold smoothly towards 1.0,
+    /// so amplified peaks don't wrap or clip harshly
+    fn soft_clip(sample: f32) -> f32 {
+        let level = sample.abs();
+        if level <= Self::CLIP_THRESHOLD {
+            return sample;
+        }
+        let headroom = 1.0 - Self::CLIP_THRESHOLD;
+        let compressed =
+            Self::CLIP_THRESHOLD + headroom * ((level - Self::CLIP_THRESHOLD) / headroom).tanh();
+        compressed.copysign(sample)
+    }
+
+    /// Samples fitting in a buffer of given length, for all channels
+    fn sample_capacity(latency_ms: u32, sample_rate: u32, channels: usize) -> usize {
+        (((latency_ms as f32 / 1000.0) * sample_rate as f32) as usize) * channels
+    }
+
+    /// Sets the length of audio the buffer fits, with 0 using the default.
+    /// Returns if it changed, in which case playback has to be initialized again
+    /// with a buffer of the new capacity
+    pub fn set_latency(&mut self, latency_ms: u32) -> bool {
+        let latency_ms = if latency_ms == 0 {
+            Self::DEFAULT_LATENCY_MS
+        } else {
+            latency_ms.clamp(Self::MIN_LATENCY_MS, Self::MAX_LATENCY_MS)
+        };
+        if latency_ms == self.latency_ms {
+            return false;
+        }
+        self.latency_ms = latency_ms;
+        self.sample_capacity = Self::sample_capacity(latency_ms, self.sample_rate, self.channels);
+        self.stream = None;
+        true
+    }
+
+    /// Returns the length of audio the device takes at a time in milliseconds,
+    /// or 0.0 if the device chooses it itself
+    pub fn device_buffer_ms(&self) -> f32 {
+        self.device_buffer_frames.map_or(0.0, |frames| {
+            frames as f32 * 1000.0 / self.sample_rate as f32
+        })
+    }
+
+    /// Stream config requesting the device to take a quarter of the buffer at a time,
+    /// so the buffer keeps room for the emulator to refill it in between
+    fn stream_config(&self) -> cpal::StreamConfig {
+        let mut config = self.config.clone();
+        config.buffer_size = match self.buffer_size_range {
+            cpal::SupportedBufferSize::Range { min, max } => {
+                let frames = (self.sample_capacity / self.channels / 4) as u32;
+                cpal::BufferSize::Fixed(frames.clamp(min, max))
+            }
+            cpal::SupportedBufferSize::Unknown => cpal::BufferSize::Default,
+        };
+        config
+    }
+
+    /// Returns if the stream has reported an error since it was built
+    pub fn has_failed(&self) -> bool {
+        self.stream_failed.load(Ordering::Relaxed)
+    }
+
+    /// Stops the stream and switches to the current default output device.
+    /// Playback has to be initialized again with a buffer matching the new sample rate and
+    /// channels. If no device is available, the old one is kept
+    pub fn reset_device(&mut self) -> Result<(), BridgeError> {
+        let (device, config) = Self::default_output()?;
+        self.stream = None;
+        self.stream_failed.store(false, Ordering::Relaxed);
+        self.sample_rate = config.sample_rate().0;
+        self.channels = config.channels() as usize;
+        self.sample_capacity =
+            Self::sample_capacity(self.latency_ms, self.sample_rate, self.channels);
+        self.sample_format = config.sample_format();
+        self.buffer_size_range = *config.buffer_size();
+        self.device = device;
+        self.config = config.into();
+        Ok(())
+    }
+
+    /// Stops playback and drops the stream, releasing the audio device.
+    /// Playback has to be initialized again to play anything
+    pub fn stop(&mut self) {
+        *self.paused.write().unwrap() = true;
+        if let Some(stream) = self.stream.take() {
+            // Dropping closes the stream, pausing first stops it right away
+            let _ = stream.pause();
+        }
+    }
+
+    /// Returns if the stream is currently consuming samples from the buffer
+    pub fn is_playing(&self) -> bool {
+        self.stream.is_some() && !*self.paused.read().unwrap()
+    }
+
+    /// Returns how full the buffer was when the stream last took samples, between 0.0 and 1.0.
+    /// Unlike the fill seen by the CPU, this follows the clock of the audio device
+    pub fn buffer_fill(&self) -> f32 {
+        f32::from_bits(self.buffer_fill.load(Ordering::Relaxed))
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Starts capturing the played samples, discarding any previous capture
+    pub fn start_capture(&self, max_seconds: f32) {
+        let capture = AudioCapture::new(self.sample_rate, self.channels, max_seconds);
+        *self.capture.lock().unwrap() = Some(capture);
+    }
+
+    /// Stops capturing and returns the capture, if one was started
+    pub fn stop_capture(&self) -> Option<AudioCapture> {
+        self.capture.lock().unwrap().take()
+    }
+
+    /// Returns the amount of underruns since last call and resets the counter
+    pub fn take_underruns(&self) -> u32 {
+        self.underruns.swap(0, Ordering::Relaxed)
+    }
+
     pub fn init_playback(&mut self, consumer: AudioBufferConsumer) {
         match self.sample_format {
             cpal::SampleFormat::F32 => self.init_stream::<f32>(consumer),
@@ -54,6 +237,8 @@ impl AudioHandler {
             cpal::SampleFormat::U16 => self.init_stream::<u16>(consumer),
             _ => panic!("Unsupported sample format!"),
         };
+        self.underruns.store(0, Ordering::Relaxed);
+        self.buffer_fill.store(0, Ordering::Relaxed);
         *self.paused.write().unwrap() = false;
     }
 
@@ -61,44 +246,77 @@ impl AudioHandler {
     where
         T: SizedSample + FromSample<f32>,
     {
-        let err_fn = |err| log::error!("Audio stream error: {}", err);
+        let stream_failed_ref = Arc::clone(&self.stream_failed);
+        let err_fn = move |err| {
+            log::error!("Audio stream error: {}", err);
+            stream_failed_ref.store(true, Ordering::Relaxed);
+        };
 
         let volume_ref = Arc::clone(&self.volume);
         let paused_ref = Arc::clone(&self.paused);
+        let muted_ref = Arc::clone(&self.muted);
+        let underruns_ref = Arc::clone(&self.underruns);
+        let buffer_fill_ref = Arc::clone(&self.buffer_fill);
+        let capture_ref = Arc::clone(&self.capture);
+        let (sample_rate, channels) = (self.sample_rate, self.channels);
         let mut last_sample = 0.0;
+        let config = self.stream_config();
         let stream = self
             .device
             .build_output_stream(
-                &self.config,
+                &config,
                 move |data: &mut [T], _| {
                     if *paused_ref.read().unwrap() {
                         data.fill(T::from_sample::<f32>(0.0));
                         return;
                     }
-                    let volume = volume_ref.read().unwrap();
+                    let volume = if muted_ref.load(Ordering::Relaxed) {
+                        0.0
+                    } else {
+                        *volume_ref.read().unwrap()
+                    };
+                    let mut capture = capture_ref.lock().unwrap();
+                    // Captures started for another device are left as they are
+                    let mut capture = capture
+                        .as_mut()
+                        .filter(|capture| capture.matches(sample_rate, channels));
                     let mut late = false;
                     for sample in data.iter_mut() {
-                        *sample = match consumer.try_pop() {
-                            Some(s) => {
-                                let new_sample = s * 0.01 * *volume;
-                                last_sample = new_sample;
-                                T::from_sample::<f32>(new_sample)
-                            }
-                            None => {
-                                late = true;
-                                T::from_sample::<f32>(last_sample)
-                            }
-                        };
+                        match consumer.try_pop() {
+                            Some(s) => last_sample = Self::soft_clip(s * volume),
+                            None => late = true,
+                        }
+                        if let Some(capture) = &mut capture {
+                            capture.push(last_sample);
+                        }
+                        *sample = T::from_sample::<f32>(last_sample);
                     }
                     if late {
-                        log::warn!("Audio producer is late");
+                        underruns_ref.fetch_add(1, Ordering::Relaxed);
                     }
+                    let fill = consumer.occupied_len() as f32 / consumer.capacity().get() as f32;
+                    buffer_fill_ref.store(fill.to_bits(), Ordering::Relaxed);
                 },
                 err_fn,
                 None,
             )
-            .unwrap();
-        stream.play().unwrap();
-        self.stream = Some(stream);
+            .map_err(|e| e.to_string())
+            .and_then(|stream| {
+                stream.play().map_err(|e| e.to_string())?;
+                Ok(stream)
+            });
+        self.device_buffer_frames = match config.buffer_size {
+            cpal::BufferSize::Fixed(frames) => Some(frames),
+            cpal::BufferSize::Default => None,
+        };
+        match stream {
+            Ok(stream) => self.stream = Some(stream),
+            // The device may have gone away, so this is retried like stream errors
+            Err(e) => {
+                log::error!("Couldn't start audio stream: {}", e);
+                self.stream_failed.store(true, Ordering::Relaxed);
+                self.stream = None;
+            }
+        }
     }
 }
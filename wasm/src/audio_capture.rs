@@ -0,0 +1,84 @@
+/// Audio samples as they are played to the output device, encoded into a WAV file when stopped.
+///
+/// Samples are taken after the volume is applied, so the capture sounds like the playback.
+/// Nothing is captured while playback is paused, and capturing stops once the length limit
+/// is reached
+#[derive(Debug)]
+pub struct AudioCapture {
+    /// Interleaved samples of all channels
+    samples: Vec<i16>,
+    sample_rate: u32,
+    channels: u16,
+    /// Length limit in samples of all channels
+    max_samples: usize,
+}
+
+impl AudioCapture {
+    /// Longest capture that can be requested
+    pub const MAX_SECONDS: f32 = 300.0;
+
+    /// Starts capturing audio played at given sample rate and channels
+    pub fn new(sample_rate: u32, channels: usize, max_seconds: f32) -> Self {
+        let max_seconds = max_seconds.clamp(0.0, Self::MAX_SECONDS);
+        let max_frames = (max_seconds * sample_rate as f32).ceil() as usize;
+        Self {
+            samples: Vec::new(),
+            sample_rate,
+            channels: channels as u16,
+            max_samples: max_frames * channels,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.samples.len() >= self.max_samples
+    }
+
+    /// Returns if the capture was started for given sample rate and channels,
+    /// as samples of another output device can't be added to it
+    pub fn matches(&self, sample_rate: u32, channels: usize) -> bool {
+        self.sample_rate == sample_rate && usize::from(self.channels) == channels
+    }
+
+    /// Adds a played sample between -1.0 and 1.0
+    pub fn push(&mut self, sample: f32) {
+        if self.is_full() {
+            return;
+        }
+        self.samples
+            .push((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16);
+        if self.is_full() {
+            log::info!("Audio capture reached its length limit");
+        }
+    }
+
+    /// Encodes the captured samples into a 16-bit PCM WAV file
+    pub fn encode_wav(&self) -> Vec<u8> {
+        const BYTES_PER_SAMPLE: u16 = 2;
+        let data_size = (self.samples.len() * BYTES_PER_SAMPLE as usize) as u32;
+        let block_align = self.channels * BYTES_PER_SAMPLE;
+        let byte_rate = self.sample_rate * block_align as u32;
+
+        let mut wav = Vec::with_capacity(44 + data_size as usize);
+        wav.extend_from_slice(b"RIFF");
+        // Size of the rest of the file after this field
+        wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+        wav.extend_from_slice(b"WAVE");
+
+        wav.extend_from_slice(b"fmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        // Uncompressed PCM
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&self.channels.to_le_bytes());
+        wav.extend_from_slice(&self.sample_rate.to_le_bytes());
+        wav.extend_from_slice(&byte_rate.to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&(BYTES_PER_SAMPLE * 8).to_le_bytes());
+
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_size.to_le_bytes());
+        for sample in &self.samples {
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        wav
+    }
+}
@@ -0,0 +1,26 @@
+//! Desktop frontend running the same App as the web build in a normal window.
+//!
+//! Usage: `cargo run --release --features native --bin gb_web_native -- <rom>`
+//! ROMs ending in `.zip` are decompressed like uploads on the page.
+//! Skins are read from `app/public`, or from the directory in `GB_WEB_ASSETS`
+
+use std::{env, fs, process};
+
+fn main() {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("Usage: gb_web_native <rom>");
+        process::exit(2);
+    };
+    let rom = match fs::read(&path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Couldn't read {path}: {e}");
+            process::exit(1);
+        }
+    };
+    let is_zip = path.to_lowercase().ends_with(".zip");
+    if let Err(e) = gb_web_wasm::run_native(rom, is_zip) {
+        eprintln!("{e}");
+        process::exit(1);
+    }
+}
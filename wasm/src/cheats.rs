@@ -0,0 +1,204 @@
+use gb_web_core::{Cheat, CheatKind};
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Format of a cheat code as named by the frontend
+#[derive(Tsify, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheatCodeKind {
+    GameGenie,
+    GameShark,
+}
+
+impl From<CheatCodeKind> for CheatKind {
+    fn from(value: CheatCodeKind) -> Self {
+        match value {
+            CheatCodeKind::GameGenie => CheatKind::GameGenie,
+            CheatCodeKind::GameShark => CheatKind::GameShark,
+        }
+    }
+}
+
+/// Info about an added cheat sent to the frontend
+#[derive(Tsify, Debug, Clone, Serialize)]
+pub struct CheatInfo {
+    pub id: u32,
+    pub kind: CheatCodeKind,
+    /// The code as it was given
+    pub code: String,
+    pub enabled: bool,
+}
+
+#[derive(Debug)]
+struct CheatEntry {
+    info: CheatInfo,
+    cheat: Cheat,
+}
+
+/// Cheats added for the current ROM, applied to every CPU created or restored.
+///
+/// Cheats aren't part of save states, so they stay active when a state is loaded
+/// or the emulator is reset. Ids aren't reused, so a stale id can't refer to another cheat
+#[derive(Debug, Default)]
+pub struct CheatList {
+    entries: Vec<CheatEntry>,
+    next_id: u32,
+}
+
+impl CheatList {
+    /// Adds an enabled cheat and returns its id
+    pub fn add(&mut self, kind: CheatCodeKind, code: String, cheat: Cheat) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.push(CheatEntry {
+            info: CheatInfo {
+                id,
+                kind,
+                code,
+                enabled: true,
+            },
+            cheat,
+        });
+        id
+    }
+
+    /// Removes the cheat with given id, returns false if there is none
+    pub fn remove(&mut self, id: u32) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.info.id != id);
+        self.entries.len() != len
+    }
+
+    /// Enables or disables the cheat with given id, returns false if there is none
+    pub fn set_enabled(&mut self, id: u32, enabled: bool) -> bool {
+        match self.entries.iter_mut().find(|entry| entry.info.id == id) {
+            Some(entry) => {
+                entry.info.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn list(&self) -> Vec<CheatInfo> {
+        self.entries
+            .iter()
+            .map(|entry| entry.info.clone())
+            .collect()
+    }
+
+    /// Returns the enabled cheats in the order they were added
+    pub fn active(&self) -> Vec<Cheat> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.info.enabled)
+            .map(|entry| entry.cheat)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BridgeError, BridgeQuery, ErrorCode};
+    use CheatCodeKind::{GameGenie, GameShark};
+
+    const GAME_GENIE_CODE: &str = "00A-17B-C49";
+    const GAME_SHARK_CODE: &str = "010738C1";
+
+    fn parse(kind: CheatCodeKind, code: &str) -> Cheat {
+        Cheat::parse(kind.into(), code).unwrap()
+    }
+
+    fn add(cheats: &mut CheatList, kind: CheatCodeKind, code: &str) -> u32 {
+        cheats.add(kind, code.to_string(), parse(kind, code))
+    }
+
+    fn listed(cheats: &CheatList) -> Vec<(u32, CheatCodeKind, String, bool)> {
+        cheats
+            .list()
+            .into_iter()
+            .map(|info| (info.id, info.kind, info.code, info.enabled))
+            .collect()
+    }
+
+    #[test]
+    fn added_cheats_are_listed_as_given() {
+        let mut cheats = CheatList::default();
+        let genie = add(&mut cheats, GameGenie, GAME_GENIE_CODE);
+        let shark = add(&mut cheats, GameShark, GAME_SHARK_CODE);
+        assert_eq!(
+            listed(&cheats),
+            [
+                (genie, GameGenie, GAME_GENIE_CODE.to_string(), true),
+                (shark, GameShark, GAME_SHARK_CODE.to_string(), true),
+            ]
+        );
+        assert_eq!(
+            cheats.active(),
+            [
+                parse(GameGenie, GAME_GENIE_CODE),
+                parse(GameShark, GAME_SHARK_CODE)
+            ]
+        );
+    }
+
+    #[test]
+    fn disabled_cheats_stay_listed_but_inactive() {
+        let mut cheats = CheatList::default();
+        let genie = add(&mut cheats, GameGenie, GAME_GENIE_CODE);
+        add(&mut cheats, GameShark, GAME_SHARK_CODE);
+        assert!(cheats.set_enabled(genie, false));
+        assert!(!listed(&cheats)[0].3);
+        assert_eq!(cheats.active(), [parse(GameShark, GAME_SHARK_CODE)]);
+        assert!(cheats.set_enabled(genie, true));
+        assert_eq!(cheats.active().len(), 2);
+    }
+
+    #[test]
+    fn removed_ids_are_not_reused() {
+        let mut cheats = CheatList::default();
+        let first = add(&mut cheats, GameGenie, GAME_GENIE_CODE);
+        assert!(cheats.remove(first));
+        assert!(!cheats.remove(first));
+        assert!(!cheats.set_enabled(first, true));
+        let second = add(&mut cheats, GameGenie, GAME_GENIE_CODE);
+        assert_ne!(first, second);
+        cheats.clear();
+        assert!(cheats.list().is_empty());
+        assert!(add(&mut cheats, GameShark, GAME_SHARK_CODE) > second);
+    }
+
+    #[test]
+    fn add_cheat_query_round_trips() {
+        let json = r#"{"AddCheat":{"kind":"gameshark","code":"010738C1"}}"#;
+        let query: BridgeQuery = serde_json::from_str(json).unwrap();
+        let BridgeQuery::AddCheat { kind, code } = &query else {
+            panic!("Parsed as {query:?}");
+        };
+        assert_eq!((*kind, code.as_str()), (GameShark, GAME_SHARK_CODE));
+        assert_eq!(serde_json::to_string(&query).unwrap(), json);
+    }
+
+    #[test]
+    fn cheat_info_is_sent_with_frontend_kind_names() {
+        let mut cheats = CheatList::default();
+        add(&mut cheats, GameGenie, GAME_GENIE_CODE);
+        assert_eq!(
+            serde_json::to_string(&cheats.list()).unwrap(),
+            r#"[{"id":0,"kind":"gamegenie","code":"00A-17B-C49","enabled":true}]"#
+        );
+    }
+
+    #[test]
+    fn invalid_codes_are_rejected_as_invalid_cheats() {
+        for (kind, code) in [(GameShark, "020738C1"), (GameGenie, "00A-17G")] {
+            let error = BridgeError::from(Cheat::parse(kind.into(), code).unwrap_err());
+            assert_eq!(error.code, ErrorCode::InvalidCheat, "{code}");
+        }
+    }
+}
@@ -12,6 +12,31 @@ impl SerializedRect {
     }
 }
 
+#[derive(Debug, serde::Deserialize)]
+struct SerializedDPad {
+    center: (i16, i16),
+    radius: f32,
+    #[serde(default)]
+    dead_zone: f32,
+    #[serde(default = "SerializedDPad::default_diagonal_bias")]
+    diagonal_bias: f32,
+}
+
+impl SerializedDPad {
+    fn default_diagonal_bias() -> f32 {
+        0.5
+    }
+
+    pub fn to_dpad(&self) -> RadialDPad {
+        RadialDPad {
+            center: Vector::new(Fp::from(self.center.0), Fp::from(self.center.1)),
+            radius: self.radius,
+            dead_zone: self.dead_zone,
+            diagonal_bias: self.diagonal_bias.clamp(0.0, 1.0),
+        }
+    }
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct BackgroundDefinitionSerialized {
     controls: SerializedRect,
@@ -24,6 +49,82 @@ struct BackgroundDefinitionSerialized {
     down: SerializedRect,
     select: SerializedRect,
     start: SerializedRect,
+    dpad: Option<SerializedDPad>,
+}
+
+/// D-pad split into 8 sectors around its center,
+/// so diagonals can be pressed without hitting an overlap of rects
+#[derive(Debug, Clone, Copy)]
+pub struct RadialDPad {
+    pub center: Vector,
+    pub radius: f32,
+    /// Radius around the center where no direction is pressed
+    pub dead_zone: f32,
+    /// Share of each quadrant that presses both neighbouring directions,
+    /// 0.5 splits the d-pad into equal sectors
+    pub diagonal_bias: f32,
+}
+
+impl RadialDPad {
+    /// Returns the directions pressed at given position,
+    /// with the radius enlarged by given scale
+    pub fn get_input(&self, pos: Vector, scale: f32) -> InputFlag {
+        let dx = f32::from(pos.x) - f32::from(self.center.x);
+        let dy = f32::from(pos.y) - f32::from(self.center.y);
+        let distance = dx.hypot(dy);
+        if distance > self.radius * scale || distance < self.dead_zone {
+            return InputFlag::from_bits_retain(0);
+        }
+        // Y axis points down, so angles increase clockwise starting from right
+        let angle = dy.atan2(dx).to_degrees().rem_euclid(360.0);
+        // Directions reach past the halfway point between them by half of the diagonal sector
+        let reach = 45.0 + 45.0 * self.diagonal_bias;
+
+        let mut input = InputFlag::from_bits_retain(0);
+        for (direction, direction_angle) in [
+            (InputFlag::RIGHT, 0.0),
+            (InputFlag::DOWN, 90.0),
+            (InputFlag::LEFT, 180.0),
+            (InputFlag::UP, 270.0),
+        ] {
+            let diff = (angle - direction_angle).rem_euclid(360.0);
+            let angular_distance = diff.min(360.0 - diff);
+            input.set(direction, angular_distance < reach);
+        }
+        input
+    }
+}
+
+/// Problem found in a background definition by `BackgroundDefinition::validate`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    /// Fatal issues would break the layout, so the definition can't be used.
+    /// Others are suspicious, but the definition still works
+    pub fatal: bool,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    fn fatal(message: String) -> Self {
+        Self {
+            fatal: true,
+            message,
+        }
+    }
+
+    fn warning(message: String) -> Self {
+        Self {
+            fatal: false,
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = if self.fatal { "error" } else { "warning" };
+        write!(f, "{severity}: {}", self.message)
+    }
 }
 
 /// Defines areas in the background image needed for scaling and input
@@ -41,14 +142,117 @@ pub struct BackgroundDefinition {
     pub down: Rect,
     pub select: Rect,
     pub start: Rect,
+    /// Replaces the direction rects if defined
+    pub dpad: Option<RadialDPad>,
 }
 
 impl BackgroundDefinition {
     pub fn from_str(string: &str) -> Self {
+        Self::try_from_str(string).expect("Couldn't deserialize background definition")
+    }
+
+    /// Parses a definition, the error is boxed as it's much larger than the definition
+    pub fn try_from_str(string: &str) -> Result<Self, Box<figment::Error>> {
         let serialized: BackgroundDefinitionSerialized = Figment::from(Toml::string(string))
             .extract()
-            .expect("Couldn't deserialize background definition");
-        Self::from(serialized)
+            .map_err(Box::new)?;
+        Ok(Self::from(serialized))
+    }
+
+    /// Checks that the areas make sense for an image of given size.
+    /// Missing areas are already rejected when parsing, so this finds areas that parse
+    /// but would break the layout, like empty display or controls areas,
+    /// and ones that are suspicious, like buttons outside the image
+    pub fn validate(&self, image_size: Vector) -> Result<(), Vec<ValidationIssue>> {
+        let mut issues = Vec::new();
+        let size = |rect: &Rect| (f32::from(rect.size.x), f32::from(rect.size.y));
+        let inside_image = |rect: &Rect| {
+            let (x, y) = (f32::from(rect.pos.x), f32::from(rect.pos.y));
+            let (width, height) = size(rect);
+            x >= 0.0
+                && y >= 0.0
+                && x + width <= f32::from(image_size.x)
+                && y + height <= f32::from(image_size.y)
+        };
+
+        // Layout divides by the sizes of the display and controls areas
+        for (name, rect) in [("display", &self.display), ("controls", &self.controls)] {
+            let (width, height) = size(rect);
+            if width <= 0.0 || height <= 0.0 {
+                issues.push(ValidationIssue::fatal(format!(
+                    "The {name} area has no size ({width}x{height})"
+                )));
+            } else if !inside_image(rect) {
+                issues.push(ValidationIssue::warning(format!(
+                    "The {name} area reaches outside the image"
+                )));
+            }
+        }
+        let (display_x, display_y) = (f32::from(self.display.pos.x), f32::from(self.display.pos.y));
+        let (controls_x, controls_y) = (
+            f32::from(self.controls.pos.x),
+            f32::from(self.controls.pos.y),
+        );
+        let (display_width, display_height) = size(&self.display);
+        let (controls_width, controls_height) = size(&self.controls);
+        if display_x < controls_x
+            || display_y < controls_y
+            || display_x + display_width > controls_x + controls_width
+            || display_y + display_height > controls_y + controls_height
+        {
+            issues.push(ValidationIssue::warning(
+                "The display area isn't inside the controls area".to_string(),
+            ));
+        }
+
+        let buttons = [
+            ("a", &self.a),
+            ("b", &self.b),
+            ("select", &self.select),
+            ("start", &self.start),
+        ];
+        let directions = [
+            ("left", &self.left),
+            ("right", &self.right),
+            ("up", &self.up),
+            ("down", &self.down),
+        ];
+        // Directions are replaced by the radial d-pad if it's defined
+        let directions = if self.dpad.is_some() {
+            &[][..]
+        } else {
+            &directions[..]
+        };
+        for (name, rect) in buttons.iter().chain(directions) {
+            let (width, height) = size(rect);
+            if width <= 0.0 || height <= 0.0 {
+                issues.push(ValidationIssue::warning(format!(
+                    "The {name} button has no size, so it can't be pressed"
+                )));
+            } else if !inside_image(rect) {
+                issues.push(ValidationIssue::warning(format!(
+                    "The {name} button reaches outside the image"
+                )));
+            }
+        }
+
+        if let Some(dpad) = &self.dpad {
+            if dpad.radius <= 0.0 {
+                issues.push(ValidationIssue::warning(
+                    "The d-pad has no radius, so it can't be pressed".to_string(),
+                ));
+            } else if dpad.dead_zone >= dpad.radius {
+                issues.push(ValidationIssue::warning(
+                    "The d-pad dead zone covers the whole d-pad".to_string(),
+                ));
+            }
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
     }
 
     pub fn get_input_rect(&self, input: InputFlag) -> Rect {
@@ -64,6 +268,50 @@ impl BackgroundDefinition {
             _ => unreachable!(),
         }
     }
+
+    /// Returns the inputs pressed at given position in background.
+    ///
+    /// Touch targets are enlarged around their centers by given scale, without changing
+    /// how they're drawn. Positions inside the drawn buttons press them as before,
+    /// while positions only inside enlarged targets press the one with the nearest center,
+    /// so enlarged neighbours don't press both
+    pub fn get_pressed_inputs(&self, pos: Vector, touch_target_scale: f32) -> InputFlag {
+        let scale = touch_target_scale.max(1.0);
+        let mut pressed_inputs = match &self.dpad {
+            Some(dpad) => dpad.get_input(pos, scale),
+            None => InputFlag::from_bits_retain(0),
+        };
+        let (x, y) = (f32::from(pos.x), f32::from(pos.y));
+        let mut nearest: Option<(InputFlag, f32)> = None;
+        for b in 0..8 {
+            let input = InputFlag::from_bits_truncate(1 << b);
+            // Directions are handled by the radial d-pad
+            if self.dpad.is_some() && b < 4 {
+                continue;
+            }
+            let rect = self.get_input_rect(input);
+            // Update input if pointer is inside rectangle
+            pressed_inputs.set(input, rect.contains_point(&pos));
+
+            let half_width = f32::from(rect.size.x) / 2.0;
+            let half_height = f32::from(rect.size.y) / 2.0;
+            let dx = x - (f32::from(rect.pos.x) + half_width);
+            let dy = y - (f32::from(rect.pos.y) + half_height);
+            if dx.abs() <= half_width * scale && dy.abs() <= half_height * scale {
+                let distance = dx.hypot(dy);
+                if nearest.is_none_or(|(_, nearest_distance)| distance < nearest_distance) {
+                    nearest = Some((input, distance));
+                }
+            }
+        }
+        // Enlarged targets only apply where nothing is pressed directly
+        if pressed_inputs.is_empty()
+            && let Some((input, _)) = nearest
+        {
+            pressed_inputs.insert(input);
+        }
+        pressed_inputs
+    }
 }
 
 impl From<BackgroundDefinitionSerialized> for BackgroundDefinition {
@@ -79,6 +327,256 @@ impl From<BackgroundDefinitionSerialized> for BackgroundDefinition {
             down: value.down.to_rect(),
             select: value.select.to_rect(),
             start: value.start.to_rect(),
+            dpad: value.dpad.as_ref().map(SerializedDPad::to_dpad),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: i16, y: i16) -> Vector {
+        Vector::new(Fp::from(x), Fp::from(y))
+    }
+
+    /// Returns the bits of the inputs pressed, as `InputFlag` can't be printed
+    fn bits(input: InputFlag) -> u8 {
+        input.bits()
+    }
+
+    fn rect(x: i16, y: i16, width: i16, height: i16) -> Rect {
+        Rect::new(point(x, y), point(width, height))
+    }
+
+    /// Definition of a 200x300 image with every area inside it
+    fn definition() -> BackgroundDefinition {
+        BackgroundDefinition::from_str(
+            r#"
+            controls = [0, 0, 200, 300]
+            display = [20, 20, 160, 144]
+            a = [150, 200, 30, 30]
+            b = [110, 220, 30, 30]
+            left = [10, 200, 30, 30]
+            right = [70, 200, 30, 30]
+            up = [40, 170, 30, 30]
+            down = [40, 230, 30, 30]
+            select = [60, 270, 30, 20]
+            start = [110, 270, 30, 20]
+            "#,
+        )
+    }
+
+    fn issues(definition: &BackgroundDefinition, width: i16, height: i16) -> Vec<String> {
+        match definition.validate(point(width, height)) {
+            Ok(()) => vec![],
+            Err(issues) => issues.iter().map(ValidationIssue::to_string).collect(),
+        }
+    }
+
+    fn dpad(diagonal_bias: f32) -> RadialDPad {
+        RadialDPad {
+            center: point(0, 0),
+            radius: 100.0,
+            dead_zone: 10.0,
+            diagonal_bias,
+        }
+    }
+
+    #[test]
+    fn dpad_angles_press_nearest_directions() {
+        let dpad = dpad(0.5);
+        assert_eq!(
+            bits(dpad.get_input(point(50, 0), 1.0)),
+            bits(InputFlag::RIGHT)
+        );
+        assert_eq!(
+            bits(dpad.get_input(point(0, 50), 1.0)),
+            bits(InputFlag::DOWN)
+        );
+        assert_eq!(
+            bits(dpad.get_input(point(-50, 0), 1.0)),
+            bits(InputFlag::LEFT)
+        );
+        assert_eq!(
+            bits(dpad.get_input(point(0, -50), 1.0)),
+            bits(InputFlag::UP)
+        );
+        assert_eq!(
+            bits(dpad.get_input(point(-40, -40), 1.0)),
+            bits(InputFlag::LEFT | InputFlag::UP)
+        );
+        // 15 and 30 degrees below right, on either side of the diagonal sector's edge at 22.5
+        assert_eq!(
+            bits(dpad.get_input(point(48, 13), 1.0)),
+            bits(InputFlag::RIGHT)
+        );
+        assert_eq!(
+            bits(dpad.get_input(point(43, 25), 1.0)),
+            bits(InputFlag::RIGHT | InputFlag::DOWN)
+        );
+    }
+
+    #[test]
+    fn diagonal_bias_widens_diagonal_sectors() {
+        // 30 degrees below right
+        let pos = point(43, 25);
+        assert_eq!(bits(dpad(0.0).get_input(pos, 1.0)), bits(InputFlag::RIGHT));
+        assert_eq!(
+            bits(dpad(1.0).get_input(pos, 1.0)),
+            bits(InputFlag::RIGHT | InputFlag::DOWN)
+        );
+        // Straight right stays a single direction even with the widest diagonals
+        assert_eq!(
+            bits(dpad(1.0).get_input(point(50, 0), 1.0)),
+            bits(InputFlag::RIGHT)
+        );
+    }
+
+    #[test]
+    fn dpad_ignores_dead_zone_and_outside() {
+        let dpad = dpad(0.5);
+        assert!(dpad.get_input(point(5, 0), 1.0).is_empty());
+        assert!(dpad.get_input(point(150, 0), 1.0).is_empty());
+        // Enlarged touch targets reach further
+        assert_eq!(
+            bits(dpad.get_input(point(150, 0), 2.0)),
+            bits(InputFlag::RIGHT)
+        );
+    }
+
+    #[test]
+    fn valid_definition_has_no_issues() {
+        assert!(definition().validate(point(200, 300)).is_ok());
+    }
+
+    #[test]
+    fn empty_layout_areas_are_fatal() {
+        let mut definition = definition();
+        definition.display = rect(20, 20, 0, 144);
+        definition.controls = rect(0, 0, 200, -10);
+        assert_eq!(
+            issues(&definition, 200, 300),
+            [
+                "error: The display area has no size (0x144)",
+                "error: The controls area has no size (200x-10)",
+                // Empty areas can still be checked against each other
+                "warning: The display area isn't inside the controls area",
+            ]
+        );
+    }
+
+    #[test]
+    fn areas_outside_image_are_warnings() {
+        assert_eq!(
+            issues(&definition(), 170, 300),
+            [
+                "warning: The display area reaches outside the image",
+                "warning: The controls area reaches outside the image",
+                "warning: The a button reaches outside the image",
+            ]
+        );
+    }
+
+    #[test]
+    fn display_outside_controls_is_warning() {
+        let mut definition = definition();
+        definition.controls = rect(0, 100, 200, 200);
+        assert_eq!(
+            issues(&definition, 200, 300),
+            ["warning: The display area isn't inside the controls area"]
+        );
+    }
+
+    #[test]
+    fn empty_buttons_are_warnings() {
+        let mut definition = definition();
+        definition.start = rect(110, 270, 0, 0);
+        definition.up = rect(40, 170, 30, 0);
+        assert_eq!(
+            issues(&definition, 200, 300),
+            [
+                "warning: The start button has no size, so it can't be pressed",
+                "warning: The up button has no size, so it can't be pressed",
+            ]
+        );
+    }
+
+    #[test]
+    fn radial_dpad_replaces_direction_checks() {
+        let mut definition = definition();
+        // Direction rects are unused with the radial d-pad, so they aren't checked
+        definition.up = rect(40, 170, 30, 0);
+        definition.left = rect(-50, 200, 30, 30);
+        definition.dpad = Some(RadialDPad {
+            center: point(55, 215),
+            radius: 40.0,
+            dead_zone: 0.0,
+            diagonal_bias: 0.5,
+        });
+        assert!(definition.validate(point(200, 300)).is_ok());
+
+        definition.dpad = Some(RadialDPad {
+            dead_zone: 40.0,
+            ..definition.dpad.unwrap()
+        });
+        assert_eq!(
+            issues(&definition, 200, 300),
+            ["warning: The d-pad dead zone covers the whole d-pad"]
+        );
+        definition.dpad = Some(RadialDPad {
+            radius: 0.0,
+            ..definition.dpad.unwrap()
+        });
+        assert_eq!(
+            issues(&definition, 200, 300),
+            ["warning: The d-pad has no radius, so it can't be pressed"]
+        );
+    }
+
+    #[test]
+    fn drawn_buttons_are_pressed_at_any_scale() {
+        let definition = definition();
+        for scale in [1.0, 2.0] {
+            assert_eq!(
+                bits(definition.get_pressed_inputs(point(165, 215), scale)),
+                bits(InputFlag::A)
+            );
         }
+        // Scales below 1 don't shrink the targets
+        assert_eq!(
+            bits(definition.get_pressed_inputs(point(151, 201), 0.5)),
+            bits(InputFlag::A)
+        );
+    }
+
+    #[test]
+    fn enlarged_targets_press_nearest_center() {
+        let definition = definition();
+        // Between the a and b buttons, in both of their doubled targets
+        let nearer_a = point(145, 222);
+        let nearer_b = point(142, 232);
+        assert!(definition.get_pressed_inputs(nearer_a, 1.0).is_empty());
+        assert!(definition.get_pressed_inputs(nearer_b, 1.0).is_empty());
+        assert_eq!(
+            bits(definition.get_pressed_inputs(nearer_a, 2.0)),
+            bits(InputFlag::A)
+        );
+        assert_eq!(
+            bits(definition.get_pressed_inputs(nearer_b, 2.0)),
+            bits(InputFlag::B)
+        );
+    }
+
+    #[test]
+    fn drawn_button_wins_over_enlarged_neighbour() {
+        let mut definition = definition();
+        definition.b = rect(110, 150, 30, 150);
+        // Inside the top of the tall b button, but nearer to the center of the tripled a target
+        let pos = point(139, 175);
+        assert_eq!(
+            bits(definition.get_pressed_inputs(pos, 3.0)),
+            bits(InputFlag::B)
+        );
     }
 }
@@ -0,0 +1,100 @@
+use super::*;
+use std::cell::{Cell, RefCell};
+
+/// What a crash report includes besides the panic, updated as the emulator runs
+#[derive(Debug, Default, Clone, Copy)]
+struct CrashContext {
+    rom_hash: Option<u32>,
+    /// Program counter when the last emulation run started
+    pc: Option<u16>,
+}
+
+thread_local! {
+    /// Callbacks and requests of the running emulators, which are all told about a panic
+    /// as they share the WebAssembly instance it leaves broken
+    static CRASH_TARGETS: RefCell<Vec<(ProxyCallbacks, SharedPendingRequests)>> =
+        const { RefCell::new(Vec::new()) };
+    static CRASH_CONTEXT: Cell<CrashContext> = Cell::new(CrashContext::default());
+}
+
+/// Installs a panic hook that logs panics to the console or stderr, reports them through
+/// the error callbacks of the running emulators and makes their queries reject from then on.
+/// Without it the page only sees a canvas that stopped updating
+pub fn install_panic_hook() {
+    static INSTALLED: std::sync::Once = std::sync::Once::new();
+    INSTALLED.call_once(|| {
+        std::panic::set_hook(Box::new(|info| {
+            console_error_panic_hook::hook(info);
+            report_panic(info);
+        }));
+    });
+}
+
+/// Adds an emulator whose callbacks and requests a panic is reported to
+pub fn watch_for_crash(callbacks: ProxyCallbacks, pending: SharedPendingRequests) {
+    CRASH_TARGETS.with_borrow_mut(|targets| targets.push((callbacks, pending)));
+    CRASH_CONTEXT.set(CrashContext::default());
+}
+
+/// Stops reporting panics to the emulator with given requests, used when it's shut down
+pub fn stop_watching_for_crash(pending: &SharedPendingRequests) {
+    CRASH_TARGETS.with_borrow_mut(|targets| {
+        targets.retain(|(_, watched)| !Rc::ptr_eq(watched, pending));
+    });
+}
+
+pub fn record_rom_hash(rom_hash: u32) {
+    CRASH_CONTEXT.set(CrashContext {
+        rom_hash: Some(rom_hash),
+        pc: None,
+    });
+}
+
+/// Records the program counter before running the CPU, so a crash can be traced
+/// to roughly where the game was
+pub fn record_pc(pc: u16) {
+    let context = CRASH_CONTEXT.get();
+    CRASH_CONTEXT.set(CrashContext {
+        pc: Some(pc),
+        ..context
+    });
+}
+
+fn report_panic(info: &std::panic::PanicHookInfo) {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Unknown panic");
+    let mut details = message.to_string();
+    if let Some(location) = info.location() {
+        details.push_str(&format!(" at {location}"));
+    }
+    let context = CRASH_CONTEXT.get();
+    if let Some(rom_hash) = context.rom_hash {
+        details.push_str(&format!(", ROM hash {rom_hash}"));
+    }
+    if let Some(pc) = context.pc {
+        details.push_str(&format!(", PC {pc:04X}"));
+    }
+    // The panic itself is already printed, this adds where the emulation was
+    log::error!("Emulator crashed: {details}");
+    let error =
+        BridgeError::new(ErrorCode::EmulatorCrashed, "Emulator crashed").with_details(details);
+
+    // State borrowed where the panic happened can't be reached, so it's skipped
+    let Some(targets) = CRASH_TARGETS.with(|targets| targets.try_borrow().ok().map(|t| t.clone()))
+    else {
+        return;
+    };
+    for (callbacks, pending) in targets {
+        // Further panics, like ones from the event loop left in a broken state, aren't reported
+        match pending.try_borrow_mut() {
+            Ok(mut pending) if pending.crash.is_none() => pending.crash = Some(error.clone()),
+            _ => continue,
+        }
+        PendingRequests::reject_all(&pending, error.clone());
+        callbacks.call(Callback::Error(error.clone()));
+    }
+}
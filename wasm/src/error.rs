@@ -0,0 +1,244 @@
+use super::*;
+use serde::Serialize;
+use tsify::Tsify;
+
+/// Identifies why a query failed, so the frontend can react without matching messages
+#[derive(Tsify, Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum ErrorCode {
+    /// The query needs a loaded ROM
+    CPUNotInitialized,
+    /// The zip archive couldn't be read or doesn't contain a ROM
+    InvalidZip,
+    /// The ROM is too small to contain a header
+    NoHeader,
+    /// The memory bank controller of the cartridge isn't supported
+    UnsupportedMapper,
+    /// The header checksum doesn't match, the ROM can still be loaded with `force`
+    BadHeaderChecksum,
+    /// The header doesn't have the logo, the ROM can still be loaded with `force`
+    BadLogo,
+    /// The cartridge doesn't have RAM to load a save into
+    NoRAM,
+    /// The save doesn't match the RAM size of the cartridge
+    RAMSizeMismatch,
+    /// The emulator state couldn't be serialized
+    SerializationFailed,
+    /// The save state is corrupted or from an incompatible version
+    DeserializationFailed,
+    /// The state slot number is out of range
+    InvalidSlot,
+    /// No state is saved in the slot
+    EmptySlot,
+    /// The state in the slot was saved with another ROM
+    WrongROM,
+    /// The state is too large to be kept in a slot
+    StateTooLarge,
+    /// A keybind refers to an unknown input
+    UnknownInput,
+    /// The query wasn't handled within its timeout
+    Timeout,
+    /// The event loop has exited and can't handle queries anymore
+    EventLoopClosed,
+    /// The skin isn't listed in the skin manifest
+    SkinNotFound,
+    /// The files of the skin couldn't be fetched
+    SkinFetchFailed,
+    /// The skin image or definition couldn't be decoded
+    InvalidSkin,
+    /// The query needs the renderer, which is initialized shortly after starting
+    RendererNotInitialized,
+    /// The rendered frame couldn't be read back
+    CaptureFailed,
+    /// The query finishes asynchronously and can't be part of a batch
+    NotBatchable,
+    /// Recording is stopped without being started
+    NotRecording,
+    /// The recording couldn't be encoded
+    EncodingFailed,
+    /// Audio capture is stopped without being started
+    NotCapturingAudio,
+    /// The graphics device or surface couldn't be created
+    RendererInitFailed,
+    /// The graphics device was lost, the renderer is recreated automatically
+    DeviceLost,
+    /// A frame couldn't be rendered
+    RenderFailed,
+    /// No audio output device can be used
+    AudioUnavailable,
+    /// The link cable partner's channel closed or couldn't be sent to
+    SerialLinkDisconnected,
+    /// The link cable partner didn't reply to a lockstep transfer within the timeout
+    SerialLinkTimeout,
+    /// The cheat code isn't valid for its format
+    InvalidCheat,
+    /// No cheat has the given id
+    CheatNotFound,
+    /// An argument passed to the proxy doesn't have the expected shape
+    InvalidArgument,
+    /// The emulator has panicked and doesn't handle queries anymore,
+    /// the details contain the panic message and where it happened
+    EmulatorCrashed,
+}
+
+/// Rejection value of the query promises
+#[derive(Tsify, Debug, Clone, Serialize)]
+pub struct BridgeError {
+    pub code: ErrorCode,
+    /// Human readable description that can be shown to the user
+    pub message: String,
+    /// Underlying error, useful for debugging
+    #[tsify(optional)]
+    pub details: Option<String>,
+    /// Build of the emulator, included in errors passed to the error callback
+    /// so they can be reported as they are
+    #[tsify(optional)]
+    pub version: Option<Box<VersionInfo>>,
+}
+
+impl BridgeError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+            version: None,
+        }
+    }
+
+    pub fn with_version_info(mut self) -> Self {
+        self.version = Some(Box::new(VersionInfo::current()));
+        self
+    }
+
+    pub fn with_details(mut self, details: impl ToString) -> Self {
+        self.details = Some(details.to_string());
+        self
+    }
+
+    pub fn cpu_not_initialized() -> Self {
+        Self::new(ErrorCode::CPUNotInitialized, "CPU not initialized")
+    }
+
+    pub fn cheat_not_found(id: u32) -> Self {
+        Self::new(ErrorCode::CheatNotFound, format!("Cheat {id} not found"))
+    }
+}
+
+impl From<MemoryInitializationError> for BridgeError {
+    fn from(value: MemoryInitializationError) -> Self {
+        let code = match value.error_type() {
+            MemoryInitializationErrorType::NoHeader => ErrorCode::NoHeader,
+            MemoryInitializationErrorType::UnimplementedMBC(_) => ErrorCode::UnsupportedMapper,
+            MemoryInitializationErrorType::BadHeaderChecksum => ErrorCode::BadHeaderChecksum,
+            MemoryInitializationErrorType::BadLogo => ErrorCode::BadLogo,
+        };
+        Self::new(code, value.to_string())
+    }
+}
+
+impl From<RAMLoadError> for BridgeError {
+    fn from(value: RAMLoadError) -> Self {
+        let code = match value {
+            RAMLoadError::NoRAM => ErrorCode::NoRAM,
+            RAMLoadError::SizeMismatch { .. } => ErrorCode::RAMSizeMismatch,
+        };
+        Self::new(code, value.to_string())
+    }
+}
+
+impl From<StateSlotError> for BridgeError {
+    fn from(value: StateSlotError) -> Self {
+        let code = match value {
+            StateSlotError::InvalidSlot(_) => ErrorCode::InvalidSlot,
+            StateSlotError::EmptySlot(_) => ErrorCode::EmptySlot,
+            StateSlotError::WrongROM(_) => ErrorCode::WrongROM,
+            StateSlotError::TooLarge(_) => ErrorCode::StateTooLarge,
+        };
+        Self::new(code, value.to_string())
+    }
+}
+
+impl From<tsify::Error> for BridgeError {
+    fn from(value: tsify::Error) -> Self {
+        Self::new(ErrorCode::InvalidArgument, value.to_string())
+    }
+}
+
+impl From<CheatParseError> for BridgeError {
+    fn from(value: CheatParseError) -> Self {
+        Self::new(ErrorCode::InvalidCheat, value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a 32 KiB ROM of given cartridge type with a header that passes the checks
+    fn rom(cartridge_type: u8, ram_size: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x104..0x134].copy_from_slice(&CartridgeInfo::NINTENDO_LOGO);
+        rom[0x134..0x13C].copy_from_slice(b"ERRORS  ");
+        rom[0x147] = cartridge_type;
+        rom[0x149] = ram_size;
+        rom[0x14D] = rom[0x134..0x14D].iter().fold(0u8, |checksum, byte| {
+            checksum.wrapping_sub(*byte).wrapping_sub(1)
+        });
+        rom
+    }
+
+    /// Returns the code LoadROM rejects given ROM with when the header is checked
+    fn load_error(rom: Vec<u8>) -> ErrorCode {
+        let Err(error) = CPU::new_checked(rom.into()) else {
+            panic!("ROM was loaded");
+        };
+        BridgeError::from(error).code
+    }
+
+    #[test]
+    fn valid_rom_loads() {
+        assert!(CPU::new_checked(rom(0x00, 0x00).into()).is_ok());
+    }
+
+    #[test]
+    fn truncated_rom_has_no_header() {
+        let mut rom = rom(0x00, 0x00);
+        rom.truncate(0x14F);
+        assert_eq!(load_error(rom), ErrorCode::NoHeader);
+    }
+
+    #[test]
+    fn missing_logo_is_rejected() {
+        let mut rom = rom(0x00, 0x00);
+        rom[0x110] ^= 0xFF;
+        assert_eq!(load_error(rom), ErrorCode::BadLogo);
+    }
+
+    #[test]
+    fn header_checksum_mismatch_is_rejected() {
+        let mut rom = rom(0x00, 0x00);
+        rom[0x14D] ^= 0xFF;
+        assert_eq!(load_error(rom.clone()), ErrorCode::BadHeaderChecksum);
+        // Forcing the load skips the check
+        assert!(CPU::new(rom.into()).is_ok());
+    }
+
+    #[test]
+    fn unimplemented_mapper_is_unsupported() {
+        // MBC6
+        assert_eq!(load_error(rom(0x20, 0x00)), ErrorCode::UnsupportedMapper);
+    }
+
+    #[test]
+    fn save_of_wrong_size_is_rejected() {
+        // MBC1+RAM+BATTERY with 8 KiB of RAM
+        let mut cpu = CPU::new_checked(rom(0x03, 0x02).into()).unwrap();
+        let error = BridgeError::from(cpu.set_ram(vec![0; 0x800]).unwrap_err());
+        assert_eq!(error.code, ErrorCode::RAMSizeMismatch);
+        assert!(cpu.set_ram(vec![0; 0x2000]).is_ok());
+
+        let mut cpu = CPU::new_checked(rom(0x00, 0x00).into()).unwrap();
+        let error = BridgeError::from(cpu.set_ram(vec![0; 0x2000]).unwrap_err());
+        assert_eq!(error.code, ErrorCode::NoRAM);
+    }
+}
@@ -0,0 +1,191 @@
+/// How long the emulator runs for at a time
+#[derive(Debug, Clone, Copy)]
+pub enum RunLength {
+    Millis(f32),
+    Frames(u32),
+}
+
+/// Decides how many whole frames auto-run emulates on each redraw, so frames follow the
+/// hardware rate of about 59.73 Hz on displays refreshing at other rates.
+///
+/// The refresh interval of the display is estimated from the times of the redraws, and each
+/// redraw adds the frames emulated in one interval to the frames owed. Whole frames are run
+/// and the fraction is carried over, so the redraws that repeat the previous frame or skip
+/// one come at an even cadence, instead of wherever the timing of the redraws jitters
+#[derive(Debug, Default)]
+pub struct FramePacer {
+    /// Estimated time between redraws, from the refresh rate of the display
+    refresh_interval_ms: Option<f64>,
+    /// Consecutive intervals too far from the estimate to update it
+    outliers: u32,
+    last_redraw: Option<f64>,
+    /// Frames owed to keep up with the emulation speed, including the fraction carried over
+    owed_frames: f64,
+    /// Redraws that ran no frame since the counts were last taken
+    repeated_frames: u32,
+    /// Frames run but replaced by another before being drawn since the counts were last taken
+    dropped_frames: u32,
+}
+
+/// Counts of uneven redraws, reported through frame stats
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PacingCounts {
+    pub repeated_frames: u32,
+    pub dropped_frames: u32,
+    /// Estimated refresh rate of the display in Hz, 0.0 before it's known
+    pub refresh_rate: f32,
+}
+
+impl FramePacer {
+    /// Length of a Game Boy frame, 70224 cycles at 4194304 Hz
+    pub const FRAME_MS: f64 = 70224.0 * 1000.0 / 4_194_304.0;
+    /// Weight of each new interval in the estimate, low enough to average out jitter
+    const SMOOTHING: f64 = 0.05;
+    /// Intervals further than this share of the estimate from it are missed redraws or
+    /// extra ones, not a change of the refresh rate
+    const OUTLIER_THRESHOLD: f64 = 0.25;
+    /// After this many outliers in a row the refresh rate has changed,
+    /// like when the window moved to another display
+    const MAX_OUTLIERS: u32 = 30;
+
+    /// Forgets the time of the last redraw, so time spent paused isn't caught up.
+    /// The refresh rate estimate and the carried over fraction are kept
+    pub fn restart(&mut self) {
+        self.last_redraw = None;
+    }
+
+    /// Returns how many frames to run on the redraw at given time.
+    /// Time since the last redraw is clamped to given amount, so a throttled tab doesn't
+    /// run a burst of frames when resumed
+    pub fn frames_for_redraw(&mut self, now: f64, speed: f32, max_catchup_ms: f32) -> u32 {
+        let Some(last_redraw) = self.last_redraw.replace(now) else {
+            return 0;
+        };
+        let elapsed = (now - last_redraw).clamp(0.0, f64::from(max_catchup_ms));
+        let interval = self.update_refresh_interval(elapsed);
+        // Missed redraws still owe their frames
+        let redraws = (elapsed / interval).round().max(1.0);
+        self.owed_frames += redraws * interval * f64::from(speed) / Self::FRAME_MS;
+        // Owed frames beyond the catch-up limit are forgotten like the elapsed time
+        let max_frames = (f64::from(max_catchup_ms) * f64::from(speed) / Self::FRAME_MS).max(1.0);
+        self.owed_frames = self.owed_frames.min(max_frames.ceil());
+
+        let frames = self.owed_frames.floor();
+        self.owed_frames -= frames;
+        let frames = frames as u32;
+        match frames {
+            0 => self.repeated_frames += 1,
+            frames => self.dropped_frames += frames - 1,
+        }
+        frames
+    }
+
+    fn update_refresh_interval(&mut self, elapsed: f64) -> f64 {
+        let interval = match self.refresh_interval_ms {
+            Some(interval) if (elapsed - interval).abs() <= interval * Self::OUTLIER_THRESHOLD => {
+                self.outliers = 0;
+                interval + (elapsed - interval) * Self::SMOOTHING
+            }
+            Some(interval) if self.outliers < Self::MAX_OUTLIERS => {
+                self.outliers += 1;
+                return interval;
+            }
+            // The first interval, or one after the refresh rate has changed
+            _ if elapsed > 0.0 => {
+                self.outliers = 0;
+                elapsed
+            }
+            _ => return Self::FRAME_MS,
+        };
+        self.refresh_interval_ms = Some(interval);
+        interval
+    }
+
+    /// Returns the counts since the last call and resets them
+    pub fn take_counts(&mut self) -> PacingCounts {
+        let counts = PacingCounts {
+            repeated_frames: self.repeated_frames,
+            dropped_frames: self.dropped_frames,
+            refresh_rate: self
+                .refresh_interval_ms
+                .map_or(0.0, |interval| (1000.0 / interval) as f32),
+        };
+        self.repeated_frames = 0;
+        self.dropped_frames = 0;
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Redraws for 10 seconds at given refresh rate, with up to 1 ms of jitter on each redraw.
+    /// Returns the frames run on each redraw and the counts taken at the end
+    fn simulate(refresh_rate: f64) -> (Vec<u32>, PacingCounts) {
+        let mut pacer = FramePacer::default();
+        let interval = 1000.0 / refresh_rate;
+        let mut seed = 1u32;
+        let frames = (0..(10.0 * refresh_rate) as u32)
+            .map(|redraw| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                let jitter = f64::from(seed >> 16) / f64::from(u16::MAX) - 0.5;
+                pacer.frames_for_redraw(f64::from(redraw) * interval + jitter, 1.0, 100.0)
+            })
+            .collect();
+        (frames, pacer.take_counts())
+    }
+
+    /// Asserts that the frames run follow the hardware rate over the whole simulation
+    fn assert_hardware_rate(frames: &[u32]) {
+        let expected = 10_000.0 / FramePacer::FRAME_MS;
+        let total: u32 = frames.iter().sum();
+        assert!((f64::from(total) - expected).abs() < 2.0, "{total} frames");
+    }
+
+    #[test]
+    fn display_at_60_hz_occasionally_repeats_a_frame() {
+        let (frames, counts) = simulate(60.0);
+        assert_hardware_rate(&frames);
+        assert!((counts.refresh_rate - 60.0).abs() < 0.5);
+        assert_eq!(counts.dropped_frames, 0);
+        // 60 Hz is 0.45% faster than the hardware, so one redraw in about 220 repeats
+        assert!((2..=4).contains(&counts.repeated_frames));
+    }
+
+    #[test]
+    fn display_at_144_hz_runs_frames_at_even_cadence() {
+        let (frames, counts) = simulate(144.0);
+        assert_hardware_rate(&frames);
+        assert_eq!(counts.dropped_frames, 0);
+        // Every frame is shown for 2 or 3 redraws, never 1 or 4
+        let shown: Vec<usize> = frames[1..]
+            .split(|&frames| frames == 1)
+            .skip(1)
+            .map(|repeats| repeats.len() + 1)
+            .collect();
+        assert!(
+            shown[..shown.len() - 1]
+                .iter()
+                .all(|&redraws| redraws == 2 || redraws == 3)
+        );
+    }
+
+    #[test]
+    fn display_at_hardware_rate_runs_one_frame_per_redraw() {
+        let (frames, counts) = simulate(1000.0 / FramePacer::FRAME_MS);
+        assert_hardware_rate(&frames);
+        assert!(frames[1..].iter().all(|&frames| frames == 1));
+        assert_eq!(counts.dropped_frames, 0);
+    }
+
+    #[test]
+    fn display_at_50_hz_runs_two_frames_on_some_redraws() {
+        let (frames, counts) = simulate(50.0);
+        assert_hardware_rate(&frames);
+        assert!(frames[1..].iter().all(|&frames| frames == 1 || frames == 2));
+        assert_eq!(counts.repeated_frames, 0);
+        // 59.73 Hz on 50 Hz needs an extra frame on about every fifth redraw
+        assert!((90..=105).contains(&counts.dropped_frames));
+    }
+}
@@ -0,0 +1,223 @@
+use super::*;
+use winit::{event_loop::EventLoopClosed, window::WindowId};
+
+/// Identifies one of the emulators running in the event loop
+pub type InstanceId = u32;
+
+/// Event sent to the event loop, which runs any number of emulators
+#[derive(Debug)]
+pub enum PageEvent {
+    /// Starts another emulator next to the ones already running
+    #[cfg(target_family = "wasm")]
+    Spawn(InstanceProxy, AppInit),
+    /// Event for the emulator with given id
+    Instance(InstanceId, UserEvent),
+}
+
+/// Sends events to one of the emulators of the event loop
+#[derive(Debug, Clone)]
+pub struct InstanceProxy {
+    proxy: EventLoopProxy<PageEvent>,
+    id: InstanceId,
+}
+
+impl InstanceProxy {
+    pub fn new(proxy: EventLoopProxy<PageEvent>, id: InstanceId) -> Self {
+        Self { proxy, id }
+    }
+
+    pub fn id(&self) -> InstanceId {
+        self.id
+    }
+
+    /// Sends an event to the emulator, fails if the event loop has exited
+    pub fn send_event(&self, event: UserEvent) -> Result<(), EventLoopClosed<()>> {
+        self.proxy
+            .send_event(PageEvent::Instance(self.id, event))
+            .map_err(|_| EventLoopClosed(()))
+    }
+
+    /// Starts an emulator with this proxy in the event loop
+    #[cfg(target_family = "wasm")]
+    pub fn spawn(&self, init: AppInit) -> Result<(), EventLoopClosed<()>> {
+        self.proxy
+            .send_event(PageEvent::Spawn(self.clone(), init))
+            .map_err(|_| EventLoopClosed(()))
+    }
+}
+
+/// What the event loop needs to know to route events to an emulator
+pub trait Instance {
+    /// Window the emulator draws to, whose events are routed to it
+    fn window_id(&self) -> Option<WindowId>;
+
+    /// If the emulator has been shut down, after which it's removed from the event loop
+    fn is_stopped(&self) -> bool;
+}
+
+/// Emulators run by the event loop, each with its own window, audio stream and emulation.
+/// Winit allows only one event loop per page, so every canvas on the page is driven by it
+pub struct Instances<A> {
+    apps: HashMap<InstanceId, A>,
+    /// Set once the event loop can create windows,
+    /// emulators added before that create theirs when it's resumed
+    resumed: bool,
+}
+
+impl<A> Default for Instances<A> {
+    fn default() -> Self {
+        Self {
+            apps: HashMap::new(),
+            resumed: false,
+        }
+    }
+}
+
+impl<A: Instance> Instances<A> {
+    /// Adds an emulator, returning it so it can be resumed if the event loop already is
+    pub fn insert(&mut self, id: InstanceId, app: A) -> &mut A {
+        self.apps.entry(id).insert_entry(app).into_mut()
+    }
+
+    /// Returns the emulator drawing to given window
+    fn by_window(&mut self, window_id: WindowId) -> Option<&mut A> {
+        self.apps
+            .values_mut()
+            .find(|app| app.window_id() == Some(window_id))
+    }
+
+    /// Removes the emulator if it has been shut down, dropping what it still holds
+    fn remove_if_stopped(&mut self, id: InstanceId) {
+        if self.apps.get(&id).is_some_and(A::is_stopped) {
+            self.apps.remove(&id);
+        }
+    }
+}
+
+impl Instance for App {
+    fn window_id(&self) -> Option<WindowId> {
+        self.window_id
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+}
+
+impl ApplicationHandler<PageEvent> for Instances<App> {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        self.resumed = true;
+        for app in self.apps.values_mut() {
+            app.resumed(event_loop);
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window_id: WindowId,
+        event: WindowEvent,
+    ) {
+        if let Some(app) = self.by_window(window_id) {
+            app.window_event(event_loop, window_id, event);
+        }
+    }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: PageEvent) {
+        match event {
+            #[cfg(target_family = "wasm")]
+            PageEvent::Spawn(proxy, init) => {
+                let resumed = self.resumed;
+                let app = self.insert(proxy.id(), App::new(proxy, init));
+                if resumed {
+                    app.resumed(event_loop);
+                }
+            }
+            PageEvent::Instance(id, event) => match self.apps.get_mut(&id) {
+                Some(app) => {
+                    app.user_event(event_loop, event);
+                    self.remove_if_stopped(id);
+                }
+                // Requests sent through the proxy of an emulator that has shut down
+                None => {
+                    if let Some(request) = event.into_request() {
+                        request.finish(Err(App::stopped_error()));
+                    }
+                }
+            },
+        }
+    }
+
+    fn exiting(&mut self, event_loop: &ActiveEventLoop) {
+        for app in self.apps.values_mut() {
+            app.exiting(event_loop);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for an emulator drawing to a canvas
+    struct Canvas {
+        window_id: WindowId,
+        frames: u32,
+        stopped: bool,
+    }
+
+    impl Instance for Canvas {
+        fn window_id(&self) -> Option<WindowId> {
+            Some(self.window_id)
+        }
+
+        fn is_stopped(&self) -> bool {
+            self.stopped
+        }
+    }
+
+    fn canvas(window: u64) -> Canvas {
+        Canvas {
+            window_id: WindowId::from(window),
+            frames: 0,
+            stopped: false,
+        }
+    }
+
+    /// Redraws the canvas drawing to given window, like a RedrawRequested event would
+    fn redraw(instances: &mut Instances<Canvas>, window: u64) {
+        instances.by_window(WindowId::from(window)).unwrap().frames += 1;
+    }
+
+    #[test]
+    fn two_canvases_run_at_once() {
+        let mut instances = Instances::default();
+        instances.insert(0, canvas(10));
+        instances.insert(1, canvas(11));
+        for _ in 0..3 {
+            redraw(&mut instances, 10);
+            redraw(&mut instances, 11);
+        }
+        redraw(&mut instances, 11);
+        assert_eq!(instances.apps[&0].frames, 3);
+        assert_eq!(instances.apps[&1].frames, 4);
+    }
+
+    #[test]
+    fn shutting_down_one_canvas_keeps_the_other_running() {
+        let mut instances = Instances::default();
+        instances.insert(0, canvas(10));
+        instances.insert(1, canvas(11));
+        instances.apps.get_mut(&0).unwrap().stopped = true;
+        instances.remove_if_stopped(0);
+        instances.remove_if_stopped(1);
+        assert!(instances.by_window(WindowId::from(10)).is_none());
+        redraw(&mut instances, 11);
+        assert_eq!(instances.apps[&1].frames, 1);
+
+        // Another canvas can be started in its place
+        instances.insert(2, canvas(12));
+        redraw(&mut instances, 12);
+        assert_eq!(instances.apps.len(), 2);
+    }
+}
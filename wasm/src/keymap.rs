@@ -0,0 +1,61 @@
+use gb_web_core::InputFlag;
+use std::collections::HashMap;
+use winit::keyboard::KeyCode;
+
+/// Returns the input matching the name used by the frontend
+pub fn input_from_name(name: &str) -> Option<InputFlag> {
+    match name {
+        "D-Pad Right" => Some(InputFlag::RIGHT),
+        "D-Pad Left" => Some(InputFlag::LEFT),
+        "D-Pad Up" => Some(InputFlag::UP),
+        "D-Pad Down" => Some(InputFlag::DOWN),
+        "A" => Some(InputFlag::A),
+        "B" => Some(InputFlag::B),
+        "Select" => Some(InputFlag::SELECT),
+        "Start" => Some(InputFlag::START),
+        _ => None,
+    }
+}
+
+/// Maps physical keys to emulator inputs
+#[derive(Clone)]
+pub struct Keymap {
+    keys: HashMap<KeyCode, InputFlag>,
+}
+
+impl Keymap {
+    /// Creates keymap from input names and the physical keys bound to them,
+    /// returns the name that isn't a valid input if there is one
+    pub fn from_names(binds: HashMap<String, Vec<KeyCode>>) -> Result<Self, String> {
+        let mut keys = HashMap::new();
+        for (name, codes) in binds {
+            let input = input_from_name(&name).ok_or(name)?;
+            for code in codes {
+                keys.insert(code, input);
+            }
+        }
+        Ok(Self { keys })
+    }
+
+    pub fn get(&self, code: KeyCode) -> Option<InputFlag> {
+        self.keys.get(&code).copied()
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            keys: HashMap::from([
+                (KeyCode::ArrowRight, InputFlag::RIGHT),
+                (KeyCode::ArrowLeft, InputFlag::LEFT),
+                (KeyCode::ArrowUp, InputFlag::UP),
+                (KeyCode::ArrowDown, InputFlag::DOWN),
+                (KeyCode::KeyX, InputFlag::A),
+                (KeyCode::KeyZ, InputFlag::B),
+                (KeyCode::ShiftLeft, InputFlag::SELECT),
+                (KeyCode::ShiftRight, InputFlag::SELECT),
+                (KeyCode::Enter, InputFlag::START),
+            ]),
+        }
+    }
+}
@@ -8,99 +8,502 @@ use fixed32_math::{Rect, Vector};
 use hash32::{Hasher as _, Murmur3Hasher};
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::rc::Rc;
 use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 use winit::{
     application::ApplicationHandler,
     event::*,
     event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy},
+    keyboard::{KeyCode, PhysicalKey},
     window::Window,
 };
 
 mod audio;
 use audio::*;
+mod audio_capture;
+use audio_capture::*;
+mod cheats;
+use cheats::*;
 mod config;
 use config::*;
+mod crash;
+use crash::*;
+mod error;
+use error::*;
+mod frame_pacer;
+use frame_pacer::*;
+mod instances;
+use instances::*;
+mod keymap;
+use keymap::*;
+mod logging;
+use logging::*;
+mod platform;
+use platform::*;
 mod renderer;
 use renderer::*;
 mod proxy;
 use proxy::*;
+mod recording;
+use recording::*;
+mod rewind;
+use rewind::*;
+mod serial_link;
+use serial_link::*;
+mod shared_frame;
+use shared_frame::*;
+mod skins;
+use skins::*;
+mod slots;
+use slots::*;
+mod stats;
+use stats::*;
+mod version;
+use version::*;
 
+/// Id of the canvas used when no canvas is given
+#[cfg(target_family = "wasm")]
 const CANVAS_ID: &str = "canvas";
 
+/// Returns the canvas given either as an element or as the id of one
+#[cfg(target_family = "wasm")]
+fn find_canvas(canvas: &JsValue) -> Result<web_sys::HtmlCanvasElement, JsValue> {
+    use wasm_bindgen::JsCast;
+
+    if let Some(element) = canvas.dyn_ref::<web_sys::HtmlCanvasElement>() {
+        return Ok(element.clone());
+    }
+    let id = if canvas.is_undefined() || canvas.is_null() {
+        CANVAS_ID.to_string()
+    } else {
+        canvas
+            .as_string()
+            .ok_or("Canvas must be given as an element or an id")?
+    };
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or("Document not available")?;
+    document
+        .get_element_by_id(&id)
+        .ok_or_else(|| format!("Element with id \"{id}\" not found"))?
+        .dyn_into()
+        .map_err(|_| format!("Element with id \"{id}\" isn't a canvas").into())
+}
+
+/// Converts the display into RGBA pixels colored with given palette,
+/// or with the palettes of a Super Game Boy game
+fn display_pixels(display: &DisplayBuffer, palette: &Palette, sgb: Option<&SGB>) -> Vec<u8> {
+    let palette = [palette.0, palette.1, palette.2, palette.3].map(Color::to_srgb8);
+    display_color_indices(display)
+        .enumerate()
+        .flat_map(|(pixel, i)| match sgb {
+            Some(sgb) => {
+                let (x, y) = (pixel % 160, pixel / 160);
+                let attribute = sgb.attributes()[(y / 8) * 20 + x / 8];
+                Color::from_rgb555(sgb.palettes()[attribute as usize][i as usize]).to_srgb8()
+            }
+            None => palette[i as usize],
+        })
+        .collect()
+}
+
+#[cfg(target_family = "wasm")]
+thread_local! {
+    /// Proxy of the page's event loop once it's spawned. Winit allows only one event loop
+    /// per page, so it runs every emulator on the page and keeps running when they shut down
+    static PAGE_EVENT_LOOP: std::cell::RefCell<Option<EventLoopProxy<PageEvent>>> =
+        const { std::cell::RefCell::new(None) };
+    /// Id given to the next emulator spawned on the page
+    static NEXT_INSTANCE_ID: std::cell::Cell<InstanceId> = const { std::cell::Cell::new(0) };
+}
+
+/// Starts an emulator on given canvas, which can be an HTMLCanvasElement or its id.
+/// Defaults to the element with id "canvas".
+/// The background image is fetched from given URL, or from "skins/classic.png" relative to the page.
+/// Messages from Info up are logged to the console unless other log options are given.
+///
+/// Emulators spawned on other canvases run at the same time, each with its own audio stream
+#[cfg(target_family = "wasm")]
 #[wasm_bindgen]
-pub fn spawn_event_loop() -> Result<Proxy, JsValue> {
+pub fn spawn_event_loop(
+    callbacks: ProxyCallbacks,
+    canvas: JsValue,
+    background_url: Option<String>,
+    log_options: Option<tsify::Ts<LogOptions>>,
+) -> Result<Proxy, JsValue> {
     // Initialize debugging tools
-    console_error_panic_hook::set_once();
-    console_log::init_with_level(log::Level::Info).unwrap_throw();
+    install_panic_hook();
+    let log_options = log_options
+        .map(|options| options.to_rust())
+        .transpose()
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    init_logging(&log_options.unwrap_or_default());
+
+    let canvas = find_canvas(&canvas)?;
+    let pending = SharedPendingRequests::default();
+    watch_for_crash(callbacks.clone(), Rc::clone(&pending));
+    let init = AppInit {
+        callbacks,
+        pending: Rc::clone(&pending),
+        target: RenderTarget::canvas(canvas),
+        background_url: background_url.unwrap_or_else(|| DEFAULT_BACKGROUND_URL.to_string()),
+    };
+    let id = NEXT_INSTANCE_ID.get();
+    NEXT_INSTANCE_ID.set(id.wrapping_add(1));
+
+    // Emulators after the first are started in the running event loop
+    if let Some(page_proxy) = PAGE_EVENT_LOOP.with_borrow(Clone::clone) {
+        let proxy = InstanceProxy::new(page_proxy, id);
+        proxy
+            .spawn(init)
+            .map_err(|_| JsValue::from_str("Event loop has exited"))?;
+        return Ok(Proxy { proxy, pending });
+    }
 
     // Create event loop and a proxy to communicate with it from the frontend
-    let event_loop = EventLoop::with_user_event().build().unwrap_throw();
+    let event_loop = EventLoop::with_user_event()
+        .build()
+        .map_err(|e| JsValue::from_str(&format!("Unable to create event loop: {e}")))?;
     event_loop.set_control_flow(winit::event_loop::ControlFlow::Wait);
-    let app = App::new(&event_loop);
-    let proxy = event_loop.create_proxy();
+    let page_proxy = event_loop.create_proxy();
+    let proxy = InstanceProxy::new(page_proxy.clone(), id);
+    let mut instances = Instances::default();
+    instances.insert(id, App::new(proxy.clone(), init));
 
     use winit::platform::web::EventLoopExtWebSys;
-    event_loop.spawn_app(app);
-    Ok(Proxy { proxy })
+    event_loop.spawn_app(instances);
+    PAGE_EVENT_LOOP.set(Some(page_proxy));
+    Ok(Proxy { proxy, pending })
+}
+
+/// Runs the emulator in a desktop window until it's closed, starting with given ROM.
+/// Built with the `native` feature, see `src/bin/native.rs`
+#[cfg(not(target_family = "wasm"))]
+pub fn run_native(rom: Vec<u8>, is_zip: bool) -> Result<(), String> {
+    // Fails only if a logger is already set
+    let _ = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .try_init();
+    install_panic_hook();
+
+    let event_loop = EventLoop::with_user_event()
+        .build()
+        .map_err(|e| format!("Unable to create event loop: {e}"))?;
+    event_loop.set_control_flow(winit::event_loop::ControlFlow::Wait);
+    // Queued before the loop starts, so they're handled once it's running
+    let proxy = InstanceProxy::new(event_loop.create_proxy(), 0);
+    let callbacks = ProxyCallbacks::default();
+    let pending = SharedPendingRequests::default();
+    watch_for_crash(callbacks.clone(), Rc::clone(&pending));
+    let mut instances = Instances::default();
+    instances.insert(
+        proxy.id(),
+        App::new(
+            proxy.clone(),
+            AppInit {
+                callbacks,
+                pending,
+                target: RenderTarget::window(),
+                background_url: DEFAULT_BACKGROUND_URL.to_string(),
+            },
+        ),
+    );
+    // The page sends its options on startup, the desktop starts with the defaults
+    for query in [
+        BridgeQuery::UpdateOptions {
+            options: EmulatorOptions::default(),
+        },
+        BridgeQuery::LoadROM {
+            file: rom,
+            is_zip,
+            force: None,
+        },
+        BridgeQuery::SetAutoRun {
+            enabled: true,
+            max_catchup_ms: 100.0,
+        },
+    ] {
+        let _ = proxy.send_event(UserEvent::LocalQuery(query));
+    }
+    event_loop
+        .run_app(&mut instances)
+        .map_err(|e| format!("Event loop failed: {e}"))
+}
+
+/// What an emulator is started with
+#[derive(Debug)]
+pub struct AppInit {
+    callbacks: ProxyCallbacks,
+    pending: SharedPendingRequests,
+    target: RenderTarget,
+    background_url: String,
+}
+
+/// A pointer pressing the onscreen controls
+struct Pointer {
+    pos: Vector,
+    /// Timestamp of the last update from frontend
+    last_update: f64,
 }
 
 pub struct App {
     background_def: BackgroundDefinition,
-    proxy: Option<winit::event_loop::EventLoopProxy<UserEvent>>,
+    background_url: String,
+    /// Fetched image of the default skin, kept for renderers recreated after device loss
+    background_image: Option<Vec<u8>>,
+    /// Sends the results of asynchronous work back to the event loop
+    proxy: InstanceProxy,
+    callbacks: ProxyCallbacks,
+    pending: SharedPendingRequests,
+    target: RenderTarget,
+    /// Window created for the target, renderers created for earlier windows are discarded
+    window_id: Option<winit::window::WindowId>,
     renderer: Option<Renderer>,
     options: EmulatorOptions,
     audio: AudioHandler,
+    /// Browsers block audio until a user gesture, so playback starts on the first input.
+    /// Until then the CPU has no audio buffer and its samples are discarded
+    audio_enabled: bool,
     keyboard_input_state: InputFlag,
+    /// Input from keys handled directly by winit,
+    /// kept apart from `keyboard_input_state` so both paths can be active
+    builtin_keyboard_state: InputFlag,
+    builtin_keyboard: bool,
+    keymap: Keymap,
     screen_input_state: InputFlag,
     cpu: Option<CPU>,
-    rom: Vec<u8>,
-    last_cpu_frame: u8,
-    pointers: HashMap<i32, Vector>,
+    /// Shared with the CPU and every restored state
+    rom: Arc<[u8]>,
+    rom_hash: u32,
+    state_slots: StateSlots,
+    /// Kept across resets and cleared when another ROM is loaded
+    cheats: CheatList,
+    /// Reused for serializing save states
+    state_buffer: Vec<u8>,
+    shared_frame: Option<SharedFrame>,
+    rewind: RewindBuffer,
+    rewinding: bool,
+    recording: Option<Recording>,
+    /// If rendering has failed and the page hasn't been told it works again
+    render_failed: bool,
+    paused: bool,
+    speed: f32,
+    safe_area_insets: SafeAreaInsets,
+    /// If emulation is run on redraws instead of RunCPU queries
+    auto_run: bool,
+    max_catchup_ms: f32,
+    last_auto_run: Option<f64>,
+    frame_pacer: FramePacer,
+    /// Emulation time left over from runs capped to the room in the audio buffer
+    run_carry_ms: f32,
+    /// Link cable to another emulator, connected by the page
+    serial_link: Option<SerialLink>,
+    last_cpu_frame: u32,
+    pointers: HashMap<i32, Pointer>,
+    last_ram_check: f64,
+    last_underrun_check: f64,
+    last_audio_reset: f64,
+    /// Relative change of the audio sample rate by rate control
+    audio_rate_correction: f32,
+    /// Set while the page is hidden, muting audio if enabled in options
+    page_hidden: bool,
+    last_haptic: f64,
+    /// Rumble motor state last reported to the page
+    rumble_active: bool,
+    last_pacing_warning: f64,
+    stats: StatsCounter,
+    /// Set once shut down, after which requests are rejected until a new emulator is started
+    stopped: bool,
 }
 
 impl App {
-    pub fn new(event_loop: &EventLoop<UserEvent>) -> Self {
+    /// How often cartridge RAM is checked for changes.
+    /// Also debounces the callback when RAM is written to constantly
+    const RAM_CHECK_INTERVAL_MS: f64 = 1000.0;
+    /// Minimum interval between audio underrun callbacks
+    const UNDERRUN_CHECK_INTERVAL_MS: f64 = 1000.0;
+    /// Minimum interval between attempts to rebuild a failed audio stream
+    const AUDIO_RESET_INTERVAL_MS: f64 = 1000.0;
+    /// Pointers not updated for this long are released,
+    /// in case the frontend missed the release event
+    const POINTER_TIMEOUT_MS: f64 = 10000.0;
+    /// Minimum interval between vibrations, so sliding over buttons doesn't buzz continuously
+    const HAPTIC_INTERVAL_MS: f64 = 50.0;
+    /// Audio buffer fill level that audio pacing aims for
+    const AUDIO_PACING_TARGET_FILL: f32 = 0.5;
+    /// How strongly audio pacing corrects the emulated time towards the target fill
+    const AUDIO_PACING_GAIN: f32 = 1.0;
+    /// Below this fill level the emulator can't keep up with audio playback
+    const AUDIO_PACING_STARVED_FILL: f32 = 0.1;
+    /// Minimum interval between warnings about starved audio
+    const AUDIO_PACING_WARNING_INTERVAL_MS: f64 = 5000.0;
+    /// Size of the largest cartridges, larger files in zip archives aren't Game Boy ROMs
+    const MAX_ROM_SIZE: u64 = 8 * 1024 * 1024;
+    /// Largest relative change of the audio sample rate by rate control, small enough to not
+    /// be heard as a change in pitch
+    const RATE_CONTROL_MAX_CORRECTION: f32 = 0.005;
+    /// Audio buffer fill level that rate control aims for
+    const RATE_CONTROL_TARGET_FILL: f32 = 0.5;
+    /// Longest emulation time carried over to the next run when the audio buffer is full,
+    /// so audio that stops being consumed doesn't build up a burst of emulation
+    const MAX_RUN_CARRY_MS: f32 = 250.0;
+
+    pub fn new(proxy: InstanceProxy, init: AppInit) -> Self {
+        let AppInit {
+            callbacks,
+            pending,
+            target,
+            background_url,
+        } = init;
         Self {
             background_def: BackgroundDefinition::from_str(include_str!(
                 "./assets/background_definition.toml"
             )),
-            proxy: Some(event_loop.create_proxy()),
+            background_url,
+            background_image: None,
+            proxy,
+            callbacks,
+            pending,
+            target,
+            window_id: None,
             renderer: None,
             options: EmulatorOptions::default(),
             audio: AudioHandler::new(),
+            // Browsers block audio until a user gesture, desktops play it right away
+            audio_enabled: !cfg!(target_family = "wasm"),
             keyboard_input_state: InputFlag::from_bits_truncate(0),
+            builtin_keyboard_state: InputFlag::from_bits_truncate(0),
+            builtin_keyboard: true,
+            keymap: Keymap::default(),
             screen_input_state: InputFlag::from_bits_truncate(0),
             cpu: None,
-            rom: vec![],
+            rom: Arc::default(),
+            rom_hash: 0,
+            state_slots: StateSlots::default(),
+            cheats: CheatList::default(),
+            state_buffer: vec![],
+            shared_frame: None,
+            rewind: RewindBuffer::default(),
+            rewinding: false,
+            recording: None,
+            render_failed: false,
+            paused: false,
+            speed: 1.0,
+            safe_area_insets: SafeAreaInsets::default(),
+            auto_run: false,
+            max_catchup_ms: 0.0,
+            last_auto_run: None,
+            frame_pacer: FramePacer::default(),
+            run_carry_ms: 0.0,
+            serial_link: None,
             last_cpu_frame: 0,
             pointers: HashMap::new(),
+            last_ram_check: 0.0,
+            last_underrun_check: 0.0,
+            last_audio_reset: 0.0,
+            audio_rate_correction: 0.0,
+            page_hidden: false,
+            last_haptic: 0.0,
+            rumble_active: false,
+            last_pacing_warning: 0.0,
+            stats: StatsCounter::default(),
+            stopped: false,
+        }
+    }
+
+    fn stopped_error() -> BridgeError {
+        BridgeError::new(ErrorCode::EventLoopClosed, "Emulator has stopped")
+    }
+
+    /// Releases the audio stream, the renderer and the emulation, after which the app
+    /// only rejects requests and is removed from the event loop. On the web the event loop
+    /// keeps running the other emulators on the page, and new ones can be started in it
+    fn shut_down(&mut self) {
+        if let Some(cpu) = &mut self.cpu {
+            Self::flush_ram(cpu, &self.callbacks);
         }
+        self.audio.stop();
+        self.disconnect_serial();
+        // Fields are dropped in order, so the surface goes before its device
+        self.renderer = None;
+        VersionInfo::set_graphics_backend(None);
+        self.window_id = None;
+        self.cpu = None;
+        self.update_rumble();
+        self.shared_frame = None;
+        self.recording = None;
+        self.rewind.clear();
+        self.stopped = true;
+        stop_watching_for_crash(&self.pending);
     }
 
-    fn init_cpu(&mut self, rom: Vec<u8>) -> Result<ROMInfo, MemoryInitializationError> {
+    /// Sends cartridge RAM to the frontend if it has changed since last flush
+    /// and the cartridge has a battery for saving it.
+    /// The cartridge clock is saved with it, so it keeps counting while the page is closed
+    fn flush_ram(cpu: &mut CPU, callbacks: &ProxyCallbacks) {
+        let info = cpu.get_cartridge_info();
+        let should_be_saved = (info.has_ram || info.has_rtc) && info.has_battery;
+        if cpu.take_ram_dirty() && should_be_saved {
+            callbacks.call(Callback::RAMChanged(cpu.get_ram_with_rtc(unix_time_secs())));
+        }
+    }
+
+    fn init_cpu(
+        &mut self,
+        rom: Arc<[u8]>,
+        check_header: bool,
+    ) -> Result<ROMInfo, MemoryInitializationError> {
         // Hash ROM into a number that can be used to index database
         let mut hasher = Murmur3Hasher::default();
         rom.hash(&mut hasher);
         let hash = hasher.finish32();
 
-        match CPU::new(rom) {
+        let cpu = if check_header {
+            CPU::new_checked(rom)
+        } else {
+            CPU::new(rom)
+        };
+        match cpu {
             Ok(mut cpu) => {
                 // Gather info about loaded ROM
                 let info = cpu.get_cartridge_info();
                 let rom_info = ROMInfo {
                     title: info.title.clone(),
-                    should_be_saved: info.has_ram && info.has_battery,
+                    should_be_saved: (info.has_ram || info.has_rtc) && info.has_battery,
                     hash,
+                    mbc: format!("{:?}", info.mbc),
+                    rom_banks: info.rom_banks,
+                    ram_banks: info.ram_banks,
+                    has_rtc: info.has_rtc,
+                    has_rumble: info.has_rumble,
+                    has_accelerometer: info.has_accelerometer,
+                    cgb_support: format!("{:?}", info.cgb_support),
+                    sgb_support: info.sgb_support,
+                    header_checksum_valid: info.header_checksum_valid,
+                    global_checksum_valid: info.global_checksum_valid,
                 };
 
-                // Initialize audio playback
-                cpu.set_audio_sample_rate(self.audio.sample_rate);
-                let audio_consumer =
-                    cpu.init_audio_buffer(self.audio.sample_capacity, self.audio.channels);
-                self.audio.init_playback(audio_consumer);
+                self.init_audio(&mut cpu);
+                if !self.audio_enabled {
+                    self.callbacks.call(Callback::AudioBlocked(true));
+                }
+                if hash != self.rom_hash {
+                    self.cheats.clear();
+                }
+                cpu.set_cheats(self.cheats.active());
+                self.rewind.clear();
+                self.rewind.sync_frame(&cpu);
+                if self.recording.take().is_some() {
+                    log::info!("Recording aborted, as the ROM was changed");
+                }
                 self.cpu = Some(cpu);
-                self.renderer.as_ref().unwrap().window.request_redraw();
+                self.update_rumble();
+                self.rom_hash = hash;
+                record_rom_hash(hash);
+                if let Some(renderer) = &mut self.renderer {
+                    renderer.clear_frame_history();
+                    renderer.window.request_redraw();
+                }
 
                 Ok(rom_info)
             }
@@ -108,60 +511,1049 @@ impl App {
         }
     }
 
+    /// Requests a redraw, used to show changes outside of new frames and to restart auto-run
+    fn request_redraw(&self) {
+        if let Some(renderer) = &self.renderer {
+            renderer.window.request_redraw();
+        }
+    }
+
+    /// Sample rate the CPU produces audio at, lowered when sped up so the audio keeps its pitch
+    /// and corrected by rate control
+    fn audio_sample_rate(&self) -> u32 {
+        let sample_rate = self.audio.sample_rate as f32 * (1.0 + self.audio_rate_correction);
+        (sample_rate / self.speed).round() as u32
+    }
+
+    /// Nudges the audio sample rate to steer the buffer fill measured by the stream
+    /// towards the target. Producing more samples when the buffer runs low avoids crackling,
+    /// and producing fewer when it fills up avoids latency building up
+    fn update_rate_control(&mut self) {
+        let enabled = self.options.audio_rate_control
+            && !self.options.audio_pacing
+            && self.audio.is_playing();
+        let correction = if enabled {
+            let fill_error = Self::RATE_CONTROL_TARGET_FILL - self.audio.buffer_fill();
+            (fill_error * 2.0).clamp(-1.0, 1.0) * Self::RATE_CONTROL_MAX_CORRECTION
+        } else {
+            0.0
+        };
+        if correction != self.audio_rate_correction {
+            self.audio_rate_correction = correction;
+            let sample_rate = self.audio_sample_rate();
+            if let Some(cpu) = &mut self.cpu {
+                cpu.set_audio_sample_rate(sample_rate);
+            }
+        }
+    }
+
+    /// Rebuilds audio playback on the current default output device,
+    /// giving the CPU a new buffer matching its sample rate and channels
+    fn reset_audio(&mut self) -> Result<(), BridgeError> {
+        self.audio.reset_device()?;
+        if let Some(mut cpu) = self.cpu.take() {
+            self.init_audio(&mut cpu);
+            self.cpu = Some(cpu);
+        }
+        Ok(())
+    }
+
+    /// Starts audio playback once the page has had a user gesture.
+    /// Playback starts from a new buffer, so no samples from before are played
+    fn enable_audio(&mut self) {
+        if self.audio_enabled {
+            return;
+        }
+        self.audio_enabled = true;
+        if let Some(mut cpu) = self.cpu.take() {
+            self.init_audio(&mut cpu);
+            self.cpu = Some(cpu);
+        }
+        self.callbacks.call(Callback::AudioBlocked(false));
+    }
+
+    /// Rebuilds audio playback if its stream has failed, retrying at most once per interval
+    fn recover_audio(&mut self) {
+        let now = now_ms();
+        if !self.audio.has_failed() || now - self.last_audio_reset < Self::AUDIO_RESET_INTERVAL_MS {
+            return;
+        }
+        self.last_audio_reset = now;
+        match self.reset_audio() {
+            Ok(()) if !self.audio.has_failed() => {
+                log::info!("Audio recovered at {} Hz", self.audio.sample_rate);
+                self.callbacks.call(Callback::AudioRecovered {
+                    sample_rate: self.audio.sample_rate,
+                });
+            }
+            Ok(()) => {}
+            Err(e) => log::warn!("Couldn't recover audio: {}", e.message),
+        }
+    }
+
+    /// Initializes audio playback from the buffer of given CPU
+    fn init_audio(&mut self, cpu: &mut CPU) {
+        cpu.set_audio_sample_rate(self.audio_sample_rate());
+        cpu.set_audio_channel_volumes(self.options.channel_volumes());
+        if !self.audio_enabled {
+            return;
+        }
+        let audio_consumer = cpu.init_audio_buffer(self.audio.sample_capacity, self.audio.channels);
+        self.audio.init_playback(audio_consumer);
+        *self.audio.paused.write().unwrap() = self.paused || self.rewinding;
+    }
+
+    /// Deserializes a save state and replaces the CPU with it
+    fn load_state(&mut self, buffer: &[u8]) -> Result<(), postcard::Error> {
+        let deserialized = postcard::from_bytes::<CPU>(buffer)?;
+        self.restore_cpu(deserialized);
+        Ok(())
+    }
+
+    /// Replaces the CPU with one deserialized from a save state
+    fn restore_cpu(&mut self, mut deserialized: CPU) {
+        deserialized.set_rom(Arc::clone(&self.rom));
+        deserialized.set_cheats(self.cheats.active());
+        self.init_audio(&mut deserialized);
+        // Time carried over was meant for the replaced state
+        self.run_carry_ms = 0.0;
+        // Older snapshots would rewind to a different timeline
+        self.rewind.clear();
+        self.rewind.sync_frame(&deserialized);
+        if let Some(recording) = &mut self.recording {
+            recording.sync_frame(&deserialized);
+        }
+        self.cpu = Some(deserialized);
+        if let Some(renderer) = &mut self.renderer {
+            renderer.clear_frame_history();
+        }
+        self.request_redraw();
+    }
+
+    /// Restores the newest rewind snapshot and shows its display.
+    /// Audio isn't initialized, as it's muted until rewinding stops
+    fn step_rewind(&mut self) -> Result<(), postcard::Error> {
+        let Some(snapshot) = self.rewind.pop() else {
+            // Stay on the oldest state when there is nothing left to rewind
+            return Ok(());
+        };
+        let mut restored = postcard::from_bytes::<CPU>(&snapshot.state)?;
+        restored.set_rom(Arc::clone(&self.rom));
+        restored.set_cheats(self.cheats.active());
+        self.rewind.sync_frame(&restored);
+        if let Some(recording) = &mut self.recording {
+            recording.sync_frame(&restored);
+        }
+        self.last_cpu_frame = restored.frame_counter;
+        if let Some(renderer) = &mut self.renderer {
+            renderer.update_display(&snapshot.display, restored.get_sgb());
+            renderer.update_debug_overlay(&restored);
+        }
+        if let Some(shared_frame) = &self.shared_frame {
+            shared_frame.publish(&snapshot.display);
+        }
+        self.rewind.recycle(snapshot.state);
+        self.cpu = Some(restored);
+        Ok(())
+    }
+
+    /// Releases all pointers and the onscreen inputs pressed by them
+    fn release_pointers(&mut self) {
+        self.pointers.clear();
+        self.screen_input_state = InputFlag::from_bits_retain(0);
+    }
+
+    /// Releases pointers that haven't been updated in a while
+    fn drop_stale_pointers(&mut self, now: f64) {
+        let count = self.pointers.len();
+        self.pointers
+            .retain(|_, pointer| now - pointer.last_update < Self::POINTER_TIMEOUT_MS);
+        if self.pointers.len() != count {
+            self.update_screen_input();
+        }
+    }
+
+    /// Tells the page when the rumble motor of the cartridge turns on or off.
+    /// The motor is stopped while paused and without a CPU
+    fn update_rumble(&mut self) {
+        let active = !self.paused && self.cpu.as_ref().is_some_and(CPU::rumble_active);
+        if active != self.rumble_active {
+            self.rumble_active = active;
+            self.callbacks.call(Callback::Rumble(active));
+        }
+    }
+
+    /// Vibrates the device if haptics are enabled and supported by the browser
+    fn vibrate(&mut self) {
+        let now = now_ms();
+        if !self.options.haptics_enabled || now - self.last_haptic < Self::HAPTIC_INTERVAL_MS {
+            return;
+        }
+        self.last_haptic = now;
+        vibrate(self.options.haptics_duration_ms);
+    }
+
+    /// Creates the renderer for the window in the background
+    fn spawn_renderer(&self, window: Arc<Window>) {
+        let bg_def = self.background_def.clone();
+        // Run the future asynchronously and use the
+        // proxy to send the results to the event loop
+        let proxy = self.proxy.clone();
+        spawn_local(async move {
+            let result = Renderer::new(window, bg_def)
+                .await
+                .map(Box::new)
+                .map_err(|e| {
+                    BridgeError::new(
+                        ErrorCode::RendererInitFailed,
+                        "Couldn't initialize graphics",
+                    )
+                    .with_details(format!("{e:#}"))
+                });
+            let _ = proxy.send_event(UserEvent::InitRenderer(result));
+        });
+    }
+
+    /// Fetches the image of the default skin in the background, so starting doesn't wait for it
+    fn fetch_background(&self) {
+        let url = self.background_url.clone();
+        let proxy = self.proxy.clone();
+        spawn_local(async move {
+            let result = fetch_background(&url).await;
+            let _ = proxy.send_event(UserEvent::BackgroundFetched(result));
+        });
+    }
+
+    /// Replaces the renderer after its device has been lost.
+    /// Options and layout are applied again once the new renderer is initialized
+    fn recover_renderer(&mut self) {
+        let Some(renderer) = self.renderer.take() else {
+            return;
+        };
+        let window = renderer.window.clone();
+        drop(renderer);
+        // Recovery is reported on the first frame rendered successfully
+        self.render_failed = true;
+        self.callbacks
+            .call(Callback::RendererStatus(Some(BridgeError::new(
+                ErrorCode::DeviceLost,
+                "Graphics device lost, recovering",
+            ))));
+        self.spawn_renderer(window);
+    }
+
+    /// Shows a message over the display, for actions that have no other feedback on the canvas
+    fn show_osd(&mut self, text: &str) {
+        if let Some(renderer) = &mut self.renderer {
+            renderer.show_osd(text, self.options.osd_duration_ms as f64);
+        }
+    }
+
+    /// Fetches a skin in the background, the request is finished once it's shown
+    fn load_skin(&self, name: String, request: BridgeRequest) {
+        let proxy = self.proxy.clone();
+        spawn_local(async move {
+            let result = fetch_skin(&name).await;
+            // If the event loop has exited, the request has already been rejected
+            let _ = proxy.send_event(UserEvent::SkinFetched {
+                name,
+                result,
+                request,
+            });
+        });
+    }
+
+    /// Captures the next rendered frame, the request is finished once it's read back
+    fn capture_screenshot(&mut self, request: BridgeRequest) {
+        // Frames are only rendered while a ROM is loaded
+        if self.cpu.is_none() {
+            request.finish(Err(BridgeError::cpu_not_initialized()));
+            return;
+        }
+        let Some(renderer) = &mut self.renderer else {
+            request.finish(Err(BridgeError::new(
+                ErrorCode::RendererNotInitialized,
+                "Renderer isn't ready for taking screenshots",
+            )));
+            return;
+        };
+        renderer.capture_frame(move |pixels, width, height| {
+            let result = if pixels.is_empty() {
+                Err(BridgeError::new(
+                    ErrorCode::CaptureFailed,
+                    "Couldn't read back the rendered frame",
+                ))
+            } else {
+                Ok(Some(BridgeResponse::Screenshot {
+                    pixels,
+                    width,
+                    height,
+                }))
+            };
+            request.finish(result);
+        });
+    }
+
+    /// Encodes the recording in the background, the request is finished with the encoded GIF
+    fn stop_recording(&mut self, request: BridgeRequest) {
+        let Some(recording) = self.recording.take() else {
+            request.finish(Err(BridgeError::new(
+                ErrorCode::NotRecording,
+                "Recording hasn't been started",
+            )));
+            return;
+        };
+        self.show_osd("Recording stopped");
+        spawn_local(async move {
+            let result = recording
+                .encode_gif()
+                .await
+                .map(|gif| Some(BridgeResponse::RecordingEncoded(gif)));
+            request.finish(result);
+        });
+    }
+
     fn update_screen_input(&mut self) {
+        let previous_state = self.screen_input_state;
         self.screen_input_state = InputFlag::from_bits_retain(0);
+        let Some(renderer) = &self.renderer else {
+            return;
+        };
+        // The buttons can't be seen or pressed before the background has an image
+        if !renderer.has_background() {
+            return;
+        }
         for pointer in self.pointers.values() {
             // Convert pointer position to background
-            let bg_pos = self
-                .renderer
-                .as_ref()
-                .unwrap()
-                .get_pos_in_background(*pointer);
-            // Loop through inputs
-            let mut pressed_inputs = InputFlag::from_bits_retain(0);
-            for b in 0..8 {
-                let input = InputFlag::from_bits_truncate(1 << b);
-                let rect = self.background_def.get_input_rect(input);
-                // Update input if pointer is inside rectangle
-                pressed_inputs.set(input, rect.contains_point(&bg_pos))
-            }
+            let bg_pos = renderer.get_pos_in_background(pointer.pos);
+            let pressed_inputs = renderer
+                .background_definition()
+                .get_pressed_inputs(bg_pos, self.options.touch_target_scale);
             // Merge inputs from different pointers
             self.screen_input_state = self.screen_input_state.union(pressed_inputs);
         }
+        // Give feedback only when a button starts being pressed
+        if !self
+            .screen_input_state
+            .difference(previous_state)
+            .is_empty()
+        {
+            self.vibrate();
+        }
     }
-}
 
-impl ApplicationHandler<UserEvent> for App {
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        #[allow(unused_mut)]
-        let mut window_attributes = Window::default_attributes();
+    /// Runs the emulator for given amount of milliseconds or frames,
+    /// or steps back a snapshot while rewinding
+    fn run_cpu(&mut self, length: RunLength) -> Result<(), BridgeError> {
+        self.drop_stale_pointers(now_ms());
+        self.update_rate_control();
+        if self.rewinding && self.cpu.is_some() {
+            self.step_rewind().map_err(|e| {
+                BridgeError::new(ErrorCode::DeserializationFailed, "Failed to rewind")
+                    .with_details(e)
+            })
+        } else if self.update_serial_link() {
+            // The time spent waiting for the link partner is skipped instead of caught up
+            self.run_carry_ms = 0.0;
+            Ok(())
+        } else if let Some(cpu) = &mut self.cpu {
+            // Audio pacing falls back to given time when nothing consumes the audio
+            let audio_pacing = self.options.audio_pacing && self.audio.is_playing();
+            record_pc(cpu.get_registers().pc);
+            let run_start = self.callbacks.has_frame_stats().then(now_ms);
+            match length {
+                RunLength::Millis(millis) => {
+                    let millis = if audio_pacing {
+                        let fill_error =
+                            Self::AUDIO_PACING_TARGET_FILL - cpu.get_audio_buffer_fill();
+                        millis * (1.0 + fill_error * Self::AUDIO_PACING_GAIN)
+                    } else {
+                        millis
+                    };
+                    // Emulating more than the audio buffer takes would drop samples and leave
+                    // a gap in the sound, so the rest is run once playback has made room
+                    let millis = if self.audio.is_playing()
+                        && let Some(room) = cpu.get_audio_buffer_room_ms()
+                    {
+                        let requested = millis + self.run_carry_ms;
+                        let capped = requested.min(room);
+                        self.run_carry_ms = (requested - capped).min(Self::MAX_RUN_CARRY_MS);
+                        capped
+                    } else {
+                        self.run_carry_ms = 0.0;
+                        millis
+                    };
+                    cpu.run(millis);
+                }
+                // Frames are paced to the display, so audio rate control absorbs the drift
+                RunLength::Frames(frames) => {
+                    self.run_carry_ms = 0.0;
+                    for _ in 0..frames {
+                        cpu.run_frame();
+                    }
+                }
+            }
+            if let Some(run_start) = run_start {
+                self.stats.add_run(now_ms() - run_start);
+            }
+            self.rewind.update(cpu);
+            if let Some(recording) = &mut self.recording {
+                recording.update(cpu);
+            }
 
-        use wasm_bindgen::JsCast;
-        use winit::platform::web::WindowAttributesExtWebSys;
+            if audio_pacing && cpu.get_audio_buffer_fill() < Self::AUDIO_PACING_STARVED_FILL {
+                let now = now_ms();
+                if now - self.last_pacing_warning >= Self::AUDIO_PACING_WARNING_INTERVAL_MS {
+                    self.last_pacing_warning = now;
+                    log::warn!("Emulation can't keep the audio buffer filled");
+                }
+            }
+            // Send the transfer started during the run without waiting for the next one
+            self.update_serial_link();
+            Ok(())
+        } else {
+            Err(BridgeError::cpu_not_initialized())
+        }
+    }
 
-        let document = web_sys::window().unwrap_throw().document().unwrap_throw();
-        let canvas = document.get_element_by_id(CANVAS_ID).unwrap_throw();
-        let html_canvas_element = canvas.unchecked_into();
-        window_attributes = window_attributes.with_canvas(Some(html_canvas_element));
+    /// Exchanges bytes with the link cable partner, returning if emulation has to wait for its
+    /// reply. If the link has failed, it's disconnected and the page is told,
+    /// as it is when the partner disagrees on the exchanged bytes
+    fn update_serial_link(&mut self) -> bool {
+        let Some(cpu) = &mut self.cpu else {
+            return false;
+        };
+        // CPUs replaced by loading a ROM or a state start unlinked
+        cpu.set_serial_linked(self.serial_link.is_some());
+        let Some(link) = &mut self.serial_link else {
+            return false;
+        };
+        match link.update(cpu, now_ms()) {
+            Ok(desync) => {
+                let stalled = link.is_stalled(cpu);
+                if let Some(transfer) = desync {
+                    log::warn!("Link partner disagrees on transfers from #{transfer}");
+                    self.callbacks.call(Callback::NetplayDesync { transfer });
+                }
+                stalled
+            }
+            Err(error) => {
+                log::error!("{}", error.message);
+                self.disconnect_serial();
+                self.callbacks.call(Callback::SerialDisconnected(error));
+                false
+            }
+        }
+    }
 
-        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
-        let bg_def = self.background_def.clone();
-        // Run the future asynchronously and use the
-        // proxy to send the results to the event loop
-        if let Some(proxy) = self.proxy.take() {
-            wasm_bindgen_futures::spawn_local(async move {
-                assert!(
-                    proxy
-                        .send_event(UserEvent::InitRenderer(Box::new(
-                            Renderer::new(window, bg_def)
-                                .await
-                                .expect("Unable to create canvas")
-                        )))
-                        .is_ok()
+    fn disconnect_serial(&mut self) {
+        self.serial_link = None;
+        if let Some(cpu) = &mut self.cpu {
+            cpu.set_serial_linked(false);
+        }
+    }
+
+    /// Runs the emulator for the redraw, when auto-run is enabled. Whole frames are run at the
+    /// hardware rate with vsync pacing, otherwise the time elapsed since the last redraw
+    fn auto_run_cpu(&mut self) {
+        if !self.auto_run || self.paused || self.cpu.is_none() {
+            return;
+        }
+        let now = now_ms();
+        // Audio pacing follows the audio clock instead of the display
+        let audio_pacing = self.options.audio_pacing && self.audio.is_playing();
+        let length = if self.options.frame_pacing == FramePacing::Vsync && !audio_pacing {
+            self.last_auto_run = None;
+            RunLength::Frames(self.frame_pacer.frames_for_redraw(
+                now,
+                self.speed,
+                self.max_catchup_ms,
+            ))
+        } else {
+            self.frame_pacer.restart();
+            // Clamp catch-up so a throttled tab doesn't run a burst of frames when resumed
+            let elapsed = self
+                .last_auto_run
+                .map_or(0.0, |last| (now - last) as f32)
+                .min(self.max_catchup_ms);
+            self.last_auto_run = Some(now);
+            RunLength::Millis(elapsed * self.speed)
+        };
+        if let Err(e) = self.run_cpu(length) {
+            log::error!("Failed to run emulator: {}", e.message);
+        }
+        self.update_rumble();
+    }
+
+    /// Executes a query sent from the frontend
+    fn handle_query(&mut self, event_loop: &ActiveEventLoop, query: BridgeQuery) -> QueryResult {
+        use BridgeQuery as Q;
+        // Queries batched after a shutdown
+        if self.stopped {
+            return Err(Self::stopped_error());
+        }
+        match query {
+            Q::LoadROM {
+                file,
+                is_zip,
+                force,
+            } => {
+                let rom = if is_zip {
+                    use std::io::{Cursor, Read};
+                    use std::path::Path;
+
+                    let mut rom_option = None;
+                    if let Ok(mut archive) = zip::ZipArchive::new(Cursor::new(&file[..])) {
+                        // Loop through files in zip to find ROM
+                        for i in 0..archive.len() {
+                            if let Ok(mut archive_file) = archive.by_index(i) {
+                                // Choose first file inside zip that either has no extension or .gb
+                                if Path::new(archive_file.name())
+                                    .extension()
+                                    .is_none_or(|ext| ext == "gb")
+                                    && archive_file.size() <= Self::MAX_ROM_SIZE
+                                {
+                                    // Decompress straight into the allocation the emulator keeps
+                                    let mut rom: Arc<[u8]> =
+                                        std::iter::repeat_n(0, archive_file.size() as usize)
+                                            .collect();
+                                    let buffer = Arc::get_mut(&mut rom)
+                                        .expect("ROM isn't shared before it's loaded");
+                                    // Reading to the end verifies the checksum of the file
+                                    let complete = archive_file.read_exact(buffer).is_ok()
+                                        && matches!(archive_file.read(&mut [0]), Ok(0));
+                                    if complete {
+                                        rom_option = Some(rom);
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    rom_option
+                } else {
+                    Some(Arc::from(file))
+                };
+
+                if let Some(rom) = rom {
+                    // Kept only once loaded, so reloading after a rejected ROM restarts the old one
+                    let info = self.init_cpu(Arc::clone(&rom), !force.unwrap_or(false))?;
+                    self.rom = rom;
+                    Ok(Some(BridgeResponse::ROMLoaded(info)))
+                } else {
+                    Err(BridgeError::new(
+                        ErrorCode::InvalidZip,
+                        "Zip archive is invalid",
+                    ))
+                }
+            }
+            Q::Reload { preserve_sram } => {
+                // Battery backed RAM survives a reset on real hardware
+                let saved_ram = self
+                    .cpu
+                    .as_ref()
+                    .filter(|cpu| {
+                        let info = cpu.get_cartridge_info();
+                        (info.has_ram || info.has_rtc) && preserve_sram.unwrap_or(info.has_battery)
+                    })
+                    .map(|cpu| cpu.get_ram_with_rtc(unix_time_secs()));
+                // The ROM has been loaded before, so its header isn't checked again
+                match self.init_cpu(Arc::clone(&self.rom), false) {
+                    Ok(_) => {
+                        let sram_preserved = match (saved_ram, &mut self.cpu) {
+                            (Some(ram), Some(cpu)) => {
+                                cpu.set_ram_with_rtc(ram, unix_time_secs()).is_ok()
+                            }
+                            _ => false,
+                        };
+                        self.show_osd("Reset");
+                        Ok(Some(BridgeResponse::Reloaded { sram_preserved }))
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            }
+            Q::LoadRAM { ram } => {
+                if let Some(cpu) = &mut self.cpu {
+                    match cpu.set_ram_with_rtc(ram, unix_time_secs()) {
+                        Ok(_) => {
+                            log::info!("RAM set");
+                            Ok(None)
+                        }
+                        Err(e) => Err(e.into()),
+                    }
+                } else {
+                    Err(BridgeError::cpu_not_initialized())
+                }
+            }
+            Q::RunCPU { millis } => {
+                self.run_cpu(RunLength::Millis(millis))?;
+                self.update_rumble();
+                // Completed frames are shown on the next redraw
+                if self
+                    .cpu
+                    .as_ref()
+                    .is_some_and(|cpu| cpu.frame_counter != self.last_cpu_frame)
+                {
+                    self.request_redraw();
+                }
+                Ok(None)
+            }
+            Q::SetAutoRun {
+                enabled,
+                max_catchup_ms,
+            } => {
+                self.auto_run = enabled;
+                self.max_catchup_ms = max_catchup_ms;
+                self.last_auto_run = None;
+                self.frame_pacer.restart();
+                self.request_redraw();
+                Ok(None)
+            }
+            Q::SaveRAM {} => {
+                if let Some(cpu) = &self.cpu {
+                    Ok(Some(BridgeResponse::RAMSaved(
+                        cpu.get_ram_with_rtc(unix_time_secs()),
+                    )))
+                } else {
+                    Err(BridgeError::cpu_not_initialized())
+                }
+            }
+            Q::SerializeCPU {} => {
+                if let Some(cpu) = &self.cpu {
+                    // The response is copied to the page, so the grown buffer is kept
+                    // and the response takes a single allocation of the exact size
+                    match cpu.save_state_into(&mut self.state_buffer) {
+                        Ok(()) => Ok(Some(BridgeResponse::CPUSerialized(
+                            self.state_buffer.clone(),
+                        ))),
+                        Err(e) => Err(BridgeError::new(
+                            ErrorCode::SerializationFailed,
+                            "Failed to serialize",
+                        )
+                        .with_details(e)),
+                    }
+                } else {
+                    Err(BridgeError::cpu_not_initialized())
+                }
+            }
+            Q::DeserializeCPU { buffer } => match self.load_state(&buffer) {
+                Ok(_) => {
+                    self.show_osd("State loaded");
+                    Ok(None)
+                }
+                Err(e) => Err(BridgeError::new(
+                    ErrorCode::DeserializationFailed,
+                    "Failed to deserialize",
                 )
-            });
+                .with_details(e)),
+            },
+            Q::SaveStateSlot { slot } => {
+                if let Some(cpu) = &self.cpu {
+                    let result = match cpu.save_state_into(&mut self.state_buffer) {
+                        Ok(()) => self
+                            .state_slots
+                            .save(
+                                slot,
+                                StateSlot {
+                                    state: self.state_buffer.clone(),
+                                    rom_hash: self.rom_hash,
+                                    timestamp: date_now_ms(),
+                                    display: *cpu.get_display_buffer(),
+                                },
+                            )
+                            .map_err(BridgeError::from),
+                        Err(e) => Err(BridgeError::new(
+                            ErrorCode::SerializationFailed,
+                            "Failed to serialize",
+                        )
+                        .with_details(e)),
+                    };
+                    if result.is_ok() {
+                        self.show_osd(&format!("State {slot} saved"));
+                    }
+                    result.map(|_| None)
+                } else {
+                    Err(BridgeError::cpu_not_initialized())
+                }
+            }
+            Q::LoadStateSlot { slot } => {
+                if self.cpu.is_none() {
+                    return Err(BridgeError::cpu_not_initialized());
+                }
+                let state_slot = self.state_slots.load(slot, self.rom_hash)?;
+                match postcard::from_bytes::<CPU>(&state_slot.state) {
+                    Ok(deserialized) => {
+                        self.restore_cpu(deserialized);
+                        self.show_osd(&format!("State {slot} loaded"));
+                        Ok(None)
+                    }
+                    Err(e) => Err(BridgeError::new(
+                        ErrorCode::DeserializationFailed,
+                        "Failed to deserialize",
+                    )
+                    .with_details(e)),
+                }
+            }
+            Q::EnableSharedFrame {} => {
+                if self.shared_frame.is_none() {
+                    self.shared_frame = SharedFrame::new();
+                }
+                let buffer = self.shared_frame.as_ref().map(SharedFrame::buffer);
+                Ok(Some(BridgeResponse::SharedFrameEnabled(buffer)))
+            }
+            Q::ListStateSlots {} => {
+                let slots = self.state_slots.list(self.rom_hash);
+                Ok(Some(BridgeResponse::StateSlotsListed(slots)))
+            }
+            Q::AddCheat { kind, code } => {
+                let Some(cpu) = &mut self.cpu else {
+                    return Err(BridgeError::cpu_not_initialized());
+                };
+                let cheat = Cheat::parse(kind.into(), &code)?;
+                let id = self.cheats.add(kind, code, cheat);
+                cpu.set_cheats(self.cheats.active());
+                Ok(Some(BridgeResponse::CheatAdded(id)))
+            }
+            Q::RemoveCheat { id } => {
+                if !self.cheats.remove(id) {
+                    return Err(BridgeError::cheat_not_found(id));
+                }
+                if let Some(cpu) = &mut self.cpu {
+                    cpu.set_cheats(self.cheats.active());
+                }
+                Ok(None)
+            }
+            Q::SetCheatEnabled { id, enabled } => {
+                if !self.cheats.set_enabled(id, enabled) {
+                    return Err(BridgeError::cheat_not_found(id));
+                }
+                if let Some(cpu) = &mut self.cpu {
+                    cpu.set_cheats(self.cheats.active());
+                }
+                Ok(None)
+            }
+            Q::ListCheats {} => Ok(Some(BridgeResponse::CheatsListed(self.cheats.list()))),
+            Q::SetRewindEnabled { seconds_budget } => {
+                self.rewind.set_budget(seconds_budget);
+                if !self.rewind.is_enabled() {
+                    self.rewind.clear();
+                    self.rewinding = false;
+                }
+                Ok(None)
+            }
+            Q::Rewind { active } => {
+                if active == self.rewinding || !self.rewind.is_enabled() {
+                    return Ok(None);
+                }
+                self.rewinding = active;
+                if active {
+                    // Mute audio while rewinding
+                    *self.audio.paused.write().unwrap() = true;
+                    self.show_osd("Rewinding");
+                } else if let Some(mut cpu) = self.cpu.take() {
+                    // Restored states have no audio buffer,
+                    // so playback is resumed from the current state
+                    self.init_audio(&mut cpu);
+                    self.cpu = Some(cpu);
+                }
+                Ok(None)
+            }
+            Q::AdvanceFrame { count } => {
+                if let Some(cpu) = &mut self.cpu {
+                    // Stepped frames would otherwise play as a burst of sound
+                    cpu.set_audio_muted(true);
+                    record_pc(cpu.get_registers().pc);
+                    for _ in 0..count {
+                        cpu.run_frame();
+                    }
+                    cpu.set_audio_muted(false);
+                    self.rewind.update(cpu);
+                    if let Some(recording) = &mut self.recording {
+                        recording.update(cpu);
+                    }
+                    if let Some(renderer) = &mut self.renderer {
+                        renderer.update_display(cpu.get_display_buffer(), cpu.get_sgb());
+                        renderer.update_debug_overlay(cpu);
+                        renderer.window.request_redraw();
+                    }
+                    if let Some(shared_frame) = &self.shared_frame {
+                        shared_frame.publish(cpu.get_display_buffer());
+                    }
+                    self.last_cpu_frame = cpu.frame_counter;
+                    Ok(Some(BridgeResponse::FrameAdvanced(cpu.frame_counter)))
+                } else {
+                    Err(BridgeError::cpu_not_initialized())
+                }
+            }
+            Q::SetPaused { paused } => {
+                self.paused = paused;
+                // Time spent paused isn't caught up when resumed
+                self.last_auto_run = None;
+                self.frame_pacer.restart();
+                self.release_pointers();
+                *self.audio.paused.write().unwrap() = paused || self.rewinding;
+                // Save changes immediately, as the page might be closed while paused
+                if paused && let Some(cpu) = &mut self.cpu {
+                    Self::flush_ram(cpu, &self.callbacks);
+                }
+                if let Some(renderer) = &mut self.renderer {
+                    renderer.set_paused(paused);
+                }
+                self.update_rumble();
+                // Restart the redraw loop, pausing stops it after the next frame.
+                // A frame is also rendered when already stopped, to show the pause indicator
+                self.request_redraw();
+                Ok(None)
+            }
+            Q::SetSpeed { speed } => {
+                if speed != self.speed {
+                    self.show_osd(&format!("Speed {speed}x"));
+                }
+                self.speed = speed;
+                // Update audio sample speed
+                let sample_rate = self.audio_sample_rate();
+                if let Some(cpu) = &mut self.cpu {
+                    cpu.set_audio_sample_rate(sample_rate);
+                }
+                Ok(None)
+            }
+            Q::UpdateInput { input, pressed } => {
+                if pressed {
+                    self.enable_audio();
+                }
+                if let Some(input_flag) = input_from_name(&input) {
+                    self.keyboard_input_state.set(input_flag, pressed);
+                }
+                Ok(None)
+            }
+            Q::UpdateTilt { x, y } => {
+                if let Some(cpu) = &mut self.cpu {
+                    cpu.set_accelerometer(x, y);
+                }
+                Ok(None)
+            }
+            Q::SetKeybinds { keybinds } => match Keymap::from_names(keybinds) {
+                Ok(keymap) => {
+                    self.keymap = keymap;
+                    self.builtin_keyboard_state = InputFlag::from_bits_retain(0);
+                    Ok(None)
+                }
+                Err(name) => Err(BridgeError::new(
+                    ErrorCode::UnknownInput,
+                    format!("Unknown input: {name}"),
+                )),
+            },
+            Q::SetBuiltinKeyboard { enabled } => {
+                self.builtin_keyboard = enabled;
+                self.builtin_keyboard_state = InputFlag::from_bits_retain(0);
+                Ok(None)
+            }
+            Q::ResetAudio {} => self.reset_audio().map(|_| None),
+            Q::EnableAudio {} => {
+                self.enable_audio();
+                Ok(None)
+            }
+            Q::UpdatePointerPos { id, pos } => {
+                if let Some(pointer) = self.pointers.get_mut(&id) {
+                    pointer.pos = Vector::new(Fp::from(pos[0]), Fp::from(pos[1]));
+                    pointer.last_update = now_ms();
+                    self.update_screen_input();
+                }
+                Ok(None)
+            }
+            Q::UpdatePointerPressed { id, pressed } => {
+                if pressed {
+                    self.enable_audio();
+                    self.pointers.insert(
+                        id,
+                        Pointer {
+                            pos: Vector::default(),
+                            last_update: now_ms(),
+                        },
+                    );
+                } else {
+                    self.pointers.remove(&id);
+                }
+                self.update_screen_input();
+                Ok(None)
+            }
+            Q::ReleasePointers {} => {
+                self.release_pointers();
+                Ok(None)
+            }
+            Q::SetPageHidden { hidden } => {
+                self.page_hidden = hidden;
+                // Muting is separate from pausing, so a paused emulator stays paused
+                // and a running one keeps running either way
+                self.audio
+                    .set_muted(hidden && self.options.mute_in_background);
+                // Hidden pages may be discarded by the browser without warning
+                if hidden && let Some(cpu) = &mut self.cpu {
+                    Self::flush_ram(cpu, &self.callbacks);
+                }
+                Ok(None)
+            }
+            Q::SetSafeAreaInsets { insets } => {
+                // Kept for when the renderer is initialized
+                self.safe_area_insets = insets;
+                if let Some(renderer) = &mut self.renderer {
+                    renderer.set_safe_area_insets(insets);
+                    renderer.window.request_redraw();
+                }
+                Ok(None)
+            }
+            Q::SelectSkin { name } => {
+                if let Some(renderer) = &mut self.renderer
+                    && renderer.select_skin(&name)
+                {
+                    renderer.window.request_redraw();
+                    Ok(None)
+                } else {
+                    Err(BridgeError::new(
+                        ErrorCode::SkinNotFound,
+                        format!("Skin \"{name}\" isn't loaded"),
+                    ))
+                }
+            }
+            Q::ReloadBackgroundDefinition { definition } => {
+                let Some(renderer) = &mut self.renderer else {
+                    return Err(BridgeError::new(
+                        ErrorCode::RendererNotInitialized,
+                        "Renderer isn't initialized yet",
+                    ));
+                };
+                let definition = BackgroundDefinition::try_from_str(&definition).map_err(|e| {
+                    BridgeError::new(ErrorCode::InvalidSkin, "Skin definition is invalid")
+                        .with_details(e)
+                })?;
+                renderer.set_background_definition(definition.clone())?;
+                // Kept for when the renderer is recreated
+                if renderer.skin() == DEFAULT_SKIN {
+                    self.background_def = definition;
+                }
+                renderer.window.request_redraw();
+                Ok(None)
+            }
+            Q::TakeScreenshot {
+                include_effects: false,
+            } => {
+                let Some(cpu) = &self.cpu else {
+                    return Err(BridgeError::cpu_not_initialized());
+                };
+                Ok(Some(BridgeResponse::Screenshot {
+                    pixels: display_pixels(
+                        cpu.get_display_buffer(),
+                        &self.options.palette,
+                        cpu.get_sgb(),
+                    ),
+                    width: 160,
+                    height: 144,
+                }))
+            }
+            // Handled in user_event, as the frame is read back asynchronously
+            Q::TakeScreenshot {
+                include_effects: true,
+            } => Err(BridgeError::new(
+                ErrorCode::NotBatchable,
+                "Screenshots with effects can't be taken in a batch",
+            )),
+            Q::StartRecording { max_seconds } => {
+                let Some(cpu) = &self.cpu else {
+                    return Err(BridgeError::cpu_not_initialized());
+                };
+                // Starting again discards the previous recording
+                self.recording = Some(Recording::new(cpu, self.options.palette, max_seconds));
+                self.show_osd("Recording");
+                Ok(None)
+            }
+            Q::StartAudioCapture { max_seconds } => {
+                // Starting again discards the previous capture
+                self.audio.start_capture(max_seconds);
+                self.show_osd("Capturing audio");
+                Ok(None)
+            }
+            Q::StopAudioCapture {} => {
+                let capture = self.audio.stop_capture().ok_or_else(|| {
+                    BridgeError::new(
+                        ErrorCode::NotCapturingAudio,
+                        "Audio capture hasn't been started",
+                    )
+                })?;
+                self.show_osd("Audio capture stopped");
+                Ok(Some(BridgeResponse::AudioCaptured(capture.encode_wav())))
+            }
+            // Handled in user_event, as encoding is done in the background
+            Q::StopRecording {} => Err(BridgeError::new(
+                ErrorCode::NotBatchable,
+                "Recording can't be stopped in a batch",
+            )),
+            Q::UpdateOptions { options } => {
+                // Update renderer options
+                if let Some(renderer) = &mut self.renderer {
+                    renderer.update_options(&options);
+                    // An enabled overlay shows the current frame right away, even while paused
+                    if let Some(cpu) = &self.cpu {
+                        renderer.update_debug_overlay(cpu);
+                    }
+                }
+                // Update audio volume. Channel volumes apply to new samples,
+                // so they're heard once the buffered ones have played
+                *self.audio.volume.write().unwrap() = AudioHandler::volume_gain(options.volume);
+                if let Some(cpu) = &mut self.cpu {
+                    cpu.set_audio_channel_volumes(options.channel_volumes());
+                }
+                // A new buffer capacity needs a new buffer and stream
+                if self.audio.set_latency(options.audio_latency_ms)
+                    && let Some(mut cpu) = self.cpu.take()
+                {
+                    self.init_audio(&mut cpu);
+                    self.cpu = Some(cpu);
+                }
+                self.audio
+                    .set_muted(self.page_hidden && options.mute_in_background);
+                self.options = options;
+                self.request_redraw();
+                Ok(None)
+            }
+            Q::Batch {
+                queries,
+                stop_on_error,
+            } => {
+                let mut results = Vec::with_capacity(queries.len());
+                for query in queries {
+                    let result = self.handle_query(event_loop, query);
+                    let failed = result.is_err();
+                    results.push(result);
+                    if failed && stop_on_error {
+                        break;
+                    }
+                }
+                Ok(Some(BridgeResponse::Batch(results)))
+            }
+            Q::SerialDisconnect {} => {
+                self.disconnect_serial();
+                Ok(None)
+            }
+            Q::Shutdown {} => {
+                self.shut_down();
+                // The native window closes with its event loop
+                if !cfg!(target_family = "wasm") {
+                    event_loop.exit();
+                }
+                Ok(None)
+            }
         }
     }
+}
+
+impl ApplicationHandler<UserEvent> for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        let window_attributes = self.target.window_attributes();
+        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+        self.window_id = Some(window.id());
+        self.spawn_renderer(window);
+        self.fetch_background();
+    }
 
     fn window_event(
         &mut self,
@@ -172,35 +1564,152 @@ impl ApplicationHandler<UserEvent> for App {
         if self.renderer.is_none() {
             return;
         }
+        if self.renderer.as_ref().is_some_and(Renderer::is_device_lost) {
+            self.recover_renderer();
+            return;
+        }
+        // Run emulation before rendering, so the newest frame is shown
+        if matches!(event, WindowEvent::RedrawRequested) {
+            self.recover_audio();
+            self.auto_run_cpu();
+        }
         let renderer = self.renderer.as_mut().unwrap();
 
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
-            WindowEvent::Resized(size) => renderer.resize(size.width, size.height),
+            WindowEvent::Resized(size) => {
+                renderer.resize(size.width, size.height);
+                renderer.window.request_redraw();
+            }
+            // Zooming or moving between screens changes the amount of physical pixels
+            WindowEvent::ScaleFactorChanged {
+                scale_factor,
+                mut inner_size_writer,
+            } => {
+                // Keep the canvas at its CSS size
+                let size = self.target.physical_size(&renderer.window, scale_factor);
+                let _ = inner_size_writer.request_inner_size(size);
+                renderer.resize(size.width, size.height);
+                renderer.window.request_redraw();
+            }
+            WindowEvent::KeyboardInput { event, .. } => {
+                if !self.builtin_keyboard || event.repeat {
+                    return;
+                }
+                if event.state.is_pressed() {
+                    self.enable_audio();
+                }
+                if let PhysicalKey::Code(code) = event.physical_key
+                    && let Some(input) = self.keymap.get(code)
+                {
+                    self.builtin_keyboard_state
+                        .set(input, event.state.is_pressed());
+                }
+            }
+            // Release inputs, as release events are missed while unfocused
+            WindowEvent::Focused(false) => {
+                self.builtin_keyboard_state = InputFlag::from_bits_retain(0);
+                self.release_pointers();
+            }
             WindowEvent::RedrawRequested => {
                 if let Some(cpu) = &mut self.cpu {
+                    let measure_stats = self.callbacks.has_frame_stats();
+                    if measure_stats {
+                        let new_frames = cpu.frame_counter.wrapping_sub(self.last_cpu_frame);
+                        self.stats.add_frames(new_frames);
+                    }
                     // Update buffer only when there is new frame available
                     if self.last_cpu_frame != cpu.frame_counter {
-                        renderer.update_display(cpu.get_display_buffer());
+                        renderer.update_display(cpu.get_display_buffer(), cpu.get_sgb());
+                        renderer.update_debug_overlay(cpu);
+                        if let Some(shared_frame) = &self.shared_frame {
+                            shared_frame.publish(cpu.get_display_buffer());
+                        }
                         self.last_cpu_frame = cpu.frame_counter;
                     }
                     // Update input
+                    let keyboard_input =
+                        self.keyboard_input_state.union(self.builtin_keyboard_state);
                     let input = if self.screen_input_state.bits() == 0 {
-                        &self.keyboard_input_state
+                        &keyboard_input
                     } else {
                         &self.screen_input_state
                     };
                     cpu.update_input(input);
+                    renderer.update_pressed_inputs(*input);
 
+                    // Check for changes in RAM periodically
+                    let now = now_ms();
+                    if now - self.last_ram_check >= Self::RAM_CHECK_INTERVAL_MS {
+                        self.last_ram_check = now;
+                        Self::flush_ram(cpu, &self.callbacks);
+                    }
+                    // Report audio underruns, rate limited to one callback per interval
+                    let window_ms = now - self.last_underrun_check;
+                    if window_ms >= Self::UNDERRUN_CHECK_INTERVAL_MS {
+                        self.last_underrun_check = now;
+                        let count = self.audio.take_underruns();
+                        if count > 0 {
+                            self.callbacks
+                                .call(Callback::AudioUnderrun { count, window_ms });
+                        }
+                    }
+
+                    let render_start = if measure_stats { now_ms() } else { 0.0 };
                     match renderer.render() {
-                        Ok(_) => {}
+                        Ok(_) => {
+                            if self.render_failed {
+                                self.render_failed = false;
+                                self.callbacks.call(Callback::RendererStatus(None));
+                            }
+                        }
                         // Reconfigure the surface if it's lost or outdated
                         Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
                             let size = renderer.window.inner_size();
                             renderer.resize(size.width, size.height);
                         }
+                        // Report failures once, instead of every frame
                         Err(e) => {
                             log::error!("Unable to render {}", e);
+                            if !self.render_failed {
+                                self.render_failed = true;
+                                self.callbacks.call(Callback::RendererStatus(Some(
+                                    BridgeError::new(
+                                        ErrorCode::RenderFailed,
+                                        "Couldn't render a frame",
+                                    )
+                                    .with_details(e),
+                                )));
+                            }
+                        }
+                    }
+                    // Auto-run emulation is timed by redraws, so keep them coming while it runs.
+                    // The renderer skips frames where nothing changed, to let the GPU idle
+                    if self.auto_run && !self.paused {
+                        renderer.window.request_redraw();
+                    }
+
+                    if measure_stats {
+                        let now = now_ms();
+                        self.stats.add_render(now - render_start);
+                        if let Some(mut stats) = self.stats.take_if_elapsed(
+                            now,
+                            self.callbacks.frame_stats_interval_ms(),
+                            cpu.get_audio_buffer_fill(),
+                        ) {
+                            stats.present_mode = format!("{:?}", renderer.present_mode());
+                            stats.audio_rate_correction = self.audio_rate_correction;
+                            stats.audio_device_buffer_ms = self.audio.device_buffer_ms();
+                            let (uniforms, display) = renderer.take_write_counts();
+                            stats.uniform_writes = uniforms.written;
+                            stats.uniform_writes_skipped = uniforms.skipped;
+                            stats.display_uploads = display.written;
+                            stats.display_uploads_skipped = display.skipped;
+                            let pacing = self.frame_pacer.take_counts();
+                            stats.repeated_frames = pacing.repeated_frames;
+                            stats.dropped_frames = pacing.dropped_frames;
+                            stats.refresh_rate = pacing.refresh_rate;
+                            self.callbacks.call(Callback::FrameStats(stats));
                         }
                     }
                 }
@@ -209,174 +1718,134 @@ impl ApplicationHandler<UserEvent> for App {
         }
     }
 
-    fn user_event(&mut self, _event_loop: &ActiveEventLoop, event: UserEvent) {
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        PendingRequests::reject_all(&self.pending, Self::stopped_error());
+    }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
+        // Requests of a stopped emulator, or sent through the proxy of an earlier one
+        if self.stopped
+            || event
+                .request()
+                .is_some_and(|request| !request.is_from(&self.pending))
+        {
+            if let Some(request) = event.into_request() {
+                request.finish(Err(Self::stopped_error()));
+            }
+            return;
+        }
         match event {
-            UserEvent::InitRenderer(mut renderer) => {
+            UserEvent::InitRenderer(Err(error)) => {
+                log::error!("{}: {:?}", error.message, error.details);
+                self.callbacks.call(Callback::RendererStatus(Some(error)));
+            }
+            UserEvent::InitRenderer(Ok(renderer))
+                if self.window_id != Some(renderer.window.id()) =>
+            {
+                log::info!("Discarding renderer created for a previous window");
+            }
+            UserEvent::InitRenderer(Ok(mut renderer)) => {
                 log::info!("Renderer initialized");
                 renderer.window.request_redraw();
-                renderer.resize(
-                    renderer.window.inner_size().width,
-                    renderer.window.inner_size().height,
-                );
+                renderer.set_safe_area_insets(self.safe_area_insets);
+                let size = self
+                    .target
+                    .physical_size(&renderer.window, renderer.window.scale_factor());
+                renderer.resize(size.width, size.height);
                 renderer.update_options(&self.options);
+                renderer.set_paused(self.paused);
+                if let Some(image) = &self.background_image
+                    && let Err(e) = renderer.set_default_background(image)
+                {
+                    log::error!("{}", e.message);
+                }
+                // A recreated renderer shows the display right away
+                if let Some(cpu) = &self.cpu {
+                    renderer.update_display(cpu.get_display_buffer(), cpu.get_sgb());
+                    renderer.update_debug_overlay(cpu);
+                }
+                VersionInfo::set_graphics_backend(Some(renderer.backend()));
                 self.renderer = Some(*renderer);
             }
+            // The emulator runs without the background, so failures are only reported
+            UserEvent::BackgroundFetched(result) => {
+                let result = result.and_then(|image| {
+                    if let Some(renderer) = &mut self.renderer {
+                        renderer.set_default_background(&image)?;
+                    }
+                    self.background_image = Some(image);
+                    Ok(())
+                });
+                if let Err(error) = result {
+                    log::error!("{}", error.message);
+                    self.callbacks.call(Callback::Error(error));
+                }
+            }
             UserEvent::Query(mut request) => {
-                use BridgeQuery as Q;
                 let query = request.query.take().unwrap();
-                match query {
-                    Q::LoadROM { file, is_zip } => {
-                        let rom = if is_zip {
-                            use std::io::{BufReader, Cursor, Read, Result};
-                            use std::path::Path;
-
-                            let mut rom_option = None;
-                            if let Ok(mut archive) = zip::ZipArchive::new(Cursor::new(&file[..])) {
-                                // Loop through files in zip to find ROM
-                                for i in 0..archive.len() {
-                                    if let Ok(archive_file) = archive.by_index(i) {
-                                        // Choose first file inside zip that either has no extension or .gb
-                                        if Path::new(archive_file.name())
-                                            .extension()
-                                            .is_none_or(|ext| ext == "gb")
-                                        {
-                                            let buf = BufReader::new(archive_file);
-                                            let rom_result: Result<Vec<u8>> = buf.bytes().collect();
-                                            if let Ok(deflated_rom) = rom_result {
-                                                rom_option = Some(deflated_rom);
-                                                break;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            rom_option
-                        } else {
-                            Some(file)
-                        };
-
-                        if let Some(rom) = rom {
-                            match self.init_cpu(rom.clone()) {
-                                Ok(info) => request.respond(BridgeResponse::ROMLoaded(info)),
-                                Err(e) => request.reject(&e.to_string()),
-                            }
-                            self.rom = rom;
-                        } else {
-                            request.reject("Zip archive is invalid");
-                        }
+                // Queries that finish asynchronously keep the request until they're done
+                match &query {
+                    // Skins that aren't loaded are fetched before finishing the request
+                    BridgeQuery::SelectSkin { name }
+                        if self
+                            .renderer
+                            .as_ref()
+                            .is_some_and(|renderer| !renderer.has_skin(name)) =>
+                    {
+                        self.load_skin(name.clone(), request);
+                        return;
                     }
-                    Q::Reload {} => match self.init_cpu(self.rom.clone()) {
-                        Ok(_) => request.resolve(),
-                        Err(e) => request.reject(&e.to_string()),
-                    },
-                    Q::LoadRAM { ram } => {
-                        if let Some(cpu) = &mut self.cpu {
-                            cpu.set_ram(ram);
-                            log::info!("RAM set");
-                            request.resolve();
-                        } else {
-                            request.reject("CPU not initialized");
-                        }
+                    BridgeQuery::TakeScreenshot {
+                        include_effects: true,
+                    } => {
+                        self.capture_screenshot(request);
+                        return;
                     }
-                    Q::RunCPU { millis } => {
-                        if let Some(cpu) = &mut self.cpu {
-                            cpu.run(millis);
-                            request.resolve();
-                        } else {
-                            request.reject("CPU not initialized");
-                        }
-                    }
-                    Q::SaveRAM {} => {
-                        if let Some(cpu) = &self.cpu {
-                            request.respond(BridgeResponse::RAMSaved(cpu.get_ram()));
-                        } else {
-                            request.reject("CPU not initialized");
-                        }
-                    }
-                    Q::SerializeCPU {} => {
-                        if let Some(cpu) = &self.cpu {
-                            match postcard::to_stdvec(&cpu) {
-                                Ok(serialized) => {
-                                    request.respond(BridgeResponse::CPUSerialized(serialized));
-                                }
-                                Err(e) => request.reject(&format!("Failed to serialize: {e}")),
-                            };
-                        } else {
-                            request.reject("CPU not initialized");
-                        }
-                    }
-                    Q::DeserializeCPU { buffer } => match postcard::from_bytes::<CPU>(&buffer) {
-                        Ok(mut deserialized) => {
-                            deserialized.set_rom(self.rom.clone());
-                            deserialized.set_audio_sample_rate(self.audio.sample_rate);
-                            let audio_consumer = deserialized
-                                .init_audio_buffer(self.audio.sample_capacity, self.audio.channels);
-                            self.audio.init_playback(audio_consumer);
-                            self.cpu = Some(deserialized);
-                            request.resolve();
-                        }
-                        Err(e) => request.reject(&format!("Failed to deserialize: {e}")),
-                    },
-                    Q::SetPaused { paused } => {
-                        *self.audio.paused.write().unwrap() = paused;
-                        request.resolve();
-                    }
-                    Q::SetSpeed { speed } => {
-                        // Update audio sample speed
-                        if let Some(cpu) = &mut self.cpu {
-                            let new_sample_rate = if speed == 1.0 {
-                                self.audio.sample_rate
-                            } else {
-                                ((self.audio.sample_rate as f32) / speed) as u32
-                            };
-                            cpu.set_audio_sample_rate(new_sample_rate);
-                        }
-                        request.resolve();
-                    }
-                    Q::UpdateInput { input, pressed } => {
-                        let input_option = match input.as_str() {
-                            "D-Pad Right" => Some(InputFlag::RIGHT),
-                            "D-Pad Left" => Some(InputFlag::LEFT),
-                            "D-Pad Up" => Some(InputFlag::UP),
-                            "D-Pad Down" => Some(InputFlag::DOWN),
-                            "A" => Some(InputFlag::A),
-                            "B" => Some(InputFlag::B),
-                            "Select" => Some(InputFlag::SELECT),
-                            "Start" => Some(InputFlag::START),
-                            _ => None,
-                        };
-                        if let Some(input_flag) = input_option {
-                            self.keyboard_input_state.set(input_flag, pressed);
-                        }
-                        request.resolve();
-                    }
-                    Q::UpdatePointerPos { id, pos } => {
-                        if let Some(pointer) = self.pointers.get_mut(&id) {
-                            *pointer = Vector::new(Fp::from(pos[0]), Fp::from(pos[1]));
-                            self.update_screen_input();
-                        }
-                        request.resolve();
-                    }
-                    Q::UpdatePointerPressed { id, pressed } => {
-                        if pressed {
-                            self.pointers.insert(id, Vector::default());
-                        } else {
-                            self.pointers.remove(&id);
-                        }
-                        self.update_screen_input();
-                        request.resolve();
-                    }
-                    Q::UpdateOptions { options } => {
-                        // Update renderer options
-                        if let Some(renderer) = &mut self.renderer {
-                            renderer.update_options(&options);
-                        }
-                        // Update audio volume
-                        *self.audio.volume.write().unwrap() = options.volume;
-                        self.options = options;
-                        request.resolve();
+                    BridgeQuery::StopRecording {} => {
+                        self.stop_recording(request);
+                        return;
                     }
+                    _ => {}
+                }
+                let result = self.handle_query(event_loop, query);
+                request.finish(result);
+                if self.stopped {
+                    PendingRequests::reject_all(&self.pending, Self::stopped_error());
+                }
+            }
+            #[cfg(not(target_family = "wasm"))]
+            UserEvent::LocalQuery(query) => {
+                if let Err(error) = self.handle_query(event_loop, query) {
+                    log::error!("{}", error.message);
+                }
+            }
+            UserEvent::SkinFetched {
+                name,
+                result,
+                request,
+            } => {
+                let result = result.and_then(|files| {
+                    let renderer = self.renderer.as_mut().ok_or_else(|| {
+                        BridgeError::new(
+                            ErrorCode::RendererNotInitialized,
+                            "Renderer was recreated while fetching the skin",
+                        )
+                    })?;
+                    renderer.add_skin(name.clone(), files)?;
+                    renderer.select_skin(&name);
+                    renderer.window.request_redraw();
+                    Ok(None)
+                });
+                request.finish(result);
+            }
+            UserEvent::SerialConnect { link, request } => {
+                // A transfer waiting for the previous partner would never get a reply
+                self.disconnect_serial();
+                self.serial_link = Some(link);
+                if let Some(cpu) = &mut self.cpu {
+                    cpu.set_serial_linked(true);
                 }
+                request.finish(Ok(None));
             }
         }
     }
@@ -0,0 +1,118 @@
+#[cfg(target_family = "wasm")]
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+#[cfg(target_family = "wasm")]
+use tsify::Tsify;
+
+/// Most severe level of the messages that are logged
+#[cfg(target_family = "wasm")]
+#[derive(Tsify, Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[cfg(target_family = "wasm")]
+impl From<LogLevel> for log::LevelFilter {
+    fn from(value: LogLevel) -> Self {
+        match value {
+            LogLevel::Off => Self::Off,
+            LogLevel::Error => Self::Error,
+            LogLevel::Warn => Self::Warn,
+            LogLevel::Info => Self::Info,
+            LogLevel::Debug => Self::Debug,
+            LogLevel::Trace => Self::Trace,
+        }
+    }
+}
+
+/// Logging options given when spawning the emulator, the desktop logs with `RUST_LOG` instead
+#[cfg(target_family = "wasm")]
+#[derive(Tsify, Debug, Clone, Default, Deserialize)]
+pub struct LogOptions {
+    /// Info by default
+    #[tsify(optional)]
+    pub level: Option<LogLevel>,
+    /// Keeps the latest log lines for `Proxy.get_log_buffer`, besides logging them to the console
+    #[serde(default)]
+    #[tsify(optional)]
+    pub capture: bool,
+}
+
+/// Logs to the browser console, optionally keeping the latest lines for bug reports
+struct PageLogger {
+    captured: Mutex<Option<VecDeque<String>>>,
+}
+
+static LOGGER: PageLogger = PageLogger {
+    captured: Mutex::new(None),
+};
+
+impl PageLogger {
+    /// Lines kept while capturing, older ones are dropped
+    const MAX_CAPTURED_LINES: usize = 1000;
+}
+
+impl log::Log for PageLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        console_log::log(record);
+        if let Some(lines) = self.captured.lock().unwrap().as_mut() {
+            if lines.len() == Self::MAX_CAPTURED_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(format!(
+                "{:.0} {} {}: {}",
+                crate::now_ms(),
+                record.level(),
+                record.target(),
+                record.args()
+            ));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Sets up logging with given options. Can be called again to change them,
+/// stopping capture discards the captured lines
+#[cfg(target_family = "wasm")]
+pub fn init_logging(options: &LogOptions) {
+    // Fails only if the logger is already set, in which case only the options change
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(options.level.unwrap_or(LogLevel::Info).into());
+    let mut captured = LOGGER.captured.lock().unwrap();
+    match (options.capture, captured.is_some()) {
+        (true, false) => *captured = Some(VecDeque::new()),
+        (false, true) => *captured = None,
+        _ => {}
+    }
+}
+
+/// Returns up to given amount of the latest captured log lines, oldest first
+pub fn captured_log_lines(max_lines: usize) -> Vec<String> {
+    LOGGER
+        .captured
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|lines| {
+            lines
+                .iter()
+                .skip(lines.len().saturating_sub(max_lines))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,165 @@
+//! Functionality that differs between the web build and the native desktop build.
+//! The web build uses browser APIs, the native build uses the standard library
+//! and a normal window in place of the page's canvas
+
+use super::*;
+use std::future::Future;
+use winit::window::WindowAttributes;
+
+/// Returns the current timestamp in milliseconds
+#[cfg(target_family = "wasm")]
+pub fn now_ms() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map(|performance| performance.now())
+        .unwrap_or_default()
+}
+
+/// Returns the current timestamp in milliseconds
+#[cfg(not(target_family = "wasm"))]
+pub fn now_ms() -> f64 {
+    use std::{sync::OnceLock, time::Instant};
+
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.0
+}
+
+/// Returns the wall clock time in milliseconds since the Unix epoch
+#[cfg(target_family = "wasm")]
+pub fn date_now_ms() -> f64 {
+    web_sys::js_sys::Date::now()
+}
+
+/// Returns the wall clock time in milliseconds since the Unix epoch
+#[cfg(not(target_family = "wasm"))]
+pub fn date_now_ms() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0.0, |duration| duration.as_secs_f64() * 1000.0)
+}
+
+/// Returns the wall clock time in whole seconds since the Unix epoch.
+/// The native build uses `gb_web_core::unix_time_secs`
+#[cfg(target_family = "wasm")]
+pub fn unix_time_secs() -> u64 {
+    (date_now_ms() / 1000.0) as u64
+}
+
+/// Runs a future in the background of the event loop
+#[cfg(target_family = "wasm")]
+pub fn spawn_local(future: impl Future<Output = ()> + 'static) {
+    wasm_bindgen_futures::spawn_local(future);
+}
+
+/// Runs a future to completion. The native build blocks on it, as the futures
+/// only wait for the graphics device, files or encoding
+#[cfg(not(target_family = "wasm"))]
+pub fn spawn_local(future: impl Future<Output = ()> + 'static) {
+    pollster::block_on(future);
+}
+
+/// Lets the browser handle other events before continuing long running work
+#[cfg(target_family = "wasm")]
+pub async fn yield_to_event_loop() {
+    use wasm_bindgen_futures::JsFuture;
+
+    let promise = web_sys::js_sys::Promise::new(&mut |resolve, _| {
+        let scheduled = web_sys::window()
+            .map(|window| window.set_timeout_with_callback(&resolve).is_ok())
+            .unwrap_or(false);
+        if !scheduled {
+            let _ = resolve.call0(&JsValue::NULL);
+        }
+    });
+    let _ = JsFuture::from(promise).await;
+}
+
+/// Lets the browser handle other events before continuing long running work,
+/// native work is blocked on so there's nothing to yield to
+#[cfg(not(target_family = "wasm"))]
+pub async fn yield_to_event_loop() {}
+
+/// Vibrates the device for given duration if the browser supports it
+#[cfg(target_family = "wasm")]
+pub fn vibrate(duration_ms: u32) {
+    let Some(navigator) = web_sys::window().map(|window| window.navigator()) else {
+        return;
+    };
+    // Calling vibrate would throw on browsers without the Vibration API
+    let supported =
+        web_sys::js_sys::Reflect::has(&navigator, &JsValue::from_str("vibrate")).unwrap_or(false);
+    if supported {
+        navigator.vibrate_with_duration(duration_ms);
+    }
+}
+
+/// Desktops have nothing to vibrate
+#[cfg(not(target_family = "wasm"))]
+pub fn vibrate(_duration_ms: u32) {}
+
+/// Returns the path of a file the web build would fetch from given URL relative to the page.
+/// The native build reads it from the frontend's public directory, or from the directory
+/// in the `GB_WEB_ASSETS` environment variable
+#[cfg(not(target_family = "wasm"))]
+pub fn asset_path(url: &str) -> std::path::PathBuf {
+    let assets = std::env::var("GB_WEB_ASSETS")
+        .unwrap_or_else(|_| concat!(env!("CARGO_MANIFEST_DIR"), "/../app/public").to_string());
+    std::path::Path::new(&assets).join(url)
+}
+
+/// Where the emulator is drawn: a canvas on the page, or a native window
+#[derive(Debug)]
+pub struct RenderTarget {
+    #[cfg(target_family = "wasm")]
+    canvas: web_sys::HtmlCanvasElement,
+}
+
+impl RenderTarget {
+    #[cfg(target_family = "wasm")]
+    pub fn canvas(canvas: web_sys::HtmlCanvasElement) -> Self {
+        Self { canvas }
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    pub fn window() -> Self {
+        Self {}
+    }
+
+    /// Returns the attributes for creating the window drawn to
+    #[cfg(target_family = "wasm")]
+    pub fn window_attributes(&self) -> WindowAttributes {
+        use winit::platform::web::WindowAttributesExtWebSys;
+
+        Window::default_attributes().with_canvas(Some(self.canvas.clone()))
+    }
+
+    /// Returns the attributes for creating the window drawn to
+    #[cfg(not(target_family = "wasm"))]
+    pub fn window_attributes(&self) -> WindowAttributes {
+        Window::default_attributes()
+            .with_title("gb-web")
+            .with_inner_size(winit::dpi::LogicalSize::new(480, 720))
+    }
+
+    /// Returns the size of the surface in physical pixels at given scale factor.
+    /// The canvas is kept at its CSS size, so pixels stay sharp on HiDPI screens
+    #[cfg(target_family = "wasm")]
+    pub fn physical_size(
+        &self,
+        _window: &Window,
+        scale_factor: f64,
+    ) -> winit::dpi::PhysicalSize<u32> {
+        winit::dpi::LogicalSize::new(self.canvas.client_width(), self.canvas.client_height())
+            .to_physical(scale_factor)
+    }
+
+    /// Returns the size of the surface in physical pixels, which is the size of the window
+    #[cfg(not(target_family = "wasm"))]
+    pub fn physical_size(
+        &self,
+        window: &Window,
+        _scale_factor: f64,
+    ) -> winit::dpi::PhysicalSize<u32> {
+        window.inner_size()
+    }
+}
@@ -1,6 +1,9 @@
 use super::*;
 use serde::{Deserialize, Serialize};
-use tsify::Tsify;
+use std::cell::RefCell;
+use std::rc::Rc;
+use tsify::{Ts, Tsify};
+use wasm_bindgen::JsCast;
 use web_sys::js_sys;
 
 /// A color in linear RGB space
@@ -8,15 +11,55 @@ use web_sys::js_sys;
 #[derive(
     Tsify, Debug, Copy, Clone, PartialEq, Deserialize, Serialize, bytemuck::Pod, bytemuck::Zeroable,
 )]
-#[tsify(from_wasm_abi)]
 pub struct Color(pub f32, pub f32, pub f32, pub f32);
 
+impl Default for Color {
+    /// Opaque white
+    fn default() -> Self {
+        Self(1.0, 1.0, 1.0, 1.0)
+    }
+}
+
+impl Color {
+    /// Converts a color of the Super Game Boy, which has 5 bits of sRGB for each channel
+    /// starting from red in the lowest bits
+    pub fn from_rgb555(color: u16) -> Self {
+        let decode = |shift: u16| {
+            let c = f32::from((color >> shift) & 0x1F) / 31.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        Self(decode(0), decode(5), decode(10), 1.0)
+    }
+
+    /// Converts the color into 8-bit sRGB, as shown on the display
+    pub fn to_srgb8(self) -> [u8; 4] {
+        let encode = |c: f32| {
+            let c = c.clamp(0.0, 1.0);
+            let c = if c <= 0.0031308 {
+                12.92 * c
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            };
+            (c * 255.0).round() as u8
+        };
+        [
+            encode(self.0),
+            encode(self.1),
+            encode(self.2),
+            (self.3.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ]
+    }
+}
+
 /// Represents color palette for display
 #[repr(C)]
 #[derive(
     Tsify, Debug, Copy, Clone, Deserialize, Serialize, PartialEq, bytemuck::Pod, bytemuck::Zeroable,
 )]
-#[tsify(from_wasm_abi)]
 pub struct Palette(pub Color, pub Color, pub Color, pub Color);
 
 impl Palette {
@@ -36,22 +79,270 @@ impl Default for Palette {
     }
 }
 
-#[derive(Tsify, Default, Debug, Clone, Copy, Deserialize, Serialize)]
-#[tsify(from_wasm_abi)]
+/// Adjustments applied to the colors of the display
+#[derive(Tsify, Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ColorCorrection {
+    /// From 0.5 to 2.5, higher values brighten dark colors
+    pub gamma: f32,
+    /// Added to every channel, from -0.5 to 0.5
+    pub brightness: f32,
+    /// From 0.5 to 1.5
+    pub contrast: f32,
+    /// From 0 to 2, 0 being grayscale
+    pub saturation: f32,
+}
+
+impl Default for ColorCorrection {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+        }
+    }
+}
+
+/// How the display is scaled to fit the screen
+#[derive(Tsify, Default, Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum ScalingMode {
+    /// Largest integer scale that fits, so every pixel has the same size
+    #[default]
+    Integer,
+    /// Largest scale that fits while preserving the aspect ratio
+    Fit,
+    /// Fills the screen, falls back to fit while the onscreen controls are shown
+    Stretch,
+}
+
+/// How rendered frames are presented, trading tearing for input latency
+#[derive(Tsify, Default, Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum PresentMode {
+    /// Synced to the display, late frames may tear if the browser supports it
+    #[default]
+    Auto,
+    /// Always synced to the display
+    Fifo,
+    /// Shows the newest frame as soon as possible, falls back to Fifo if not supported
+    LowLatency,
+}
+
+/// Rotation of the display and background on the screen
+#[derive(Tsify, Default, Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum DisplayRotation {
+    #[default]
+    None,
+    /// Rotated by 90 degrees clockwise
+    Clockwise,
+    /// Rotated by 270 degrees clockwise
+    CounterClockwise,
+}
+
+/// Sizes of the screen edges covered by notches and system UI, in physical pixels
+#[derive(Tsify, Default, Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct SafeAreaInsets {
+    pub top: u16,
+    pub right: u16,
+    pub bottom: u16,
+    pub left: u16,
+}
+
+/// Effect drawn between the pixels of the display
+#[derive(Tsify, Default, Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum PixelEffect {
+    /// Soft light bleeding across pixel edges
+    #[default]
+    Scanlines,
+    /// Grid of gaps between the pixels like the DMG LCD
+    DotMatrix,
+}
+
+/// How auto-run decides how much to emulate on each redraw
+#[derive(Tsify, Default, Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum FramePacing {
+    /// Runs whole frames at the hardware rate of about 59.73 Hz, occasionally running none or
+    /// two on a redraw to stay in sync with the refresh rate of the display
+    #[default]
+    Vsync,
+    /// Runs the time elapsed since the last redraw, which can split frames across redraws
+    Elapsed,
+}
+
+/// Missing fields are defaulted and unknown fields ignored,
+/// so options saved by older versions keep loading
+#[derive(Tsify, Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
 pub struct EmulatorOptions {
+    /// Volume on a decibel scale, with 1.0 for full level,
+    /// each 0.1 from it changing the level by 3 dB and 0.0 muting
     pub volume: f32,
     pub show_controls: bool,
+    pub scaling_mode: ScalingMode,
+    /// Steps the integer scale of the display down, like to leave room for browser UI,
+    /// or up to fill more of the screen when the controls leave room.
+    /// The scale stays at least 1 and fits the screen. Fit and stretch scaling ignore it
+    pub scale_offset: i32,
+    pub present_mode: PresentMode,
+    /// Dims the display and shows a pause glyph over it while paused
+    pub pause_indicator: bool,
+    /// Shows messages over the display when actions like saving a state are done
+    pub osd_enabled: bool,
+    /// How long messages are shown for, including fading out
+    pub osd_duration_ms: u32,
+    /// Outlines sprites, the window and the background map edges over the display,
+    /// with sprites dropped by the sprite limit in a different color
+    pub debug_overlay: bool,
+    pub rotation: DisplayRotation,
     pub display_glow_strength: f32,
     pub background_glow_strength: f32,
     pub glow_enabled: bool,
     pub glow_iterations: usize,
     pub glow_radius: f32,
+    /// Divides the resolution the glow is blurred at, as it has no fine detail.
+    /// 1 blurs at full resolution
+    pub glow_downscale: u32,
+    /// Intensity of the light on the background
     pub ambient_light: f32,
+    /// Color of the light on the background, alpha is ignored.
+    /// White keeps the background neutral, only scaled by `ambient_light`
+    pub ambient_color: Color,
     pub scanline_strength: f32,
     pub scanline_size: f32,
+    pub pixel_effect: PixelEffect,
+    /// Size of the gaps between pixels in dot matrix mode, as a share of the pixel
+    pub grid_size: f32,
+    /// Darkness of the gaps between pixels in dot matrix mode, from 0 to 1
+    pub grid_strength: f32,
+    /// Tints the gaps with the color of an unlit pixel instead of darkening them
+    pub grid_tint: bool,
+    pub color_correction: ColorCorrection,
+    /// Strength of previous frames blended onto the current one like the slow LCD response,
+    /// from 0 to 1
+    pub frame_persistence: f32,
+    /// Barrel distortion of the display, from 0 to 1
+    pub crt_curvature: f32,
+    /// Darkening of the display corners, from 0 to 1
+    pub crt_vignette: f32,
+    /// Used while emulation is auto-run, unless audio pacing is active
+    pub frame_pacing: FramePacing,
+    /// Adjusts the emulation time to keep the audio buffer near a target fill,
+    /// instead of following the time given by the frontend
+    pub audio_pacing: bool,
+    /// Adjusts the audio sample rate slightly to keep the audio buffer half full,
+    /// as the clocks of audio output and emulation drift apart. Not used with audio pacing
+    pub audio_rate_control: bool,
+    /// Length of audio buffered ahead of playback, from 30 to 200 ms, with 0 using the default
+    /// of 100 ms. Shorter buffers make the sound follow the game more closely, but crackle
+    /// when a frame takes too long on slow devices
+    pub audio_latency_ms: u32,
+    /// Volumes of the individual sound channels, from 0.0 for muted to 1.0 for unchanged
+    pub square1_volume: f32,
+    pub square2_volume: f32,
+    pub wave_volume: f32,
+    pub noise_volume: f32,
+    /// Silences audio while the page is hidden, like when its tab is in the background.
+    /// Emulation keeps running as it would otherwise
+    pub mute_in_background: bool,
+    /// Enlarges the areas pressing onscreen buttons around their centers without changing
+    /// how they're drawn, like 1.5 for half as large again. Values below 1.0 keep them as drawn
+    pub touch_target_scale: f32,
+    /// Vibrates when an onscreen button is pressed
+    pub haptics_enabled: bool,
+    pub haptics_duration_ms: u32,
     pub(crate) palette: Palette,
 }
 
+impl Default for EmulatorOptions {
+    /// Same as the defaults of the page, so fields missing from stored options
+    /// load like a new user would have them
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            show_controls: false,
+            scaling_mode: ScalingMode::default(),
+            scale_offset: 0,
+            present_mode: PresentMode::default(),
+            pause_indicator: true,
+            osd_enabled: true,
+            osd_duration_ms: 2000,
+            debug_overlay: false,
+            rotation: DisplayRotation::default(),
+            display_glow_strength: 0.65,
+            background_glow_strength: 0.8,
+            glow_enabled: true,
+            glow_iterations: 6,
+            glow_radius: 0.5,
+            glow_downscale: 2,
+            ambient_light: 0.9,
+            ambient_color: Color::default(),
+            scanline_strength: 0.2,
+            scanline_size: 0.25,
+            pixel_effect: PixelEffect::default(),
+            grid_size: 0.2,
+            grid_strength: 0.4,
+            grid_tint: true,
+            color_correction: ColorCorrection::default(),
+            frame_persistence: 0.0,
+            crt_curvature: 0.0,
+            crt_vignette: 0.0,
+            frame_pacing: FramePacing::default(),
+            audio_pacing: false,
+            audio_rate_control: true,
+            audio_latency_ms: 0,
+            square1_volume: 1.0,
+            square2_volume: 1.0,
+            wave_volume: 1.0,
+            noise_volume: 1.0,
+            mute_in_background: false,
+            touch_target_scale: 1.0,
+            haptics_enabled: true,
+            haptics_duration_ms: 15,
+            palette: Palette::default(),
+        }
+    }
+}
+
+impl EmulatorOptions {
+    /// Volumes of the square 1, square 2, wave and noise channels
+    pub(crate) fn channel_volumes(&self) -> [f32; 4] {
+        [
+            self.square1_volume,
+            self.square2_volume,
+            self.wave_volume,
+            self.noise_volume,
+        ]
+    }
+
+    /// Serializes options for storing them
+    pub fn to_json(self) -> String {
+        serde_json::to_string(&self).expect("Options should always be serializable")
+    }
+
+    /// Deserializes options stored with `to_json`
+    pub fn from_json(json: &str) -> Result<EmulatorOptions, JsValue> {
+        serde_json::from_str(json).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+}
+
+/// Serializes options into a JSON string for storing them
+#[wasm_bindgen]
+pub fn emulator_options_to_json(options: Ts<EmulatorOptions>) -> Result<String, JsValue> {
+    let options = options
+        .to_rust()
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    Ok(options.to_json())
+}
+
+/// Deserializes options from a JSON string,
+/// fields missing from the JSON are set to their defaults
+#[wasm_bindgen]
+pub fn emulator_options_from_json(json: &str) -> Result<Ts<EmulatorOptions>, JsValue> {
+    EmulatorOptions::from_json(json)?
+        .into_ts()
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
 #[wasm_bindgen]
 #[derive(Debug, Clone, Default)]
 pub struct ROMInfo {
@@ -62,19 +353,45 @@ pub struct ROMInfo {
     pub should_be_saved: bool,
     /// The hash of the ROM file
     pub hash: u32,
+    /// The memory bank controller type
+    pub(crate) mbc: String,
+    /// Amount of 16 KiB ROM banks
+    pub rom_banks: u16,
+    /// Amount of 8 KiB RAM banks
+    pub ram_banks: u16,
+    /// If the cartridge has a real time clock
+    pub has_rtc: bool,
+    /// If the cartridge has a rumble motor, reported through the rumble callback
+    pub has_rumble: bool,
+    /// If the cartridge has an accelerometer, which is tilted with UpdateTilt
+    pub has_accelerometer: bool,
+    /// Game Boy Color support: "None", "Compatible" or "Only"
+    pub(crate) cgb_support: String,
+    /// If the game uses Super Game Boy colors and borders
+    pub sgb_support: bool,
+    /// If the header checksum is valid
+    pub header_checksum_valid: bool,
+    /// If the global checksum is valid, which hardware doesn't check
+    pub global_checksum_valid: bool,
 }
 
 #[derive(Tsify, Debug, Clone, Serialize, Deserialize)]
-#[tsify(from_wasm_abi)]
 pub enum BridgeQuery {
-    /// Loads a new ROM into emulator
+    /// Loads a new ROM into emulator. ROMs without the logo or with a header checksum
+    /// mismatch are rejected, unless forced for homebrew with a deliberately broken header
     LoadROM {
         #[tsify(type = "Uint8Array")]
         file: Vec<u8>,
         is_zip: bool,
+        #[tsify(optional)]
+        force: Option<bool>,
+    },
+    /// Restarts emulator with previously loaded ROM.
+    /// Cartridge RAM is kept by default if the cartridge has a battery
+    Reload {
+        #[tsify(optional)]
+        preserve_sram: Option<bool>,
     },
-    /// Restarts emulator with previously loaded ROM
-    Reload {},
     /// Sets the external RAM from save file
     LoadRAM {
         #[tsify(type = "Uint8Array")]
@@ -82,6 +399,9 @@ pub enum BridgeQuery {
     },
     /// Runs the emulator for given amount of milliseconds
     RunCPU { millis: f32 },
+    /// Runs the emulator on every redraw using the elapsed time,
+    /// so RunCPU doesn't need to be queried. Elapsed time is clamped to max_catchup_ms
+    SetAutoRun { enabled: bool, max_catchup_ms: f32 },
     /// Serializes the external RAM
     SaveRAM {},
     /// Serializes the entire emulator state
@@ -91,19 +411,113 @@ pub enum BridgeQuery {
         #[tsify(type = "Uint8Array")]
         buffer: Vec<u8>,
     },
+    /// Saves the emulator state into a slot kept in memory
+    SaveStateSlot { slot: u8 },
+    /// Loads the emulator state from a slot kept in memory
+    LoadStateSlot { slot: u8 },
+    /// Returns info about the slots saved with the current ROM
+    ListStateSlots {},
+    /// Parses a cheat code and activates it, returns the id of the cheat.
+    /// Cheats are kept when the emulator is reset or a state is loaded,
+    /// and cleared when another ROM is loaded
+    AddCheat { kind: CheatCodeKind, code: String },
+    /// Removes the cheat with given id
+    RemoveCheat { id: u32 },
+    /// Enables or disables the cheat with given id
+    SetCheatEnabled { id: u32, enabled: bool },
+    /// Returns the added cheats in the order they were added
+    ListCheats {},
+    /// Enables capturing the emulator state for rewinding,
+    /// 0 seconds disables rewinding
+    SetRewindEnabled { seconds_budget: f32 },
+    /// Starts or stops rewinding, runs emulation backwards while active
+    Rewind { active: bool },
+    /// Runs the emulator for given amount of frames, also while paused.
+    /// Audio of the frames is discarded
+    AdvanceFrame { count: u32 },
+    /// Starts publishing frames into a SharedArrayBuffer.
+    /// Resolves with the buffer, or null if the page isn't cross-origin isolated
+    EnableSharedFrame {},
     /// Toggles pause state
     SetPaused { paused: bool },
     /// Sets emulation speed
     SetSpeed { speed: f32 },
     /// Updates input state
     UpdateInput { input: String, pressed: bool },
+    /// Tilts the cartridge accelerometer, as offsets from the value it reads while lying flat.
+    /// The values change about 0x70 per 1 g
+    UpdateTilt { x: i16, y: i16 },
+    /// Remaps the keys handled by the emulator,
+    /// maps input names to physical key codes
+    SetKeybinds {
+        #[tsify(type = "Record<string, string[]>")]
+        keybinds: HashMap<String, Vec<KeyCode>>,
+    },
+    /// Toggles handling keyboard input directly in the emulator,
+    /// pages that handle keys themselves through UpdateInput can disable it
+    SetBuiltinKeyboard { enabled: bool },
+    /// Rebuilds audio playback on the current default output device,
+    /// used when the page knows the output has changed
+    ResetAudio {},
+    /// Starts audio playback, which browsers only allow after a user gesture.
+    /// Playback also starts on the first input, so this is needed for other gestures like clicks
+    EnableAudio {},
     /// Updates the position of a pointer,
     /// used for onscreen input controls
     UpdatePointerPos { pos: [f32; 2], id: i32 },
     /// Updates the pressed state of a pointer
     UpdatePointerPressed { pressed: bool, id: i32 },
+    /// Releases all pointers, used when their release events might be missed
+    ReleasePointers {},
+    /// Tells whether the page is hidden, like when its tab is in the background.
+    /// Audio is muted while hidden if enabled in options, and RAM is saved when hidden
+    SetPageHidden { hidden: bool },
+    /// Keeps the layout inside the safe area of the screen
+    SetSafeAreaInsets { insets: SafeAreaInsets },
+    /// Shows the skin with given name, fetching it from the skin manifest if it isn't loaded.
+    /// Inside a batch, only skins that have been loaded before can be selected
+    SelectSkin { name: String },
+    /// Replaces the definition of the shown skin with given TOML, keeping its image,
+    /// so skin authors can see changes without rebuilding.
+    /// The previous definition is kept if the new one can't be parsed or is invalid
+    ReloadBackgroundDefinition { definition: String },
+    /// Takes a screenshot of the display at its native resolution,
+    /// or of the whole canvas with background and effects as they are rendered.
+    /// Screenshots with effects can't be taken inside a batch
+    TakeScreenshot {
+        #[serde(default)]
+        #[tsify(optional)]
+        include_effects: bool,
+    },
+    /// Starts recording the display at its native resolution,
+    /// for at most given amount of seconds of emulated time (up to 60).
+    /// Recording is aborted when another ROM is loaded
+    StartRecording { max_seconds: f32 },
+    /// Stops recording and encodes the recorded frames into an animated GIF.
+    /// Can't be part of a batch
+    StopRecording {},
+    /// Starts capturing the audio as it's played, after volume is applied,
+    /// for at most given amount of seconds of playback (up to 300).
+    /// Nothing is captured while paused
+    StartAudioCapture { max_seconds: f32 },
+    /// Stops capturing audio and returns the captured audio as a 16-bit PCM WAV file
+    StopAudioCapture {},
     /// Updates options struct and applies changes to emulation
     UpdateOptions { options: EmulatorOptions },
+    /// Executes queries in order within a single event,
+    /// optionally skipping the rest after the first error
+    Batch {
+        queries: Vec<BridgeQuery>,
+        #[serde(default)]
+        #[tsify(optional)]
+        stop_on_error: bool,
+    },
+    /// Disconnects the link cable partner connected with `Proxy.connect_serial`.
+    /// A transfer waiting for the partner finishes as if the cable was pulled
+    SerialDisconnect {},
+    /// Flushes unsaved data and releases the audio stream and graphics device.
+    /// Later queries are rejected, the native build also closes its window
+    Shutdown {},
 }
 
 #[wasm_bindgen]
@@ -112,6 +526,16 @@ impl ROMInfo {
     pub fn title(&self) -> String {
         self.title.clone()
     }
+
+    #[wasm_bindgen(getter)]
+    pub fn mbc(&self) -> String {
+        self.mbc.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn cgb_support(&self) -> String {
+        self.cgb_support.clone()
+    }
 }
 
 pub enum BridgeResponse {
@@ -123,77 +547,619 @@ pub enum BridgeResponse {
     /// CPU is successfully serialized into a save state,
     /// returns the serialized CPU
     CPUSerialized(Vec<u8>),
+    /// Returns info about the state slots of current ROM
+    StateSlotsListed(Vec<StateSlotInfo>),
+    /// Cheat is added, returns its id
+    CheatAdded(u32),
+    /// Returns info about the added cheats
+    CheatsListed(Vec<CheatInfo>),
+    /// Emulator is restarted, returns if cartridge RAM was kept
+    Reloaded { sram_preserved: bool },
+    /// Frames are advanced, returns the new frame counter
+    FrameAdvanced(u32),
+    /// Returns the buffer frames are published to, if available
+    SharedFrameEnabled(Option<js_sys::SharedArrayBuffer>),
+    /// Returns the recording encoded as an animated GIF
+    RecordingEncoded(Vec<u8>),
+    /// Returns the captured audio encoded as a WAV file
+    AudioCaptured(Vec<u8>),
+    /// Returns the screenshot as ImageData
+    Screenshot {
+        pixels: Vec<u8>,
+        width: u32,
+        height: u32,
+    },
+    /// Returns the results of batched queries in order,
+    /// as objects containing either `value` or `error`
+    Batch(Vec<QueryResult>),
 }
 
+/// Response of a query, or the error it was rejected with
+pub type QueryResult = Result<Option<BridgeResponse>, BridgeError>;
+
 #[derive(Debug)]
 pub struct BridgeRequest {
-    resolve: js_sys::Function,
-    reject: js_sys::Function,
+    id: u32,
+    pending: SharedPendingRequests,
     pub query: Option<BridgeQuery>,
 }
 
+impl BridgeResponse {
+    /// Converts the response into the value the promise is resolved with
+    fn into_js_value(self) -> JsValue {
+        use BridgeResponse as R;
+        match self {
+            R::ROMLoaded(info) => info.into(),
+            R::CPUSerialized(buffer)
+            | R::RAMSaved(buffer)
+            | R::RecordingEncoded(buffer)
+            | R::AudioCaptured(buffer) => js_sys::Uint8Array::new_from_slice(&buffer).into(),
+            R::StateSlotsListed(slots) => {
+                let array = js_sys::Array::new();
+                for slot in slots {
+                    array.push(&slot.into_js().unwrap_throw().into());
+                }
+                array.into()
+            }
+            R::CheatsListed(cheats) => {
+                let array = js_sys::Array::new();
+                for cheat in cheats {
+                    array.push(&cheat.into_js().unwrap_throw().into());
+                }
+                array.into()
+            }
+            R::CheatAdded(id) => JsValue::from(id),
+            R::FrameAdvanced(frame) => JsValue::from(frame),
+            R::SharedFrameEnabled(buffer) => buffer.map_or(JsValue::NULL, JsValue::from),
+            R::Reloaded { sram_preserved } => JsValue::from_bool(sram_preserved),
+            R::Screenshot {
+                pixels,
+                width,
+                height,
+            } => web_sys::ImageData::new_with_u8_clamped_array_and_sh(
+                wasm_bindgen::Clamped(&pixels),
+                width,
+                height,
+            )
+            .unwrap_throw()
+            .into(),
+            R::Batch(results) => {
+                // Each result is an object with either a value or an error
+                let array = js_sys::Array::new();
+                for result in results {
+                    let object = js_sys::Object::new();
+                    let (key, value) = match result {
+                        Ok(response) => (
+                            "value",
+                            response.map_or(JsValue::UNDEFINED, Self::into_js_value),
+                        ),
+                        Err(error) => ("error", error.into_js().unwrap_throw().into()),
+                    };
+                    js_sys::Reflect::set(&object, &key.into(), &value).unwrap_throw();
+                    array.push(&object);
+                }
+                array.into()
+            }
+        }
+    }
+}
+
 impl BridgeRequest {
-    fn full_resolve(&self, response: Option<BridgeResponse>) {
-        if let Some(response) = response {
-            use BridgeResponse as R;
-            match response {
-                R::ROMLoaded(info) => self.resolve.call1(&JsValue::NULL, &info.into()),
-                R::CPUSerialized(buffer) => self
-                    .resolve
-                    .call1(&JsValue::NULL, &js_sys::Uint8Array::new_from_slice(&buffer)),
-                R::RAMSaved(buffer) => self
-                    .resolve
-                    .call1(&JsValue::NULL, &js_sys::Uint8Array::new_from_slice(&buffer)),
+    /// Returns if the request was sent through the proxy with given pending requests
+    pub fn is_from(&self, pending: &SharedPendingRequests) -> bool {
+        Rc::ptr_eq(&self.pending, pending)
+    }
+
+    /// Resolves the promise with the response or rejects it with the error.
+    /// Does nothing if the promise has already been rejected by a timeout
+    pub fn finish(&self, result: QueryResult) {
+        let promise = self.pending.borrow_mut().requests.remove(&self.id);
+        if let Some(promise) = promise {
+            promise.settle(result);
+        }
+    }
+}
+
+/// Functions for settling the promise of a query
+#[derive(Debug)]
+struct PendingPromise {
+    resolve: js_sys::Function,
+    reject: js_sys::Function,
+}
+
+impl PendingPromise {
+    fn settle(self, result: QueryResult) {
+        match result {
+            Ok(response) => {
+                let value = response.map_or(JsValue::UNDEFINED, BridgeResponse::into_js_value);
+                self.resolve.call1(&JsValue::NULL, &value).unwrap_throw();
+            }
+            Err(error) => {
+                self.reject
+                    .call1(&JsValue::NULL, &error.into_js().unwrap_throw().into())
+                    .unwrap_throw();
             }
-            .unwrap_throw();
-        } else {
-            self.resolve.call0(&JsValue::NULL).unwrap_throw();
         }
     }
+}
+
+/// Events sent to the frontend without a preceding query
+#[derive(Debug)]
+pub enum Callback {
+    /// Battery backed RAM has changed,
+    /// contains the new RAM buffer to be saved
+    RAMChanged(Vec<u8>),
+    /// Performance statistics collected over the reporting interval
+    FrameStats(FrameStats),
+    /// Audio output has run out of samples,
+    /// contains the amount of underruns during the time window
+    AudioUnderrun { count: u32, window_ms: f64 },
+    /// Rendering has failed, or has been recovered from a failure if there's no error
+    RendererStatus(Option<BridgeError>),
+    /// Work done outside of a query has failed, like fetching the background image,
+    /// or the emulator has crashed
+    Error(BridgeError),
+    /// Audio playback has been rebuilt after its stream failed,
+    /// contains the sample rate of the new output device
+    AudioRecovered { sample_rate: u32 },
+    /// Audio waits for a user gesture before playing, so the page can prompt for one.
+    /// Called with false once audio has been enabled
+    AudioBlocked(bool),
+    /// The link cable partner has disconnected or stopped replying
+    SerialDisconnected(BridgeError),
+    /// The link cable partner has recorded other bytes for the exchanges starting from
+    /// the given transfer number
+    NetplayDesync { transfer: u32 },
+    /// The rumble motor of the cartridge has turned on or off
+    Rumble(bool),
+}
 
-    pub fn resolve(&self) {
-        self.full_resolve(None);
+/// Functions registered by the frontend for receiving callbacks
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default)]
+pub struct ProxyCallbacks {
+    ram_changed: Option<js_sys::Function>,
+    frame_stats: Option<js_sys::Function>,
+    frame_stats_interval_ms: f64,
+    audio_underrun: Option<js_sys::Function>,
+    renderer_status: Option<js_sys::Function>,
+    error: Option<js_sys::Function>,
+    audio_recovered: Option<js_sys::Function>,
+    audio_blocked: Option<js_sys::Function>,
+    serial_disconnected: Option<js_sys::Function>,
+    netplay_desync: Option<js_sys::Function>,
+    rumble: Option<js_sys::Function>,
+}
+
+#[wasm_bindgen]
+impl ProxyCallbacks {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets function to call with the new RAM buffer when battery backed RAM changes
+    pub fn set_ram_changed(&mut self, callback: js_sys::Function) {
+        self.ram_changed = Some(callback);
+    }
+
+    /// Sets function to call with performance statistics.
+    /// The statistics are only measured when this is set,
+    /// and are reported every second unless another interval is given
+    pub fn set_frame_stats(&mut self, callback: js_sys::Function, interval_ms: Option<f64>) {
+        self.frame_stats = Some(callback);
+        self.frame_stats_interval_ms = interval_ms.unwrap_or(1000.0);
+    }
+
+    /// Sets function to call with the underrun count and the time window in milliseconds
+    /// when audio output runs out of samples. Called at most once per second
+    pub fn set_audio_underrun(&mut self, callback: js_sys::Function) {
+        self.audio_underrun = Some(callback);
+    }
+
+    /// Sets function to call with an error when rendering fails, so the page can tell the
+    /// canvas has stopped updating. After the graphics device is lost and the renderer has been
+    /// recreated, it's called without arguments
+    pub fn set_renderer_status(&mut self, callback: js_sys::Function) {
+        self.renderer_status = Some(callback);
+    }
+
+    /// Sets function to call with an error when work not started by a query fails.
+    /// Also called with an EmulatorCrashed error if the emulator panics
+    pub fn set_error(&mut self, callback: js_sys::Function) {
+        self.error = Some(callback);
+    }
+
+    /// Sets function to call with the new sample rate when audio playback has been rebuilt
+    /// after its stream failed, like when the output device was removed
+    pub fn set_audio_recovered(&mut self, callback: js_sys::Function) {
+        self.audio_recovered = Some(callback);
+    }
+
+    /// Sets function to call with true when a ROM is loaded before audio can play, so the page
+    /// can ask for a tap or key press. Called with false once audio has been enabled
+    pub fn set_audio_blocked(&mut self, callback: js_sys::Function) {
+        self.audio_blocked = Some(callback);
+    }
+
+    /// Sets function to call with an error when the link cable partner's channel closes
+    /// or the partner stops replying. The link is disconnected before the call
+    pub fn set_serial_disconnected(&mut self, callback: js_sys::Function) {
+        self.serial_disconnected = Some(callback);
     }
 
-    pub fn respond(&self, response: BridgeResponse) {
-        self.full_resolve(Some(response));
+    /// Sets function to call with the number of the first link cable transfer that the partner
+    /// saw differently, like after a speculative transfer got another byte than the partner sent.
+    /// Called once each time the link goes out of sync, and the link stays connected
+    pub fn set_netplay_desync(&mut self, callback: js_sys::Function) {
+        self.netplay_desync = Some(callback);
     }
 
-    pub fn reject(&self, reason: &str) {
-        self.reject
-            .call1(&JsValue::NULL, &reason.into())
-            .unwrap_throw();
+    /// Sets function to call with true when the rumble motor of the cartridge turns on,
+    /// and with false when it turns off. Games pulse the motor for weaker rumble, so calls
+    /// can come every few frames. The motor is turned off while paused
+    pub fn set_rumble(&mut self, callback: js_sys::Function) {
+        self.rumble = Some(callback);
+    }
+}
+
+impl ProxyCallbacks {
+    /// Returns if the frame statistics should be measured
+    pub fn has_frame_stats(&self) -> bool {
+        self.frame_stats.is_some()
+    }
+
+    pub fn frame_stats_interval_ms(&self) -> f64 {
+        self.frame_stats_interval_ms
+    }
+
+    /// Calls the function registered for the callback.
+    /// The callback data is only converted if a function is registered
+    pub fn call(&self, callback: Callback) {
+        use Callback as C;
+        let result = match callback {
+            C::RAMChanged(ram) => self
+                .ram_changed
+                .as_ref()
+                .map(|f| f.call1(&JsValue::NULL, &js_sys::Uint8Array::new_from_slice(&ram))),
+            C::FrameStats(stats) => self
+                .frame_stats
+                .as_ref()
+                .map(|f| f.call1(&JsValue::NULL, &stats.into_js().unwrap_throw().into())),
+            C::AudioUnderrun { count, window_ms } => self
+                .audio_underrun
+                .as_ref()
+                .map(|f| f.call2(&JsValue::NULL, &count.into(), &window_ms.into())),
+            C::RendererStatus(error) => self.renderer_status.as_ref().map(|f| match error {
+                Some(error) => f.call1(&JsValue::NULL, &error.into_js().unwrap_throw().into()),
+                None => f.call0(&JsValue::NULL),
+            }),
+            C::Error(error) => self.error.as_ref().map(|f| {
+                let error = error.with_version_info();
+                f.call1(&JsValue::NULL, &error.into_js().unwrap_throw().into())
+            }),
+            C::AudioRecovered { sample_rate } => self
+                .audio_recovered
+                .as_ref()
+                .map(|f| f.call1(&JsValue::NULL, &sample_rate.into())),
+            C::AudioBlocked(blocked) => self
+                .audio_blocked
+                .as_ref()
+                .map(|f| f.call1(&JsValue::NULL, &blocked.into())),
+            C::SerialDisconnected(error) => self
+                .serial_disconnected
+                .as_ref()
+                .map(|f| f.call1(&JsValue::NULL, &error.into_js().unwrap_throw().into())),
+            C::NetplayDesync { transfer } => self
+                .netplay_desync
+                .as_ref()
+                .map(|f| f.call1(&JsValue::NULL, &transfer.into())),
+            C::Rumble(active) => self
+                .rumble
+                .as_ref()
+                .map(|f| f.call1(&JsValue::NULL, &active.into())),
+        };
+        if let Some(Err(e)) = result {
+            log::error!("Callback failed: {e:?}");
+        }
     }
 }
 
 #[derive(Debug)]
 pub enum UserEvent {
-    InitRenderer(Box<Renderer>),
+    /// The renderer has been created, or creating it has failed
+    InitRenderer(Result<Box<Renderer>, BridgeError>),
     Query(BridgeRequest),
+    /// A query sent by the native frontend, which has no pending request to finish
+    #[cfg(not(target_family = "wasm"))]
+    LocalQuery(BridgeQuery),
+    /// The image of the default skin has been fetched
+    BackgroundFetched(Result<Vec<u8>, BridgeError>),
+    /// Files of a skin have been fetched for a SelectSkin query
+    SkinFetched {
+        name: String,
+        result: Result<SkinFiles, BridgeError>,
+        request: BridgeRequest,
+    },
+    /// The page has handed in a channel for the link cable with `Proxy.connect_serial`
+    SerialConnect {
+        link: SerialLink,
+        request: BridgeRequest,
+    },
+}
+
+impl UserEvent {
+    /// Returns the request the event finishes, if it has one
+    pub fn request(&self) -> Option<&BridgeRequest> {
+        match self {
+            Self::Query(request)
+            | Self::SkinFetched { request, .. }
+            | Self::SerialConnect { request, .. } => Some(request),
+            _ => None,
+        }
+    }
+
+    pub fn into_request(self) -> Option<BridgeRequest> {
+        match self {
+            Self::Query(request)
+            | Self::SkinFetched { request, .. }
+            | Self::SerialConnect { request, .. } => Some(request),
+            _ => None,
+        }
+    }
+}
+
+/// Queries sent to the event loop whose promises haven't been settled yet
+#[derive(Debug, Default)]
+pub struct PendingRequests {
+    next_id: u32,
+    requests: HashMap<u32, PendingPromise>,
+    /// Set when the emulator has panicked, later requests are rejected with it
+    pub crash: Option<BridgeError>,
+}
+
+pub type SharedPendingRequests = Rc<RefCell<PendingRequests>>;
+
+impl PendingRequests {
+    /// Rejects all pending queries, used when the event loop won't handle them anymore
+    pub fn reject_all(pending: &SharedPendingRequests, error: BridgeError) {
+        let requests = std::mem::take(&mut pending.borrow_mut().requests);
+        for promise in requests.into_values() {
+            promise.settle(Err(error.clone()));
+        }
+    }
 }
 
 // A proxy to communicate with the event loop from frontend
 #[wasm_bindgen]
 pub struct Proxy {
-    pub(crate) proxy: EventLoopProxy<UserEvent>,
+    pub(crate) proxy: InstanceProxy,
+    pub(crate) pending: SharedPendingRequests,
 }
 
 #[wasm_bindgen]
 impl Proxy {
-    fn send(&self, event: UserEvent) {
+    fn send(&self, event: UserEvent) -> Result<(), BridgeError> {
+        // A crashed emulator would never handle the event
+        if let Some(error) = &self.pending.borrow().crash {
+            return Err(error.clone());
+        }
         self.proxy
             .send_event(event)
-            .expect("Couldn't send event to EventLoop");
+            .map_err(|_| BridgeError::new(ErrorCode::EventLoopClosed, "Emulator is not running"))
     }
 
-    pub fn query(&self, query: BridgeQuery) -> js_sys::Promise {
-        js_sys::Promise::new(&mut |resolve, reject| {
-            let request = BridgeRequest {
-                resolve,
-                reject,
-                query: Some(query.clone()),
-            };
-            self.send(UserEvent::Query(request));
+    /// Sends query to the emulator, the promise settles with its result.
+    /// If timeout is given, the promise is rejected when the query takes longer
+    pub fn query(&self, query: Ts<BridgeQuery>, timeout_ms: Option<u32>) -> js_sys::Promise {
+        match query.to_rust() {
+            Ok(query) => self.request(Some(query), timeout_ms, UserEvent::Query),
+            Err(e) => Self::rejected(e.into()),
+        }
+    }
+
+    /// Sends a request made into an event, returning a promise settled when it's finished
+    fn request(
+        &self,
+        query: Option<BridgeQuery>,
+        timeout_ms: Option<u32>,
+        event: impl FnOnce(BridgeRequest) -> UserEvent,
+    ) -> js_sys::Promise {
+        let id = {
+            let mut pending = self.pending.borrow_mut();
+            pending.next_id = pending.next_id.wrapping_add(1);
+            pending.next_id
+        };
+        let promise = js_sys::Promise::new(&mut |resolve, reject| {
+            self.pending
+                .borrow_mut()
+                .requests
+                .insert(id, PendingPromise { resolve, reject });
+        });
+
+        let request = BridgeRequest {
+            id,
+            pending: Rc::clone(&self.pending),
+            query,
+        };
+        if let Err(error) = self.send(event(request)) {
+            // Nothing pending will be handled anymore
+            PendingRequests::reject_all(&self.pending, error);
+            return promise;
+        }
+
+        if let Some(timeout_ms) = timeout_ms {
+            let pending = Rc::clone(&self.pending);
+            let on_timeout = Closure::once_into_js(move || {
+                let promise = pending.borrow_mut().requests.remove(&id);
+                if let Some(promise) = promise {
+                    promise.settle(Err(BridgeError::new(
+                        ErrorCode::Timeout,
+                        format!("Query didn't finish in {timeout_ms} ms"),
+                    )));
+                }
+            });
+            if let Some(window) = web_sys::window() {
+                let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(
+                    on_timeout.unchecked_ref(),
+                    timeout_ms as i32,
+                );
+            }
+        }
+        promise
+    }
+
+    /// Returns a promise already rejected with given error
+    fn rejected(error: BridgeError) -> js_sys::Promise {
+        js_sys::Promise::reject(&error.into_js().unwrap_throw().into())
+    }
+
+    /// Steps the emulator forward by given amount of frames,
+    /// resolves with the new frame counter
+    pub fn advance_frame(&self, count: u32) -> js_sys::Promise {
+        self.request(
+            Some(BridgeQuery::AdvanceFrame { count }),
+            None,
+            UserEvent::Query,
+        )
+    }
+
+    /// Connects the link cable to another emulator over an RTCDataChannel, replacing the
+    /// previous link. The page does the signaling and both emulators connect their end of the
+    /// channel. A partner that is paused or not running can't reply, so lockstep transfers
+    /// time out. Disconnects are reported through the serial disconnected callback
+    pub fn connect_serial(
+        &self,
+        channel: web_sys::RtcDataChannel,
+        options: Ts<SerialLinkOptions>,
+    ) -> js_sys::Promise {
+        let options = match options.to_rust() {
+            Ok(options) => options,
+            Err(e) => return Self::rejected(e.into()),
+        };
+        let link = SerialLink::new(channel, options);
+        self.request(None, None, |request| UserEvent::SerialConnect {
+            link,
+            request,
         })
     }
+
+    /// Returns which build of the emulator is running, for bug reports
+    pub fn version_info(&self) -> Ts<VersionInfo> {
+        VersionInfo::current().into_ts().unwrap_throw()
+    }
+
+    /// Returns up to given amount of the latest log lines, or all of them, oldest first.
+    /// Lines are only kept if capturing was enabled in the log options
+    pub fn get_log_buffer(&self, max_lines: Option<u32>) -> Vec<String> {
+        captured_log_lines(max_lines.map_or(usize::MAX, |max_lines| max_lines as usize))
+    }
+
+    /// Starts audio playback, call from a click or other user gesture handler
+    pub fn enable_audio(&self) -> js_sys::Promise {
+        self.request(Some(BridgeQuery::EnableAudio {}), None, UserEvent::Query)
+    }
+
+    /// Flushes unsaved data and stops the emulator, releasing its audio stream and graphics
+    /// device. Pending and later queries are rejected. Other emulators on the page keep running,
+    /// and a new one can be started on the canvas with `spawn_event_loop`
+    pub fn shutdown(&self) -> js_sys::Promise {
+        self.request(Some(BridgeQuery::Shutdown {}), None, UserEvent::Query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Options as saved before the per-channel volumes were added
+    const OPTIONS_BEFORE_CHANNEL_VOLUMES: &str = r#"{"volume":0.8,"show_controls":true,
+        "scaling_mode":"Integer","present_mode":"Auto","pause_indicator":true,"osd_enabled":true,
+        "osd_duration_ms":2000,"debug_overlay":false,"rotation":"None",
+        "display_glow_strength":0.3,"background_glow_strength":0.5,"glow_enabled":true,
+        "glow_iterations":4,"glow_radius":1.5,"glow_downscale":2,"ambient_light":0.8,
+        "ambient_color":[1.0,1.0,1.0,1.0],"scanline_strength":0.2,"scanline_size":0.5,
+        "pixel_effect":"Scanlines","grid_size":0.1,"grid_strength":0.5,"grid_tint":false,
+        "color_correction":{"gamma":1.0,"brightness":0.0,"contrast":1.0,"saturation":1.0},
+        "frame_persistence":0.0,"crt_curvature":0.0,"crt_vignette":0.0,"audio_pacing":false,
+        "audio_rate_control":true,"audio_latency_ms":0,"haptics_enabled":true,
+        "haptics_duration_ms":15}"#;
+
+    /// Options as saved when they were first stored as JSON
+    const FIRST_STORED_OPTIONS: &str = r#"{"volume":0.5,"show_controls":false,
+        "display_glow_strength":0.3,"background_glow_strength":0.5,"glow_enabled":false,
+        "glow_iterations":4,"glow_radius":1.5,"ambient_light":0.7,"scanline_strength":0.2,
+        "scanline_size":0.5,"haptics_enabled":false,"haptics_duration_ms":30,
+        "palette":[[0.1,0.2,0.3,1.0],[0.4,0.5,0.6,1.0],[0.7,0.8,0.9,1.0],[1.0,1.0,1.0,1.0]]}"#;
+
+    #[test]
+    fn options_round_trip_through_json() {
+        let options = EmulatorOptions {
+            volume: 0.7,
+            scaling_mode: ScalingMode::Stretch,
+            rotation: DisplayRotation::Clockwise,
+            ambient_color: Color(1.0, 0.74, 0.47, 1.0),
+            color_correction: ColorCorrection {
+                gamma: 1.5,
+                ..Default::default()
+            },
+            wave_volume: 0.25,
+            palette: Palette::new(
+                Color(0.1, 0.2, 0.3, 1.0),
+                Color(0.4, 0.5, 0.6, 1.0),
+                Color(0.7, 0.8, 0.9, 1.0),
+                Color(1.0, 1.0, 1.0, 1.0),
+            ),
+            ..Default::default()
+        };
+        let json = options.to_json();
+        let restored = EmulatorOptions::from_json(&json).unwrap();
+        assert_eq!(restored.to_json(), json);
+        assert_eq!(restored.palette, options.palette);
+        assert_eq!(restored.ambient_color, options.ambient_color);
+    }
+
+    #[test]
+    fn first_stored_options_load_with_defaults_for_newer_fields() {
+        let options = EmulatorOptions::from_json(FIRST_STORED_OPTIONS).unwrap();
+        // Stored fields are kept
+        assert_eq!(options.volume, 0.5);
+        assert!(!options.glow_enabled);
+        assert_eq!(options.glow_iterations, 4);
+        assert_eq!(options.ambient_light, 0.7);
+        assert!(!options.haptics_enabled);
+        assert_eq!(options.haptics_duration_ms, 30);
+        assert_eq!(options.palette.0, Color(0.1, 0.2, 0.3, 1.0));
+        // Fields added later have the defaults of the page instead of zeros
+        let defaults = EmulatorOptions::default();
+        assert_eq!(options.glow_downscale, 2);
+        assert!(options.audio_rate_control);
+        assert_eq!(options.osd_duration_ms, 2000);
+        assert_eq!(options.touch_target_scale, 1.0);
+        assert_eq!(options.ambient_color, Color::default());
+        assert_eq!(options.color_correction, ColorCorrection::default());
+        assert_eq!(options.channel_volumes(), defaults.channel_volumes());
+        assert_eq!(options.scaling_mode, ScalingMode::Integer);
+    }
+
+    #[test]
+    fn missing_fields_take_defaults() {
+        let options = EmulatorOptions::from_json("{}").unwrap();
+        assert_eq!(options.to_json(), EmulatorOptions::default().to_json());
+    }
+
+    #[test]
+    fn unknown_fields_are_ignored() {
+        let options =
+            EmulatorOptions::from_json(r#"{"volume":0.3,"turbo_speed":4,"theme":"dark"}"#).unwrap();
+        assert_eq!(options.volume, 0.3);
+    }
+
+    #[test]
+    fn options_without_channel_volumes_keep_channels_unchanged() {
+        let options = EmulatorOptions::from_json(OPTIONS_BEFORE_CHANNEL_VOLUMES).unwrap();
+        assert_eq!(options.channel_volumes(), [1.0; 4]);
+        assert_eq!(options.volume, 0.8);
+        assert_eq!(options.glow_iterations, 4);
+    }
 }
@@ -0,0 +1,147 @@
+use super::*;
+use std::borrow::Cow;
+
+/// Display frame kept for a recording
+#[derive(Debug)]
+struct RecordedFrame {
+    display: Box<DisplayBuffer>,
+    /// Amount of emulated frames the display is shown for
+    duration_frames: u32,
+}
+
+/// Display frames collected while the emulator runs, encoded into an animated GIF when stopped.
+///
+/// Frames are recorded at most every other emulated frame, and frames that don't change
+/// the display only extend the previous one. Recording stops once the length limit is reached
+#[derive(Debug)]
+pub struct Recording {
+    frames: Vec<RecordedFrame>,
+    /// Colors of the display when recording was started
+    palette: Palette,
+    /// Length limit in emulated frames
+    max_frames: u32,
+    recorded_frames: u32,
+    frames_since_capture: u32,
+    last_frame: u32,
+}
+
+impl Recording {
+    /// Longest recording that can be requested
+    pub const MAX_SECONDS: f32 = 60.0;
+    /// Recording every other frame limits the frame rate to 30
+    const CAPTURE_INTERVAL_FRAMES: u32 = 2;
+    /// Frames encoded before yielding to the event loop
+    const ENCODE_CHUNK_FRAMES: usize = 30;
+    const FRAMES_PER_SECOND: f32 = 59.7275;
+
+    /// Starts recording from the current display of given CPU
+    pub fn new(cpu: &CPU, palette: Palette, max_seconds: f32) -> Self {
+        let max_seconds = max_seconds.clamp(0.0, Self::MAX_SECONDS);
+        Self {
+            frames: vec![RecordedFrame {
+                display: Box::new(*cpu.get_display_buffer()),
+                duration_frames: 0,
+            }],
+            palette,
+            max_frames: (max_seconds * Self::FRAMES_PER_SECOND).ceil() as u32,
+            recorded_frames: 0,
+            frames_since_capture: 0,
+            last_frame: cpu.frame_counter,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.recorded_frames >= self.max_frames
+    }
+
+    /// Records the display of the CPU if enough frames have passed since last capture
+    pub fn update(&mut self, cpu: &CPU) {
+        let new_frames = cpu.frame_counter.wrapping_sub(self.last_frame);
+        self.last_frame = cpu.frame_counter;
+        if self.is_full() {
+            return;
+        }
+        self.frames_since_capture += new_frames;
+        if self.frames_since_capture < Self::CAPTURE_INTERVAL_FRAMES {
+            return;
+        }
+        // The previous frame was shown until now
+        let duration = std::mem::take(&mut self.frames_since_capture);
+        self.recorded_frames += duration;
+        let display = cpu.get_display_buffer();
+        if let Some(last) = self.frames.last_mut() {
+            last.duration_frames += duration;
+            if *last.display == *display {
+                return;
+            }
+        }
+        self.frames.push(RecordedFrame {
+            display: Box::new(*display),
+            duration_frames: 0,
+        });
+        if self.is_full() {
+            log::info!("Recording reached its length limit");
+        }
+    }
+
+    /// Restarts frame counting from the frame of given CPU,
+    /// so a restored state doesn't count as elapsed frames
+    pub fn sync_frame(&mut self, cpu: &CPU) {
+        self.last_frame = cpu.frame_counter;
+    }
+
+    /// Encodes the recording into an animated GIF.
+    /// Yields to the event loop between chunks of frames, so encoding doesn't freeze the page
+    pub async fn encode_gif(mut self) -> Result<Vec<u8>, BridgeError> {
+        let encode_error = |e: gif::EncodingError| {
+            BridgeError::new(ErrorCode::EncodingFailed, "Couldn't encode the recording")
+                .with_details(e)
+        };
+        // The last frame is shown for at least a capture interval
+        if let Some(last) = self.frames.last_mut() {
+            last.duration_frames += self.frames_since_capture.max(Self::CAPTURE_INTERVAL_FRAMES);
+        }
+
+        let palette = self.palette;
+        let global_palette: Vec<u8> = [palette.0, palette.1, palette.2, palette.3]
+            .iter()
+            .flat_map(|color| {
+                let [r, g, b, _] = color.to_srgb8();
+                [r, g, b]
+            })
+            .collect();
+        let mut encoder =
+            gif::Encoder::new(Vec::new(), 160, 144, &global_palette).map_err(encode_error)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(encode_error)?;
+
+        // Delays are in hundredths of a second,
+        // so they're rounded from the total elapsed time to not drift
+        let mut elapsed_frames = 0;
+        let mut elapsed_centis = 0;
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i > 0 && i.is_multiple_of(Self::ENCODE_CHUNK_FRAMES) {
+                yield_to_event_loop().await;
+            }
+            elapsed_frames += frame.duration_frames;
+            let end_centis =
+                (elapsed_frames as f32 * 100.0 / Self::FRAMES_PER_SECOND).round() as u32;
+            let delay = (end_centis - elapsed_centis).min(u16::MAX as u32) as u16;
+            elapsed_centis = end_centis;
+
+            let gif_frame = gif::Frame {
+                width: 160,
+                height: 144,
+                delay,
+                buffer: Cow::Owned(display_color_indices(&frame.display).collect()),
+                ..Default::default()
+            };
+            encoder.write_frame(&gif_frame).map_err(encode_error)?;
+        }
+        encoder.into_inner().map_err(|e| {
+            BridgeError::new(ErrorCode::EncodingFailed, "Couldn't encode the recording")
+                .with_details(e)
+        })
+    }
+}
@@ -3,30 +3,29 @@ use super::*;
 #[repr(C)]
 #[derive(Debug, Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct DisplayOptionsUniform {
-    pub palette: Palette,
+    /// Four colors for each Super Game Boy palette, other games only use the first four
+    pub palettes: [Palette; 4],
 
     pub scanline_strength: f32,
     pub scanline_size: f32,
-    _pad1: [u32; 2],
+    /// Strength of previous frames blended onto the current one, 0 disables it
+    pub frame_persistence: f32,
+    /// Index of the effect drawn between pixels, matching `PixelEffect`
+    pub pixel_effect: u32,
 
     pub origin: [i32; 2],
-    pub scale: u32,
-    _pad2: u32,
-}
+    /// Size of a display pixel on the screen, not an integer outside integer scaling
+    pub scale: [f32; 2],
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-// Uniform of display data
-pub struct DisplayBufferUniform {
-    pub buffer: DisplayBuffer,
-}
+    pub grid_size: f32,
+    pub grid_strength: f32,
+    pub grid_tint: u32,
+    _pad3: u32,
 
-impl Default for DisplayBufferUniform {
-    fn default() -> Self {
-        Self {
-            buffer: [0; DISPLAY_BUFFER_SIZE],
-        }
-    }
+    pub viewport_size: [f32; 2],
+    /// Index of the rotation of the display, matching `DisplayRotation`
+    pub rotation: u32,
+    _pad4: u32,
 }
 
 #[repr(C)]
@@ -42,6 +41,7 @@ pub struct FinalOptionsUniform {
     pub glow_enabled: u32,
     pub glow_strength_display: f32,
     pub glow_strength_background: f32,
+    /// Intensity of the light on the background, multiplied with its color
     pub ambient_light: f32,
 
     pub display_origin: [i32; 2],
@@ -51,12 +51,111 @@ pub struct FinalOptionsUniform {
     pub background_size: [f32; 2],
 
     pub viewport_size: [u32; 2],
-    _pad: [u32; 2],
+    /// Bits of the inputs pressed, highlighted on the onscreen controls
+    pub pressed_inputs: u32,
+    _pad: u32,
+
+    /// Rects of the onscreen inputs in background texture UV,
+    /// indexed by the bit of the input
+    pub input_rects: [[f32; 4]; 8],
+
+    /// Amount of barrel distortion and chromatic aberration of the display, 0 disables it
+    pub crt_curvature: f32,
+    /// Darkening of the display corners, 0 disables it
+    pub crt_vignette: f32,
+    _pad2: [u32; 2],
+
+    /// Color correction of the display
+    pub gamma: f32,
+    pub brightness: f32,
+    pub contrast: f32,
+    pub saturation: f32,
+
+    /// Index of the rotation of the display and background, matching `DisplayRotation`
+    pub rotation: u32,
+    _pad3: [u32; 3],
+
+    /// Linear color of the light on the background.
+    /// Followed by a scalar, so it's packed the same under WGSL and std140 rules
+    pub ambient_color: [f32; 3],
+    /// If the display is dimmed and the pause glyph drawn over it
+    pub paused: u32,
+}
+
+// Vectors of the uniform have to stay aligned to 16 bytes
+const _: () = assert!(std::mem::offset_of!(FinalOptionsUniform, ambient_color).is_multiple_of(16));
+const _: () = assert!(std::mem::size_of::<FinalOptionsUniform>().is_multiple_of(16));
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct OsdOptionsUniform {
+    /// Top left corner of the text on the screen
+    pub origin: [f32; 2],
+    /// Size of a font pixel on the screen
+    pub scale: f32,
+    pub opacity: f32,
+    /// Amount of characters shown
+    pub length: u32,
+    _pad: [u32; 3],
+    /// Glyph indices of the characters, four in each element
+    pub chars: [[u32; 4]; Osd::MAX_CHARS / 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugOverlayUniform {
+    /// Rect of the display in the unrotated layout
+    pub display_origin: [f32; 2],
+    pub display_size: [f32; 2],
+    pub viewport_size: [f32; 2],
+    /// Index of the rotation of the display, matching `DisplayRotation`
+    pub rotation: u32,
+    /// Amount of rects outlined
+    pub rect_count: u32,
+    /// Outlined rects as x, y, width and height in Game Boy pixels
+    pub rects: [[f32; 4]; DebugOverlay::MAX_RECTS],
+    /// Kinds of the rects, matching `DebugRectKind`, four in each element
+    pub kinds: [[u32; 4]; DebugOverlay::MAX_RECTS / 4],
+}
+
+// Arrays longer than 32 elements don't implement Default
+impl Default for DebugOverlayUniform {
+    fn default() -> Self {
+        bytemuck::Zeroable::zeroed()
+    }
+}
+
+/// Amounts of GPU writes made and skipped as the contents hadn't changed
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WriteCounts {
+    pub written: u32,
+    pub skipped: u32,
+}
+
+impl WriteCounts {
+    fn count(&mut self, written: bool) {
+        // Counts are only taken while frame stats are reported, so they saturate in between
+        if written {
+            self.written = self.written.saturating_add(1);
+        } else {
+            self.skipped = self.skipped.saturating_add(1);
+        }
+    }
+}
+
+impl std::ops::AddAssign for WriteCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.written = self.written.saturating_add(other.written);
+        self.skipped = self.skipped.saturating_add(other.skipped);
+    }
 }
 
 #[derive(Debug)]
 pub struct UniformBuffer<U> {
     uniform: U,
+    /// Contents of the GPU buffer, to skip writes that wouldn't change it
+    uploaded: U,
+    write_counts: WriteCounts,
     buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
     pub bind_group_layout: wgpu::BindGroupLayout,
@@ -99,14 +198,27 @@ where
         });
         Self {
             uniform,
+            uploaded: uniform,
+            write_counts: WriteCounts::default(),
             buffer,
             bind_group,
             bind_group_layout,
         }
     }
 
-    pub fn update_buffer(&self, queue: &wgpu::Queue) {
-        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    /// Writes the uniform to the GPU buffer if it has changed since the last write
+    pub fn update_buffer(&mut self, queue: &wgpu::Queue) {
+        let changed = bytemuck::bytes_of(&self.uniform) != bytemuck::bytes_of(&self.uploaded);
+        if changed {
+            queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&self.uniform));
+            self.uploaded = self.uniform;
+        }
+        self.write_counts.count(changed);
+    }
+
+    /// Returns the writes counted since the last call
+    pub fn take_write_counts(&mut self) -> WriteCounts {
+        std::mem::take(&mut self.write_counts)
     }
 }
 
@@ -124,6 +236,57 @@ impl<U> std::ops::DerefMut for UniformBuffer<U> {
     }
 }
 
+/// Uniforms of the passes blurring the display for the glow, alternating between horizontal
+/// and vertical blurs. Buffers are only created when the amount of passes changes
+#[derive(Debug, Default)]
+pub struct BlurPasses {
+    passes: Vec<UniformBuffer<BlurOptionsUniform>>,
+}
+
+impl BlurPasses {
+    /// Sets the passes for given glow options, writing only the uniforms that changed
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        options: &BlurOptionsUniform,
+        iterations: usize,
+        glow_radius: f32,
+    ) {
+        if self.passes.len() != iterations {
+            self.passes = (1..=iterations)
+                .map(|i| UniformBuffer::new(device, &format!("Blur {i}")))
+                .collect();
+        }
+        let radius = glow_radius / iterations as f32;
+        for (i, uniform) in (1..=iterations).zip(self.passes.iter_mut()) {
+            **uniform = *options;
+            // Calculate radius for blurring
+            let blur_radius = ((iterations - i) as f32) * radius;
+            // First blur horizontally, then vertically
+            uniform.direction = if i.is_multiple_of(2) {
+                [0.0, blur_radius]
+            } else {
+                [blur_radius, 0.0]
+            };
+            uniform.update_buffer(queue);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &UniformBuffer<BlurOptionsUniform>> {
+        self.passes.iter()
+    }
+
+    /// Returns the writes of all passes counted since the last call
+    pub fn take_write_counts(&mut self) -> WriteCounts {
+        let mut counts = WriteCounts::default();
+        for uniform in &mut self.passes {
+            counts += uniform.take_write_counts();
+        }
+        counts
+    }
+}
+
 #[derive(Debug)]
 pub struct Texture {
     pub texture: wgpu::Texture,
@@ -132,19 +295,32 @@ pub struct Texture {
 }
 
 impl Texture {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
     pub fn new(
         device: &wgpu::Device,
         bind_group_layout: &wgpu::BindGroupLayout,
         size: &wgpu::Extent3d,
         name: &str,
+    ) -> Self {
+        Self::with_mip_levels(device, bind_group_layout, size, 1, name)
+    }
+
+    /// Creates a texture with given amount of mipmap levels, which the sampler blends between
+    pub fn with_mip_levels(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        size: &wgpu::Extent3d,
+        mip_level_count: u32,
+        name: &str,
     ) -> Self {
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some(&format!("{name} Texture")),
             size: *size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format: Self::FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT
                 | wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::COPY_DST,
@@ -187,3 +363,239 @@ impl Texture {
         Vector::new(Fp::from(size.width as i16), Fp::from(size.height as i16))
     }
 }
+
+/// Textures of the current and previous display frames, containing the color index of each
+/// pixel plus 4 times its Super Game Boy palette.
+/// Previous frames are kept for blending them like the slow response of the LCD
+#[derive(Debug)]
+pub struct DisplayTexture {
+    textures: [wgpu::Texture; Self::FRAMES],
+    /// Bind groups for each rotation of the textures, binding the current frame first
+    bind_groups: [wgpu::BindGroup; Self::FRAMES],
+    /// Index of the texture containing the current frame
+    current: usize,
+    /// If the previous frames should be replaced by the next frame
+    history_cleared: bool,
+    /// Display buffer unpacked to a byte per pixel
+    pixels: Vec<u8>,
+    /// Display buffer and Super Game Boy attributes of the current frame
+    last_display: DisplayBuffer,
+    last_attributes: SGBAttributes,
+    /// Amount of textures holding the current frame, counting from the newest
+    repeats: usize,
+    write_counts: WriteCounts,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl DisplayTexture {
+    const WIDTH: u32 = 160;
+    const HEIGHT: u32 = 144;
+    /// Amount of frames bound, including the current one
+    const FRAMES: usize = 3;
+
+    pub fn new(device: &wgpu::Device, name: &str) -> Self {
+        let textures: [wgpu::Texture; Self::FRAMES] = std::array::from_fn(|i| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(&format!("{name} Texture {i}")),
+                size: Self::size(),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Uint,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            })
+        });
+        let texture_views: [wgpu::TextureView; Self::FRAMES] = std::array::from_fn(|i| {
+            textures[i].create_view(&wgpu::TextureViewDescriptor::default())
+        });
+        let entries: [wgpu::BindGroupLayoutEntry; Self::FRAMES] =
+            std::array::from_fn(|i| wgpu::BindGroupLayoutEntry {
+                binding: i as u32,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Uint,
+                },
+                count: None,
+            });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(&format!("{name} Bind Group Layout")),
+            entries: &entries,
+        });
+        let bind_groups = std::array::from_fn(|current| {
+            // Bind frames from newest to oldest
+            let entries: [wgpu::BindGroupEntry; Self::FRAMES] =
+                std::array::from_fn(|age| wgpu::BindGroupEntry {
+                    binding: age as u32,
+                    resource: wgpu::BindingResource::TextureView(
+                        &texture_views[(current + Self::FRAMES - age) % Self::FRAMES],
+                    ),
+                });
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("{name} Bind Group {current}")),
+                layout: &bind_group_layout,
+                entries: &entries,
+            })
+        });
+        Self {
+            textures,
+            bind_groups,
+            current: 0,
+            history_cleared: true,
+            pixels: vec![0; (Self::WIDTH * Self::HEIGHT) as usize],
+            last_display: [0; DISPLAY_BUFFER_SIZE],
+            last_attributes: [0; SGB::ATTRIBUTES_SIZE],
+            repeats: 0,
+            write_counts: WriteCounts::default(),
+            bind_group_layout,
+        }
+    }
+
+    fn size() -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: Self::WIDTH,
+            height: Self::HEIGHT,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_groups[self.current]
+    }
+
+    /// Makes the next frame replace all previous frames
+    pub fn clear_history(&mut self) {
+        self.history_cleared = true;
+    }
+
+    fn write(&self, queue: &wgpu::Queue, index: usize) {
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.textures[index],
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &self.pixels,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(Self::WIDTH),
+                rows_per_image: Some(Self::HEIGHT),
+            },
+            Self::size(),
+        );
+    }
+
+    /// Unpacks the 2 bit color indices of the display buffer, adds the palette of their tile
+    /// and uploads them in place of the oldest frame. A frame identical to the previous ones,
+    /// like while the LCD is off, is skipped once every texture holds it, as blending them
+    /// with frame persistence gives the same result.
+    /// Returns if the frame was uploaded
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        display: &DisplayBuffer,
+        attributes: &SGBAttributes,
+    ) -> bool {
+        let repeated = !self.history_cleared
+            && *display == self.last_display
+            && *attributes == self.last_attributes;
+        if repeated && self.repeats >= Self::FRAMES {
+            self.write_counts.count(false);
+            return false;
+        }
+        self.repeats = if repeated { self.repeats + 1 } else { 1 };
+        self.last_display = *display;
+        self.last_attributes = *attributes;
+        for (i, pixel) in self.pixels.iter_mut().enumerate() {
+            let bit_i = 2 * i;
+            let (x, y) = (i % Self::WIDTH as usize, i / Self::WIDTH as usize);
+            let palette = attributes[(y / 8) * 20 + x / 8];
+            *pixel = ((display[bit_i / 32] >> (bit_i % 32)) & 3) as u8 | (palette << 2);
+        }
+        if self.history_cleared {
+            self.history_cleared = false;
+            self.repeats = Self::FRAMES;
+            for i in 0..Self::FRAMES {
+                self.write(queue, i);
+            }
+        } else {
+            self.current = (self.current + 1) % Self::FRAMES;
+            self.write(queue, self.current);
+        }
+        self.write_counts.count(true);
+        true
+    }
+
+    /// Returns the frame uploads counted since the last call
+    pub fn take_write_counts(&mut self) -> WriteCounts {
+        std::mem::take(&mut self.write_counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn final_options_match_shader_layout() {
+        assert_eq!(std::mem::size_of::<FinalOptionsUniform>(), 256);
+        assert_eq!(std::mem::align_of::<FinalOptionsUniform>(), 4);
+        let uniform = FinalOptionsUniform {
+            ambient_light: 0.75,
+            ambient_color: [0.25, 0.5, 1.0],
+            paused: 1,
+            ..Default::default()
+        };
+        let bytes = bytemuck::bytes_of(&uniform);
+        let floats: &[f32] = bytemuck::cast_slice(bytes);
+        assert_eq!(floats[3], 0.75);
+        // The vec3 starts a 16 byte row and the pause flag fills the rest of it
+        assert_eq!(floats[60..63], [0.25, 0.5, 1.0]);
+        assert_eq!(bytes[252..], 1u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn blur_passes_are_reused_while_pass_count_stays() {
+        let Some((device, queue)) = test_device() else {
+            eprintln!("No graphics adapter available, skipping");
+            return;
+        };
+        let options = BlurOptionsUniform {
+            direction: [0.0, 0.0],
+            resolution: [160.0, 144.0],
+        };
+        let mut passes = BlurPasses::default();
+        passes.update(&device, &queue, &options, 6, 0.5);
+        let buffers: Vec<wgpu::Buffer> = passes.iter().map(|pass| pass.buffer.clone()).collect();
+        assert_eq!(buffers.len(), 6);
+        assert_eq!(passes.take_write_counts().written, 6);
+
+        // Steady state frames create no buffers and write nothing
+        for _ in 0..3 {
+            passes.update(&device, &queue, &options, 6, 0.5);
+        }
+        assert!(passes.iter().map(|pass| &pass.buffer).eq(&buffers));
+        let counts = passes.take_write_counts();
+        assert_eq!((counts.written, counts.skipped), (0, 18));
+
+        // A new radius or resolution is written into the same buffers
+        passes.update(&device, &queue, &options, 6, 1.0);
+        let resized = BlurOptionsUniform {
+            resolution: [320.0, 288.0],
+            ..options
+        };
+        passes.update(&device, &queue, &resized, 6, 1.0);
+        assert!(passes.iter().map(|pass| &pass.buffer).eq(&buffers));
+        let counts = passes.take_write_counts();
+        // The radius of the last pass is always 0
+        assert_eq!((counts.written, counts.skipped), (11, 1));
+
+        // Only a new amount of passes creates buffers
+        passes.update(&device, &queue, &resized, 4, 1.0);
+        assert_eq!(passes.iter().count(), 4);
+        assert!(!passes.iter().any(|pass| buffers.contains(&pass.buffer)));
+    }
+}
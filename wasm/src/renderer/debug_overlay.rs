@@ -0,0 +1,111 @@
+use super::*;
+
+/// Kinds of rects outlined by the debug overlay, each drawn in its own color
+#[derive(Debug, Clone, Copy)]
+enum DebugRectKind {
+    Sprite = 0,
+    /// Sprite skipped on some of its scanlines due to the limit of 10 sprites per line
+    DroppedSprite = 1,
+    Window = 2,
+    /// Edges of the background map, which move with the scroll position
+    BackgroundMap = 3,
+}
+
+/// Outlines of sprites, the window and the background map drawn over the display.
+/// Only exists while enabled, so it costs nothing otherwise
+#[derive(Debug)]
+pub struct DebugOverlay {
+    render_pipeline: wgpu::RenderPipeline,
+    options: UniformBuffer<DebugOverlayUniform>,
+}
+
+impl DebugOverlay {
+    /// Fits all 40 sprites, the window and the background map
+    pub const MAX_RECTS: usize = 48;
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let options = UniformBuffer::<DebugOverlayUniform>::new(device, "Debug Overlay Options");
+        let shader = device.create_shader_module(wgpu::include_wgsl!("debug_overlay.wgsl"));
+        let render_pipeline = Renderer::init_render_pipeline(
+            device,
+            format,
+            &shader,
+            &[&options.bind_group_layout],
+            wgpu::BlendState::ALPHA_BLENDING,
+        );
+        Self {
+            render_pipeline,
+            options,
+        }
+    }
+
+    /// Places the overlay over the display as laid out for the final pass
+    pub fn set_layout(&mut self, queue: &wgpu::Queue, layout: &FinalOptionsUniform) {
+        self.options.display_origin = layout.display_origin.map(|v| v as f32);
+        self.options.display_size = layout.display_size.map(|v| v as f32);
+        self.options.viewport_size = layout.viewport_size.map(|v| v as f32);
+        self.options.rotation = layout.rotation;
+        self.options.update_buffer(queue);
+    }
+
+    /// Replaces the outlined rects with the state of the latest frame
+    pub fn update(&mut self, queue: &wgpu::Queue, info: &PPUDebugInfo) {
+        let (scroll_x, scroll_y) = info.scroll;
+        // The display starts at the scroll position in the map,
+        // so the map is offset the other way on the display
+        let map = (
+            [-(scroll_x as f32), -(scroll_y as f32), 256.0, 256.0],
+            DebugRectKind::BackgroundMap,
+        );
+        let window = info.window.map(|(x, y)| {
+            (
+                [x as f32, y as f32, 160.0 - x as f32, 144.0 - y as f32],
+                DebugRectKind::Window,
+            )
+        });
+        let sprites = info.sprites.iter().map(|sprite| {
+            let kind = if sprite.dropped {
+                DebugRectKind::DroppedSprite
+            } else {
+                DebugRectKind::Sprite
+            };
+            (
+                [sprite.x as f32, sprite.y as f32, 8.0, sprite.height as f32],
+                kind,
+            )
+        });
+        // Sprites are listed first, so they're drawn over the layers
+        let rects = sprites.chain(window).chain(std::iter::once(map));
+
+        let mut count = 0;
+        for (i, (rect, kind)) in rects.take(Self::MAX_RECTS).enumerate() {
+            self.options.rects[i] = rect;
+            self.options.kinds[i / 4][i % 4] = kind as u32;
+            count = i + 1;
+        }
+        self.options.rect_count = count as u32;
+        self.options.update_buffer(queue);
+    }
+
+    /// Draws the outlines over the view
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut overlay_render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Debug Overlay Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                depth_slice: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        overlay_render_pass.set_pipeline(&self.render_pipeline);
+        overlay_render_pass.set_bind_group(0, &self.options.bind_group, &[]);
+        overlay_render_pass.draw(0..6, 0..1);
+    }
+}
@@ -0,0 +1,87 @@
+struct Options {
+    display_origin: vec2<f32>,
+    display_size: vec2<f32>,
+    viewport_size: vec2<f32>,
+    rotation: u32,
+    rect_count: u32,
+    rects: array<vec4<f32>, 48>,
+    kinds: array<vec4<u32>, 12>,
+}
+
+@group(0) @binding(0)
+var<uniform> options: Options;
+
+const ROTATION_CLOCKWISE: u32 = 1u;
+const ROTATION_COUNTERCLOCKWISE: u32 = 2u;
+
+const DISPLAY_SIZE: vec2<f32> = vec2f(160.0, 144.0);
+// Colors of the outlines, indexed by the kind of the rect
+const KIND_COLORS = array<vec4<f32>, 4>(
+    // Sprite
+    vec4f(0.2, 1.0, 0.2, 0.9),
+    // Sprite dropped by the sprite limit
+    vec4f(1.0, 0.2, 0.2, 0.9),
+    // Window
+    vec4f(0.2, 0.6, 1.0, 0.9),
+    // Background map edges
+    vec4f(1.0, 0.9, 0.2, 0.9),
+);
+
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    // List of vertices that form a full clip space quad
+    var square_vertices = array<vec2<f32>, 6>(
+        vec2(-1.0, -1.0),
+        vec2(1.0, -1.0),
+        vec2(-1.0, 1.0),
+        vec2(1.0, 1.0),
+        vec2(-1.0, 1.0),
+        vec2(1.0, -1.0),
+    );
+    var out: VertexOutput;
+    out.pos = vec4f(square_vertices[in_vertex_index], 0.0, 1.0);
+    return out;
+}
+
+// Converts a position on the screen to the unrotated layout of the display
+fn screen_to_layout(pos: vec2<f32>) -> vec2<f32> {
+    switch options.rotation {
+        case ROTATION_CLOCKWISE: {
+            return vec2f(pos.y, options.viewport_size.x - pos.x);
+        }
+        case ROTATION_COUNTERCLOCKWISE: {
+            return vec2f(options.viewport_size.y - pos.y, pos.x);
+        }
+        default: {
+            return pos;
+        }
+    }
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Position on the display in Game Boy pixels
+    let pos = (screen_to_layout(in.pos.xy) - options.display_origin) / options.display_size
+        * DISPLAY_SIZE;
+    // Outlines are a screen pixel wide, derived before any invocation is discarded
+    let line_width = fwidth(pos);
+    if any(pos < vec2f(0.0)) || any(pos >= DISPLAY_SIZE) {
+        discard;
+    }
+
+    var kind_colors = KIND_COLORS;
+    for (var i = 0u; i < options.rect_count; i++) {
+        let rect = options.rects[i];
+        let rel = pos - rect.xy;
+        let inside = all(rel >= vec2f(0.0)) && all(rel < rect.zw);
+        let on_edge = any(rel < line_width) || any(rel >= rect.zw - line_width);
+        if inside && on_edge {
+            return kind_colors[options.kinds[i / 4u][i % 4u]];
+        }
+    }
+    return vec4f(0.0);
+}
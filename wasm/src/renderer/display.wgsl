@@ -1,34 +1,54 @@
 /// Options for rendering
 struct OptionsUniform {
-    /// The colored palette to get the final drawn pixel color from
-    palette: array<vec4<f32>, 4>,
+    /// The colored palettes to get the final drawn pixel color from,
+    /// indexed by the color index plus 4 times the Super Game Boy palette
+    palettes: array<vec4<f32>, 16>,
     
     /// The brightness or strength of scanlines
     scanline_strength: f32,
     /// The size of the scanline
     scanline_size: f32,
-    pad1: vec2<u32>,
+    /// The strength of previous frames blended onto the current one
+    frame_persistence: f32,
+    /// The effect drawn between pixels
+    pixel_effect: u32,
   
     /// The origin of the display in pixel space
     origin: vec2<i32>,
-    /// The scale of pixels
-    scale: u32,
-    pad2: u32,
+    /// The size of a display pixel on screen, only an integer with integer scaling
+    scale: vec2<f32>,
+
+    /// The size of the dot matrix gaps relative to a pixel
+    grid_size: f32,
+    /// The darkness of the dot matrix gaps
+    grid_strength: f32,
+    /// If the gaps are tinted with the unlit pixel color
+    grid_tint: u32,
+    pad3: u32,
+
+    /// The size of the screen
+    viewport_size: vec2<f32>,
+    /// The rotation of the display on the screen
+    rotation: u32,
+    pad4: u32,
 }
 
+const ROTATION_CLOCKWISE: u32 = 1u;
+const ROTATION_COUNTERCLOCKWISE: u32 = 2u;
+
+const PIXEL_EFFECT_DOT_MATRIX: u32 = 1u;
+
 @group(0) @binding(0)
 var<uniform> options: OptionsUniform;
 
-// Contains the display pixel data for rendering.
-// Structured as an array of 4D vectors to
-// satisfy the memory alignment system.
-// 1440 integers in 4D vectors = 360 vectors
-struct DisplayUniform {
-    buffer: array<vec4<u32>, 360>
-};
-
+// Contain the color index of each display pixel,
+// for the current frame and the two frames before it
 @group(1) @binding(0)
-var<uniform> display: DisplayUniform;
+var display: texture_2d<u32>;
+@group(1) @binding(1)
+var previous_display: texture_2d<u32>;
+@group(1) @binding(2)
+var older_display: texture_2d<u32>;
 
 struct VertexOutput {
     @builtin(position) pos: vec4<f32>,
@@ -53,6 +73,23 @@ fn vs_main(
     return out;
 }
 
+fn get_texel_color(pixel: vec2<u32>) -> vec4<f32> {
+    // Get color index of pixel
+    let color = options.palettes[textureLoad(display, pixel, 0).r];
+    let persistence = options.frame_persistence;
+    if persistence == 0.0 {
+        // Return color from current palette
+        return color;
+    }
+    // Blend in previous frames, weighted by how long the LCD takes to fade them out
+    let previous_color = options.palettes[textureLoad(previous_display, pixel, 0).r];
+    let older_color = options.palettes[textureLoad(older_display, pixel, 0).r];
+    let previous_weight = persistence;
+    let older_weight = persistence * persistence;
+    let blended = color + previous_color * previous_weight + older_color * older_weight;
+    return blended / (1.0 + previous_weight + older_weight);
+}
+
 fn get_pixel_color(pos: vec2<i32>) -> vec4<f32> {
     let origin = options.origin;
     let scale = options.scale;
@@ -61,29 +98,28 @@ fn get_pixel_color(pos: vec2<i32>) -> vec4<f32> {
     if pos.x < origin.x || pos.y < origin.y {
         return vec4f(0.0);
     }
-    let pixel = vec2u(pos - origin) / scale;
+    // Position of the center of the screen pixel in display pixels
+    let texel_pos = (vec2f(pos - origin) + 0.5) / scale;
+    let pixel = vec2u(texel_pos);
     // Crop out pixels on the bottom and right sides of display
     if pixel.x >= 160u || pixel.y >= 144u {
         return vec4f(0.0);
     }
-    // Calculate index of pixel on display
-    let pixel_i = pixel.y * 160u + pixel.x;
-    // Calculate index of the two color bits in display buffer
-    let bit_i = 2u * pixel_i;
-    // Calculate index of vector containing bit
-    // (vec4 contains 128 bits of data)
-    let vec_i = bit_i / 128u;
-    // Calculate index of vector integer containing bit
-    let vec_bit_i = bit_i % 128u;
-    let int_i = vec_bit_i / 32u;
-    // Calculate index of color bits inside integer 
-    let int_bit_i = ((vec_bit_i % 32u) / 2u) * 2u;
-
-    // Get integer value and bit mask the color value
-    let int = display.buffer[vec_i][int_i];
-    let color = (int >> int_bit_i) & 3u;
-    // Return color from current palette
-    return options.palette[color];
+    // With an integer scale, every screen pixel is inside a single display pixel
+    if all(fract(scale) == vec2f(0.0)) {
+        return get_texel_color(pixel);
+    }
+
+    // Otherwise blend screen pixels covering two display pixels by the covered amount,
+    // which keeps the edges sharp unlike regular bilinear filtering
+    let half_size = 0.5 / scale;
+    let low = vec2u(max(texel_pos - half_size, vec2f(0.0)));
+    let high = min(vec2u(texel_pos + half_size), vec2u(159u, 143u));
+    let high_coverage = fract(texel_pos + half_size) * scale;
+    let weight = select(vec2f(0.0), clamp(high_coverage, vec2f(0.0), vec2f(1.0)), high != low);
+    let top = mix(get_texel_color(low), get_texel_color(vec2u(high.x, low.y)), weight.x);
+    let bottom = mix(get_texel_color(vec2u(low.x, high.y)), get_texel_color(high), weight.x);
+    return mix(top, bottom, weight.y);
 }
 
 const PI = 3.14159265359;
@@ -96,21 +132,21 @@ fn scanline_sin(x: f32, freq: f32) -> f32 {
 }
 
 fn get_scanline_color(color: vec4<f32>, pos: vec2<i32>) -> vec4<f32> {
-    let scale = f32(options.scale);
+    let scale = options.scale;
     let size = options.scanline_size;
     let strength = options.scanline_strength / 10.0;
     // Don't draw scanlines if pixel size is only one
-    if scale == 1.0 {
+    if all(scale <= vec2f(1.0)) {
         return color;
     }
     
     // Correct pos so the right and bottom sides show
     // the fully lit scanline
     var corrected_pos = pos;
-    if pos.x == 160 * i32(options.scale) - 1 {
+    if pos.x == i32(round(160.0 * scale.x)) - 1 {
         corrected_pos.x += 1;
     }
-    if pos.y == 144 * i32(options.scale) - 1 {
+    if pos.y == i32(round(144.0 * scale.y)) - 1 {
         corrected_pos.y += 1;
     }
 
@@ -123,13 +159,51 @@ fn get_scanline_color(color: vec4<f32>, pos: vec2<i32>) -> vec4<f32> {
     return color + vec4f(vec3f(value * strength), 0.0);
 }
 
+fn get_dot_matrix_color(color: vec4<f32>, pos: vec2<i32>) -> vec4<f32> {
+    let scale = options.scale;
+    // Don't draw a grid if pixel size is only one
+    if all(scale <= vec2f(1.0)) {
+        return color;
+    }
+    // Gaps are whole screen pixels on the bottom and right sides of each display pixel,
+    // so every pixel looks the same at any integer scale
+    let gap = clamp(round(options.grid_size * scale), vec2f(1.0), max(scale - 1.0, vec2f(1.0)));
+    let pixel_pos = vec2f(pos) % scale;
+    if pixel_pos.x < scale.x - gap.x && pixel_pos.y < scale.y - gap.y {
+        return color;
+    }
+    if options.grid_tint > 0u {
+        // The lightest palette color is the unlit LCD
+        return mix(color, options.palettes[0], options.grid_strength);
+    }
+    return vec4f(color.rgb * (1.0 - options.grid_strength), color.a);
+}
+
+// Converts a position on the screen to the unrotated layout of the display
+fn screen_to_layout(pos: vec2<f32>) -> vec2<f32> {
+    switch options.rotation {
+        case ROTATION_CLOCKWISE: {
+            return vec2f(pos.y, options.viewport_size.x - pos.x);
+        }
+        case ROTATION_COUNTERCLOCKWISE: {
+            return vec2f(options.viewport_size.y - pos.y, pos.x);
+        }
+        default: {
+            return pos;
+        }
+    }
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let pos = vec2i(in.pos.xy);
+    let pos = vec2i(screen_to_layout(in.pos.xy));
     let color = get_pixel_color(pos);
     if color.a == 0.0 {
       discard;
     }
+    if options.pixel_effect == PIXEL_EFFECT_DOT_MATRIX {
+        return get_dot_matrix_color(color, pos - options.origin);
+    }
     return get_scanline_color(color, pos - options.origin);
 }
 
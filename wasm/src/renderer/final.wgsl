@@ -21,9 +21,40 @@ struct Options {
     background_origin: vec2<f32>,
     background_size: vec2<f32>,
     viewport_size: vec2<u32>,
-    pad2: vec2<u32>,
+    pressed_inputs: u32,
+    pad2: u32,
+    input_rects: array<vec4<f32>, 8>,
+    crt_curvature: f32,
+    crt_vignette: f32,
+    pad3: vec2<u32>,
+    gamma: f32,
+    brightness: f32,
+    contrast: f32,
+    saturation: f32,
+    rotation: u32,
+    pad4: u32,
+    pad5: vec2<u32>,
+    ambient_color: vec3<f32>,
+    paused: u32,
 }
 
+const ROTATION_CLOCKWISE: u32 = 1u;
+const ROTATION_COUNTERCLOCKWISE: u32 = 2u;
+
+// Brightness of the onscreen buttons while pressed
+const PRESSED_BRIGHTNESS: f32 = 0.6;
+// Barrel distortion at the display corners with full curvature
+const CRT_MAX_DISTORTION: f32 = 0.2;
+// Offset of the red and blue channels at the display corners with full curvature
+const CRT_MAX_ABERRATION: f32 = 0.004;
+// Brightness of the display while paused
+const PAUSED_BRIGHTNESS: f32 = 0.5;
+// Size of the pause glyph and its bars, in Game Boy pixels
+const PAUSE_GLYPH_HEIGHT: f32 = 24.0;
+const PAUSE_BAR_WIDTH: f32 = 7.0;
+const PAUSE_BAR_GAP: f32 = 6.0;
+const PAUSE_GLYPH_OPACITY: f32 = 0.8;
+
 @group(3) @binding(0)
 var<uniform> options: Options;
 
@@ -58,36 +89,155 @@ fn sample_background(pos: vec2<u32>) -> vec4<f32> {
     // Scale position to texture UV
     let texture_uv = (vec2f(pos) - options.background_origin) / options.background_size;
     // Sample background texture
-    return textureSample(background_texture, background_sampler, texture_uv);
+    var color = textureSample(background_texture, background_sampler, texture_uv);
+    // Darken the onscreen buttons of pressed inputs
+    for (var i = 0u; i < 8u; i++) {
+        let rect = options.input_rects[i];
+        let inside = all(texture_uv >= rect.xy) && all(texture_uv < rect.xy + rect.zw);
+        if (options.pressed_inputs & (1u << i)) != 0u && inside {
+            color = vec4f(color.rgb * PRESSED_BRIGHTNESS, color.a);
+        }
+    }
+    return color;
+}
+
+// Converts a position on the screen to the unrotated layout of the display and background
+fn screen_to_layout(pos: vec2<f32>) -> vec2<f32> {
+    let viewport_size = vec2f(options.viewport_size);
+    switch options.rotation {
+        case ROTATION_CLOCKWISE: {
+            return vec2f(pos.y, viewport_size.x - pos.x);
+        }
+        case ROTATION_COUNTERCLOCKWISE: {
+            return vec2f(viewport_size.y - pos.y, pos.x);
+        }
+        default: {
+            return pos;
+        }
+    }
+}
+
+// Converts a position in the unrotated layout to a position on the screen
+fn layout_to_screen(pos: vec2<f32>) -> vec2<f32> {
+    let viewport_size = vec2f(options.viewport_size);
+    switch options.rotation {
+        case ROTATION_CLOCKWISE: {
+            return vec2f(viewport_size.x - pos.y, pos.x);
+        }
+        case ROTATION_COUNTERCLOCKWISE: {
+            return vec2f(pos.y, viewport_size.y - pos.x);
+        }
+        default: {
+            return pos;
+        }
+    }
+}
+
+// Maps a position in the display to a position on a curved CRT screen,
+// where both are 0 to 1 inside the display
+fn crt_distort(display_pos: vec2<f32>) -> vec2<f32> {
+    let centered = display_pos * 2.0 - 1.0;
+    let distortion = options.crt_curvature * CRT_MAX_DISTORTION * dot(centered, centered);
+    return (centered * (1.0 + distortion) + 1.0) / 2.0;
+}
+
+// Converts a position in the display to viewport UV
+fn display_to_uv(display_pos: vec2<f32>) -> vec2<f32> {
+    let pos = vec2f(options.display_origin) + display_pos * vec2f(options.display_size);
+    return layout_to_screen(pos) / vec2f(options.viewport_size);
+}
+
+fn sample_crt_display(display_pos: vec2<f32>) -> vec4<f32> {
+    let curved_pos = crt_distort(display_pos);
+    // Area curved out of the display is left dark like the edge of the tube
+    if any(curved_pos < vec2f(0.0)) || any(curved_pos > vec2f(1.0)) {
+        return vec4f(0.0, 0.0, 0.0, 1.0);
+    }
+    // Offset red and blue channels towards the edges
+    let aberration = (curved_pos * 2.0 - 1.0) * options.crt_curvature * CRT_MAX_ABERRATION;
+    var color = textureSample(display_texture, display_sampler, display_to_uv(curved_pos));
+    color.r = textureSample(display_texture, display_sampler, display_to_uv(curved_pos + aberration)).r;
+    color.b = textureSample(display_texture, display_sampler, display_to_uv(curved_pos - aberration)).b;
+
+    // Darken the corners
+    let edge = 16.0 * curved_pos.x * curved_pos.y * (1.0 - curved_pos.x) * (1.0 - curved_pos.y);
+    let vignette = mix(1.0, pow(edge, 0.25), options.crt_vignette);
+    return vec4f(color.rgb * vignette, color.a);
+}
+
+// Applies brightness, contrast, saturation and gamma to a display color
+fn correct_color(color: vec4<f32>) -> vec4<f32> {
+    // Skip when neutral so the palette colors stay exact
+    if options.gamma == 1.0 && options.brightness == 0.0 && options.contrast == 1.0
+        && options.saturation == 1.0 {
+        return color;
+    }
+    var rgb = (color.rgb - 0.5) * options.contrast + 0.5 + options.brightness;
+    let luma = dot(rgb, vec3f(0.2126, 0.7152, 0.0722));
+    rgb = mix(vec3f(luma), rgb, options.saturation);
+    rgb = pow(clamp(rgb, vec3f(0.0), vec3f(1.0)), vec3f(1.0 / options.gamma));
+    return vec4f(rgb, color.a);
+}
+
+// Dims the display and draws two bars in its center.
+// The glyph is placed on the screen, so it stays upright when the display is rotated
+fn apply_pause_overlay(color: vec4<f32>, screen_pos: vec2<f32>) -> vec4<f32> {
+    let display_center = vec2f(options.display_origin) + vec2f(options.display_size) * 0.5;
+    let pixel_size = f32(min(options.display_size.x, options.display_size.y)) / 144.0;
+    let offset = abs(screen_pos - layout_to_screen(display_center)) / pixel_size;
+    let in_bar = offset.y < PAUSE_GLYPH_HEIGHT * 0.5
+        && offset.x >= PAUSE_BAR_GAP * 0.5
+        && offset.x < PAUSE_BAR_GAP * 0.5 + PAUSE_BAR_WIDTH;
+    var rgb = color.rgb * PAUSED_BRIGHTNESS;
+    if in_bar {
+        rgb = mix(rgb, vec3f(1.0), PAUSE_GLYPH_OPACITY);
+    }
+    return vec4f(rgb, color.a);
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let pos = vec2u(in.pos.xy);
+    let layout_pos = screen_to_layout(in.pos.xy);
+    let pos = vec2u(layout_pos);
     // Calculate display bounds
     let display_min = vec2u(max(options.display_origin, vec2i(0)));
     let display_max = display_min + vec2u(options.display_size) - 1u;
 
     var color = vec4f(0.0);
     var glow_strength = 0.0;
+    var glow_uv = in.uv;
+    var is_display = false;
     if pos.x < display_min.x || pos.x > display_max.x || pos.y < display_min.y || pos.y > display_max.y {
         // If nothing needs to be drawn on the background, discard fragment
         if options.ambient_light == 0.0 && options.glow_enabled == 0u {
           discard;
         }
-        // Sample background with brightness
-        color = sample_background(pos) * options.ambient_light;
+        // Sample background lit by the ambient light
+        let light = options.ambient_color * options.ambient_light;
+        color = sample_background(pos) * vec4f(light, options.ambient_light);
         glow_strength = options.glow_strength_background;
     } else {
         // Sample display texture
-        color = textureSample(display_texture, display_sampler, in.uv);
+        if options.crt_curvature > 0.0 || options.crt_vignette > 0.0 {
+            let display_pos = (layout_pos - vec2f(options.display_origin)) / vec2f(options.display_size);
+            color = sample_crt_display(display_pos);
+            // Glow follows the curved display
+            glow_uv = display_to_uv(crt_distort(display_pos));
+        } else {
+            color = textureSample(display_texture, display_sampler, in.uv);
+        }
+        color = correct_color(color);
         glow_strength = options.glow_strength_display;
+        is_display = true;
     }
     // Apply glow if enabled
     if options.glow_enabled > 0u {
-        let glow = textureSample(blur_texture, blur_sampler, in.uv);
+        let glow = textureSample(blur_texture, blur_sampler, glow_uv);
         color += (glow * glow_strength);
     }
+    if is_display && options.paused > 0u {
+        color = apply_pause_overlay(color, in.pos.xy);
+    }
     return color;
 }
 
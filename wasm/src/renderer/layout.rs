@@ -0,0 +1,374 @@
+use super::*;
+
+/// Options the display and background are laid out with.
+/// The layout is calculated unrotated, so the sides of the screen are swapped when rotated
+/// sideways, and positions are converted between the screen and the layout
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Layout {
+    pub scaling_mode: ScalingMode,
+    /// Added to the integer scale of the display
+    pub scale_offset: i32,
+    pub rotation: DisplayRotation,
+    pub safe_area_insets: SafeAreaInsets,
+}
+
+/// Pixel scale, top-left origin and size of the display in the unrotated layout
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayPlacement {
+    pub scale: Vector,
+    pub origin: Vector,
+    pub size: Vector,
+}
+
+impl Layout {
+    fn lcd_size() -> Vector {
+        Vector::new(Fp::from(160), Fp::from(144))
+    }
+
+    /// Places the display on a screen of given size.
+    /// If the onscreen controls are shown, `controls` has the rects of the controls and the
+    /// display in the background image, and the display is placed so the part of the
+    /// background containing the controls is fully visible.
+    /// Otherwise the display is centered and scaled to fit.
+    /// In both cases, the display is scaled following the scaling mode
+    pub fn place_display(
+        &self,
+        screen_size: Vector,
+        controls: Option<(Rect, Rect)>,
+    ) -> DisplayPlacement {
+        let surface_size = if self.rotation == DisplayRotation::None {
+            screen_size
+        } else {
+            Vector::new(screen_size.y, screen_size.x)
+        };
+        let lcd_size = Self::lcd_size();
+        // Keep the layout out of areas covered by notches and system UI
+        let (inset_origin, inset_size) = self.insets();
+        let usable_size = surface_size - inset_size;
+
+        match controls {
+            Some((controls_rect, display_rect)) => {
+                // Fit the rectangle containing controls onto the screen
+                let controls_scale = (usable_size.x / controls_rect.size.x)
+                    .min(usable_size.y / controls_rect.size.y);
+                let fitted_controls_size = controls_rect.size * controls_scale;
+
+                // Calculate size of display in fitted rectangle
+                let rect_diff = controls_rect.size / display_rect.size;
+                let fitted_display_size = fitted_controls_size / rect_diff;
+
+                // Calculate display pixel scale, stretching would distort the controls.
+                // Stepping the scale up lets the display fill the room left by the controls
+                let display_scale = self.fit_display_scale(fitted_display_size, false);
+                let display_scale = self.offset_display_scale(display_scale, usable_size);
+                let pixel_scale = display_scale * lcd_size / display_rect.size;
+
+                // Calculate actual size of control rectangle
+                let final_controls_size = display_scale * lcd_size * rect_diff;
+                // Calculate the origin of the display
+                let controls_origin = inset_origin + (usable_size - final_controls_size) / 2;
+                let rect_pos_diff = display_rect.pos - controls_rect.pos;
+
+                DisplayPlacement {
+                    scale: display_scale,
+                    origin: controls_origin + (rect_pos_diff * pixel_scale),
+                    size: lcd_size * display_scale,
+                }
+            }
+            None => {
+                let scale = self.fit_display_scale(usable_size, true);
+                let scale = self.offset_display_scale(scale, usable_size);
+                // Calculate size of the display
+                let size = lcd_size * scale;
+
+                DisplayPlacement {
+                    scale,
+                    // Calculate top-left origin in pixel space for centered canvas
+                    origin: inset_origin + (usable_size - size) / 2,
+                    size,
+                }
+            }
+        }
+    }
+
+    /// Adds the scale offset to an integer display scale,
+    /// keeping it at least 1 and the display within given size
+    fn offset_display_scale(&self, scale: Vector, max_size: Vector) -> Vector {
+        if self.scaling_mode != ScalingMode::Integer || self.scale_offset == 0 {
+            return scale;
+        }
+        let max_scale = max_size / Self::lcd_size();
+        let max_scale = i32::from(max_scale.x.floor().min(max_scale.y.floor())).max(1);
+        let offset_scale = (i32::from(scale.x) + self.scale_offset).clamp(1, max_scale);
+        let offset_scale = Fp::from(offset_scale as i16);
+        Vector::new(offset_scale, offset_scale)
+    }
+
+    /// Returns the display pixel scale fitting the display in given size
+    fn fit_display_scale(&self, size: Vector, allow_stretch: bool) -> Vector {
+        let scale = size / Self::lcd_size();
+        match self.scaling_mode {
+            ScalingMode::Stretch if allow_stretch => scale,
+            ScalingMode::Integer => {
+                // Largest integer scale which still fits display in both dimensions
+                let integer_scale = scale.x.floor().min(scale.y.floor());
+                Vector::new(integer_scale, integer_scale)
+            }
+            _ => {
+                let fit_scale = scale.x.min(scale.y);
+                Vector::new(fit_scale, fit_scale)
+            }
+        }
+    }
+
+    /// Returns the offset and the total size of the safe area insets in the unrotated layout
+    fn insets(&self) -> (Vector, Vector) {
+        let SafeAreaInsets {
+            top,
+            right,
+            bottom,
+            left,
+        } = self.safe_area_insets;
+        // Move the sides of the screen to the sides of the layout
+        let (top, right, bottom, left) = match self.rotation {
+            DisplayRotation::None => (top, right, bottom, left),
+            DisplayRotation::Clockwise => (right, bottom, left, top),
+            DisplayRotation::CounterClockwise => (left, top, right, bottom),
+        };
+        let origin = Vector::new(Fp::from(left as i16), Fp::from(top as i16));
+        let size = Vector::new(
+            Fp::from((left + right) as i16),
+            Fp::from((top + bottom) as i16),
+        );
+        (origin, size)
+    }
+
+    /// Converts a position on a screen of given size to the unrotated layout
+    pub fn screen_to_layout(&self, screen_size: Vector, pos: Vector) -> Vector {
+        match self.rotation {
+            DisplayRotation::None => pos,
+            DisplayRotation::Clockwise => Vector::new(pos.y, screen_size.x - pos.x),
+            DisplayRotation::CounterClockwise => Vector::new(screen_size.y - pos.y, pos.x),
+        }
+    }
+
+    /// Converts a position in the unrotated layout to a position on a screen of given size
+    pub fn layout_to_screen(&self, screen_size: Vector, pos: Vector) -> Vector {
+        match self.rotation {
+            DisplayRotation::None => pos,
+            DisplayRotation::Clockwise => Vector::new(screen_size.x - pos.y, pos.x),
+            DisplayRotation::CounterClockwise => Vector::new(pos.y, screen_size.y - pos.x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vector(x: i16, y: i16) -> Vector {
+        Vector::new(Fp::from(x), Fp::from(y))
+    }
+
+    fn rotated(rotation: DisplayRotation) -> Layout {
+        Layout {
+            rotation,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn screen_corners_map_to_rotated_layout() {
+        let screen = vector(400, 300);
+        let clockwise = rotated(DisplayRotation::Clockwise);
+        // The top-right corner of the screen is the top-left corner of the layout
+        assert_eq!(
+            clockwise.screen_to_layout(screen, vector(400, 0)),
+            vector(0, 0)
+        );
+        assert_eq!(
+            clockwise.screen_to_layout(screen, vector(0, 300)),
+            vector(300, 400)
+        );
+        let counter_clockwise = rotated(DisplayRotation::CounterClockwise);
+        // The bottom-left corner of the screen is the top-left corner of the layout
+        assert_eq!(
+            counter_clockwise.screen_to_layout(screen, vector(0, 300)),
+            vector(0, 0)
+        );
+        assert_eq!(
+            counter_clockwise.screen_to_layout(screen, vector(400, 0)),
+            vector(300, 400)
+        );
+        let unrotated = rotated(DisplayRotation::None);
+        assert_eq!(
+            unrotated.screen_to_layout(screen, vector(12, 34)),
+            vector(12, 34)
+        );
+    }
+
+    #[test]
+    fn layout_to_screen_reverses_screen_to_layout() {
+        let screen = vector(640, 360);
+        for rotation in [
+            DisplayRotation::None,
+            DisplayRotation::Clockwise,
+            DisplayRotation::CounterClockwise,
+        ] {
+            let layout = rotated(rotation);
+            for pos in [
+                vector(0, 0),
+                vector(17, 250),
+                vector(360, 640),
+                vector(123, 45),
+            ] {
+                let screen_pos = layout.layout_to_screen(screen, pos);
+                assert_eq!(
+                    layout.screen_to_layout(screen, screen_pos),
+                    pos,
+                    "{rotation:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn insets_follow_rotation() {
+        let insets = SafeAreaInsets {
+            top: 1,
+            right: 2,
+            bottom: 4,
+            left: 8,
+        };
+        let layout = |rotation| Layout {
+            rotation,
+            safe_area_insets: insets,
+            ..Default::default()
+        };
+        assert_eq!(
+            layout(DisplayRotation::None).insets(),
+            (vector(8, 1), vector(10, 5))
+        );
+        // The right side of the screen is the top of the layout when rotated clockwise
+        assert_eq!(
+            layout(DisplayRotation::Clockwise).insets(),
+            (vector(1, 2), vector(5, 10))
+        );
+        assert_eq!(
+            layout(DisplayRotation::CounterClockwise).insets(),
+            (vector(4, 8), vector(5, 10))
+        );
+    }
+
+    #[test]
+    fn sideways_display_is_scaled_by_swapped_screen_sides() {
+        // A portrait screen fits the display 4 times when rotated, but only twice upright
+        let screen = vector(600, 700);
+        let upright = rotated(DisplayRotation::None).place_display(screen, None);
+        assert_eq!(upright.scale, vector(3, 3));
+        for rotation in [
+            DisplayRotation::Clockwise,
+            DisplayRotation::CounterClockwise,
+        ] {
+            let display = rotated(rotation).place_display(screen, None);
+            assert_eq!(display.scale, vector(4, 4));
+            assert_eq!(display.size, vector(640, 576));
+            // Centered on the 700x600 layout
+            assert_eq!(display.origin, vector(30, 12));
+        }
+    }
+
+    #[test]
+    fn rotated_display_covers_same_screen_area_from_both_sides() {
+        let screen = vector(500, 900);
+        let corners = |rotation| {
+            let layout = rotated(rotation);
+            let display = layout.place_display(screen, None);
+            let a = layout.layout_to_screen(screen, display.origin);
+            let b = layout.layout_to_screen(screen, display.origin + display.size);
+            (
+                Vector::new(a.x.min(b.x), a.y.min(b.y)),
+                Vector::new(a.x.max(b.x), a.y.max(b.y)),
+            )
+        };
+        let clockwise = corners(DisplayRotation::Clockwise);
+        assert_eq!(clockwise, corners(DisplayRotation::CounterClockwise));
+        // The display is 432x480 on the screen, centered
+        assert_eq!(clockwise, (vector(34, 210), vector(466, 690)));
+    }
+
+    fn offset(scaling_mode: ScalingMode, scale_offset: i32) -> Layout {
+        Layout {
+            scaling_mode,
+            scale_offset,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn scale_offset_steps_integer_scale() {
+        // Fits 5x4, so the display is scaled by 4
+        let screen = vector(800, 600);
+        let display = offset(ScalingMode::Integer, -1).place_display(screen, None);
+        assert_eq!(display.scale, vector(3, 3));
+        assert_eq!(display.size, vector(480, 432));
+        assert_eq!(display.origin, vector(160, 84));
+        // Stepping up is clamped to what fits, stepping down to a scale of 1
+        let display = offset(ScalingMode::Integer, 1).place_display(screen, None);
+        assert_eq!(display.scale, vector(4, 4));
+        let display = offset(ScalingMode::Integer, -10).place_display(screen, None);
+        assert_eq!(display.scale, vector(1, 1));
+    }
+
+    #[test]
+    fn scale_offset_at_window_sizes() {
+        for (screen, scale_offset, expected_scale) in [
+            (vector(1920, 1080), -2, 5),
+            (vector(1920, 1080), 0, 7),
+            (vector(1280, 720), -1, 4),
+            (vector(360, 640), 1, 2),
+            (vector(160, 144), -1, 1),
+        ] {
+            let display = offset(ScalingMode::Integer, scale_offset).place_display(screen, None);
+            assert_eq!(
+                display.scale,
+                vector(expected_scale, expected_scale),
+                "{screen:?} offset by {scale_offset}"
+            );
+            // The display stays centered
+            assert_eq!(display.origin * 2 + display.size, screen);
+        }
+    }
+
+    #[test]
+    fn scale_offset_is_ignored_by_fit_and_stretch() {
+        let screen = vector(800, 600);
+        for scaling_mode in [ScalingMode::Fit, ScalingMode::Stretch] {
+            assert_eq!(
+                offset(scaling_mode, -2).place_display(screen, None),
+                offset(scaling_mode, 0).place_display(screen, None)
+            );
+        }
+    }
+
+    #[test]
+    fn scale_offset_moves_display_around_controls() {
+        // Controls are twice the size of the display, which is 20 pixels in and 40 pixels down
+        let controls = Rect::new(vector(0, 0), vector(320, 288));
+        let display = Rect::new(vector(20, 40), vector(160, 144));
+        let screen = vector(700, 600);
+
+        let fitted =
+            offset(ScalingMode::Integer, 0).place_display(screen, Some((controls, display)));
+        assert_eq!(fitted.scale, vector(2, 2));
+        // The 640x576 controls are centered
+        assert_eq!(fitted.origin, vector(30 + 40, 12 + 80));
+
+        // Stepping up lets the controls overflow the screen while the display still fits
+        let stepped =
+            offset(ScalingMode::Integer, 1).place_display(screen, Some((controls, display)));
+        assert_eq!(stepped.scale, vector(3, 3));
+        assert_eq!(stepped.size, vector(480, 432));
+        assert_eq!(stepped.origin, vector(-130 + 60, -132 + 120));
+    }
+}
@@ -1,18 +1,57 @@
 use wgpu::util::DeviceExt;
 
 use super::*;
-use gb_web_core::{DISPLAY_BUFFER_SIZE, DisplayBuffer};
+use gb_web_core::DisplayBuffer;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 mod buffers;
 use buffers::*;
+mod debug_overlay;
+use debug_overlay::*;
+mod layout;
+use layout::*;
+mod osd;
+use osd::*;
 
+/// Background image and the areas defined in it
 #[derive(Debug)]
+struct Skin {
+    texture: Texture,
+    definition: BackgroundDefinition,
+}
+
+/// Border sent by a Super Game Boy game and the backdrop color it's drawn with
+#[derive(Debug)]
+struct SGBBorderTexture {
+    border: SGBBorder,
+    backdrop: u16,
+    texture: Texture,
+}
+
+/// Palettes of games that don't support the Super Game Boy
+const NO_SGB_ATTRIBUTES: SGBAttributes = [0; SGB::ATTRIBUTES_SIZE];
+
 pub struct Renderer {
     pub surface: wgpu::Surface<'static>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
+    /// Format of the view rendered onto the surface
+    output_format: wgpu::TextureFormat,
     pub is_surface_configured: bool,
+    /// Set when the device is lost, the renderer has to be created again to recover
+    device_lost: Arc<AtomicBool>,
+    /// Graphics API the device was created on
+    backend: wgpu::Backend,
+    /// Present modes supported by the surface
+    present_modes: Vec<wgpu::PresentMode>,
+    present_mode: PresentMode,
+    /// Size given to resize, before clamping to texture size limits
+    requested_size: [u32; 2],
+    /// Converts positions relative to the requested size to the surface size
+    input_scale: Vector,
+    /// If the size limit warning has been logged
+    surface_size_clamped: bool,
     pub window: Arc<Window>,
 
     display_render_pipeline: wgpu::RenderPipeline,
@@ -27,21 +66,65 @@ pub struct Renderer {
 
     background_definition: BackgroundDefinition,
     background_rendered_rect: Rect,
+    /// Name of the skin the background texture and definition are from
+    skin: String,
+    /// If the image of the default skin has been fetched, until then its texture is a placeholder
+    default_background_loaded: bool,
+    /// Skins that have been loaded but aren't shown, so switching back to them is instant
+    cached_skins: HashMap<String, Skin>,
+    /// Drawn in place of the skin while the onscreen controls are hidden
+    sgb_border: Option<SGBBorderTexture>,
+    /// Colors of the display chosen in the options
+    palette: Palette,
+    /// Colors set by a Super Game Boy game, shown instead of the chosen palette
+    sgb_palettes: Option<[Palette; 4]>,
     show_controls: bool,
+    /// If the emulator is paused, shown if the pause indicator is enabled
+    paused: bool,
+    pause_indicator: bool,
+    layout: Layout,
     display_options: UniformBuffer<DisplayOptionsUniform>,
-    display: UniformBuffer<DisplayBufferUniform>,
+    display: DisplayTexture,
     blur_options: UniformBuffer<BlurOptionsUniform>,
+    /// Uniforms of each blur pass, rebuilt when glow options or resolution change
+    blur_passes: BlurPasses,
     glow_iterations: usize,
     glow_radius: f32,
+    glow_downscale: u32,
+    /// If the blur textures still hold the glow from before it was disabled
+    stale_glow: bool,
     final_options: UniformBuffer<FinalOptionsUniform>,
+    osd: Osd,
+    /// Outlines sprites and layers over the display, if enabled
+    debug_overlay: Option<DebugOverlay>,
+    /// Callbacks waiting for the next rendered frame, captured together
+    pending_captures: Vec<CaptureCallback>,
+    /// If anything shown has changed since the last rendered frame.
+    /// Otherwise rendering is skipped and the canvas keeps showing the previous frame
+    needs_render: bool,
+}
+
+/// Receives the RGBA pixels, width and height of a captured frame.
+/// Empty pixels are given if the frame couldn't be read back
+pub type CaptureCallback = Box<dyn FnOnce(Vec<u8>, u32, u32)>;
+
+impl std::fmt::Debug for Renderer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Pending capture callbacks can't be printed, so only the surface is shown
+        f.debug_struct("Renderer")
+            .field("config", &self.config)
+            .field("output_format", &self.output_format)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Renderer {
     fn init_render_pipeline(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
+        format: wgpu::TextureFormat,
         shader: &wgpu::ShaderModule,
         bind_group_layouts: &[&wgpu::BindGroupLayout],
+        blend: wgpu::BlendState,
     ) -> wgpu::RenderPipeline {
         let display_render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -62,8 +145,8 @@ impl Renderer {
                 module: shader,
                 entry_point: Some("fs_main"),
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
+                    format,
+                    blend: Some(blend),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
@@ -88,6 +171,102 @@ impl Renderer {
         })
     }
 
+    /// Decodes a PNG image into a texture usable as a background
+    fn create_background_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        png: &[u8],
+    ) -> Result<Texture, image::ImageError> {
+        let background_image = image::load_from_memory(png)?;
+        let background_rgba = background_image.to_rgba8();
+        // Initialize background texture
+        let background_texture_size = wgpu::Extent3d {
+            width: background_rgba.width(),
+            height: background_rgba.height(),
+            depth_or_array_layers: 1,
+        };
+        let mip_level_count = background_texture_size.max_mips(wgpu::TextureDimension::D2);
+        let background_texture = Texture::with_mip_levels(
+            device,
+            bind_group_layout,
+            &background_texture_size,
+            mip_level_count,
+            "Background",
+        );
+        // The image is usually shown much smaller than its size, and minifying its fine details
+        // without mipmaps makes them shimmer. Each level is downsampled from the previous one
+        let mut level_rgba = background_rgba;
+        for mip_level in 0..mip_level_count {
+            let level_size =
+                background_texture_size.mip_level_size(mip_level, wgpu::TextureDimension::D2);
+            if mip_level > 0 {
+                level_rgba = image::imageops::resize(
+                    &level_rgba,
+                    level_size.width,
+                    level_size.height,
+                    image::imageops::FilterType::Triangle,
+                );
+            }
+            queue.write_texture(
+                // Tells wgpu where to copy the pixel data
+                wgpu::TexelCopyTextureInfo {
+                    texture: &background_texture.texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                // The actual pixel data
+                &level_rgba,
+                // The layout of the texture
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * level_size.width),
+                    rows_per_image: Some(level_size.height),
+                },
+                level_size,
+            );
+        }
+        Ok(background_texture)
+    }
+
+    /// Validates a definition against its background image, logging warnings.
+    /// Fails if the definition would break the layout
+    fn validate_definition(
+        definition: &BackgroundDefinition,
+        texture: &Texture,
+    ) -> Result<(), BridgeError> {
+        let Err(issues) = definition.validate(texture.size()) else {
+            return Ok(());
+        };
+        for issue in issues.iter().filter(|issue| !issue.fatal) {
+            log::warn!("Background definition {issue}");
+        }
+        let fatal: Vec<String> = issues
+            .iter()
+            .filter(|issue| issue.fatal)
+            .map(|issue| issue.message.clone())
+            .collect();
+        if fatal.is_empty() {
+            return Ok(());
+        }
+        Err(
+            BridgeError::new(ErrorCode::InvalidSkin, "Skin definition is invalid")
+                .with_details(fatal.join("\n")),
+        )
+    }
+
+    /// Returns the rects of the onscreen inputs in background texture UV
+    fn get_input_rects(definition: &BackgroundDefinition, texture: &Texture) -> [[f32; 4]; 8] {
+        let texture_size = texture.size();
+        std::array::from_fn(|b| {
+            let rect = definition.get_input_rect(InputFlag::from_bits_truncate(1 << b));
+            let pos = rect.pos / texture_size;
+            let size = rect.size / texture_size;
+            [pos.x.into(), pos.y.into(), size.x.into(), size.y.into()]
+        })
+    }
+
     pub async fn new(
         window: Arc<Window>,
         background_definition: BackgroundDefinition,
@@ -95,13 +274,14 @@ impl Renderer {
         let size = window.inner_size();
 
         // The instance is a handle to our GPU
-        // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::GL,
+        // WebGPU is only enabled if the browser supports it, otherwise WebGL2 is used
+        let instance = wgpu::util::new_instance_with_webgpu_detection(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::BROWSER_WEBGPU | wgpu::Backends::GL,
             ..Default::default()
-        });
+        })
+        .await;
 
-        let surface = instance.create_surface(window.clone()).unwrap();
+        let surface = instance.create_surface(window.clone())?;
 
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
@@ -110,10 +290,17 @@ impl Renderer {
                 force_fallback_adapter: false,
             })
             .await?;
+        let backend = adapter.get_info().backend;
+        log::info!("Using {backend:?} backend");
 
-        let mut limits = wgpu::Limits::downlevel_webgl2_defaults();
-        // Increase max texture size so website can be ran on bigger screens
-        limits.max_texture_dimension_2d = 4096;
+        let limits = if backend == wgpu::Backend::Gl {
+            let mut limits = wgpu::Limits::downlevel_webgl2_defaults();
+            // Increase max texture size so website can be ran on bigger screens
+            limits.max_texture_dimension_2d = 4096;
+            limits
+        } else {
+            wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits())
+        };
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: None,
@@ -125,6 +312,21 @@ impl Renderer {
             })
             .await?;
 
+        // The loss is handled on the next redraw, as the callback can't reach the event loop
+        let device_lost = Arc::new(AtomicBool::new(false));
+        {
+            let device_lost = device_lost.clone();
+            let window = window.clone();
+            device.set_device_lost_callback(move |reason, message| {
+                // Dropping the renderer destroys the device on purpose
+                if reason != wgpu::DeviceLostReason::Destroyed {
+                    log::error!("Graphics device lost: {message}");
+                    device_lost.store(true, Ordering::Relaxed);
+                    window.request_redraw();
+                }
+            });
+        }
+
         let surface_caps = surface.get_capabilities(&adapter);
         let surface_format = surface_caps
             .formats
@@ -132,26 +334,37 @@ impl Renderer {
             .find(|f| f.is_srgb())
             .copied()
             .unwrap_or(surface_caps.formats[0]);
+        // WebGPU surfaces usually don't support sRGB formats directly,
+        // so the output is written through an sRGB view to match WebGL2
+        let output_format = surface_format.add_srgb_suffix();
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: surface_caps.present_modes[0],
+            present_mode: Self::select_present_mode(
+                &surface_caps.present_modes,
+                PresentMode::default(),
+            ),
             alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
+            view_formats: if output_format == surface_format {
+                vec![]
+            } else {
+                vec![output_format]
+            },
             desired_maximum_frame_latency: 2,
         };
 
         let options = UniformBuffer::<DisplayOptionsUniform>::new(&device, "Options");
-        let display = UniformBuffer::<DisplayBufferUniform>::new(&device, "Display");
+        let display = DisplayTexture::new(&device, "Display");
         // Initialize render pipeline for rendering the raw display data
         let display_shader = device.create_shader_module(wgpu::include_wgsl!("display.wgsl"));
         let display_render_pipeline = Self::init_render_pipeline(
             &device,
-            &config,
+            Texture::FORMAT,
             &display_shader,
-            &[&display.bind_group_layout, &options.bind_group_layout],
+            &[&options.bind_group_layout, &display.bind_group_layout],
+            wgpu::BlendState::REPLACE,
         );
 
         let blur_options = UniformBuffer::<BlurOptionsUniform>::new(&device, "Effect Options");
@@ -183,52 +396,34 @@ impl Renderer {
         let blur_shader = device.create_shader_module(wgpu::include_wgsl!("blur.wgsl"));
         let blur_render_pipeline = Self::init_render_pipeline(
             &device,
-            &config,
+            Texture::FORMAT,
             &blur_shader,
             &[&texture_bind_group_layout, &blur_options.bind_group_layout],
+            wgpu::BlendState::REPLACE,
         );
 
-        let final_options = UniformBuffer::<FinalOptionsUniform>::new(&device, "Final Options");
-        // Load background image into a byte array
-        let background_png = include_bytes!("../assets/background.png");
-        let background_image = image::load_from_memory(background_png).unwrap();
-        let background_rgba = background_image.to_rgba8();
-        // Initialize background texture
-        let background_texture_size = wgpu::Extent3d {
-            width: background_rgba.width(),
-            height: background_rgba.height(),
-            depth_or_array_layers: 1,
-        };
+        let mut final_options = UniformBuffer::<FinalOptionsUniform>::new(&device, "Final Options");
+        // The background image is fetched after starting, so it's transparent until then
         let background_texture = Texture::new(
             &device,
             &texture_bind_group_layout,
-            &background_texture_size,
-            "Background",
-        );
-        queue.write_texture(
-            // Tells wgpu where to copy the pixel data
-            wgpu::TexelCopyTextureInfo {
-                texture: &background_texture.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            // The actual pixel data
-            &background_rgba,
-            // The layout of the texture
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * background_rgba.width()),
-                rows_per_image: Some(background_rgba.height()),
+            &wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
             },
-            background_texture_size,
+            "Placeholder Background",
         );
+        // Pass the onscreen input rects for highlighting pressed buttons
+        final_options.input_rects =
+            Self::get_input_rects(&background_definition, &background_texture);
+        final_options.update_buffer(&queue);
 
         // Initialize render pipeline for final composite pass
         let final_shader = device.create_shader_module(wgpu::include_wgsl!("final.wgsl"));
         let final_render_pipeline = Self::init_render_pipeline(
             &device,
-            &config,
+            output_format,
             &final_shader,
             &[
                 // Display texture
@@ -240,14 +435,24 @@ impl Renderer {
                 // Options
                 &final_options.bind_group_layout,
             ],
+            wgpu::BlendState::REPLACE,
         );
+        let osd = Osd::new(&device, &queue, output_format);
 
         Ok(Self {
             surface,
             device,
             queue,
             config,
+            output_format,
             is_surface_configured: false,
+            device_lost,
+            backend,
+            present_modes: surface_caps.present_modes,
+            present_mode: PresentMode::default(),
+            requested_size: [size.width, size.height],
+            input_scale: Vector::new(Fp::from(1), Fp::from(1)),
+            surface_size_clamped: false,
             window,
 
             display_render_pipeline,
@@ -262,22 +467,43 @@ impl Renderer {
 
             background_definition,
             background_rendered_rect: Rect::default(),
+            skin: DEFAULT_SKIN.to_string(),
+            default_background_loaded: false,
+            cached_skins: HashMap::new(),
+            sgb_border: None,
+            palette: Palette::default(),
+            sgb_palettes: None,
             show_controls: false,
+            paused: false,
+            pause_indicator: false,
+            layout: Layout::default(),
             display_options: options,
             display,
             blur_options,
+            blur_passes: BlurPasses::default(),
             final_options,
+            osd,
+            debug_overlay: None,
             glow_iterations: 0,
             glow_radius: 0.0,
+            glow_downscale: 1,
+            stale_glow: false,
+            pending_captures: Vec::new(),
+            needs_render: true,
         })
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         // We can't render unless the surface is configured
-        if !self.is_surface_configured {
+        if !self.is_surface_configured || !self.needs_render {
             return Ok(());
         }
 
+        // Finish reading back frames captured before
+        if !self.pending_captures.is_empty() {
+            let _ = self.device.poll(wgpu::PollType::Poll);
+        }
+
         // Create command encoder
         let mut encoder = self
             .device
@@ -308,7 +534,7 @@ impl Renderer {
         });
         display_render_pass.set_pipeline(&self.display_render_pipeline);
         display_render_pass.set_bind_group(0, &self.display_options.bind_group, &[]);
-        display_render_pass.set_bind_group(1, &self.display.bind_group, &[]);
+        display_render_pass.set_bind_group(1, self.display.bind_group(), &[]);
         display_render_pass.draw(0..6, 0..1);
         drop(display_render_pass);
 
@@ -318,27 +544,15 @@ impl Renderer {
         );
         // Don't apply glow if not enabled
         if self.final_options.glow_enabled > 0 {
-            // Generate bind groups for uniforms to be used in blur passes'
-            let mut blur_uniform_bind_groups = vec![];
-            let radius = self.glow_radius / self.glow_iterations as f32;
-            for i in 1..=self.glow_iterations {
-                let mut buffer = *self.blur_options;
-                // Calculate radius for blurring
-                let blur_radius = ((self.glow_iterations - i) as f32) * radius;
-                // First blur horizontally, then vertically
-                buffer.direction = if i.is_multiple_of(2) {
-                    [0.0, blur_radius]
-                } else {
-                    [blur_radius, 0.0]
-                };
-                // Copy uniform into a new wrapper and push generated bind group into list
-                let uniform = UniformBuffer::from(buffer, &self.device, &format!("Blur {i}"));
-                blur_uniform_bind_groups.push(uniform.bind_group);
+            // The first pass reads the horizontal blur of the previous frame,
+            // which is cleared so glow from before it was disabled doesn't show up
+            if self.stale_glow {
+                self.stale_glow = false;
+                Self::encode_blur_clear(&mut encoder, [&h_blur.texture_view, &v_blur.texture_view]);
             }
-
             // Run blur shader for iterations to blur the result of the display render pass onto a
             // texture
-            for (i, uniform_bind_group) in blur_uniform_bind_groups.iter().enumerate() {
+            for (i, uniform) in self.blur_passes.iter().enumerate() {
                 // Choose texture view and texture bind group based on iteration count
                 let (view, mut bind_group) = if i.is_multiple_of(2) {
                     (&v_blur.texture_view, &h_blur.bind_group)
@@ -369,7 +583,7 @@ impl Renderer {
                     });
                 effect_render_pass.set_pipeline(&self.blur_render_pipeline);
                 effect_render_pass.set_bind_group(0, Some(bind_group), &[]);
-                effect_render_pass.set_bind_group(1, uniform_bind_group, &[]);
+                effect_render_pass.set_bind_group(1, &uniform.bind_group, &[]);
                 effect_render_pass.draw(0..6, 0..1);
             }
         }
@@ -378,15 +592,65 @@ impl Renderer {
         let output_texture = self.surface.get_current_texture()?;
         let output_view = output_texture
             .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+            .create_view(&wgpu::TextureViewDescriptor {
+                format: Some(self.output_format),
+                ..Default::default()
+            });
+        self.encode_final_pass(&mut encoder, &output_view, wgpu::LoadOp::Load);
+        if let Some(debug_overlay) = &self.debug_overlay {
+            debug_overlay.render(&mut encoder, &output_view);
+        }
+        // Keep redrawing while the message fades out
+        let osd_shown = self.osd.render(&self.queue, &mut encoder, &output_view);
+        let capture =
+            (!self.pending_captures.is_empty()).then(|| self.encode_capture(&mut encoder));
+        self.queue.submit(std::iter::once(encoder.finish()));
+        output_texture.present();
+        self.needs_render = false;
+        if osd_shown {
+            self.request_render();
+        }
+        if let Some(buffer) = capture {
+            self.read_capture(buffer);
+        }
+        Ok(())
+    }
+
+    /// Clears the blur textures, so the glow from before it was disabled isn't shown
+    fn encode_blur_clear(encoder: &mut wgpu::CommandEncoder, views: [&wgpu::TextureView; 2]) {
+        for view in views {
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Blur Clear Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    depth_slice: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+        }
+    }
+
+    fn encode_final_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) {
         let mut final_render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Final Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &output_view,
+                view,
                 resolve_target: None,
                 depth_slice: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
+                    load,
                     store: wgpu::StoreOp::Store,
                 },
             })],
@@ -401,20 +665,175 @@ impl Renderer {
             &[],
         );
         // Read final blur result from vertically blurred texture
-        final_render_pass.set_bind_group(1, &v_blur.bind_group, &[]);
-        final_render_pass.set_bind_group(2, &self.background_texture.bind_group, &[]);
+        final_render_pass.set_bind_group(1, &self.v_blur_texture.as_ref().unwrap().bind_group, &[]);
+        final_render_pass.set_bind_group(2, &self.shown_background().0.bind_group, &[]);
         final_render_pass.set_bind_group(3, &self.final_options.bind_group, &[]);
         final_render_pass.draw(0..6, 0..1);
-        drop(final_render_pass);
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output_texture.present();
+    }
 
-        self.window.request_redraw();
-        Ok(())
+    /// Returns the best present mode from the supported ones for given preference
+    fn select_present_mode(
+        supported: &[wgpu::PresentMode],
+        preference: PresentMode,
+    ) -> wgpu::PresentMode {
+        use wgpu::PresentMode as P;
+        let preferred: &[P] = match preference {
+            PresentMode::Auto => &[P::FifoRelaxed, P::Fifo],
+            PresentMode::Fifo => &[P::Fifo],
+            PresentMode::LowLatency => &[P::Mailbox, P::Immediate, P::Fifo],
+        };
+        preferred
+            .iter()
+            .find(|mode| supported.contains(mode))
+            .copied()
+            // Fifo is supported everywhere, but the capabilities could be missing it
+            .unwrap_or(supported[0])
+    }
+
+    /// Returns if the device has been lost and the renderer has to be created again
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Relaxed)
+    }
+
+    /// Present mode the surface is configured with
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.config.present_mode
+    }
+
+    /// Graphics API the device was created on
+    pub fn backend(&self) -> wgpu::Backend {
+        self.backend
     }
 
+    /// Captures the next rendered frame, including the background and effects.
+    /// Captures requested before the frame is rendered share a single readback
+    pub fn capture_frame(&mut self, callback: impl FnOnce(Vec<u8>, u32, u32) + 'static) {
+        self.pending_captures.push(Box::new(callback));
+        self.request_render();
+    }
+
+    /// Bytes per row of a captured frame, padded to the alignment required by copies
+    fn capture_row_size(width: u32) -> u32 {
+        (4 * width).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+    }
+
+    /// Renders the final image again onto an offscreen texture and copies it into a buffer
+    fn encode_capture(&self, encoder: &mut wgpu::CommandEncoder) -> wgpu::Buffer {
+        let size = wgpu::Extent3d {
+            width: self.config.width,
+            height: self.config.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Capture Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.output_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Areas without background are transparent on the surface, so clear to black
+        self.encode_final_pass(encoder, &view, wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+
+        let bytes_per_row = Self::capture_row_size(size.width);
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Capture Buffer"),
+            size: u64::from(bytes_per_row) * u64::from(size.height),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(size.height),
+                },
+            },
+            size,
+        );
+        buffer
+    }
+
+    /// Maps the capture buffer once the copy is done and gives the pixels to pending callbacks.
+    /// The buffer is dropped after the callbacks are called
+    fn read_capture(&mut self, buffer: wgpu::Buffer) {
+        let callbacks = std::mem::take(&mut self.pending_captures);
+        let (width, height) = (self.config.width, self.config.height);
+        let is_bgra = matches!(
+            self.output_format.remove_srgb_suffix(),
+            wgpu::TextureFormat::Bgra8Unorm
+        );
+        let mapped_buffer = buffer.clone();
+        let finish = move |result: Result<(), wgpu::BufferAsyncError>| {
+            let mut pixels = Vec::new();
+            match result {
+                Ok(()) => {
+                    // Strip the padding at the end of each row
+                    let row_size = 4 * width as usize;
+                    pixels.reserve_exact(row_size * height as usize);
+                    let data = mapped_buffer.slice(..).get_mapped_range();
+                    for row in data.chunks_exact(Self::capture_row_size(width) as usize) {
+                        pixels.extend_from_slice(&row[..row_size]);
+                    }
+                    drop(data);
+                    mapped_buffer.unmap();
+                    if is_bgra {
+                        pixels
+                            .chunks_exact_mut(4)
+                            .for_each(|pixel| pixel.swap(0, 2));
+                    }
+                }
+                Err(e) => log::error!("Couldn't read back captured frame: {e}"),
+            }
+            // The last callback takes the pixels without copying
+            let mut callbacks = callbacks;
+            let last = callbacks.pop();
+            for callback in callbacks {
+                callback(pixels.clone(), width, height);
+            }
+            if let Some(callback) = last {
+                callback(pixels, width, height);
+            }
+        };
+        #[cfg(target_family = "wasm")]
+        {
+            buffer.slice(..).map_async(wgpu::MapMode::Read, finish);
+            // Mapping finishes during polling on WebGL, WebGPU resolves it on its own
+            let _ = self.device.poll(wgpu::PollType::Poll);
+        }
+        // Native mapping can finish on another thread, where the callbacks can't be sent,
+        // so the result is waited for here
+        #[cfg(not(target_family = "wasm"))]
+        {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = sender.send(result);
+                });
+            let _ = self.device.poll(wgpu::PollType::wait_indefinitely());
+            finish(receiver.recv().unwrap_or(Err(wgpu::BufferAsyncError)));
+        }
+    }
+
+    /// Resizes the surface to given size in physical pixels
     pub fn resize(&mut self, width: u32, height: u32) {
+        self.requested_size = [width, height];
+        self.request_render();
         if width > 0 && height > 0 {
+            let (requested_width, requested_height) = (width, height);
+            let (width, height) = self.clamp_surface_size(width, height);
+            // Positions given by the page are relative to the requested size
+            self.input_scale = Vector::new(
+                Fp::from(width as i16) / Fp::from(requested_width as i16),
+                Fp::from(height as i16) / Fp::from(requested_height as i16),
+            );
             self.config.width = width;
             self.config.height = height;
             self.surface.configure(&self.device, &self.config);
@@ -432,76 +851,43 @@ impl Renderer {
                 &texture_size,
                 "Display Texture",
             ));
+            // The glow is blurred at a lower resolution and upscaled when composited
+            let blur_texture_size = wgpu::Extent3d {
+                width: width.div_ceil(self.glow_downscale),
+                height: height.div_ceil(self.glow_downscale),
+                depth_or_array_layers: 1,
+            };
             self.h_blur_texture = Some(Texture::new(
                 &self.device,
                 &self.texture_bind_group_layout,
-                &texture_size,
+                &blur_texture_size,
                 "Horizontal Blur Texture",
             ));
             self.v_blur_texture = Some(Texture::new(
                 &self.device,
                 &self.texture_bind_group_layout,
-                &texture_size,
+                &blur_texture_size,
                 "Vertical Blur Texture",
             ));
 
-            let surface_size = Vector::new(Fp::from(width as i16), Fp::from(height as i16));
-            let lcd_size = Vector::new(Fp::from(160), Fp::from(144));
-
-            // If the on-screen controls should be shown, display is placed so part of the
-            // background image containing the controls is fully visible.
-            // Otherwise display is centered and scaled to fit
-            // In both cases, the display is scaled so pixels have an even integer scale
-            let (display_scale, display_origin, display_size) = if self.show_controls {
-                // The rectangles are defined with the background image
-                let controls_rect = self.background_definition.controls;
-                let display_rect = self.background_definition.display;
-
-                // Fit the rectangle containing controls onto the screen
-                let controls_scale = (surface_size.x / controls_rect.size.x)
-                    .min(surface_size.y / controls_rect.size.y);
-                let fitted_controls_size = controls_rect.size * controls_scale;
-
-                // Calculate size of display in fitted rectangle
-                let rect_diff = controls_rect.size / display_rect.size;
-                let fitted_display_size = fitted_controls_size / rect_diff;
-
-                // Calculate scale at which the display pixel scale will be an integer
-                let display_scale = fitted_display_size / lcd_size;
-                let display_scale_rounded = display_scale.x.floor().min(display_scale.y.floor());
-                let pixel_scale = display_scale_rounded * lcd_size / display_rect.size;
-
-                // Calculate actual size of control rectangle
-                let final_controls_size = display_scale_rounded * lcd_size * rect_diff;
-                // Calculate the origin of the display
-                let controls_origin = (surface_size - final_controls_size) / 2;
-                let rect_pos_diff = display_rect.pos - controls_rect.pos;
-                let display_origin = controls_origin + (rect_pos_diff * pixel_scale);
-
-                (
-                    display_scale_rounded,
-                    display_origin,
-                    lcd_size * display_scale_rounded,
-                )
-            } else {
-                // Calculate pixel scale as the possible largest integer scale
-                // which still fits display in both dimensions
-                let scale = (surface_size.x / lcd_size.x)
-                    .min(surface_size.y / lcd_size.y)
-                    .floor();
-                // Calculate size of the display
-                let size = lcd_size * scale;
-                // Calculate top-left origin in pixel space for centered canvas
-                let origin = (surface_size - size) / 2;
-
-                (scale, origin, size)
-            };
+            let screen_size = self.screen_size();
+            // The rects are defined with the background image
+            let controls = self.controls_shown().then_some((
+                self.background_definition.controls,
+                self.background_definition.display,
+            ));
+            let DisplayPlacement {
+                scale: display_scale,
+                origin: display_origin,
+                size: display_size,
+            } = self.layout.place_display(screen_size, controls);
 
             // Scale the background to match the display position and scale
-            let background_scale = display_size / self.background_definition.display.size;
-            let background_size = self.background_texture.size() * background_scale;
+            let (background_texture, background_display_rect) = self.shown_background();
+            let background_scale = display_size / background_display_rect.size;
+            let background_size = background_texture.size() * background_scale;
             let background_origin =
-                display_origin - (self.background_definition.display.pos * background_scale);
+                display_origin - (background_display_rect.pos * background_scale);
 
             // Helper functions to convert fixed point vectors into arrays that can be passed into
             // shader
@@ -514,48 +900,596 @@ impl Renderer {
 
             // Update options
             self.background_rendered_rect = Rect::new(background_origin, background_size);
-            self.display_options.scale = i16::from(display_scale) as u32;
+            self.display_options.scale = vec_to_buffer(&display_scale);
             self.display_options.origin = vec_to_buffer_rounded(&display_origin);
+            self.display_options.viewport_size = vec_to_buffer(&screen_size);
+            self.display_options.rotation = self.layout.rotation as u32;
             self.display_options.update_buffer(&self.queue);
-            self.blur_options.resolution[0] = f32::from(surface_size.x / display_scale);
-            self.blur_options.resolution[1] = f32::from(surface_size.y / display_scale);
+            // Blurring is done on the rotated screen
+            let screen_display_scale = if self.layout.rotation == DisplayRotation::None {
+                display_scale
+            } else {
+                Vector::new(display_scale.y, display_scale.x)
+            };
+            self.blur_options.resolution = vec_to_buffer(&(screen_size / screen_display_scale));
             self.blur_options.update_buffer(&self.queue);
+            self.update_blur_passes();
             self.final_options.display_origin = vec_to_buffer_rounded(&display_origin);
             self.final_options.display_size = vec_to_buffer_rounded(&display_size);
             self.final_options.background_origin = vec_to_buffer(&background_origin);
             self.final_options.background_size = vec_to_buffer(&background_size);
             self.final_options.viewport_size = [width, height];
+            self.final_options.rotation = self.layout.rotation as u32;
             self.final_options.update_buffer(&self.queue);
+            if let Some(debug_overlay) = &mut self.debug_overlay {
+                debug_overlay.set_layout(&self.queue, &self.final_options);
+            }
+            // Messages are shown in the top left corner of the display as seen on the screen
+            let corners = [
+                self.layout.layout_to_screen(screen_size, display_origin),
+                self.layout
+                    .layout_to_screen(screen_size, display_origin + display_size),
+            ];
+            let corner = Vector::new(
+                corners[0].x.min(corners[1].x),
+                corners[0].y.min(corners[1].y),
+            );
+            self.osd.set_layout(
+                vec_to_buffer(&corner),
+                display_scale.x.min(display_scale.y).into(),
+            );
+        } else {
+            // Surfaces can't be empty, so rendering waits until the canvas has a size again
+            self.is_surface_configured = false;
         }
     }
 
+    /// Shrinks the surface size to fit the texture size limits while preserving aspect ratio.
+    /// The page scales the canvas up to its size
+    fn clamp_surface_size(&mut self, width: u32, height: u32) -> (u32, u32) {
+        let max_size = self.device.limits().max_texture_dimension_2d;
+        let larger_side = width.max(height);
+        if larger_side <= max_size {
+            return (width, height);
+        }
+        if !self.surface_size_clamped {
+            self.surface_size_clamped = true;
+            log::warn!(
+                "Canvas size {width}x{height} exceeds the texture size limit of {max_size}, \
+                rendering at a lower resolution"
+            );
+        }
+        let clamp =
+            |side: u32| ((side as u64 * max_size as u64) / larger_side as u64).max(1) as u32;
+        (clamp(width), clamp(height))
+    }
+
+    /// Lays out the display and background again at the current size
+    fn relayout(&mut self) {
+        let [width, height] = self.requested_size;
+        self.resize(width, height);
+    }
+
+    /// Updates the uniforms of blur passes to match the glow options and resolution
+    fn update_blur_passes(&mut self) {
+        self.blur_passes.update(
+            &self.device,
+            &self.queue,
+            &self.blur_options,
+            self.glow_iterations,
+            self.glow_radius,
+        );
+    }
+
+    pub fn background_definition(&self) -> &BackgroundDefinition {
+        &self.background_definition
+    }
+
+    /// Returns if the skin is shown or cached
+    pub fn has_skin(&self, name: &str) -> bool {
+        self.skin == name || self.cached_skins.contains_key(name)
+    }
+
+    /// Decodes fetched skin files and caches the skin
+    pub fn add_skin(&mut self, name: String, files: SkinFiles) -> Result<(), BridgeError> {
+        let texture = Self::create_background_texture(
+            &self.device,
+            &self.queue,
+            &self.texture_bind_group_layout,
+            &files.image,
+        )
+        .map_err(|e| {
+            BridgeError::new(ErrorCode::InvalidSkin, "Skin image is invalid").with_details(e)
+        })?;
+        let definition = BackgroundDefinition::try_from_str(&files.definition).map_err(|e| {
+            BridgeError::new(ErrorCode::InvalidSkin, "Skin definition is invalid").with_details(e)
+        })?;
+        Self::validate_definition(&definition, &texture)?;
+        self.cached_skins.insert(
+            name,
+            Skin {
+                texture,
+                definition,
+            },
+        );
+        Ok(())
+    }
+
+    /// Decodes the fetched image of the default skin and replaces its placeholder texture
+    pub fn set_default_background(&mut self, image: &[u8]) -> Result<(), BridgeError> {
+        let texture = Self::create_background_texture(
+            &self.device,
+            &self.queue,
+            &self.texture_bind_group_layout,
+            image,
+        )
+        .map_err(|e| {
+            BridgeError::new(ErrorCode::InvalidSkin, "Background image is invalid").with_details(e)
+        })?;
+        // The default definition is compiled in, so it's only checked against the image
+        if self.skin == DEFAULT_SKIN {
+            Self::validate_definition(&self.background_definition, &texture)?;
+        } else if let Some(skin) = self.cached_skins.get(DEFAULT_SKIN) {
+            Self::validate_definition(&skin.definition, &texture)?;
+        }
+        self.default_background_loaded = true;
+        if self.skin == DEFAULT_SKIN {
+            self.background_texture = texture;
+            self.final_options.input_rects =
+                Self::get_input_rects(&self.background_definition, &self.background_texture);
+            self.final_options.update_buffer(&self.queue);
+            // The controls can be laid out now that they're visible
+            self.relayout();
+        } else if let Some(skin) = self.cached_skins.get_mut(DEFAULT_SKIN) {
+            skin.texture = texture;
+        }
+        Ok(())
+    }
+
+    /// Returns the texture drawn around the display and the rect of the display in it.
+    /// A Super Game Boy border replaces the skin only while the onscreen controls are hidden,
+    /// as it doesn't have them
+    fn shown_background(&self) -> (&Texture, Rect) {
+        match &self.sgb_border {
+            Some(border) if !self.controls_shown() => {
+                let scale = Fp::from(Self::SGB_BORDER_SCALE as i16);
+                let pos = Vector::new(
+                    Fp::from(SGBBorder::DISPLAY_X as i16),
+                    Fp::from(SGBBorder::DISPLAY_Y as i16),
+                );
+                let size = Vector::new(Fp::from(160), Fp::from(144));
+                (&border.texture, Rect::new(pos * scale, size * scale))
+            }
+            _ => (&self.background_texture, self.background_definition.display),
+        }
+    }
+
+    /// Returns if the shown background has an image, which the default skin lacks until fetched
+    pub fn has_background(&self) -> bool {
+        self.skin != DEFAULT_SKIN || self.default_background_loaded
+    }
+
+    /// Returns if the display is laid out around the onscreen controls.
+    /// The controls stay hidden until the background has an image
+    fn controls_shown(&self) -> bool {
+        self.show_controls && self.has_background()
+    }
+
+    /// Shows a cached skin, returns false if it hasn't been loaded
+    pub fn select_skin(&mut self, name: &str) -> bool {
+        if self.skin == name {
+            return true;
+        }
+        let Some(skin) = self.cached_skins.remove(name) else {
+            return false;
+        };
+        // Keep the previous skin cached
+        let previous = Skin {
+            texture: std::mem::replace(&mut self.background_texture, skin.texture),
+            definition: std::mem::replace(&mut self.background_definition, skin.definition),
+        };
+        let previous_name = std::mem::replace(&mut self.skin, name.to_string());
+        self.cached_skins.insert(previous_name, previous);
+
+        self.final_options.input_rects =
+            Self::get_input_rects(&self.background_definition, &self.background_texture);
+        self.final_options.update_buffer(&self.queue);
+        // Layout depends on the background definition
+        self.relayout();
+        true
+    }
+
+    /// Returns the name of the shown skin
+    pub fn skin(&self) -> &str {
+        &self.skin
+    }
+
+    /// Replaces the definition of the shown skin, keeping its image.
+    /// The definition is validated against the image first, and kept unchanged if invalid
+    pub fn set_background_definition(
+        &mut self,
+        definition: BackgroundDefinition,
+    ) -> Result<(), BridgeError> {
+        Self::validate_definition(&definition, &self.background_texture)?;
+        self.background_definition = definition;
+        self.final_options.input_rects =
+            Self::get_input_rects(&self.background_definition, &self.background_texture);
+        self.final_options.update_buffer(&self.queue);
+        // Layout depends on the background definition
+        self.relayout();
+        Ok(())
+    }
+
+    /// Sets the insets of the screen not covered by notches and system UI
+    pub fn set_safe_area_insets(&mut self, insets: SafeAreaInsets) {
+        if self.layout.safe_area_insets != insets {
+            self.layout.safe_area_insets = insets;
+            self.relayout();
+        }
+    }
+
+    /// Returns the size of the surface in pixels
+    fn screen_size(&self) -> Vector {
+        Vector::new(
+            Fp::from(self.config.width as i16),
+            Fp::from(self.config.height as i16),
+        )
+    }
+
+    /// Renders the next redraw and requests it, as something shown has changed.
+    /// Frame persistence blends frames as they're given to `update_display`,
+    /// so skipped redraws don't affect it
+    fn request_render(&mut self) {
+        self.needs_render = true;
+        self.window.request_redraw();
+    }
+
+    /// Shows a text message over the display for given duration, if messages are enabled
+    pub fn show_osd(&mut self, text: &str, duration_ms: f64) {
+        self.osd.show(text, duration_ms);
+        self.request_render();
+    }
+
     pub fn get_pos_in_background(&self, pos: Vector) -> Vector {
+        let pos = self
+            .layout
+            .screen_to_layout(self.screen_size(), pos * self.input_scale);
         let bg_rect = self.background_rendered_rect;
-        let tx_size = self.background_texture.size();
+        let tx_size = self.shown_background().0.size();
         let uv = (pos - bg_rect.pos) / bg_rect.size;
         uv * tx_size
     }
 
     pub fn update_options(&mut self, options: &EmulatorOptions) {
-        if self.show_controls != options.show_controls {
+        let glow_downscale = options.glow_downscale.max(1);
+        if self.present_mode != options.present_mode {
+            self.present_mode = options.present_mode;
+            self.config.present_mode =
+                Self::select_present_mode(&self.present_modes, options.present_mode);
+            log::info!("Presenting with {:?}", self.config.present_mode);
+            if self.is_surface_configured {
+                self.surface.configure(&self.device, &self.config);
+            }
+        }
+        let layout = Layout {
+            scaling_mode: options.scaling_mode,
+            scale_offset: options.scale_offset,
+            rotation: options.rotation,
+            ..self.layout
+        };
+        if self.show_controls != options.show_controls
+            || self.layout != layout
+            || self.glow_downscale != glow_downscale
+        {
+            self.glow_downscale = glow_downscale;
             self.show_controls = options.show_controls;
-            self.resize(self.config.width, self.config.height);
+            self.layout = layout;
+            self.relayout();
         }
-        self.display_options.palette = options.palette;
+        self.palette = options.palette;
+        self.display_options.palettes = self.sgb_palettes.unwrap_or([self.palette; 4]);
         self.display_options.scanline_strength = options.scanline_strength;
         self.display_options.scanline_size = options.scanline_size;
+        self.display_options.frame_persistence = options.frame_persistence;
+        self.display_options.pixel_effect = options.pixel_effect as u32;
+        self.display_options.grid_size = options.grid_size;
+        self.display_options.grid_strength = options.grid_strength;
+        self.display_options.grid_tint = if options.grid_tint { 1 } else { 0 };
         self.final_options.glow_strength_display = options.display_glow_strength;
         self.final_options.glow_strength_background = options.background_glow_strength;
         self.final_options.ambient_light = options.ambient_light;
+        let Color(r, g, b, _) = options.ambient_color;
+        self.final_options.ambient_color = [r, g, b];
+        if !options.glow_enabled && self.final_options.glow_enabled > 0 {
+            self.stale_glow = true;
+        }
         self.final_options.glow_enabled = if options.glow_enabled { 1 } else { 0 };
-        self.glow_iterations = options.glow_iterations;
-        self.glow_radius = options.glow_radius;
+        self.pause_indicator = options.pause_indicator;
+        self.osd.enabled = options.osd_enabled;
+        if options.debug_overlay != self.debug_overlay.is_some() {
+            self.debug_overlay = options.debug_overlay.then(|| {
+                let mut debug_overlay = DebugOverlay::new(&self.device, self.output_format);
+                debug_overlay.set_layout(&self.queue, &self.final_options);
+                debug_overlay
+            });
+        }
+        self.final_options.paused = (self.paused && self.pause_indicator) as u32;
+        self.final_options.crt_curvature = options.crt_curvature;
+        self.final_options.crt_vignette = options.crt_vignette;
+        self.final_options.gamma = options.color_correction.gamma;
+        self.final_options.brightness = options.color_correction.brightness;
+        self.final_options.contrast = options.color_correction.contrast;
+        self.final_options.saturation = options.color_correction.saturation;
+        if self.glow_iterations != options.glow_iterations
+            || self.glow_radius != options.glow_radius
+        {
+            self.glow_iterations = options.glow_iterations;
+            self.glow_radius = options.glow_radius;
+            self.update_blur_passes();
+        }
         self.display_options.update_buffer(&self.queue);
         self.final_options.update_buffer(&self.queue);
+        self.request_render();
+    }
+
+    /// Shows the pause indicator over the display while paused, if it's enabled
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.final_options.paused = (self.paused && self.pause_indicator) as u32;
+        self.final_options.update_buffer(&self.queue);
+        self.request_render();
+    }
+
+    /// Highlights the onscreen buttons of given inputs while controls are shown
+    pub fn update_pressed_inputs(&mut self, input: InputFlag) {
+        let pressed = if self.controls_shown() {
+            input.bits() as u32
+        } else {
+            0
+        };
+        if self.final_options.pressed_inputs != pressed {
+            self.final_options.pressed_inputs = pressed;
+            self.final_options.update_buffer(&self.queue);
+            self.request_render();
+        }
+    }
+
+    /// Uploads the emulated display, requesting a render only if it changed.
+    /// Super Game Boy games color it with their palettes and can replace the skin with a border
+    pub fn update_display(&mut self, display: &DisplayBuffer, sgb: Option<&SGB>) {
+        self.update_sgb_border(sgb);
+        // A frozen display keeps showing the frame and colors from before the mask was set
+        if sgb.is_some_and(|sgb| sgb.mask() == SGBMask::Freeze) {
+            return;
+        }
+        let palettes = sgb.map(Self::get_sgb_palettes);
+        if self.sgb_palettes != palettes {
+            self.sgb_palettes = palettes;
+            self.display_options.palettes = palettes.unwrap_or([self.palette; 4]);
+            self.display_options.update_buffer(&self.queue);
+            self.request_render();
+        }
+        let attributes = sgb.map_or(&NO_SGB_ATTRIBUTES, SGB::attributes);
+        if self.display.update(&self.queue, display, attributes) {
+            self.request_render();
+        }
+    }
+
+    /// Border pixels are repeated this many times in its texture, so they stay sharp
+    /// when the texture is sampled with linear filtering
+    const SGB_BORDER_SCALE: usize = 4;
+
+    /// Converts the Super Game Boy palettes into display colors with the mask applied
+    fn get_sgb_palettes(sgb: &SGB) -> [Palette; 4] {
+        let palettes = sgb.palettes();
+        let color = |palette: usize, i: usize| match sgb.mask() {
+            SGBMask::Black => Color(0.0, 0.0, 0.0, 1.0),
+            SGBMask::Color0 => Color::from_rgb555(palettes[0][0]),
+            SGBMask::None | SGBMask::Freeze => Color::from_rgb555(palettes[palette][i]),
+        };
+        std::array::from_fn(|p| Palette(color(p, 0), color(p, 1), color(p, 2), color(p, 3)))
+    }
+
+    /// Draws the border of a Super Game Boy game into a texture when it or its backdrop
+    /// color changes, removing it when the game has no border
+    fn update_sgb_border(&mut self, sgb: Option<&SGB>) {
+        let border = sgb.and_then(|sgb| Some((sgb.border()?, sgb.palettes()[0][0])));
+        let unchanged = match (&self.sgb_border, border) {
+            (Some(shown), Some((border, backdrop))) => {
+                shown.border == *border && shown.backdrop == backdrop
+            }
+            (None, None) => true,
+            _ => false,
+        };
+        if unchanged {
+            return;
+        }
+        self.sgb_border = border.map(|(border, backdrop)| SGBBorderTexture {
+            border: border.clone(),
+            backdrop,
+            texture: self.create_sgb_border_texture(border, backdrop),
+        });
+        // Layout depends on the background shown
+        self.relayout();
     }
 
-    pub fn update_display(&mut self, display: &DisplayBuffer) {
-        self.display.buffer = *display;
-        self.display.update_buffer(&self.queue);
+    fn create_sgb_border_texture(&self, border: &SGBBorder, backdrop: u16) -> Texture {
+        let scale = Self::SGB_BORDER_SCALE;
+        let (width, height) = (SGBBorder::WIDTH * scale, SGBBorder::HEIGHT * scale);
+        let colors: Vec<[u8; 4]> = border
+            .pixels(backdrop)
+            .map(|color| Color::from_rgb555(color).to_srgb8())
+            .collect();
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for color in &colors[(y / scale) * SGBBorder::WIDTH..][..SGBBorder::WIDTH] {
+                for _ in 0..scale {
+                    rgba.extend_from_slice(color);
+                }
+            }
+        }
+        let size = wgpu::Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        };
+        let texture = Texture::new(
+            &self.device,
+            &self.texture_bind_group_layout,
+            &size,
+            "SGB Border",
+        );
+        self.queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &rgba,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size.width),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+        texture
+    }
+
+    /// Returns the uniform writes and display uploads counted since the last call
+    pub fn take_write_counts(&mut self) -> (WriteCounts, WriteCounts) {
+        let mut uniforms = self.display_options.take_write_counts();
+        uniforms += self.blur_options.take_write_counts();
+        uniforms += self.final_options.take_write_counts();
+        uniforms += self.blur_passes.take_write_counts();
+        (uniforms, self.display.take_write_counts())
+    }
+
+    /// Outlines the sprites and layers of the CPU's latest frame, if the debug overlay is enabled
+    pub fn update_debug_overlay(&mut self, cpu: &CPU) {
+        if let Some(debug_overlay) = &mut self.debug_overlay {
+            debug_overlay.update(&self.queue, &cpu.get_ppu_debug_info());
+            self.request_render();
+        }
+    }
+
+    /// Stops previous frames from being blended onto the next displayed frame
+    pub fn clear_frame_history(&mut self) {
+        self.display.clear_history();
+    }
+}
+
+/// Creates a device on any available adapter, None if there isn't one
+#[cfg(test)]
+fn test_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+    let adapter =
+        pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+            .ok()?;
+    pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default())).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a blur-sized texture filled with given color, which can be read back
+    fn filled_texture(device: &wgpu::Device, queue: &wgpu::Queue, rgba: [u8; 4]) -> wgpu::Texture {
+        let size = wgpu::Extent3d {
+            width: 64,
+            height: 4,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Test Blur Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Texture::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            &rgba.repeat((size.width * size.height) as usize),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size.width),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+        texture
+    }
+
+    /// Copies the pixels of a texture back from the GPU
+    fn read_back(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) -> Vec<u8> {
+        let size = texture.size();
+        // 64 pixels fill the row alignment exactly, so rows have no padding
+        let bytes_per_row = 4 * size.width;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Test Readback Buffer"),
+            size: u64::from(bytes_per_row * size.height),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(size.height),
+                },
+            },
+            size,
+        );
+        queue.submit([encoder.finish()]);
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::PollType::wait_indefinitely()).unwrap();
+        let pixels = buffer.slice(..).get_mapped_range().to_vec();
+        buffer.unmap();
+        pixels
+    }
+
+    #[test]
+    fn blur_clear_removes_stale_glow() {
+        let Some((device, queue)) = test_device() else {
+            eprintln!("No graphics adapter available, skipping");
+            return;
+        };
+        let glow = [255, 200, 100, 255];
+        let textures = [
+            filled_texture(&device, &queue, glow),
+            filled_texture(&device, &queue, glow),
+        ];
+        let views = textures
+            .each_ref()
+            .map(|texture| texture.create_view(&Default::default()));
+        // Without the clear, the blur of the last frame before glow was disabled stays
+        assert!(
+            read_back(&device, &queue, &textures[0])
+                .chunks_exact(4)
+                .all(|pixel| pixel == glow)
+        );
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        Renderer::encode_blur_clear(&mut encoder, views.each_ref());
+        queue.submit([encoder.finish()]);
+        for texture in &textures {
+            assert!(
+                read_back(&device, &queue, texture)
+                    .iter()
+                    .all(|byte| *byte == 0)
+            );
+        }
     }
 }
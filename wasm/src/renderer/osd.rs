@@ -0,0 +1,237 @@
+use super::*;
+
+/// Glyphs of the printable ASCII characters from space to underscore. Each glyph is an 8x8
+/// bitmap with a byte per row, starting from the top row in the most significant byte
+#[rustfmt::skip]
+const FONT: [u64; 64] = [
+    0x0000000000000000, // ' '
+    0x1010101010001000, // '!'
+    0x2828000000000000, // '"'
+    0x28287C287C282800, // '#'
+    0x103C503814781000, // '$'
+    0x60640810204C0C00, // '%'
+    0x3048502054483400, // '&'
+    0x1010000000000000, // '''
+    0x0810202020100800, // '('
+    0x2010080808102000, // ')'
+    0x0010543854100000, // '*'
+    0x0010107C10100000, // '+'
+    0x0000000030102000, // ','
+    0x0000007C00000000, // '-'
+    0x0000000000303000, // '.'
+    0x0004081020400000, // '/'
+    0x38444C5464443800, // '0'
+    0x1030101010103800, // '1'
+    0x3844040810207C00, // '2'
+    0x7C08100804443800, // '3'
+    0x081828487C080800, // '4'
+    0x7C40780404443800, // '5'
+    0x1820407844443800, // '6'
+    0x7C04081020202000, // '7'
+    0x3844443844443800, // '8'
+    0x3844443C04083000, // '9'
+    0x0030300030300000, // ':'
+    0x0030300030102000, // ';'
+    0x0810204020100800, // '<'
+    0x00007C007C000000, // '='
+    0x2010080408102000, // '>'
+    0x3844040810001000, // '?'
+    0x3844043454543800, // '@'
+    0x3844447C44444400, // 'A'
+    0x7844447844447800, // 'B'
+    0x3844404040443800, // 'C'
+    0x7048444444487000, // 'D'
+    0x7C40407840407C00, // 'E'
+    0x7C40407840404000, // 'F'
+    0x3844405C44443C00, // 'G'
+    0x4444447C44444400, // 'H'
+    0x3810101010103800, // 'I'
+    0x1C08080808483000, // 'J'
+    0x4448506050484400, // 'K'
+    0x4040404040407C00, // 'L'
+    0x446C545444444400, // 'M'
+    0x444464544C444400, // 'N'
+    0x3844444444443800, // 'O'
+    0x7844447840404000, // 'P'
+    0x3844444454483400, // 'Q'
+    0x7844447850484400, // 'R'
+    0x3C40403804047800, // 'S'
+    0x7C10101010101000, // 'T'
+    0x4444444444443800, // 'U'
+    0x4444444444281000, // 'V'
+    0x4444445454542800, // 'W'
+    0x4444281028444400, // 'X'
+    0x4444281010101000, // 'Y'
+    0x7C04081020407C00, // 'Z'
+    0x3820202020203800, // '['
+    0x0040201008040000, // '\'
+    0x3808080808083800, // ']'
+    0x1028440000000000, // '^'
+    0x0000000000007C00, // '_'
+];
+/// Glyphs per row of the font texture
+const FONT_COLUMNS: u32 = 8;
+
+/// Text message drawn over the top left corner of the display, fading out before it's hidden
+#[derive(Debug)]
+pub struct Osd {
+    render_pipeline: wgpu::RenderPipeline,
+    font_bind_group: wgpu::BindGroup,
+    options: UniformBuffer<OsdOptionsUniform>,
+    /// Time the message was shown at and how long it's shown for, in milliseconds
+    shown: Option<(f64, f64)>,
+    pub enabled: bool,
+}
+
+impl Osd {
+    /// Longer messages are cut off
+    pub const MAX_CHARS: usize = 32;
+    /// Messages fade out during the end of their duration
+    const FADE_MS: f64 = 400.0;
+    /// Distance of the text from the display corner, in font pixels
+    const MARGIN: f32 = 4.0;
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Self {
+        // Unpack the glyphs into a texture with a byte per pixel
+        let size = wgpu::Extent3d {
+            width: FONT_COLUMNS * 8,
+            height: (FONT.len() as u32).div_ceil(FONT_COLUMNS) * 8,
+            depth_or_array_layers: 1,
+        };
+        let mut pixels = vec![0; (size.width * size.height) as usize];
+        for (i, glyph) in FONT.iter().enumerate() {
+            let glyph_x = i as u32 % FONT_COLUMNS * 8;
+            let glyph_y = i as u32 / FONT_COLUMNS * 8;
+            for y in 0..8 {
+                let row = (glyph >> (56 - 8 * y)) as u8;
+                for x in 0..8 {
+                    if row & (0x80 >> x) != 0 {
+                        pixels[((glyph_y + y) * size.width + glyph_x + x) as usize] = 255;
+                    }
+                }
+            }
+        }
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("OSD Font Texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &pixels,
+        );
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let font_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("OSD Font Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                }],
+            });
+        let font_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("OSD Font Bind Group"),
+            layout: &font_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture_view),
+            }],
+        });
+
+        let options = UniformBuffer::<OsdOptionsUniform>::new(device, "OSD Options");
+        let shader = device.create_shader_module(wgpu::include_wgsl!("osd.wgsl"));
+        let render_pipeline = Renderer::init_render_pipeline(
+            device,
+            format,
+            &shader,
+            &[&font_bind_group_layout, &options.bind_group_layout],
+            wgpu::BlendState::ALPHA_BLENDING,
+        );
+        Self {
+            render_pipeline,
+            font_bind_group,
+            options,
+            shown: None,
+            enabled: true,
+        }
+    }
+
+    /// Shows a message for given duration, replacing the current one.
+    /// Lowercase letters are shown in uppercase and unsupported characters as question marks
+    pub fn show(&mut self, text: &str, duration_ms: f64) {
+        if !self.enabled {
+            return;
+        }
+        self.options.chars = Default::default();
+        let mut length = 0;
+        for (i, c) in text.chars().take(Self::MAX_CHARS).enumerate() {
+            let c = c.to_ascii_uppercase();
+            let c = if (' '..='_').contains(&c) { c } else { '?' };
+            self.options.chars[i / 4][i % 4] = c as u32 - ' ' as u32;
+            length = i + 1;
+        }
+        self.options.length = length as u32;
+        self.shown = Some((now_ms(), duration_ms));
+    }
+
+    /// Places the text at given top left corner of the display on the screen,
+    /// with font pixels scaled down to whole screen pixels
+    pub fn set_layout(&mut self, display_corner: [f32; 2], display_pixel_size: f32) {
+        let scale = (display_pixel_size / 2.0).floor().max(1.0);
+        self.options.scale = scale;
+        self.options.origin = display_corner.map(|c| c + Self::MARGIN * scale);
+    }
+
+    /// Draws the message over the view.
+    /// Returns if a message is still shown and has to be redrawn as it fades out
+    pub fn render(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+    ) -> bool {
+        let Some((shown_at, duration)) = self.shown else {
+            return false;
+        };
+        let elapsed = now_ms() - shown_at;
+        if !self.enabled || elapsed >= duration {
+            self.shown = None;
+            return false;
+        }
+        self.options.opacity = ((duration - elapsed) / Self::FADE_MS).min(1.0) as f32;
+        self.options.update_buffer(queue);
+
+        let mut osd_render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("OSD Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                depth_slice: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        osd_render_pass.set_pipeline(&self.render_pipeline);
+        osd_render_pass.set_bind_group(0, &self.font_bind_group, &[]);
+        osd_render_pass.set_bind_group(1, &self.options.bind_group, &[]);
+        osd_render_pass.draw(0..6, 0..1);
+        true
+    }
+}
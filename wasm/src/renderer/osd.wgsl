@@ -0,0 +1,63 @@
+@group(0) @binding(0)
+var font_texture: texture_2d<f32>;
+
+struct Options {
+    origin: vec2<f32>,
+    scale: f32,
+    opacity: f32,
+    length: u32,
+    pad: u32,
+    pad2: vec2<u32>,
+    chars: array<vec4<u32>, 8>,
+}
+
+@group(1) @binding(0)
+var<uniform> options: Options;
+
+// Glyphs per row of the font texture
+const FONT_COLUMNS: u32 = 8u;
+// Space around the text inside its box, in font pixels
+const BOX_PADDING: f32 = 2.0;
+const BOX_OPACITY: f32 = 0.6;
+
+struct VertexOutput {
+    @builtin(position) pos: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
+    // List of vertices that form a full clip space quad
+    var square_vertices = array<vec2<f32>, 6>(
+        vec2(-1.0, -1.0),
+        vec2(1.0, -1.0),
+        vec2(-1.0, 1.0),
+        vec2(1.0, 1.0),
+        vec2(-1.0, 1.0),
+        vec2(1.0, -1.0),
+    );
+    var out: VertexOutput;
+    out.pos = vec4f(square_vertices[in_vertex_index], 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Position relative to the text in font pixels
+    let pos = (in.pos.xy - options.origin) / options.scale;
+    let text_size = vec2f(f32(options.length) * 8.0, 8.0);
+    if any(pos < vec2f(-BOX_PADDING)) || any(pos >= text_size + BOX_PADDING) {
+        discard;
+    }
+
+    // Text is drawn over a translucent box
+    var color = vec4f(0.0, 0.0, 0.0, BOX_OPACITY);
+    if all(pos >= vec2f(0.0)) && all(pos < text_size) {
+        let pixel = vec2u(pos);
+        let i = pixel.x / 8u;
+        let glyph = options.chars[i / 4u][i % 4u];
+        let glyph_origin = vec2u(glyph % FONT_COLUMNS, glyph / FONT_COLUMNS) * 8u;
+        let coverage = textureLoad(font_texture, glyph_origin + pixel % 8u, 0).r;
+        color = mix(color, vec4f(1.0), coverage);
+    }
+    return vec4f(color.rgb, color.a * options.opacity);
+}
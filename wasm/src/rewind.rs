@@ -0,0 +1,262 @@
+use gb_web_core::{CPU, DisplayBuffer};
+use std::collections::VecDeque;
+
+/// Emulator state captured for rewinding
+#[derive(Debug)]
+pub struct Snapshot {
+    pub state: Vec<u8>,
+    /// The display isn't included in the serialized state,
+    /// so it's stored separately to be shown while rewinding
+    pub display: DisplayBuffer,
+}
+
+/// Ring of snapshots captured periodically while the emulator runs.
+///
+/// The amount of snapshots is limited both by the time budget
+/// and by a fixed memory budget, evicting the oldest snapshots first
+#[derive(Debug, Default)]
+pub struct RewindBuffer {
+    snapshots: VecDeque<Snapshot>,
+    /// Maximum amount of snapshots, 0 if rewinding is disabled
+    capacity: usize,
+    memory_usage: usize,
+    /// Buffer of a discarded snapshot, reused for the next capture
+    spare: Vec<u8>,
+    frames_since_capture: u32,
+    last_frame: u32,
+}
+
+impl RewindBuffer {
+    /// A snapshot is captured every N frames
+    pub const CAPTURE_INTERVAL_FRAMES: u32 = 4;
+    /// Maximum combined size of all snapshots
+    pub const MEMORY_BUDGET: usize = 32 * 1024 * 1024;
+    const FRAMES_PER_SECOND: f32 = 59.7275;
+
+    /// Sets the amount of seconds that can be rewound, 0 disables rewinding
+    pub fn set_budget(&mut self, seconds: f32) {
+        self.capacity = ((seconds.max(0.0) * Self::FRAMES_PER_SECOND)
+            / Self::CAPTURE_INTERVAL_FRAMES as f32)
+            .ceil() as usize;
+        while self.snapshots.len() > self.capacity {
+            self.evict_oldest();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Captures a snapshot of the CPU if enough frames have passed since last capture
+    pub fn update(&mut self, cpu: &CPU) {
+        if !self.is_enabled() {
+            return;
+        }
+        let new_frames = cpu.frame_counter.wrapping_sub(self.last_frame);
+        self.last_frame = cpu.frame_counter;
+        self.frames_since_capture = self.frames_since_capture.saturating_add(new_frames);
+        if self.frames_since_capture < Self::CAPTURE_INTERVAL_FRAMES {
+            return;
+        }
+        self.frames_since_capture = 0;
+
+        // Make room first, so the buffer of the oldest snapshot can be reused
+        if self.snapshots.len() >= self.capacity {
+            self.evict_oldest();
+        }
+        let mut state = std::mem::take(&mut self.spare);
+        match cpu.save_state_into(&mut state) {
+            Ok(()) => {
+                self.memory_usage += state.len();
+                self.snapshots.push_back(Snapshot {
+                    state,
+                    display: *cpu.get_display_buffer(),
+                });
+                while self.snapshots.len() > self.capacity
+                    || self.memory_usage > Self::MEMORY_BUDGET
+                {
+                    self.evict_oldest();
+                }
+            }
+            Err(e) => log::error!("Failed to capture rewind snapshot: {e}"),
+        }
+    }
+
+    /// Removes and returns the newest snapshot
+    pub fn pop(&mut self) -> Option<Snapshot> {
+        let snapshot = self.snapshots.pop_back()?;
+        self.memory_usage -= snapshot.state.len();
+        Some(snapshot)
+    }
+
+    /// Takes back the buffer of a restored snapshot to reuse for the next capture
+    pub fn recycle(&mut self, state: Vec<u8>) {
+        self.spare = state;
+    }
+
+    /// Removes all snapshots, used when the emulator state is replaced
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.memory_usage = 0;
+        self.frames_since_capture = 0;
+    }
+
+    /// Restarts frame counting from the frame of given CPU,
+    /// so a restored state doesn't count as elapsed frames
+    pub fn sync_frame(&mut self, cpu: &CPU) {
+        self.last_frame = cpu.frame_counter;
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some(snapshot) = self.snapshots.pop_front() {
+            self.memory_usage -= snapshot.state.len();
+            self.spare = snapshot.state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// ROM of NOPs without a mapper, which runs the same way every time
+    fn cpu() -> CPU {
+        CPU::new(Arc::from(vec![0; 0x8000])).unwrap()
+    }
+
+    /// Counts frames as passed without running them, so capturing stays cheap
+    fn skip_frames(cpu: &mut CPU, frames: u32) {
+        cpu.frame_counter = cpu.frame_counter.wrapping_add(frames);
+    }
+
+    fn stored_size(rewind: &RewindBuffer) -> usize {
+        rewind.snapshots.iter().map(|s| s.state.len()).sum()
+    }
+
+    #[test]
+    fn captures_every_interval_up_to_time_budget() {
+        let mut cpu = cpu();
+        let mut rewind = RewindBuffer::default();
+        rewind.update(&cpu);
+        assert!(
+            rewind.snapshots.is_empty(),
+            "disabled until a budget is set"
+        );
+
+        // One second is 15 snapshots 4 frames apart
+        rewind.set_budget(1.0);
+        rewind.sync_frame(&cpu);
+        for _ in 0..RewindBuffer::CAPTURE_INTERVAL_FRAMES - 1 {
+            skip_frames(&mut cpu, 1);
+            rewind.update(&cpu);
+        }
+        assert!(rewind.snapshots.is_empty());
+        skip_frames(&mut cpu, 1);
+        rewind.update(&cpu);
+        assert_eq!(rewind.snapshots.len(), 1);
+
+        for _ in 0..40 {
+            skip_frames(&mut cpu, RewindBuffer::CAPTURE_INTERVAL_FRAMES);
+            rewind.update(&cpu);
+        }
+        assert_eq!(rewind.snapshots.len(), 15);
+        assert_eq!(rewind.memory_usage, stored_size(&rewind));
+
+        // Shrinking the budget drops the oldest snapshots right away
+        rewind.set_budget(0.5);
+        assert_eq!(rewind.snapshots.len(), 8);
+        assert_eq!(rewind.memory_usage, stored_size(&rewind));
+    }
+
+    #[test]
+    fn memory_budget_evicts_oldest_snapshots() {
+        let mut cpu = cpu();
+        let mut rewind = RewindBuffer::default();
+        rewind.set_budget(3600.0);
+        rewind.sync_frame(&cpu);
+        let mut state = Vec::new();
+        cpu.save_state_into(&mut state).unwrap();
+        let fitting = RewindBuffer::MEMORY_BUDGET / state.len();
+
+        for _ in 0..fitting + 10 {
+            skip_frames(&mut cpu, RewindBuffer::CAPTURE_INTERVAL_FRAMES);
+            rewind.update(&cpu);
+        }
+        assert_eq!(rewind.snapshots.len(), fitting);
+        assert_eq!(rewind.memory_usage, stored_size(&rewind));
+        assert!(rewind.memory_usage <= RewindBuffer::MEMORY_BUDGET);
+        // The newest snapshot is kept
+        let newest = rewind.pop().unwrap();
+        let restored = postcard::from_bytes::<CPU>(&newest.state).unwrap();
+        assert_eq!(restored.frame_counter, cpu.frame_counter);
+    }
+
+    #[test]
+    fn popped_snapshot_resumes_from_captured_state() {
+        let mut cpu = cpu();
+        let mut rewind = RewindBuffer::default();
+        rewind.set_budget(1.0);
+        rewind.sync_frame(&cpu);
+        let mut hashes = Vec::new();
+        for _ in 0..3 {
+            for _ in 0..RewindBuffer::CAPTURE_INTERVAL_FRAMES {
+                cpu.run_frame();
+            }
+            rewind.update(&cpu);
+            hashes.push(cpu.state_hash().unwrap());
+        }
+
+        let usage = rewind.memory_usage;
+        let snapshot = rewind.pop().unwrap();
+        assert_eq!(rewind.memory_usage, usage - snapshot.state.len());
+        assert_eq!(snapshot.display, *cpu.get_display_buffer());
+        let restored = postcard::from_bytes::<CPU>(&snapshot.state).unwrap();
+        assert_eq!(restored.state_hash().unwrap(), hashes[2]);
+
+        // Popping again steps further back
+        let snapshot = rewind.pop().unwrap();
+        let mut restored = postcard::from_bytes::<CPU>(&snapshot.state).unwrap();
+        assert_eq!(restored.state_hash().unwrap(), hashes[1]);
+
+        // Running on from the restored state captures the same timeline again
+        restored.set_rom(Arc::from(vec![0; 0x8000]));
+        rewind.sync_frame(&restored);
+        for _ in 0..RewindBuffer::CAPTURE_INTERVAL_FRAMES {
+            restored.run_frame();
+        }
+        rewind.update(&restored);
+        assert_eq!(restored.state_hash().unwrap(), hashes[2]);
+        assert_eq!(rewind.snapshots.len(), 2);
+    }
+
+    #[test]
+    fn loading_state_clears_snapshots() {
+        let mut cpu = cpu();
+        let mut rewind = RewindBuffer::default();
+        rewind.set_budget(1.0);
+        rewind.sync_frame(&cpu);
+        let mut earlier = Vec::new();
+        cpu.save_state_into(&mut earlier).unwrap();
+        for _ in 0..5 {
+            skip_frames(&mut cpu, RewindBuffer::CAPTURE_INTERVAL_FRAMES);
+            rewind.update(&cpu);
+        }
+
+        // Done by the App when a save state is loaded
+        let loaded = postcard::from_bytes::<CPU>(&earlier).unwrap();
+        rewind.clear();
+        rewind.sync_frame(&loaded);
+        assert!(rewind.pop().is_none());
+        assert_eq!(rewind.memory_usage, 0);
+
+        // The loaded state's lower frame counter doesn't count as elapsed frames
+        let mut cpu = loaded;
+        skip_frames(&mut cpu, 1);
+        rewind.update(&cpu);
+        assert!(rewind.snapshots.is_empty());
+        skip_frames(&mut cpu, RewindBuffer::CAPTURE_INTERVAL_FRAMES - 1);
+        rewind.update(&cpu);
+        assert_eq!(rewind.snapshots.len(), 1);
+    }
+}
@@ -0,0 +1,299 @@
+use super::*;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use tsify::Tsify;
+use wasm_bindgen::JsCast;
+
+/// How transfers clocked by this Game Boy get the partner's byte
+#[derive(Tsify, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SerialLinkMode {
+    /// Emulation stalls until the partner's byte arrives, so both games receive exactly what
+    /// a cable would carry. Every transfer costs a round trip, which slows down games that
+    /// transfer often when the connection has high latency
+    Lockstep,
+    /// Transfers finish right away with the partner's previous reply, 0xFF before the first one,
+    /// so emulation never stalls. As each reply arrives one transfer late,
+    /// games that check the bytes they receive can go out of sync
+    Speculative,
+}
+
+#[derive(Tsify, Debug, Clone, Deserialize)]
+pub struct SerialLinkOptions {
+    pub mode: SerialLinkMode,
+    /// How long a lockstep transfer waits for the partner before disconnecting,
+    /// 5 seconds by default
+    #[tsify(optional)]
+    pub timeout_ms: Option<f64>,
+}
+
+/// Messages sent over the channel, encoded as a kind byte followed by the payload
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LinkMessage {
+    /// Sent by the side whose game clocks a transfer, with the byte it shifted out
+    Transfer(u8),
+    /// Answers a transfer with the byte the partner's game shifted out in exchange,
+    /// or 0xFF if its game wasn't waiting for a transfer
+    Reply(u8),
+    /// Hash of a window of exchanges, for noticing when the sides disagree on them
+    Check { window: u32, hash: u64 },
+}
+
+impl LinkMessage {
+    const TRANSFER: u8 = 0;
+    const REPLY: u8 = 1;
+    const CHECK: u8 = 2;
+
+    fn encode(self) -> Vec<u8> {
+        match self {
+            Self::Transfer(byte) => vec![Self::TRANSFER, byte],
+            Self::Reply(byte) => vec![Self::REPLY, byte],
+            Self::Check { window, hash } => [Self::CHECK]
+                .into_iter()
+                .chain(window.to_le_bytes())
+                .chain(hash.to_le_bytes())
+                .collect(),
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        match *data {
+            [Self::TRANSFER, byte] => Some(Self::Transfer(byte)),
+            [Self::REPLY, byte] => Some(Self::Reply(byte)),
+            [Self::CHECK, ref payload @ ..] if payload.len() == 12 => Some(Self::Check {
+                window: u32::from_le_bytes(payload[..4].try_into().ok()?),
+                hash: u64::from_le_bytes(payload[4..].try_into().ok()?),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Notices when the two sides disagree on the bytes they exchanged, like when a speculative
+/// transfer finished with another byte than the partner sent. Both sides hash the exchanges
+/// in windows and send each other the hashes to compare.
+///
+/// Linked Game Boys run their own games, so their states can't be compared with each other.
+/// The exchanges are the only thing both sides should agree on
+#[derive(Debug, Default)]
+struct DesyncDetector {
+    exchanges: u32,
+    /// Sum of the hashes of the exchanges in the current window. Summing gives the same hash
+    /// when transfers started at the same time by both sides are seen in different order
+    hash: u64,
+    /// Hashes of finished windows not compared yet, of this side and of the partner
+    own: VecDeque<(u32, u64)>,
+    partner: VecDeque<(u32, u64)>,
+    desynced: bool,
+}
+
+impl DesyncDetector {
+    /// Exchanges in a window, about a second of a game transferring every frame.
+    /// Checks then take a small part of the bandwidth of the transfers
+    const WINDOW: u32 = 64;
+    /// Windows kept waiting for the other side's hash, older ones are dropped
+    const MAX_PENDING: usize = 16;
+
+    /// Records an exchange, returning the check to send when it completes a window
+    fn record(&mut self, clocked: u8, answered: u8) -> Option<LinkMessage> {
+        let exchange_hash = [clocked, answered]
+            .iter()
+            .fold(0xCBF2_9CE4_8422_2325, |hash: u64, byte| {
+                (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01B3)
+            });
+        self.hash = self.hash.wrapping_add(exchange_hash);
+        self.exchanges = self.exchanges.wrapping_add(1);
+        if !self.exchanges.is_multiple_of(Self::WINDOW) {
+            return None;
+        }
+        let window = self.exchanges / Self::WINDOW;
+        let hash = std::mem::take(&mut self.hash);
+        Self::push_pending(&mut self.own, window, hash);
+        Some(LinkMessage::Check { window, hash })
+    }
+
+    fn receive_check(&mut self, window: u32, hash: u64) {
+        Self::push_pending(&mut self.partner, window, hash);
+    }
+
+    fn push_pending(pending: &mut VecDeque<(u32, u64)>, window: u32, hash: u64) {
+        if pending.len() == Self::MAX_PENDING {
+            pending.pop_front();
+        }
+        pending.push_back((window, hash));
+    }
+
+    /// Compares the windows both sides have hashed. Returns the number of the first exchange
+    /// of a window that differs after being in sync, so a lasting desync is reported once
+    fn compare(&mut self) -> Option<u32> {
+        let mut desync = None;
+        while let (Some(&(own_window, own_hash)), Some(&(partner_window, partner_hash))) =
+            (self.own.front(), self.partner.front())
+        {
+            if own_window < partner_window {
+                self.own.pop_front();
+                continue;
+            }
+            if partner_window < own_window {
+                self.partner.pop_front();
+                continue;
+            }
+            self.own.pop_front();
+            self.partner.pop_front();
+            let in_sync = own_hash == partner_hash;
+            if !in_sync && !self.desynced {
+                desync.get_or_insert((own_window - 1) * Self::WINDOW);
+            }
+            self.desynced = !in_sync;
+        }
+        desync
+    }
+}
+
+/// Link cable to another emulator over an RTCDataChannel connected by the page
+#[derive(Debug)]
+pub struct SerialLink {
+    channel: web_sys::RtcDataChannel,
+    mode: SerialLinkMode,
+    timeout_ms: f64,
+    /// Messages received since the last update
+    received: Rc<RefCell<VecDeque<LinkMessage>>>,
+    closed: Rc<Cell<bool>>,
+    /// Bytes of lockstep transfers waiting for a reply and when they were sent, oldest first.
+    /// More than one is waiting only if the game has aborted a transfer and started another
+    waiting: VecDeque<(u8, f64)>,
+    /// Byte the next speculative transfer finishes with
+    last_reply: u8,
+    desync: DesyncDetector,
+    _on_message: Closure<dyn FnMut(web_sys::MessageEvent)>,
+    _on_close: Closure<dyn FnMut()>,
+}
+
+impl SerialLink {
+    const DEFAULT_TIMEOUT_MS: f64 = 5000.0;
+
+    pub fn new(channel: web_sys::RtcDataChannel, options: SerialLinkOptions) -> Self {
+        channel.set_binary_type(web_sys::RtcDataChannelType::Arraybuffer);
+        let received: Rc<RefCell<VecDeque<LinkMessage>>> = Rc::default();
+        let closed = Rc::new(Cell::new(false));
+
+        let on_message = {
+            let received = Rc::clone(&received);
+            Closure::<dyn FnMut(_)>::new(move |event: web_sys::MessageEvent| {
+                let data = web_sys::js_sys::Uint8Array::new(&event.data()).to_vec();
+                match LinkMessage::decode(&data) {
+                    Some(message) => received.borrow_mut().push_back(message),
+                    None => log::warn!("Ignoring unknown link message {data:?}"),
+                }
+            })
+        };
+        let on_close = {
+            let closed = Rc::clone(&closed);
+            Closure::<dyn FnMut()>::new(move || closed.set(true))
+        };
+        channel.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        channel.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+        Self {
+            channel,
+            mode: options.mode,
+            timeout_ms: options.timeout_ms.unwrap_or(Self::DEFAULT_TIMEOUT_MS),
+            received,
+            closed,
+            waiting: VecDeque::new(),
+            last_reply: 0xFF,
+            desync: DesyncDetector::default(),
+            _on_message: on_message,
+            _on_close: on_close,
+        }
+    }
+
+    /// Returns if emulation has to wait for the partner's byte before continuing
+    pub fn is_stalled(&self, cpu: &CPU) -> bool {
+        self.mode == SerialLinkMode::Lockstep && cpu.is_serial_waiting()
+    }
+
+    /// Answers transfers clocked by the partner, finishes transfers the partner has replied to
+    /// and sends the transfer the game has started. Returns the number of the first exchange
+    /// the sides disagree on, if they agreed before. Fails if the channel has closed
+    /// or the partner hasn't replied within the timeout
+    pub fn update(&mut self, cpu: &mut CPU, now: f64) -> Result<Option<u32>, BridgeError> {
+        if self.closed.get() {
+            return Err(Self::disconnected("Link partner disconnected"));
+        }
+        loop {
+            // Not borrowed while handling, so sending can't conflict with receiving
+            let message = self.received.borrow_mut().pop_front();
+            let Some(message) = message else {
+                break;
+            };
+            match message {
+                LinkMessage::Transfer(byte) => {
+                    let reply = cpu.receive_serial_byte(byte).unwrap_or(0xFF);
+                    self.send(LinkMessage::Reply(reply))?;
+                    self.record_exchange(byte, reply)?;
+                }
+                LinkMessage::Reply(byte) => match self.mode {
+                    SerialLinkMode::Lockstep => {
+                        cpu.finish_serial_transfer(byte);
+                        if let Some((sent, _)) = self.waiting.pop_front() {
+                            self.record_exchange(sent, byte)?;
+                        }
+                    }
+                    SerialLinkMode::Speculative => self.last_reply = byte,
+                },
+                LinkMessage::Check { window, hash } => self.desync.receive_check(window, hash),
+            }
+        }
+
+        if let Some(byte) = cpu.take_serial_outgoing() {
+            self.send(LinkMessage::Transfer(byte))?;
+            match self.mode {
+                SerialLinkMode::Lockstep => self.waiting.push_back((byte, now)),
+                SerialLinkMode::Speculative => {
+                    cpu.finish_serial_transfer(self.last_reply);
+                    self.record_exchange(byte, self.last_reply)?;
+                }
+            }
+        }
+        // A transfer the game has aborted, or one from before loading a state,
+        // is still recorded once its reply arrives, as the partner has recorded it
+        match self.waiting.back() {
+            Some(&(_, since)) if cpu.is_serial_waiting() && now - since > self.timeout_ms => {
+                Err(BridgeError::new(
+                    ErrorCode::SerialLinkTimeout,
+                    format!("Link partner didn't reply in {} ms", self.timeout_ms),
+                ))
+            }
+            _ => Ok(self.desync.compare()),
+        }
+    }
+
+    /// Adds an exchange to the desync detector, sending its check when it completes a window
+    fn record_exchange(&mut self, clocked: u8, answered: u8) -> Result<(), BridgeError> {
+        match self.desync.record(clocked, answered) {
+            Some(check) => self.send(check),
+            None => Ok(()),
+        }
+    }
+
+    fn send(&self, message: LinkMessage) -> Result<(), BridgeError> {
+        self.channel
+            .send_with_u8_array(&message.encode())
+            .map_err(|e| {
+                Self::disconnected("Couldn't send to link partner").with_details(format!("{e:?}"))
+            })
+    }
+
+    fn disconnected(message: &str) -> BridgeError {
+        BridgeError::new(ErrorCode::SerialLinkDisconnected, message)
+    }
+}
+
+impl Drop for SerialLink {
+    fn drop(&mut self) {
+        // The channel belongs to the page, which may keep using it
+        self.channel.set_onmessage(None);
+        self.channel.set_onclose(None);
+    }
+}
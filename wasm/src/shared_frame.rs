@@ -0,0 +1,190 @@
+use gb_web_core::{DISPLAY_BUFFER_SIZE, DisplayBuffer};
+use wasm_bindgen::JsValue;
+use web_sys::js_sys::{self, Atomics, Int32Array, SharedArrayBuffer, Uint32Array};
+
+/// Memory a frame is published into, behind a sequence number
+pub trait FrameStore {
+    /// Atomically adds to the sequence number
+    fn increment_sequence(&self);
+    fn write_display(&self, display: &DisplayBuffer);
+}
+
+/// Publishes display frames into a SharedArrayBuffer,
+/// so the page can read the newest frame without it being copied through the event loop.
+///
+/// The buffer starts with a 32-bit sequence number followed by the display buffer.
+/// The sequence is odd while a frame is being written, so readers can detect torn frames:
+/// read the sequence, copy the frame, and retry if the sequence was odd or has changed
+pub struct SharedFrame<S = SharedFrameBuffer> {
+    store: S,
+}
+
+/// SharedArrayBuffer holding the sequence number and the display buffer
+pub struct SharedFrameBuffer {
+    buffer: SharedArrayBuffer,
+    sequence: Int32Array,
+    display: Uint32Array,
+}
+
+impl FrameStore for SharedFrameBuffer {
+    fn increment_sequence(&self) {
+        let _ = Atomics::add(&self.sequence, 0, 1);
+    }
+
+    fn write_display(&self, display: &DisplayBuffer) {
+        self.display.copy_from(display);
+    }
+}
+
+impl SharedFrame {
+    const HEADER_SIZE: u32 = 4;
+
+    /// Returns None if SharedArrayBuffer isn't available,
+    /// which is the case when the page isn't cross-origin isolated
+    pub fn new() -> Option<Self> {
+        let isolated =
+            js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("crossOriginIsolated"))
+                .ok()
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false);
+        if !isolated {
+            return None;
+        }
+        let buffer = SharedArrayBuffer::new(Self::HEADER_SIZE + (DISPLAY_BUFFER_SIZE as u32) * 4);
+        let sequence = Int32Array::new_with_byte_offset_and_length(&buffer, 0, 1);
+        let display = Uint32Array::new_with_byte_offset_and_length(
+            &buffer,
+            Self::HEADER_SIZE,
+            DISPLAY_BUFFER_SIZE as u32,
+        );
+        Some(Self {
+            store: SharedFrameBuffer {
+                buffer,
+                sequence,
+                display,
+            },
+        })
+    }
+
+    pub fn buffer(&self) -> SharedArrayBuffer {
+        self.store.buffer.clone()
+    }
+}
+
+impl<S: FrameStore> SharedFrame<S> {
+    /// Writes a new frame, incrementing the sequence number before and after writing
+    pub fn publish(&self, display: &DisplayBuffer) {
+        self.store.increment_sequence();
+        self.store.write_display(display);
+        self.store.increment_sequence();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+    /// Shared memory like the SharedArrayBuffer, written word by word like `copy_from`
+    struct AtomicStore {
+        sequence: AtomicU32,
+        display: Vec<AtomicU32>,
+    }
+
+    impl FrameStore for Arc<AtomicStore> {
+        fn increment_sequence(&self) {
+            self.sequence.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn write_display(&self, display: &DisplayBuffer) {
+            for (word, value) in self.display.iter().zip(display) {
+                word.store(*value, Ordering::SeqCst);
+            }
+        }
+    }
+
+    impl AtomicStore {
+        fn new() -> Arc<Self> {
+            Arc::new(Self {
+                sequence: AtomicU32::new(0),
+                display: (0..DISPLAY_BUFFER_SIZE)
+                    .map(|_| AtomicU32::new(0))
+                    .collect(),
+            })
+        }
+
+        /// Reads the newest frame the way the page does, returning the sequence it was read at
+        /// and how many reads were retried
+        fn read(&self, frame: &mut DisplayBuffer) -> (u32, u32) {
+            let mut retries = 0;
+            loop {
+                let before = self.sequence.load(Ordering::SeqCst);
+                if before.is_multiple_of(2) {
+                    for (value, word) in frame.iter_mut().zip(&self.display) {
+                        *value = word.load(Ordering::SeqCst);
+                    }
+                    if self.sequence.load(Ordering::SeqCst) == before {
+                        return (before, retries);
+                    }
+                }
+                retries += 1;
+                std::hint::spin_loop();
+            }
+        }
+    }
+
+    #[test]
+    fn sequence_is_even_between_frames() {
+        let store = AtomicStore::new();
+        let frame = SharedFrame {
+            store: Arc::clone(&store),
+        };
+        let display = [7; DISPLAY_BUFFER_SIZE];
+        frame.publish(&display);
+        frame.publish(&display);
+        assert_eq!(store.sequence.load(Ordering::SeqCst), 4);
+
+        let mut read = [0; DISPLAY_BUFFER_SIZE];
+        assert_eq!(store.read(&mut read), (4, 0));
+        assert_eq!(read, display);
+    }
+
+    #[test]
+    fn reader_never_sees_torn_frame() {
+        let store = AtomicStore::new();
+        let frame = SharedFrame {
+            store: Arc::clone(&store),
+        };
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let store = Arc::clone(&store);
+            let done = Arc::clone(&done);
+            std::thread::spawn(move || {
+                let mut read = [0; DISPLAY_BUFFER_SIZE];
+                let mut reads = 0;
+                let mut last_sequence = 0;
+                while !done.load(Ordering::SeqCst) || reads == 0 {
+                    let (sequence, _) = store.read(&mut read);
+                    // Every word of a frame holds its number, so a torn frame has mixed words
+                    assert!(
+                        read.iter().all(|word| *word == read[0]),
+                        "torn frame at sequence {sequence}"
+                    );
+                    // Frame n is complete once the sequence reaches 2n
+                    assert_eq!(read[0], sequence / 2);
+                    assert!(sequence >= last_sequence);
+                    last_sequence = sequence;
+                    reads += 1;
+                }
+                reads
+            })
+        };
+
+        for number in 1..=2000 {
+            frame.publish(&[number; DISPLAY_BUFFER_SIZE]);
+        }
+        done.store(true, Ordering::SeqCst);
+        assert!(reader.join().unwrap() > 0);
+    }
+}
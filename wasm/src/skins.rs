@@ -0,0 +1,112 @@
+use super::*;
+use serde::Deserialize;
+#[cfg(target_family = "wasm")]
+use wasm_bindgen::JsCast;
+#[cfg(target_family = "wasm")]
+use wasm_bindgen_futures::JsFuture;
+
+/// Name of the skin whose definition is compiled into the binary.
+/// Its image is fetched after starting, to keep it out of the binary
+pub const DEFAULT_SKIN: &str = "classic";
+/// Image of the default skin used unless the page gives another URL
+pub const DEFAULT_BACKGROUND_URL: &str = "skins/classic.png";
+/// Lists the skins that can be fetched on demand
+const MANIFEST_URL: &str = "skins/manifest.json";
+
+/// Skins available for fetching, keyed by their names. Stored as JSON in the form of
+/// `{ "skins": { "pocket": { "image": "skins/pocket.png", "definition": "skins/pocket.toml" } } }`
+#[derive(Debug, Deserialize)]
+struct SkinManifest {
+    skins: HashMap<String, SkinManifestEntry>,
+}
+
+/// URLs of the files of a skin, relative to the page
+#[derive(Debug, Deserialize)]
+struct SkinManifestEntry {
+    image: String,
+    definition: String,
+}
+
+/// Files of a fetched skin, decoded when added to the renderer
+#[derive(Debug)]
+pub struct SkinFiles {
+    /// PNG image of the background
+    pub image: Vec<u8>,
+    /// TOML background definition
+    pub definition: String,
+}
+
+fn fetch_error(url: &str, details: impl std::fmt::Debug) -> BridgeError {
+    BridgeError::new(ErrorCode::SkinFetchFailed, format!("Couldn't fetch {url}"))
+        .with_details(format!("{details:?}"))
+}
+
+#[cfg(target_family = "wasm")]
+async fn fetch(url: &str) -> Result<web_sys::Response, BridgeError> {
+    let window = web_sys::window().ok_or_else(|| fetch_error(url, "Window not available"))?;
+    let response: web_sys::Response = JsFuture::from(window.fetch_with_str(url))
+        .await
+        .map_err(|e| fetch_error(url, e))?
+        .dyn_into()
+        .map_err(|e| fetch_error(url, e))?;
+    if !response.ok() {
+        return Err(fetch_error(url, response.status()));
+    }
+    Ok(response)
+}
+
+#[cfg(target_family = "wasm")]
+async fn fetch_bytes(url: &str) -> Result<Vec<u8>, BridgeError> {
+    let response = fetch(url).await?;
+    let buffer = response.array_buffer().map_err(|e| fetch_error(url, e))?;
+    let buffer = JsFuture::from(buffer)
+        .await
+        .map_err(|e| fetch_error(url, e))?;
+    Ok(web_sys::js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+#[cfg(target_family = "wasm")]
+async fn fetch_text(url: &str) -> Result<String, BridgeError> {
+    let response = fetch(url).await?;
+    let text = response.text().map_err(|e| fetch_error(url, e))?;
+    JsFuture::from(text)
+        .await
+        .map_err(|e| fetch_error(url, e))?
+        .as_string()
+        .ok_or_else(|| fetch_error(url, "Response isn't text"))
+}
+
+/// Reads the file that would be fetched from given URL
+#[cfg(not(target_family = "wasm"))]
+async fn fetch_bytes(url: &str) -> Result<Vec<u8>, BridgeError> {
+    std::fs::read(asset_path(url)).map_err(|e| fetch_error(url, e))
+}
+
+/// Reads the file that would be fetched from given URL
+#[cfg(not(target_family = "wasm"))]
+async fn fetch_text(url: &str) -> Result<String, BridgeError> {
+    std::fs::read_to_string(asset_path(url)).map_err(|e| fetch_error(url, e))
+}
+
+/// Fetches the image of the default skin
+pub async fn fetch_background(url: &str) -> Result<Vec<u8>, BridgeError> {
+    fetch_bytes(url).await
+}
+
+/// Fetches the files of a skin listed in the manifest
+pub async fn fetch_skin(name: &str) -> Result<SkinFiles, BridgeError> {
+    let manifest = fetch_text(MANIFEST_URL).await?;
+    let manifest: SkinManifest = serde_json::from_str(&manifest).map_err(|e| {
+        BridgeError::new(ErrorCode::InvalidSkin, "Skin manifest is invalid").with_details(e)
+    })?;
+    let entry = manifest.skins.get(name).ok_or_else(|| {
+        BridgeError::new(
+            ErrorCode::SkinNotFound,
+            format!("Skin \"{name}\" doesn't exist"),
+        )
+    })?;
+    Ok(SkinFiles {
+        image: fetch_bytes(&entry.image).await?,
+        definition: fetch_text(&entry.definition).await?,
+    })
+}
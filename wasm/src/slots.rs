@@ -0,0 +1,141 @@
+use gb_web_core::DisplayBuffer;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tsify::Tsify;
+
+/// A save state kept in memory
+#[derive(Debug)]
+pub struct StateSlot {
+    /// The serialized CPU
+    pub state: Vec<u8>,
+    /// Hash of the ROM the state was saved with
+    pub rom_hash: u32,
+    /// Time of saving in milliseconds since the Unix epoch
+    pub timestamp: f64,
+    /// The display at the time of saving
+    pub display: DisplayBuffer,
+}
+
+/// Info about a save state slot sent to the frontend
+#[derive(Tsify, Debug, Clone, Serialize)]
+pub struct StateSlotInfo {
+    pub slot: u8,
+    /// Time of saving in milliseconds since the Unix epoch
+    pub timestamp: f64,
+    /// Size of the serialized state in bytes
+    pub size: usize,
+    /// Half resolution (80x72) image of the display,
+    /// with one palette color index (0-3) per byte
+    pub thumbnail: Vec<u8>,
+}
+
+/// Save states stored in slots inside the emulator.
+///
+/// Slots are shared between ROMs, so loading checks that the state belongs to the
+/// current ROM. If saving would exceed the memory cap, the oldest other slots are
+/// evicted until the new state fits
+#[derive(Debug, Default)]
+pub struct StateSlots {
+    slots: BTreeMap<u8, StateSlot>,
+}
+
+#[derive(Debug)]
+pub enum StateSlotError {
+    InvalidSlot(u8),
+    EmptySlot(u8),
+    WrongROM(u8),
+    TooLarge(usize),
+}
+
+impl std::fmt::Display for StateSlotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSlot(slot) => write!(
+                f,
+                "Slot {slot} doesn't exist, slots are numbered up to {}",
+                StateSlots::MAX_SLOTS - 1
+            ),
+            Self::EmptySlot(slot) => write!(f, "State not found for slot {slot}"),
+            Self::WrongROM(slot) => write!(f, "State in slot {slot} was saved with another ROM"),
+            Self::TooLarge(size) => write!(f, "State of {size} bytes is too large to be saved"),
+        }
+    }
+}
+
+impl StateSlots {
+    pub const MAX_SLOTS: u8 = 16;
+    /// Maximum combined size of all stored states
+    pub const MEMORY_CAP: usize = 16 * 1024 * 1024;
+
+    /// Stores state into slot, replacing the previous state
+    pub fn save(&mut self, slot: u8, new_state: StateSlot) -> Result<(), StateSlotError> {
+        if slot >= Self::MAX_SLOTS {
+            return Err(StateSlotError::InvalidSlot(slot));
+        }
+        let size = new_state.state.len();
+        if size > Self::MEMORY_CAP {
+            return Err(StateSlotError::TooLarge(size));
+        }
+        self.slots.remove(&slot);
+        // Evict the oldest states until new state fits under the cap
+        while self.memory_usage() + size > Self::MEMORY_CAP {
+            let oldest = self
+                .slots
+                .iter()
+                .min_by(|a, b| a.1.timestamp.total_cmp(&b.1.timestamp))
+                .map(|(slot, _)| *slot);
+            if let Some(oldest) = oldest {
+                log::info!("Evicting state slot {oldest} to stay under memory cap");
+                self.slots.remove(&oldest);
+            }
+        }
+        self.slots.insert(slot, new_state);
+        Ok(())
+    }
+
+    /// Returns the state in slot if it was saved with the ROM of given hash
+    pub fn load(&self, slot: u8, rom_hash: u32) -> Result<&StateSlot, StateSlotError> {
+        if slot >= Self::MAX_SLOTS {
+            return Err(StateSlotError::InvalidSlot(slot));
+        }
+        let state = self
+            .slots
+            .get(&slot)
+            .ok_or(StateSlotError::EmptySlot(slot))?;
+        if state.rom_hash != rom_hash {
+            return Err(StateSlotError::WrongROM(slot));
+        }
+        Ok(state)
+    }
+
+    /// Returns info about all slots saved with the ROM of given hash
+    pub fn list(&self, rom_hash: u32) -> Vec<StateSlotInfo> {
+        self.slots
+            .iter()
+            .filter(|(_, state)| state.rom_hash == rom_hash)
+            .map(|(slot, state)| StateSlotInfo {
+                slot: *slot,
+                timestamp: state.timestamp,
+                size: state.state.len(),
+                thumbnail: Self::thumbnail(&state.display),
+            })
+            .collect()
+    }
+
+    /// Returns the combined size of all stored states
+    pub fn memory_usage(&self) -> usize {
+        self.slots.values().map(|state| state.state.len()).sum()
+    }
+
+    /// Downscales display to half resolution by picking every other pixel
+    fn thumbnail(display: &DisplayBuffer) -> Vec<u8> {
+        let mut thumbnail = Vec::with_capacity(80 * 72);
+        for y in (0..144).step_by(2) {
+            for x in (0..160).step_by(2) {
+                let bit_i = 2 * (y * 160 + x);
+                thumbnail.push(((display[bit_i / 32] >> (bit_i % 32)) & 0b11) as u8);
+            }
+        }
+        thumbnail
+    }
+}
@@ -0,0 +1,111 @@
+use serde::Serialize;
+use tsify::Tsify;
+
+/// Performance statistics averaged over the reporting interval
+#[derive(Tsify, Debug, Clone, Default, Serialize)]
+pub struct FrameStats {
+    /// Frames completed by the emulator per second
+    pub emulated_fps: f32,
+    /// Average time spent rendering a frame in milliseconds
+    pub render_ms: f32,
+    /// Average time spent running the CPU per query in milliseconds
+    pub run_ms: f32,
+    /// How full the audio buffer is, between 0.0 and 1.0
+    pub audio_fill: f32,
+    /// Present mode the renderer has chosen, like "Fifo" or "Mailbox"
+    pub present_mode: String,
+    /// Relative change of the audio sample rate by rate control, like 0.002 for 0.2% faster
+    pub audio_rate_correction: f32,
+    /// Length of audio the output device takes at a time in milliseconds,
+    /// or 0.0 if the device chooses it itself
+    pub audio_device_buffer_ms: f32,
+    /// Uniform buffers written to the GPU during the interval
+    pub uniform_writes: u32,
+    /// Uniform buffer writes skipped during the interval as the contents hadn't changed
+    pub uniform_writes_skipped: u32,
+    /// Emulated frames uploaded to the GPU during the interval
+    pub display_uploads: u32,
+    /// Emulated frames not uploaded during the interval as they repeated the previous frame
+    pub display_uploads_skipped: u32,
+    /// Redraws during the interval that ran no emulated frame, showing the previous one again
+    pub repeated_frames: u32,
+    /// Emulated frames during the interval that were run but never shown,
+    /// as another one was run on the same redraw
+    pub dropped_frames: u32,
+    /// Estimated refresh rate of the display in Hz, or 0.0 if auto-run hasn't measured it
+    pub refresh_rate: f32,
+}
+
+/// Accumulates measurements between frame stat reports
+#[derive(Debug, Default)]
+pub struct StatsCounter {
+    interval_start: f64,
+    frames: u32,
+    render_ms: f64,
+    renders: u32,
+    run_ms: f64,
+    runs: u32,
+}
+
+impl StatsCounter {
+    pub fn add_frames(&mut self, frames: u32) {
+        self.frames += frames;
+    }
+
+    pub fn add_render(&mut self, millis: f64) {
+        self.render_ms += millis;
+        self.renders += 1;
+    }
+
+    pub fn add_run(&mut self, millis: f64) {
+        self.run_ms += millis;
+        self.runs += 1;
+    }
+
+    /// Returns the averaged statistics if the interval has elapsed
+    /// and starts a new interval
+    pub fn take_if_elapsed(
+        &mut self,
+        now: f64,
+        interval_ms: f64,
+        audio_fill: f32,
+    ) -> Option<FrameStats> {
+        // Start the first interval on the first measurement
+        if self.interval_start == 0.0 {
+            self.interval_start = now;
+            return None;
+        }
+        let elapsed = now - self.interval_start;
+        if elapsed < interval_ms {
+            return None;
+        }
+        fn average(total: f64, count: u32) -> f32 {
+            if count == 0 {
+                0.0
+            } else {
+                (total / count as f64) as f32
+            }
+        }
+        let stats = FrameStats {
+            emulated_fps: (self.frames as f64 / (elapsed / 1000.0)) as f32,
+            render_ms: average(self.render_ms, self.renders),
+            run_ms: average(self.run_ms, self.runs),
+            audio_fill,
+            present_mode: String::new(),
+            audio_rate_correction: 0.0,
+            audio_device_buffer_ms: 0.0,
+            uniform_writes: 0,
+            uniform_writes_skipped: 0,
+            display_uploads: 0,
+            display_uploads_skipped: 0,
+            repeated_frames: 0,
+            dropped_frames: 0,
+            refresh_rate: 0.0,
+        };
+        *self = Self {
+            interval_start: now,
+            ..Default::default()
+        };
+        Some(stats)
+    }
+}
@@ -0,0 +1,42 @@
+use serde::Serialize;
+use std::cell::Cell;
+use tsify::Tsify;
+
+thread_local! {
+    /// Backend of the current renderer, kept where version info can be read without the app
+    static GRAPHICS_BACKEND: Cell<Option<wgpu::Backend>> = const { Cell::new(None) };
+}
+
+/// Which build of the emulator is running, for bug reports
+#[derive(Tsify, Debug, Clone, Serialize)]
+pub struct VersionInfo {
+    pub core_version: String,
+    pub wasm_version: String,
+    /// Short hash of the commit the build was made from, "unknown" outside a git checkout
+    pub git_commit: String,
+    /// Time of the build in seconds since the Unix epoch
+    pub build_timestamp: u64,
+    /// Graphics API the renderer uses, missing until the renderer is initialized
+    #[tsify(optional)]
+    pub graphics_backend: Option<String>,
+    /// Version of the save state format, states only load into builds with the same version
+    pub save_state_version: u32,
+}
+
+impl VersionInfo {
+    pub fn current() -> Self {
+        Self {
+            core_version: gb_web_core::VERSION.to_string(),
+            wasm_version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: env!("GB_WEB_GIT_COMMIT").to_string(),
+            build_timestamp: env!("GB_WEB_BUILD_TIMESTAMP").parse().unwrap_or_default(),
+            graphics_backend: GRAPHICS_BACKEND.get().map(|backend| format!("{backend:?}")),
+            save_state_version: gb_web_core::CPU::STATE_FORMAT_VERSION,
+        }
+    }
+
+    /// Records the backend of a new renderer, or that there is none
+    pub fn set_graphics_backend(backend: Option<wgpu::Backend>) {
+        GRAPHICS_BACKEND.set(backend);
+    }
+}